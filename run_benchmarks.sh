@@ -11,6 +11,8 @@ WS_PORT="${WS_PORT:-443}"
 APP_KEY="${APP_KEY:-knife-library-likely}"
 CHANNEL="${CHANNEL:-trident_filter_tokens_v1}"
 TOKEN_FILE="${TOKEN_FILE:-token-addresses.json}"
+# Wire protocols to compare, space separated (pusher, raw, graphql-ws)
+PROTOCOLS="${PROTOCOLS:-pusher}"
 
 # Test configuration
 RAMP_DURATION=30
@@ -36,6 +38,7 @@ echo "Results will be saved to: $RESULTS_DIR"
 echo "Configuration:"
 echo "  Server: wss://${WS_HOST}:${WS_PORT}"
 echo "  Channel: ${CHANNEL}"
+echo "  Protocols: ${PROTOCOLS}"
 echo "  Ramp Duration: ${RAMP_DURATION}s"
 echo "  Hold Duration: ${HOLD_DURATION}s"
 echo "  Ramp Down: ${RAMP_DOWN_DURATION}s"
@@ -47,41 +50,58 @@ cargo build --release
 echo -e "${GREEN}✓ Build complete${NC}"
 echo ""
 
-# Function to run a single benchmark
+# Function to run a single benchmark, once per protocol in $PROTOCOLS
 run_benchmark() {
     local scenario=$1
     local num_clients=$2
     local scenario_name=$3
 
-    local output_file="${RESULTS_DIR}/scenario${scenario}_${num_clients}clients.log"
-
-    echo -e "${BLUE}────────────────────────────────────────────────────────────${NC}"
-    echo -e "${YELLOW}Running: Scenario ${scenario} - ${scenario_name}${NC}"
-    echo -e "${YELLOW}Clients: ${num_clients}${NC}"
-    echo -e "${BLUE}────────────────────────────────────────────────────────────${NC}"
-
-    cargo run --release -- \
-        --ws-host "$WS_HOST" \
-        --ws-port "$WS_PORT" \
-        --app-key "$APP_KEY" \
-        --channel "$CHANNEL" \
-        --scenario "$scenario" \
-        --num-clients "$num_clients" \
-        --ramp-duration "$RAMP_DURATION" \
-        --hold-duration "$HOLD_DURATION" \
-        --ramp-down-duration "$RAMP_DOWN_DURATION" \
-        2>&1 | tee "$output_file"
-
-    if [ ${PIPESTATUS[0]} -eq 0 ]; then
-        echo -e "${GREEN}✓ Scenario ${scenario} with ${num_clients} clients completed successfully${NC}"
-    else
-        echo -e "${RED}✗ Scenario ${scenario} with ${num_clients} clients failed${NC}"
-    fi
+    for protocol in $PROTOCOLS; do
+        local run_name="scenario${scenario}_${num_clients}clients_${protocol}"
+        local output_file="${RESULTS_DIR}/${run_name}.log"
+        local summary_json="${RESULTS_DIR}/${run_name}.json"
 
-    echo ""
-    sleep 5  # Cool down between tests
+        echo -e "${BLUE}────────────────────────────────────────────────────────────${NC}"
+        echo -e "${YELLOW}Running: Scenario ${scenario} - ${scenario_name}${NC}"
+        echo -e "${YELLOW}Clients: ${num_clients}, protocol: ${protocol}${NC}"
+        echo -e "${BLUE}────────────────────────────────────────────────────────────${NC}"
+
+        cargo run --release -- \
+            --ws-host "$WS_HOST" \
+            --ws-port "$WS_PORT" \
+            --app-key "$APP_KEY" \
+            --channel "$CHANNEL" \
+            --protocol "$protocol" \
+            --scenario "$scenario" \
+            --num-clients "$num_clients" \
+            --ramp-duration "$RAMP_DURATION" \
+            --hold-duration "$HOLD_DURATION" \
+            --ramp-down-duration "$RAMP_DOWN_DURATION" \
+            --output-format k6 \
+            --output-file "$summary_json" \
+            2>&1 | tee "$output_file"
+
+        if [ ${PIPESTATUS[0]} -eq 0 ]; then
+            echo -e "${GREEN}✓ Scenario ${scenario} with ${num_clients} clients over ${protocol} completed successfully${NC}"
+        else
+            echo -e "${RED}✗ Scenario ${scenario} with ${num_clients} clients over ${protocol} failed${NC}"
+        fi
+
+        echo ""
+        sleep 5  # Cool down between tests
+    done
 }
 
+# Scenario 0: unfiltered baseline
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 0: Unfiltered firehose baseline${NC}"
+echo -e "${GREEN} Description: Each client subscribes to the channel without a filter${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 0 1000 "No filter"
+run_benchmark 0 5000 "No filter"
+run_benchmark 0 10000 "No filter"
+
 # Scenario 1: Single random token_address per client (eq)
 echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
 echo -e "${GREEN} SCENARIO 1: Mass Subscribe with unique subscription tags${NC}"
@@ -132,6 +152,86 @@ run_benchmark 5 1000 "500 token_addresses (IN)"
 run_benchmark 5 5000 "500 token_addresses (IN)"
 run_benchmark 5 10000 "500 token_addresses (IN)"
 
+# Scenario 6: 10 random token_addresses AND chain_id
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 6: Compound AND filter${NC}"
+echo -e "${GREEN} Description: 10 random token_addresses (IN) AND chain_id eq 1${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 6 1000 "10 token_addresses AND chain_id"
+run_benchmark 6 5000 "10 token_addresses AND chain_id"
+run_benchmark 6 10000 "10 token_addresses AND chain_id"
+
+# Scenario 7: one random token_address OR chain_id
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 7: Compound OR filter${NC}"
+echo -e "${GREEN} Description: A random token_address (eq) OR chain_id eq 1${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 7 1000 "token_address OR chain_id"
+run_benchmark 7 5000 "token_address OR chain_id"
+run_benchmark 7 10000 "token_address OR chain_id"
+
+# Scenario 8: numeric range filter
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 8: Numeric range filter${NC}"
+echo -e "${GREEN} Description: price gt X, X uniform in 0-1000 per client${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 8 1000 "price gt X"
+run_benchmark 8 5000 "price gt X"
+run_benchmark 8 10000 "price gt X"
+
+# Scenario 9: everything but one random token_address (neq)
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 9: Negated single filter${NC}"
+echo -e "${GREEN} Description: Each client excludes one random token_address (neq)${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 9 1000 "token_address neq"
+run_benchmark 9 5000 "token_address neq"
+run_benchmark 9 10000 "token_address neq"
+
+# Scenario 10: everything but 100 random token_addresses (nin)
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 10: Negated IN filter${NC}"
+echo -e "${GREEN} Description: Each client excludes 100 random token_addresses (NIN)${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 10 1000 "100 token_addresses (NIN)"
+run_benchmark 10 5000 "100 token_addresses (NIN)"
+run_benchmark 10 10000 "100 token_addresses (NIN)"
+
+# Scenario 11: unsubscribe + resubscribe cycling
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 11: Subscription churn${NC}"
+echo -e "${GREEN} Description: Each client unsubscribes and resubscribes every 5 seconds${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 11 1000 "Unsubscribe/resubscribe every 5s"
+run_benchmark 11 5000 "Unsubscribe/resubscribe every 5s"
+run_benchmark 11 10000 "Unsubscribe/resubscribe every 5s"
+
+# Scenario 12: incremental filter updates
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 12: Delta filter updates${NC}"
+echo -e "${GREEN} Description: 100 token_addresses (IN), swapping 10% every 5 seconds${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 12 1000 "Swap 10% of 100 token_addresses every 5s"
+run_benchmark 12 5000 "Swap 10% of 100 token_addresses every 5s"
+run_benchmark 12 10000 "Swap 10% of 100 token_addresses every 5s"
+
+# Scenario 13: client events between benchmark clients
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo -e "${GREEN} SCENARIO 13: Client events${NC}"
+echo -e "${GREEN} Description: Unfiltered, each client publishes a client event every second${NC}"
+echo -e "${GREEN}═══════════════════════════════════════════════════════════${NC}"
+echo ""
+run_benchmark 13 1000 "Client event every 1s"
+run_benchmark 13 5000 "Client event every 1s"
+run_benchmark 13 10000 "Client event every 1s"
+
 # Generate summary
 echo -e "${BLUE}════════════════════════════════════════════════════════════${NC}"
 echo -e "${BLUE}     Benchmark Suite Complete!${NC}"
@@ -149,6 +249,7 @@ SUMMARY_FILE="${RESULTS_DIR}/summary.txt"
     echo "Date: $(date)"
     echo "Server: wss://${WS_HOST}:${WS_PORT}"
     echo "Channel: ${CHANNEL}"
+    echo "Protocols: ${PROTOCOLS}"
     echo ""
     echo "Configuration:"
     echo "  Ramp Duration: ${RAMP_DURATION}s"
@@ -169,6 +270,38 @@ SUMMARY_FILE="${RESULTS_DIR}/summary.txt"
             echo ""
         fi
     done
+
+    # Cross-protocol comparison of every matrix cell from its k6 summary
+    # export: one row per protocol, subscribe standing in for the join
+    if command -v jq > /dev/null; then
+        echo "Comparison (ms):"
+        echo "----------------"
+        printf "%-24s %-10s %10s %10s %10s %10s %10s %10s %8s\n" \
+            "run" "protocol" "connect95" "connect99" "sub_p50" "sub_p99" "e2e_p50" "e2e_p99" "errors"
+        for cell in $(ls "$RESULTS_DIR"/*.json 2> /dev/null | xargs -n1 basename | sed 's/_[^_]*\.json$//' | sort -uV); do
+            for protocol in $PROTOCOLS; do
+                json_file="${RESULTS_DIR}/${cell}_${protocol}.json"
+                [ -f "$json_file" ] || continue
+                jq -r '
+                    .metrics as $m
+                    | [($m.ws_connecting["p(95)"] // "-"),
+                       ($m.ws_connecting["p(99)"] // "-"),
+                       ($m.ws_subscribe_latency.med // "-"),
+                       ($m.ws_subscribe_latency["p(99)"] // "-"),
+                       ($m.ws_e2e_latency.med // "-"),
+                       ($m.ws_e2e_latency["p(99)"] // "-"),
+                       ($m.ws_connection_errors.count // 0)]
+                    | @tsv' "$json_file" |
+                    while IFS=$'\t' read -r c95 c99 s50 s99 e50 e99 errors; do
+                        printf "%-24s %-10s %10s %10s %10s %10s %10s %10s %8s\n" \
+                            "$cell" "$protocol" "$c95" "$c99" "$s50" "$s99" "$e50" "$e99" "$errors"
+                    done
+            done
+        done
+        echo ""
+    else
+        echo "(install jq for a cross-protocol comparison table)"
+    fi
 } > "$SUMMARY_FILE"
 
 echo -e "${GREEN}✓ Summary generated: ${SUMMARY_FILE}${NC}"
@@ -0,0 +1,171 @@
+//! Deliberately misbehaving clients and pong fault injection.
+
+use super::*;
+
+// =============================================================================
+// Non-Compliant Clients
+// =============================================================================
+
+/// Deliberate protocol violations used to probe server isolation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum Misbehavior {
+    /// Ignores `pusher:ping` and raw `ping`
+    NeverPong,
+    /// Sends a truncated subscribe frame instead of a valid one
+    MalformedSubscribe,
+    /// Sends one oversized junk frame before subscribing
+    OversizedFrame,
+}
+
+impl Misbehavior {
+    pub(crate) const ALL: [Misbehavior; 3] = [
+        Misbehavior::NeverPong,
+        Misbehavior::MalformedSubscribe,
+        Misbehavior::OversizedFrame,
+    ];
+
+    /// Deterministically pick the cohort for `id`, so that multi-machine runs
+    /// with `--client-id-offset` agree on which clients misbehave.
+    pub(crate) fn assign(id: usize, percent: f64) -> Option<Self> {
+        id_in_percent(id, percent, 0).then(|| Self::ALL[id % Self::ALL.len()])
+    }
+
+    pub(crate) fn label(self) -> &'static str {
+        match self {
+            Misbehavior::NeverPong => "never-pong",
+            Misbehavior::MalformedSubscribe => "malformed-subscribe",
+            Misbehavior::OversizedFrame => "oversized-frame",
+        }
+    }
+}
+
+/// Whether client `id` falls in a deterministic `percent` sample. Different
+/// `salt`s give independent samples over the same ids.
+pub(crate) fn id_in_percent(id: usize, percent: f64, salt: u64) -> bool {
+    if percent <= 0.0 {
+        return false;
+    }
+    // Knuth multiplicative hash spreads the sample across the ramp
+    let bucket = (id as u64 ^ salt).wrapping_mul(2_654_435_761) % 10_000;
+    (bucket as f64) < percent * 100.0
+}
+
+/// Truncated JSON sent by [`Misbehavior::MalformedSubscribe`] clients.
+pub(crate) const MALFORMED_SUBSCRIBE: &str = r#"{"event":"pusher:subscribe","data":{"channel":"#;
+
+// =============================================================================
+// Pong Chaos
+// =============================================================================
+
+/// How long to keep watching for recovery after the pong chaos window ends.
+pub(crate) const CHAOS_RECOVERY_LIMIT: Duration = Duration::from_secs(60);
+
+/// Longest pong chaos window `POST /chaos/drop-pongs` accepts.
+pub(crate) const MAX_CHAOS_WINDOW: Duration = Duration::from_secs(3600);
+
+/// How the server's liveness policy reacted to clients going silent on pings.
+pub(crate) struct ChaosReport {
+    pub(crate) window: Duration,
+    /// Connections open when the chaos began
+    pub(crate) baseline_active: usize,
+    pub(crate) min_active: usize,
+    /// Time from chaos start until the first connection was reaped
+    pub(crate) first_reap: Option<Duration>,
+    /// Time from chaos start until the fewest connections were open
+    pub(crate) lowest_at: Duration,
+    /// Time from chaos start until the baseline was restored after the window
+    pub(crate) recovered: Option<Duration>,
+    /// Connection errors (across all clients) during the observation
+    pub(crate) connection_errors: u64,
+}
+
+impl ChaosReport {
+    pub(crate) fn log(&self) {
+        info!(
+            "  Pongs dropped for {}s: {} active before, {} reaped",
+            self.window.as_secs(),
+            self.baseline_active,
+            self.baseline_active.saturating_sub(self.min_active)
+        );
+        if let Some(first) = self.first_reap {
+            info!(
+                "  First reap after {:.1}s, fewest connections ({}) at {:.1}s",
+                first.as_secs_f64(),
+                self.min_active,
+                self.lowest_at.as_secs_f64()
+            );
+        }
+        match self.recovered {
+            Some(at) => info!(
+                "  Recovered to {} active after {:.1}s ({} connection errors on the way)",
+                self.baseline_active,
+                at.as_secs_f64(),
+                self.connection_errors
+            ),
+            None if self.first_reap.is_some() => info!(
+                "  Not recovered within {}s of the window ending",
+                CHAOS_RECOVERY_LIMIT.as_secs()
+            ),
+            None => {}
+        }
+    }
+}
+
+/// Make every client ignore pings for `window` (at most `MAX_CHAOS_WINDOW`),
+/// watching the active count until it recovers (or `CHAOS_RECOVERY_LIMIT`
+/// after the window).
+pub(crate) fn spawn_pong_chaos(
+    live_stats: LiveStats,
+    window: Duration,
+) -> tokio::task::JoinHandle<ChaosReport> {
+    let window = window.min(MAX_CHAOS_WINDOW);
+    tokio::spawn(async move {
+        let start = Instant::now();
+        live_stats
+            .drop_pongs_until_ms
+            .store(unix_millis() + window.as_millis() as u64, Ordering::Relaxed);
+        warn!("Pong chaos: clients ignore pings for {}s", window.as_secs());
+
+        let baseline_active = live_stats.active_connections.load(Ordering::Relaxed);
+        let baseline_errors = live_stats.connection_errors.load(Ordering::Relaxed);
+        let mut report = ChaosReport {
+            window,
+            baseline_active,
+            min_active: baseline_active,
+            first_reap: None,
+            lowest_at: Duration::ZERO,
+            recovered: None,
+            connection_errors: 0,
+        };
+
+        let mut ticker = interval(Duration::from_millis(250));
+        while start.elapsed() < window + CHAOS_RECOVERY_LIMIT {
+            ticker.tick().await;
+            let active = live_stats.active_connections.load(Ordering::Relaxed);
+            if active < baseline_active && report.first_reap.is_none() {
+                report.first_reap = Some(start.elapsed());
+            }
+            if active < report.min_active {
+                report.min_active = active;
+                report.lowest_at = start.elapsed();
+            }
+            if start.elapsed() >= window && active >= baseline_active {
+                if report.first_reap.is_some() {
+                    report.recovered = Some(start.elapsed());
+                }
+                break;
+            }
+        }
+
+        report.connection_errors =
+            live_stats.connection_errors.load(Ordering::Relaxed) - baseline_errors;
+        info!("Pong chaos over");
+        report.log();
+        report
+    })
+}
+
+#[inline]
+pub(crate) fn dropping_pongs(live_stats: &LiveStats) -> bool {
+    unix_millis() < live_stats.drop_pongs_until_ms.load(Ordering::Relaxed)
+}
@@ -0,0 +1,1096 @@
+//! The per-connection WebSocket client loop.
+
+use super::*;
+
+// =============================================================================
+// Reconnect Gap Recovery
+// =============================================================================
+
+/// Bookkeeping for one disconnect -> resubscribe -> caught-up cycle.
+pub(crate) struct ReconnectGap {
+    pub(crate) disconnected_at: Instant,
+    /// Set once the resubscribe is acknowledged; messages stamped before
+    /// `resubscribed_wall_ms` were published during the gap, i.e. replays.
+    pub(crate) resubscribed_at: Option<Instant>,
+    pub(crate) resubscribed_wall_ms: u64,
+    pub(crate) replayed: u64,
+}
+
+// =============================================================================
+// Filter Update Propagation
+// =============================================================================
+
+/// Tracks delivery around a single filter update (Scenario 2).
+///
+/// Each update bumps the client's logical filter epoch; messages are then
+/// attributed to the old or new filter by their tag value, which is the only
+/// causal link we have to the server-side filter swap.
+pub(crate) struct FilterTransition {
+    pub(crate) epoch: u64,
+    pub(crate) started: Instant,
+    pub(crate) old_filter: FilterValue,
+    pub(crate) new_seen: bool,
+    pub(crate) last_stale_ms: Option<u64>,
+}
+
+impl FilterTransition {
+    /// Attribute one channel message, with tags looked up by `tag`, to this
+    /// transition. Returns the propagation latency if this is the first
+    /// message matching `new_filter`.
+    pub(crate) fn observe<'a>(
+        &mut self,
+        new_filter: &FilterValue,
+        tag: &dyn Fn(&str) -> Option<&'a sonic_rs::Value>,
+        result: &mut ClientResult,
+    ) -> Option<u64> {
+        let elapsed = self.started.elapsed().as_millis() as u64;
+        if new_filter.matches(tag) {
+            if !self.new_seen {
+                self.new_seen = true;
+                return Some(elapsed);
+            }
+        } else if self.old_filter.matches(tag) {
+            self.last_stale_ms = Some(elapsed);
+            result.stale_messages += 1;
+        }
+        None
+    }
+
+    pub(crate) fn finish(self, result: &mut ClientResult) {
+        if let Some(window) = self.last_stale_ms {
+            result.stale_delivery_windows.push(window);
+        }
+    }
+}
+
+// =============================================================================
+// WebSocket Client (returns results, no shared locks)
+// =============================================================================
+
+/// `debug!` that is promoted to `info!` while `$traced` holds.
+macro_rules! client_debug {
+    ($traced:expr, $($arg:tt)+) => {
+        if $traced {
+            info!($($arg)+)
+        } else {
+            debug!($($arg)+)
+        }
+    };
+}
+
+/// How long a client waits for the server to complete the close handshake.
+pub(crate) const CLOSE_DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub(crate) async fn run_client(
+    id: usize,
+    config: Arc<Config>,
+    tokens: TokenPool,
+    live_stats: LiveStats,
+    transport: Arc<dyn Transport>,
+    mut shutdown: broadcast::Receiver<()>,
+    raw_samples: Option<SampleSender>,
+) -> ClientResult {
+    let tokens = tokens.for_client(id, config.num_clients, config.token_overlap);
+    let mut result = ClientResult::new();
+    result.id = id;
+    let export = |kind: &'static str, value_ms: u64| {
+        if let Some(tx) = &raw_samples {
+            let _ = tx.send(RawSample {
+                unix_ms: unix_millis(),
+                client_id: id,
+                kind,
+                value_ms,
+            });
+        }
+    };
+    let misbehavior = Misbehavior::assign(id, config.misbehaving_percent);
+    result.misbehavior = misbehavior;
+    let tcp_info_sampled = id_in_percent(id, config.tcp_info_sample_percent, 0x7cb1);
+    // Whether `live_stats.subscribe_failed` already has this client; the
+    // deliberately misbehaving ones never count
+    let mut subscribe_failure_counted = misbehavior.is_some();
+
+    // Check if we should record metrics (after warmup)
+    let should_record = || {
+        live_stats.warmup_complete.load(Ordering::Relaxed)
+            && !live_stats.draining.load(Ordering::Relaxed)
+            && live_stats.canary.is_none()
+    };
+    let traced = || live_stats.debug_client.load(Ordering::Relaxed) == id;
+
+    let wire = config.protocol.wire();
+    let url = format!(
+        "{}{}",
+        config.server_origin("ws", "wss"),
+        wire.path(&config)
+    );
+
+    client_debug!(traced(), "Client {} connecting to {}", id, url);
+
+    let connect_timeout = Duration::from_millis(config.connect_timeout);
+    let reconnect_delay = Duration::from_millis(config.reconnect_delay);
+    let mut logged_first_message = false;
+
+    // Updating scenarios: Setup periodic filter updates (a plan may switch to
+    // one later), each after a `--think-time` draw
+    let mut scenario_rx = live_stats.scenario.subscribe();
+    // This client's `--mix` share, until a plan switches everyone
+    let mut scenario = config.client_scenario(id);
+    result.scenario = scenario;
+    let mut next_filter_update =
+        if config.scenarios.get(scenario).filter_updates || config.profile == LoadProfile::Plan {
+            Some(tokio::time::Instant::now() + config.think_time(scenario))
+        } else {
+            None
+        };
+
+    // Subscribe payloads are serialized once per filter and cloned for every
+    // (re)send, since `Message::Text` takes ownership. More than one when the
+    // filter is split.
+    let mut subscribe_json: Vec<String> = Vec::new();
+    let mut channels: Vec<String> = vec![config.channel.clone()];
+    let mut current_filter: Option<FilterValue> = None;
+    let mut filter_epoch: u64 = 0;
+    let mut transition: Option<FilterTransition> = None;
+    let mut shutdown_requested = false;
+
+    // With `--start-barrier`, the first connection_established frame is held
+    // here and replayed once the gate opens
+    let mut start_gate = live_stats.start_gate.subscribe();
+    let mut held_established: Option<String> = None;
+
+    // Reconnect state survives across sessions
+    let mut gap: Option<ReconnectGap> = None;
+    // Cookies the server set, resent on reconnect (`--sticky-cookies`)
+    let mut cookie_jar: Vec<(String, String)> = Vec::new();
+
+    // `--expect-msgs-per-min` windows; one only counts if the client was
+    // subscribed and measuring from end to end in a single session
+    let expect_window = Duration::from_secs(config.expect_window.max(1));
+    let mut throughput_timer = config.expect_msgs_per_min.map(|_| {
+        tokio::time::interval_at(tokio::time::Instant::now() + expect_window, expect_window)
+    });
+    let mut window_messages: u64 = 0;
+    let mut window_open = false;
+    let mut last_event_id: Option<String> = None;
+
+    'session: loop {
+        let queued = Instant::now();
+        if let Some(pacer) = &live_stats.connect_pacer {
+            tokio::select! {
+                _ = shutdown.recv() => break 'session,
+                _ = pacer.wait() => {}
+            }
+        }
+        // Held until the handshake completes or fails
+        let handshake_slot = match &live_stats.handshake_slots {
+            Some(slots) => tokio::select! {
+                _ = shutdown.recv() => break 'session,
+                permit = Arc::clone(slots).acquire_owned() => permit.ok(),
+            },
+            None => None,
+        };
+        if live_stats.connect_pacer.is_some() || live_stats.handshake_slots.is_some() {
+            result
+                .connect_queue_ms
+                .push(queued.elapsed().as_millis() as u64);
+        }
+
+        // Connect to WebSocket
+        let pending = live_stats.pending_connects.fetch_add(1, Ordering::Relaxed) + 1;
+        live_stats
+            .pending_connects_peak
+            .fetch_max(pending, Ordering::Relaxed);
+        let cookie = cookie_header(&config.cookies, &cookie_jar);
+        let connected = connect_timed(
+            transport.as_ref(),
+            &url,
+            cookie.as_deref(),
+            wire.subprotocol(),
+            connect_timeout,
+        )
+        .await;
+        live_stats.pending_connects.fetch_sub(1, Ordering::Relaxed);
+        drop(handshake_slot);
+        let (ws_stream, tcp_fd) = match connected {
+            Ok((ws_stream, setup, tcp_fd, headers)) => {
+                if gap.is_none() {
+                    result.connected = true;
+                    result.setup = Some(setup);
+                }
+                if config.sticky_cookies {
+                    for (name, value) in set_cookies(&headers) {
+                        match cookie_jar.iter_mut().find(|(n, _)| n == name) {
+                            Some((_, v)) => *v = value.to_string(),
+                            None => cookie_jar.push((name.to_string(), value.to_string())),
+                        }
+                    }
+                }
+                if let Some(backend_id) = &config.backend_id {
+                    let backend = backend_id
+                        .find(&headers, cookie.as_deref())
+                        .unwrap_or_else(|| "(unnamed)".to_string());
+                    result.backends.push(backend);
+                }
+                (ws_stream, tcp_fd)
+            }
+            Err(e) => {
+                error!("Client {} failed to connect: {}", id, e);
+                live_stats.connection_errors.fetch_add(1, Ordering::Relaxed);
+                result
+                    .errors
+                    .push((Instant::now(), ErrorCategory::from_connect(&e)));
+                if gap.is_none() || result.reconnects >= config.max_reconnects {
+                    result.connection_error |= gap.is_none();
+                    break 'session;
+                }
+                result.reconnects += 1;
+                tokio::select! {
+                    _ = shutdown.recv() => break 'session,
+                    _ = sleep(reconnect_delay) => continue 'session,
+                }
+            }
+        };
+
+        let upgraded_at = Instant::now();
+        live_stats
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        client_debug!(traced(), "Client {} connected successfully", id);
+
+        let (mut write, mut read) = ws_stream.split();
+
+        let mut subscribe_time: Option<Instant> = None;
+        let mut update_time: Option<Instant> = None;
+        let mut subscribed = false;
+        // Read error on this connection; only a connection error if not resumed
+        let mut stream_failed = false;
+        let mut is_updating = false;
+        // Sent time of the unsubscribe in flight, and whether it was acknowledged
+        let mut unsubscribed: Option<(Instant, bool)> = None;
+        let mut pending_acks: usize = 0;
+        // When the held connection_established frame is released under `--subscribe-jitter`
+        let mut subscribe_at: Option<tokio::time::Instant> = None;
+
+        // Kernel RTT sampling for a subset of TCP clients
+        let mut tcp_info_timer = tcp_fd.filter(|_| tcp_info_sampled).map(|fd| {
+            (
+                fd,
+                interval(Duration::from_millis(config.tcp_info_interval)),
+            )
+        });
+        let mut latest_rtt_us: Option<u32> = None;
+        let mut last_retrans: u32 = 0;
+
+        // `--client-ping`, and when the ping awaiting its pong was sent
+        let mut ping_timer = (config.client_ping > 0).then(|| {
+            let every = Duration::from_secs(config.client_ping);
+            tokio::time::interval_at(tokio::time::Instant::now() + every, every)
+        });
+        let mut ping_sent: Option<Instant> = None;
+        // Publishing scenarios: when the next client event is due
+        let mut next_client_event = tokio::time::Instant::now();
+
+        // Acks waiting out `--ack-delay`, and sent acks awaiting confirmation
+        let ack_delay = Duration::from_millis(config.ack_delay);
+        let mut ack_queue: std::collections::VecDeque<(tokio::time::Instant, String, String)> =
+            std::collections::VecDeque::new();
+        let mut acks_in_flight: HashMap<String, Instant> = HashMap::new();
+
+        if let Some(hello) = wire.hello(&config) {
+            if let Err(e) = write.send(Message::Text(hello.clone())).await {
+                // The read side reports the broken connection
+                error!("Client {} failed to send its hello: {}", id, e);
+            } else {
+                record_bytes(
+                    &live_stats.bytes_sent,
+                    &mut result.bytes_sent,
+                    ws_frame_len(hello.len(), true),
+                    should_record(),
+                );
+            }
+        }
+        // Protocols without subscriptions count the connection as subscribed
+        if !wire.subscribes() {
+            if let Some(g) = gap.as_mut() {
+                let lat = g.disconnected_at.elapsed().as_millis() as u64;
+                result.reconnect_latencies.push(lat);
+                export("reconnect", lat);
+                g.resubscribed_at = Some(Instant::now());
+                g.resubscribed_wall_ms = unix_millis();
+            } else if !result.subscribe_success {
+                result.subscribe_success = true;
+                live_stats.subscribe_success.fetch_add(1, Ordering::Relaxed);
+            }
+            subscribed = true;
+        }
+
+        'conn: loop {
+            tokio::select! {
+                biased;
+
+                // Handle shutdown signal (high priority)
+                _ = shutdown.recv() => {
+                    client_debug!(traced(), "Client {} received shutdown signal", id);
+                    shutdown_requested = true;
+                    break;
+                }
+
+                // Handle incoming messages (highest throughput path); a held
+                // `connection_established` comes back as `replayed`
+                (replayed, msg) = async {
+                    if held_established.is_none() {
+                        return (false, read.next().await);
+                    }
+                    tokio::select! {
+                        m = read.next() => (false, m),
+                        _ = async {
+                            let _ = start_gate.wait_for(|open| *open).await;
+                            if let Some(at) = subscribe_at {
+                                tokio::time::sleep_until(at).await;
+                            }
+                        } => match held_established.take() {
+                            Some(text) => (true, Some(Ok(Message::Text(text)))),
+                            None => (true, None),
+                        },
+                    }
+                } => {
+                    if let Some(Ok(m)) = &msg {
+                        if !replayed {
+                            record_bytes(&live_stats.bytes_received, &mut result.bytes_received, ws_frame_len(m.len(), false), should_record());
+                        }
+                        if traced() {
+                            info!("Client {} <- {:?}", id, m);
+                        }
+                    }
+
+                    // MessagePack and Protobuf frames carry the same envelopes as text ones
+                    let mut frame_len = None;
+                    let msg = match msg {
+                        Some(Ok(Message::Binary(bytes))) if config.payload_format != PayloadFormat::Json => {
+                            match binary_to_json(&config, &bytes) {
+                                Ok(text) => {
+                                    frame_len = Some(bytes.len());
+                                    Some(Ok(Message::Text(text)))
+                                }
+                                Err(e) => {
+                                    client_debug!(traced(), "Client {} undecodable {:?} frame: {}", id, config.payload_format, e);
+                                    continue;
+                                }
+                            }
+                        }
+                        msg => msg,
+                    };
+
+                    match msg {
+                        Some(Ok(Message::Text(text))) => {
+                            // Handle raw ping
+                            if text == "ping" {
+                                if misbehavior == Some(Misbehavior::NeverPong) || dropping_pongs(&live_stats) {
+                                    continue;
+                                }
+                                if write.send(Message::Text(RAW_PONG.to_owned())).await.is_ok() {
+                                    record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(RAW_PONG.len(), true), should_record());
+                                }
+                                continue;
+                            }
+
+                            // Parse into a Pusher message
+                            let Some(pusher_msg) = wire.classify(&text) else {
+                                continue;
+                            };
+
+                            match pusher_msg.event.as_str() {
+                                "pusher:ping" if misbehavior == Some(Misbehavior::NeverPong) || dropping_pongs(&live_stats) => {}
+
+                                "pusher:ping" => {
+                                    let pong = wire.pong();
+                                    if write.send(Message::Text(pong.to_owned())).await.is_ok() {
+                                        record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(pong.len(), true), should_record());
+                                    }
+                                }
+
+                                "pusher:pong" if config.client_ping_kind == PingKind::Pusher => {
+                                    if let Some(sent_at) = ping_sent.take() {
+                                        if should_record() {
+                                            result.ping_rtt_us.push(sent_at.elapsed().as_micros() as u64);
+                                        }
+                                    }
+                                }
+
+                                "pusher:connection_established" => {
+                                    client_debug!(traced(), "Client {} connection established", id);
+                                    if result.established_wait_ms.is_none() {
+                                        result.established_wait_ms = Some(upgraded_at.elapsed().as_millis() as u64);
+                                    }
+
+                                    if !*start_gate.borrow() {
+                                        client_debug!(traced(), "Client {} waiting at start barrier", id);
+                                        held_established = Some(text);
+                                        continue;
+                                    }
+                                    if config.subscribe_jitter > 0 && subscribe_at.is_none() {
+                                        let jitter = Duration::from_millis(rand::rng().random_range(0..=config.subscribe_jitter));
+                                        client_debug!(traced(), "Client {} subscribing in {:?}", id, jitter);
+                                        subscribe_at = Some(tokio::time::Instant::now() + jitter);
+                                        held_established = Some(text);
+                                        continue;
+                                    }
+                                    subscribe_at = None;
+
+                                    match misbehavior {
+                                        Some(Misbehavior::MalformedSubscribe) => {
+                                            if write.send(Message::Text(MALFORMED_SUBSCRIBE.to_owned())).await.is_ok() {
+                                                record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(MALFORMED_SUBSCRIBE.len(), true), should_record());
+                                            }
+                                            continue;
+                                        }
+                                        Some(Misbehavior::OversizedFrame) => {
+                                            let junk = "x".repeat(config.oversized_frame_bytes);
+                                            if write.send(Message::Text(junk)).await.is_ok() {
+                                                record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(config.oversized_frame_bytes, true), should_record());
+                                            }
+                                        }
+                                        _ => {}
+                                    }
+
+                                    if subscribe_json.is_empty() {
+                                        let filter = build_filter(&config, scenario, id, &tokens);
+                                        let channel = subscription_channel(&config, scenario, id, &live_stats);
+                                        let parts = split_filter(&channel, filter.clone(), config.filter_split_size);
+                                        subscribe_json = parts
+                                            .iter()
+                                            .filter_map(|(channel, part)| wire.subscribe(&config, channel, part, None))
+                                            .collect();
+                                        channels = parts.into_iter().map(|(channel, _)| channel).collect();
+                                        current_filter = Some(filter);
+                                    }
+
+                                    subscribe_time = Some(Instant::now());
+
+                                    // After a reconnect, ask the server to replay from the last seen event
+                                    let resume_json = match (&gap, &last_event_id, &current_filter) {
+                                        (Some(_), Some(event_id), Some(filter)) if wire.resumes() => {
+                                            result.resume_attempts += 1;
+                                            let channel = subscription_channel(&config, scenario, id, &live_stats);
+                                            split_filter(&channel, filter.clone(), config.filter_split_size)
+                                                .iter()
+                                                .filter_map(|(channel, part)| wire.subscribe(&config, channel, part, Some(event_id)))
+                                                .collect()
+                                        }
+                                        _ => Vec::new(),
+                                    };
+
+                                    let frames = if resume_json.is_empty() { &subscribe_json } else { &resume_json };
+                                    pending_acks = frames.len();
+                                    for json in frames {
+                                        if let Err(e) = write.send(Message::Text(json.clone())).await {
+                                            error!("Client {} failed to subscribe: {}", id, e);
+                                            break 'conn;
+                                        }
+                                        record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(json.len(), true), should_record());
+                                    }
+
+                                    if !wire.acknowledges_subscribe() {
+                                        pending_acks = 0;
+                                        if let Some(g) = gap.as_mut() {
+                                            let lat = g.disconnected_at.elapsed().as_millis() as u64;
+                                            result.reconnect_latencies.push(lat);
+                                            export("reconnect", lat);
+                                            g.resubscribed_at = Some(Instant::now());
+                                            g.resubscribed_wall_ms = unix_millis();
+                                        } else if !result.subscribe_success {
+                                            result.subscribe_success = true;
+                                            live_stats.subscribe_success.fetch_add(1, Ordering::Relaxed);
+                                        }
+                                        subscribed = true;
+                                    }
+                                }
+
+                                // Split subscriptions count as done once every part is acknowledged
+                                "pusher_internal:subscription_succeeded" if pending_acks > 1 => {
+                                    pending_acks -= 1;
+                                }
+
+                                "pusher_internal:subscription_succeeded" => {
+                                    pending_acks = 0;
+                                    if is_updating {
+                                        if let Some(start) = update_time {
+                                            if should_record() {
+                                                let lat = start.elapsed().as_millis() as u64;
+                                                if unsubscribed.is_some() {
+                                                    result.resubscribe_latencies.push(lat);
+                                                    export("resubscribe", lat);
+                                                } else {
+                                                    result.filter_update_latencies.push(lat);
+                                                    export("filter_update", lat);
+                                                }
+                                            }
+                                        }
+                                        is_updating = false;
+                                        unsubscribed = None;
+                                    } else if let Some(g) = gap.as_mut() {
+                                        let lat = g.disconnected_at.elapsed().as_millis() as u64;
+                                        result.reconnect_latencies.push(lat);
+                                        export("reconnect", lat);
+                                        g.resubscribed_at = Some(Instant::now());
+                                        g.resubscribed_wall_ms = unix_millis();
+                                        subscribed = true;
+                                        client_debug!(traced(), "Client {} resubscribed after reconnect", id);
+                                    } else {
+                                        if let Some(start) = subscribe_time {
+                                            let lat = start.elapsed().as_millis() as u64;
+                                            result.subscribe_latency_ms = Some(lat);
+                                            export("subscribe", lat);
+                                            result.subscribe_success = true;
+                                            live_stats.subscribe_success.fetch_add(1, Ordering::Relaxed);
+                                            subscribed = true;
+                                            client_debug!(traced(), "Client {} subscribed successfully", id);
+                                        }
+                                    }
+                                }
+
+                                "pusher_internal:unsubscribed" => {
+                                    if let Some((sent_at, acked @ false)) = unsubscribed.as_mut() {
+                                        *acked = true;
+                                        if should_record() {
+                                            result.unsubscribe_ack_latencies.push(sent_at.elapsed().as_millis() as u64);
+                                        }
+                                    }
+                                }
+
+                                "pusher:error" => {
+                                    error!("Client {} subscription error: {:?}", id, pusher_msg.data);
+                                    if !result.subscribe_success && !subscribe_failure_counted {
+                                        subscribe_failure_counted = true;
+                                        live_stats.subscribe_failed.fetch_add(1, Ordering::Relaxed);
+                                    }
+                                    let code = pusher_msg
+                                        .data
+                                        .as_ref()
+                                        .and_then(|d| d.get("code"))
+                                        .and_then(|c| c.as_i64());
+                                    result.errors.push((Instant::now(), ErrorCategory::Pusher(code)));
+                                }
+
+                                event if event == config.client_event => {
+                                    // Servers may relay `data` as a JSON string
+                                    let parsed;
+                                    let data = match pusher_msg.data.as_ref().and_then(|d| d.as_str()) {
+                                        Some(s) => {
+                                            parsed = sonic_rs::from_str::<sonic_rs::Value>(s).ok();
+                                            parsed.as_ref()
+                                        }
+                                        None => pusher_msg.data.as_ref(),
+                                    };
+                                    let sender = data.and_then(|d| d.get("sender")).and_then(|s| s.as_u64());
+                                    let sent_ms = data.and_then(|d| d.get("sent_ms")).and_then(|t| t.as_u64());
+                                    if let (Some(sender), Some(sent_ms)) = (sender, sent_ms) {
+                                        if sender != id as u64 && should_record() {
+                                            result.client_events_received += 1;
+                                            let latency = unix_millis().saturating_sub(sent_ms);
+                                            if latency < 60_000 {
+                                                result.client_event_latencies.push(latency);
+                                            }
+                                        }
+                                    }
+                                }
+
+                                event if config.ack_confirm_event.as_deref() == Some(event) => {
+                                    let event_id = pusher_msg
+                                        .data
+                                        .as_ref()
+                                        .and_then(|d| d.get(&config.resume_tag))
+                                        .and_then(|v| v.as_str().map(str::to_owned).or_else(|| v.as_u64().map(|n| n.to_string())))
+                                        .or_else(|| extract_event_id(&pusher_msg, &config.resume_tag));
+                                    if let Some(sent_at) = event_id.and_then(|e| acks_in_flight.remove(&e)) {
+                                        if should_record() {
+                                            result.acks_confirmed += 1;
+                                            result.ack_rtt_latencies.push(sent_at.elapsed().as_millis() as u64);
+                                        }
+                                    }
+                                }
+
+                                _ => {
+                                    // Channel message - hot path
+                                    let on_channel = !wire.channel_scoped()
+                                        || pusher_msg.channel.as_ref().is_some_and(|c| channels.contains(c));
+                                    if subscribed && on_channel {
+                                        live_stats.messages_received.fetch_add(1, Ordering::Relaxed);
+                                        if unsubscribed.is_some() && should_record() {
+                                            result.post_unsubscribe_messages += 1;
+                                        }
+
+                                        if config.ack {
+                                            if let (Some(channel), Some(event_id)) = (&pusher_msg.channel, extract_event_id(&pusher_msg, &config.resume_tag)) {
+                                                ack_queue.push_back((tokio::time::Instant::now() + ack_delay, channel.clone(), event_id));
+                                            }
+                                        }
+
+                                        // Log first message for debugging
+                                        if !logged_first_message {
+                                            info!("Client {} first message - Event: {}, Tags: {:?}",
+                                                id, pusher_msg.event, pusher_msg.tags);
+                                            logged_first_message = true;
+                                        }
+
+                                        if config.reconnect {
+                                            if let Some(event_id) = extract_event_id(&pusher_msg, &config.resume_tag) {
+                                                last_event_id = Some(event_id);
+                                            }
+                                        }
+
+                                        // Unsampled messages skip timestamp parsing, except while
+                                        // replays still have to be told apart from live messages
+                                        let e2e_sampled = config.e2e_sample_rate >= 1.0
+                                            || rand::rng().random::<f64>() < config.e2e_sample_rate;
+                                        let ts = if e2e_sampled || gap.is_some() {
+                                            wire.timestamp(&pusher_msg)
+                                        } else {
+                                            None
+                                        };
+
+                                        // Messages stamped before the resubscribe are gap replays
+                                        if let Some(g) = gap.as_mut() {
+                                            if let Some(resubscribed_at) = g.resubscribed_at {
+                                                if ts.is_some_and(|ts| ts < g.resubscribed_wall_ms) {
+                                                    g.replayed += 1;
+                                                    result.replayed_messages += 1;
+                                                    continue;
+                                                }
+                                                result.catchup_latencies.push(resubscribed_at.elapsed().as_millis() as u64);
+                                                client_debug!(traced(), "Client {} caught up after {} replayed messages", id, g.replayed);
+                                                gap = None;
+                                            }
+                                        }
+
+                                        // Only record metrics after warmup
+                                        if should_record() {
+                                            result.messages_received += 1;
+                                            window_messages += 1;
+                                            result.message_sizes.push(frame_len.unwrap_or(text.len()) as u64);
+
+                                            if config.validate_token_addresses {
+                                                if let Some(address) = extract_tag(&pusher_msg, "token_address") {
+                                                    result.addresses_checked += 1;
+                                                    if let Some(problem) = token_address_problem(address) {
+                                                        client_debug!(traced(), "Client {} malformed token_address ({}): {:?}", id, problem, address);
+                                                        *result.malformed_addresses.entry(problem).or_default() += 1;
+                                                    }
+                                                }
+                                            }
+
+                                            if config.validate_filters {
+                                                if let Some(filter) = current_filter.as_ref().filter(|f| f.verifiable()) {
+                                                    let tag = |key: &str| extract_tag_value(&pusher_msg, key);
+                                                    result.filters_checked += 1;
+                                                    // Old-filter matches mid-update count as stale instead
+                                                    if !filter.matches(&tag) && !transition.as_ref().is_some_and(|t| t.old_filter.matches(&tag)) {
+                                                        client_debug!(traced(), "Client {} received a message outside its filter: {:?}", id, pusher_msg.tags);
+                                                        result.false_positives += 1;
+                                                    }
+                                                }
+                                            }
+
+                                            // Attribute to the in-flight filter transition
+                                            if let (Some(t), Some(filter)) = (transition.as_mut(), current_filter.as_ref()) {
+                                                if let Some(lat) = t.observe(filter, &|key| extract_tag_value(&pusher_msg, key), &mut result) {
+                                                    client_debug!(traced(), "Client {} filter epoch {} propagated in {}ms", id, t.epoch, lat);
+                                                    result.filter_propagation_latencies.push(lat);
+                                                    export("filter_propagation", lat);
+                                                }
+                                            }
+
+                                            // Record E2E latency
+                                            if let Some(ts) = ts.filter(|_| e2e_sampled) {
+                                                let now_ms = unix_millis();
+                                                let latency = now_ms.saturating_sub(ts);
+
+                                                if live_stats.clock_suspect.load(Ordering::Relaxed) {
+                                                    result.clock_excluded_e2e += 1;
+                                                }
+                                                // Sanity check: ignore if > 60s
+                                                else if latency < 60_000 {
+                                                    if config.latency_window > 0 || config.profile == LoadProfile::Plan {
+                                                        result.e2e_timeline.push((now_ms, latency));
+                                                    }
+                                                    export("e2e", latency);
+                                                    if tokens.has_strata() {
+                                                        let stratum = current_filter
+                                                            .as_ref()
+                                                            .and_then(|f| extract_tag(&pusher_msg, f.key()))
+                                                            .and_then(|tag| tokens.stratum(tag));
+                                                        if let Some(stratum) = stratum {
+                                                            match result.e2e_by_stratum.get_mut(&*stratum) {
+                                                                Some(samples) => samples.push(latency),
+                                                                None => {
+                                                                    result.e2e_by_stratum.insert(stratum.to_string(), vec![latency]);
+                                                                }
+                                                            }
+                                                        }
+                                                    }
+                                                    if let Some(rtt_us) = latest_rtt_us {
+                                                        result.rtt_tagged_e2e.push((latency, rtt_us));
+                                                    }
+                                                    if live_stats.cpu_suspect.load(Ordering::Relaxed) {
+                                                        result.suspect_e2e_latencies.push(latency);
+                                                    }
+                                                    live_stats.e2e.record(latency);
+                                                    match result.e2e_latencies.get_mut(pusher_msg.event.as_str()) {
+                                                        Some(samples) => samples.push(latency),
+                                                        None => {
+                                                            let mut samples = Vec::with_capacity(1024);
+                                                            samples.push(latency);
+                                                            result.e2e_latencies.insert(pusher_msg.event.clone(), samples);
+                                                        }
+                                                    }
+                                                }
+                                            }
+                                        } else if let Some(canary) = &live_stats.canary {
+                                            let now_ms = unix_millis();
+                                            let latency = ts
+                                                .filter(|_| e2e_sampled)
+                                                .map(|ts| now_ms.saturating_sub(ts))
+                                                .filter(|&latency| latency < 60_000);
+                                            canary.observe(now_ms, latency);
+                                        } else if live_stats.draining.load(Ordering::Relaxed) {
+                                            if let Some(ts) = ts.filter(|_| e2e_sampled) {
+                                                let latency = unix_millis().saturating_sub(ts);
+                                                if latency < 60_000 {
+                                                    live_stats.drain_e2e_sum.fetch_add(latency, Ordering::Relaxed);
+                                                    live_stats.drain_e2e_count.fetch_add(1, Ordering::Relaxed);
+                                                    live_stats.drain_e2e_max.fetch_max(latency, Ordering::Relaxed);
+                                                }
+                                            }
+                                        } else {
+                                            result.messages_received_during_warmup += 1;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        Some(Ok(Message::Close(frame))) => {
+                            client_debug!(traced(), "Client {} received close frame: {:?}", id, frame);
+                            result.server_closed = true;
+                            result.close_frame = Some(match frame {
+                                Some(f) => (u16::from(f.code), f.reason.into_owned()),
+                                // No payload is reported as 1005 (no status received)
+                                None => (1005, String::new()),
+                            });
+                            break;
+                        }
+
+                        Some(Ok(Message::Pong(_))) if config.client_ping_kind == PingKind::Frame => {
+                            if let Some(sent_at) = ping_sent.take() {
+                                if should_record() {
+                                    result.ping_rtt_us.push(sent_at.elapsed().as_micros() as u64);
+                                }
+                            }
+                        }
+
+                        Some(Err(e)) => {
+                            error!("Client {} WebSocket error: {}", id, e);
+                            stream_failed = true;
+                            result.errors.push((Instant::now(), ErrorCategory::from_ws(&e)));
+                            result.server_closed = true;
+                            break;
+                        }
+
+                        None => {
+                            client_debug!(traced(), "Client {} stream ended", id);
+                            result.server_closed = true;
+                            break;
+                        }
+
+                        _ => {}
+                    }
+                }
+
+                // Send acks whose artificial delay has elapsed
+                _ = async {
+                    match ack_queue.front() {
+                        Some((due, _, _)) => tokio::time::sleep_until(*due).await,
+                        None => std::future::pending().await,
+                    }
+                } => {
+                    let now = tokio::time::Instant::now();
+                    while ack_queue.front().is_some_and(|(due, _, _)| *due <= now) {
+                        let Some((_, channel, event_id)) = ack_queue.pop_front() else {
+                            break;
+                        };
+                        let ack = sonic_rs::to_string(&AckMessage {
+                            event: &config.ack_event,
+                            data: AckData { channel: &channel, event_id: &event_id },
+                        });
+                        let Ok(ack) = ack else { continue };
+                        let len = ack.len();
+                        if let Err(e) = write.send(Message::Text(ack)).await {
+                            error!("Client {} failed to send ack: {}", id, e);
+                            break 'conn;
+                        }
+                        record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(len, true), should_record());
+                        if should_record() {
+                            result.acks_sent += 1;
+                        }
+                        if config.ack_confirm_event.is_some() {
+                            acks_in_flight.insert(event_id, Instant::now());
+                        }
+                    }
+                }
+
+                // Sample kernel TCP_INFO for RTT / retransmits
+                Some(fd) = async {
+                    match &mut tcp_info_timer {
+                        Some((fd, timer)) => {
+                            timer.tick().await;
+                            Some(*fd)
+                        }
+                        None => None,
+                    }
+                } => {
+                    if let Some(info) = query_tcp_info(fd) {
+                        latest_rtt_us = Some(info.rtt_us);
+                        if should_record() {
+                            result.tcp_rtt_us.push(info.rtt_us as u64);
+                            result.tcp_retransmits += info.total_retrans.saturating_sub(last_retrans) as u64;
+                        }
+                        last_retrans = info.total_retrans;
+                    }
+                }
+
+                // Ping the server (`--client-ping`)
+                Some(_) = async {
+                    match &mut ping_timer {
+                        Some(timer) => Some(timer.tick().await),
+                        None => None,
+                    }
+                } => {
+                    if ping_sent.is_some() && should_record() {
+                        result.pings_unanswered += 1;
+                    }
+                    let (ping, len) = match config.client_ping_kind {
+                        PingKind::Pusher => (Message::Text(wire.ping().to_owned()), wire.ping().len()),
+                        PingKind::Frame => (Message::Ping(Vec::new()), 0),
+                    };
+                    if let Err(e) = write.send(ping).await {
+                        error!("Client {} failed to send ping: {}", id, e);
+                        break 'conn;
+                    }
+                    record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(len, true), should_record());
+                    ping_sent = Some(Instant::now());
+                    if should_record() {
+                        result.pings_sent += 1;
+                    }
+                }
+
+                // Publish a client event (scenarios with `client_event_rate`)
+                Some(rate) = async {
+                    match config.scenarios.get(scenario).client_events {
+                        Some(rate) if subscribed => {
+                            tokio::time::sleep_until(next_client_event).await;
+                            Some(rate)
+                        }
+                        _ => None,
+                    }
+                } => {
+                    next_client_event = tokio::time::Instant::now() + Duration::from_secs_f64(1.0 / rate);
+                    let event = sonic_rs::to_string(&ClientEventMessage {
+                        event: &config.client_event,
+                        channel: &channels[0],
+                        data: ClientEventData { sender: id, sent_ms: unix_millis() },
+                    });
+                    let Ok(event) = event else { continue };
+                    let len = event.len();
+                    if let Err(e) = write.send(Message::Text(event)).await {
+                        error!("Client {} failed to publish a client event: {}", id, e);
+                        break 'conn;
+                    }
+                    record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(len, true), should_record());
+                    if should_record() {
+                        result.client_events_sent += 1;
+                    }
+                }
+
+                // Close a throughput window (`--expect-msgs-per-min`)
+                Some(_) = async {
+                    match &mut throughput_timer {
+                        Some(timer) => Some(timer.tick().await),
+                        None => None,
+                    }
+                } => {
+                    let measuring = subscribed && should_record();
+                    if window_open && measuring {
+                        result.throughput_windows.push(window_messages);
+                    }
+                    window_messages = 0;
+                    window_open = measuring;
+                }
+
+                // Handle filter updates (updating scenarios) and plan scenario switches
+                Some(from_timer) = async {
+                    let updating = config.scenarios.get(scenario).filter_updates;
+                    tokio::select! {
+                        Some(_) = async {
+                            match next_filter_update {
+                                Some(at) if updating => {
+                                    tokio::time::sleep_until(at).await;
+                                    Some(())
+                                }
+                                _ => None,
+                            }
+                        } => Some(true),
+                        Ok(()) = scenario_rx.changed() => Some(false),
+                        else => None,
+                    }
+                } => {
+                    if from_timer {
+                        // Scheduled from the previous deadline so fixed think times
+                        // don't drift, but never in the past
+                        let now = tokio::time::Instant::now();
+                        next_filter_update = next_filter_update
+                            .map(|at| (at + config.think_time(scenario)).max(now));
+                    } else {
+                        scenario = *scenario_rx.borrow_and_update();
+                    }
+                    // A paused load keeps its filters; the timer still ticks so
+                    // resuming doesn't fire a burst of missed updates
+                    if subscribed && !(from_timer && *live_stats.paused.borrow()) {
+                        // Resubscribing scenarios drop the old subscription first
+                        if config.scenarios.get(scenario).resubscribe {
+                            for channel in &channels {
+                                let Some(json) = wire.unsubscribe(channel) else {
+                                    continue;
+                                };
+                                if let Err(e) = write.send(Message::Text(json.clone())).await {
+                                    error!("Client {} failed to unsubscribe: {}", id, e);
+                                    break 'conn;
+                                }
+                                record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(json.len(), true), should_record());
+                            }
+                            unsubscribed = Some((Instant::now(), false));
+                            if should_record() {
+                                result.resubscribe_cycles += 1;
+                            }
+                        }
+                        let current = config.scenarios.get(scenario);
+                        let filter = match (current.delta, &current_filter) {
+                            (Some(fraction), Some(old))
+                                if config.raw_filters.is_empty() && !config.filter_map.contains_key(&id) =>
+                            {
+                                current.filter.swap(old, fraction, &tokens)
+                            }
+                            _ => build_filter(&config, scenario, id, &tokens),
+                        };
+                        let channel = subscription_channel(&config, scenario, id, &live_stats);
+                        let parts = split_filter(&channel, filter.clone(), config.filter_split_size);
+                        subscribe_json = parts
+                            .iter()
+                            .filter_map(|(channel, part)| wire.subscribe(&config, channel, part, None))
+                            .collect();
+                        channels = parts.into_iter().map(|(channel, _)| channel).collect();
+
+                        update_time = Some(Instant::now());
+                        is_updating = true;
+
+                        if let Some(prev) = transition.take() {
+                            prev.finish(&mut result);
+                        }
+                        if let Some(old_filter) = current_filter.replace(filter) {
+                            filter_epoch += 1;
+                            transition = Some(FilterTransition {
+                                epoch: filter_epoch,
+                                started: Instant::now(),
+                                old_filter,
+                                new_seen: false,
+                                last_stale_ms: None,
+                            });
+                        }
+
+                        pending_acks = subscribe_json.len();
+                        for json in &subscribe_json {
+                            if let Err(e) = write.send(Message::Text(json.clone())).await {
+                                error!("Client {} failed to send filter update: {}", id, e);
+                                break 'conn;
+                            }
+                            record_bytes(&live_stats.bytes_sent, &mut result.bytes_sent, ws_frame_len(json.len(), true), should_record());
+                        }
+                    }
+                }
+            }
+        }
+
+        result.acks_outstanding += (ack_queue.len() + acks_in_flight.len()) as u64;
+        // A session ending mid-window leaves that window unchecked
+        window_open = false;
+
+        // Close handshake: keep reading until the server acknowledges, counting
+        // anything it still fans out to us as wasted work.
+        if shutdown_requested && write.send(Message::Close(None)).await.is_ok() {
+            let drain_start = Instant::now();
+            let deadline = sleep(CLOSE_DRAIN_TIMEOUT);
+            tokio::pin!(deadline);
+
+            loop {
+                tokio::select! {
+                    _ = &mut deadline => break,
+                    msg = read.next() => match msg {
+                        Some(Ok(Message::Text(_))) | Some(Ok(Message::Binary(_))) => {
+                            result.post_close_messages += 1;
+                            live_stats.post_close_messages.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Some(Ok(Message::Close(_))) | Some(Err(_)) | None => {
+                            result.close_drain_ms = Some(drain_start.elapsed().as_millis() as u64);
+                            break;
+                        }
+                        _ => {}
+                    },
+                }
+            }
+        }
+
+        live_stats
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+        client_debug!(traced(), "Client {} disconnected", id);
+
+        if shutdown_requested || !config.reconnect || result.reconnects >= config.max_reconnects {
+            result.connection_error |= stream_failed;
+            break 'session;
+        }
+
+        // Server dropped us: reconnect and resubscribe with the resume token
+        result.reconnects += 1;
+        gap = Some(ReconnectGap {
+            disconnected_at: Instant::now(),
+            resubscribed_at: None,
+            resubscribed_wall_ms: 0,
+            replayed: 0,
+        });
+        client_debug!(
+            traced(),
+            "Client {} reconnecting (attempt {})",
+            id,
+            result.reconnects
+        );
+        tokio::select! {
+            _ = shutdown.recv() => break 'session,
+            _ = sleep(reconnect_delay) => {}
+        }
+    }
+
+    if let Some(t) = transition.take() {
+        t.finish(&mut result);
+    }
+    // Connected but never subscribed, e.g. timed out: a subscribe failure
+    // unless it already counts as a connection error
+    if result.connected
+        && !result.subscribe_success
+        && !result.connection_error
+        && !subscribe_failure_counted
+    {
+        live_stats.subscribe_failed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    result
+}
@@ -0,0 +1,872 @@
+//! MessagePack and Protobuf payload decoding and timestamp extraction.
+
+use super::*;
+
+// =============================================================================
+// MessagePack (`--payload-format msgpack`)
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PayloadFormat {
+    Json,
+    Msgpack,
+    Protobuf,
+}
+
+/// Transcode a MessagePack-encoded envelope to JSON, so binary frames share
+/// the text path. Binary strings become (lossy UTF-8) strings, extension
+/// types null, and non-string map keys their JSON text.
+pub(crate) fn msgpack_to_json(bytes: &[u8]) -> Result<String, String> {
+    let mut reader = MsgpackReader { bytes, pos: 0 };
+    let mut out = String::with_capacity(bytes.len() * 2);
+    reader.value(&mut out, 0)?;
+    if reader.pos != bytes.len() {
+        return Err(format!("{} trailing bytes", bytes.len() - reader.pos));
+    }
+    Ok(out)
+}
+
+pub(crate) struct MsgpackReader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> MsgpackReader<'a> {
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or("truncated")?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    /// Big-endian unsigned integer of `n` bytes.
+    pub(crate) fn uint(&mut self, n: usize) -> Result<u64, String> {
+        Ok(self
+            .take(n)?
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    pub(crate) fn value(&mut self, out: &mut String, depth: usize) -> Result<(), String> {
+        use std::fmt::Write;
+
+        if depth > 64 {
+            return Err("nested too deeply".into());
+        }
+        let marker = self.take(1)?[0];
+        let width = |base: u8| 1usize << (marker - base);
+        match marker {
+            0x00..=0x7f => write!(out, "{}", marker).unwrap(),
+            0x80..=0x8f => self.map((marker & 0x0f) as usize, out, depth)?,
+            0x90..=0x9f => self.array((marker & 0x0f) as usize, out, depth)?,
+            0xa0..=0xbf => self.string((marker & 0x1f) as usize, out)?,
+            0xc0 => out.push_str("null"),
+            0xc1 => return Err("invalid marker 0xc1".into()),
+            0xc2 => out.push_str("false"),
+            0xc3 => out.push_str("true"),
+            0xc4..=0xc6 => {
+                let len = self.uint(width(0xc4))? as usize;
+                self.string(len, out)?
+            }
+            0xc7..=0xc9 => {
+                let len = self.uint(width(0xc7))? as usize;
+                self.take(len + 1)?;
+                out.push_str("null");
+            }
+            0xca => push_json_f64(out, f32::from_bits(self.uint(4)? as u32) as f64),
+            0xcb => push_json_f64(out, f64::from_bits(self.uint(8)?)),
+            0xcc..=0xcf => write!(out, "{}", self.uint(width(0xcc))?).unwrap(),
+            0xd0..=0xd3 => {
+                let n = width(0xd0);
+                let shift = 64 - 8 * n as u32;
+                // Sign-extend from n bytes
+                let v = ((self.uint(n)? << shift) as i64) >> shift;
+                write!(out, "{}", v).unwrap()
+            }
+            0xd4..=0xd8 => {
+                self.take(1 + width(0xd4))?;
+                out.push_str("null");
+            }
+            0xd9..=0xdb => {
+                let len = self.uint(width(0xd9))? as usize;
+                self.string(len, out)?
+            }
+            0xdc | 0xdd => {
+                let len = self.uint(if marker == 0xdc { 2 } else { 4 })? as usize;
+                self.array(len, out, depth)?
+            }
+            0xde | 0xdf => {
+                let len = self.uint(if marker == 0xde { 2 } else { 4 })? as usize;
+                self.map(len, out, depth)?
+            }
+            0xe0..=0xff => write!(out, "{}", marker as i8).unwrap(),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn string(&mut self, len: usize, out: &mut String) -> Result<(), String> {
+        push_json_str(out, &String::from_utf8_lossy(self.take(len)?));
+        Ok(())
+    }
+
+    pub(crate) fn array(
+        &mut self,
+        len: usize,
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), String> {
+        out.push('[');
+        for i in 0..len {
+            if i > 0 {
+                out.push(',');
+            }
+            self.value(out, depth + 1)?;
+        }
+        out.push(']');
+        Ok(())
+    }
+
+    pub(crate) fn map(&mut self, len: usize, out: &mut String, depth: usize) -> Result<(), String> {
+        out.push('{');
+        let mut key = String::new();
+        for i in 0..len {
+            if i > 0 {
+                out.push(',');
+            }
+            key.clear();
+            self.value(&mut key, depth + 1)?;
+            if key.starts_with('"') {
+                out.push_str(&key);
+            } else {
+                push_json_str(out, &key);
+            }
+            out.push(':');
+            self.value(out, depth + 1)?;
+        }
+        out.push('}');
+        Ok(())
+    }
+}
+
+pub(crate) fn push_json_str(out: &mut String, s: &str) {
+    use std::fmt::Write;
+
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c < ' ' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+/// JSON has no NaN or infinities; they become null.
+pub(crate) fn push_json_f64(out: &mut String, v: f64) {
+    use std::fmt::Write;
+
+    if v.is_finite() {
+        write!(out, "{}", v).unwrap();
+    } else {
+        out.push_str("null");
+    }
+}
+
+// =============================================================================
+// Protobuf (`--payload-format protobuf`)
+// =============================================================================
+
+/// Transcode a binary frame to JSON per `--payload-format`.
+pub(crate) fn binary_to_json(config: &Config, bytes: &[u8]) -> Result<String, String> {
+    match (config.payload_format, &config.proto) {
+        (PayloadFormat::Protobuf, Some(codec)) => codec.decode(bytes),
+        (PayloadFormat::Msgpack, _) => msgpack_to_json(bytes),
+        _ => Err("no binary payload format".into()),
+    }
+}
+
+/// The message types of a compiled descriptor set, enough to transcode one of
+/// them to JSON and back without generated code.
+#[derive(Debug)]
+pub(crate) struct ProtoCodec {
+    /// By fully qualified name, without the leading dot
+    pub(crate) messages: HashMap<String, ProtoMessage>,
+    /// The type frames decode as
+    pub(crate) root: String,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ProtoMessage {
+    pub(crate) fields: Vec<ProtoField>,
+    /// Synthesized `map<K, V>` entry: rendered as object members
+    pub(crate) map_entry: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ProtoField {
+    pub(crate) name: String,
+    pub(crate) number: u64,
+    /// `FieldDescriptorProto.Type`
+    pub(crate) kind: u64,
+    pub(crate) repeated: bool,
+    /// Message type of a message field, without the leading dot
+    pub(crate) type_name: String,
+}
+
+/// Field types, as numbered in `FieldDescriptorProto.Type`
+pub(crate) const PROTO_DOUBLE: u64 = 1;
+pub(crate) const PROTO_FLOAT: u64 = 2;
+pub(crate) const PROTO_UINT64: u64 = 4;
+pub(crate) const PROTO_FIXED64: u64 = 6;
+pub(crate) const PROTO_FIXED32: u64 = 7;
+pub(crate) const PROTO_BOOL: u64 = 8;
+pub(crate) const PROTO_STRING: u64 = 9;
+pub(crate) const PROTO_GROUP: u64 = 10;
+pub(crate) const PROTO_MESSAGE: u64 = 11;
+pub(crate) const PROTO_BYTES: u64 = 12;
+pub(crate) const PROTO_UINT32: u64 = 13;
+pub(crate) const PROTO_SFIXED32: u64 = 15;
+pub(crate) const PROTO_SFIXED64: u64 = 16;
+pub(crate) const PROTO_SINT32: u64 = 17;
+pub(crate) const PROTO_SINT64: u64 = 18;
+
+/// Wire types
+pub(crate) const WIRE_VARINT: u64 = 0;
+pub(crate) const WIRE_FIXED64: u64 = 1;
+pub(crate) const WIRE_LEN: u64 = 2;
+pub(crate) const WIRE_FIXED32: u64 = 5;
+
+impl ProtoCodec {
+    /// Read the `FileDescriptorSet` at `path`, decoding frames as `root`.
+    pub(crate) fn load(path: &std::path::Path, root: &str) -> Result<Self> {
+        let bytes = std::fs::read(path)
+            .map_err(|e| anyhow::anyhow!("--proto-descriptor {:?}: {}", path, e))?;
+        let mut messages = HashMap::new();
+        proto_descriptor_set(&bytes, &mut messages)
+            .map_err(|e| anyhow::anyhow!("--proto-descriptor {:?}: {}", path, e))?;
+
+        let root = root.trim_start_matches('.').to_string();
+        if !messages.contains_key(&root) {
+            let mut known: Vec<&str> = messages.keys().map(String::as_str).collect();
+            known.sort_unstable();
+            anyhow::bail!(
+                "--proto-message {}: not in {:?} (has {})",
+                root,
+                path,
+                known.join(", ")
+            );
+        }
+        Ok(Self { messages, root })
+    }
+
+    pub(crate) fn decode(&self, bytes: &[u8]) -> Result<String, String> {
+        let mut out = String::with_capacity(bytes.len() * 2);
+        self.message(&self.root, bytes, &mut out, 0)?;
+        Ok(out)
+    }
+
+    /// Render `bytes` as a JSON object of `type_name`'s known fields, in the
+    /// order they first appear. Repeated fields become arrays and maps objects;
+    /// a singular field seen twice keeps its last value, as protobuf merges.
+    pub(crate) fn message(
+        &self,
+        type_name: &str,
+        bytes: &[u8],
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), String> {
+        if depth > 64 {
+            return Err("nested too deeply".into());
+        }
+        let message = self
+            .messages
+            .get(type_name)
+            .ok_or_else(|| format!("unknown message type {}", type_name))?;
+
+        let mut values: Vec<(&ProtoField, Vec<String>)> = Vec::new();
+        let mut reader = ProtoReader::new(bytes);
+        while let Some((number, wire)) = reader.key()? {
+            let Some(field) = message.fields.iter().find(|f| f.number == number) else {
+                reader.skip(wire)?;
+                continue;
+            };
+            let slot = match values.iter().position(|(f, _)| f.number == number) {
+                Some(i) => &mut values[i].1,
+                None => {
+                    values.push((field, Vec::new()));
+                    &mut values.last_mut().unwrap().1
+                }
+            };
+            if !field.repeated {
+                slot.clear();
+            }
+            let packed = wire == WIRE_LEN
+                && !matches!(
+                    field.kind,
+                    PROTO_STRING | PROTO_BYTES | PROTO_MESSAGE | PROTO_GROUP
+                );
+            if packed {
+                let mut items = ProtoReader::new(reader.bytes()?);
+                let wire = match field.kind {
+                    PROTO_DOUBLE | PROTO_FIXED64 | PROTO_SFIXED64 => WIRE_FIXED64,
+                    PROTO_FLOAT | PROTO_FIXED32 | PROTO_SFIXED32 => WIRE_FIXED32,
+                    _ => WIRE_VARINT,
+                };
+                while items.pos < items.bytes.len() {
+                    slot.push(self.value(field, wire, &mut items, depth)?);
+                }
+            } else {
+                slot.push(self.value(field, wire, &mut reader, depth)?);
+            }
+        }
+
+        out.push('{');
+        for (i, (field, items)) in values.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            push_json_str(out, &field.name);
+            out.push(':');
+            let map = self
+                .messages
+                .get(&field.type_name)
+                .is_some_and(|m| m.map_entry);
+            if map {
+                out.push('{');
+                out.push_str(&items.join(","));
+                out.push('}');
+            } else if field.repeated {
+                out.push('[');
+                out.push_str(&items.join(","));
+                out.push(']');
+            } else {
+                // An empty packed run leaves a singular field without a value
+                out.push_str(items.last().map_or("null", String::as_str));
+            }
+        }
+        out.push('}');
+        Ok(())
+    }
+
+    /// One value of `field` as JSON; a map entry as its `"key":value` member.
+    pub(crate) fn value(
+        &self,
+        field: &ProtoField,
+        wire: u64,
+        reader: &mut ProtoReader,
+        depth: usize,
+    ) -> Result<String, String> {
+        let mut out = String::new();
+        match field.kind {
+            PROTO_STRING | PROTO_BYTES => {
+                push_json_str(&mut out, &String::from_utf8_lossy(reader.bytes()?))
+            }
+            PROTO_MESSAGE => {
+                let bytes = reader.bytes()?;
+                match self.messages.get(&field.type_name) {
+                    Some(entry) if entry.map_entry => {
+                        self.map_entry(entry, bytes, &mut out, depth)?
+                    }
+                    _ => self.message(&field.type_name, bytes, &mut out, depth + 1)?,
+                }
+            }
+            PROTO_GROUP => return Err(format!("field {}: groups are not supported", field.name)),
+            kind => {
+                let raw = match wire {
+                    WIRE_VARINT => reader.varint()?,
+                    WIRE_FIXED64 => u64::from_le_bytes(reader.take(8)?.try_into().unwrap()),
+                    WIRE_FIXED32 => u32::from_le_bytes(reader.take(4)?.try_into().unwrap()) as u64,
+                    _ => {
+                        return Err(format!(
+                            "field {}: unexpected wire type {}",
+                            field.name, wire
+                        ))
+                    }
+                };
+                use std::fmt::Write;
+                match kind {
+                    PROTO_DOUBLE => push_json_f64(&mut out, f64::from_bits(raw)),
+                    PROTO_FLOAT => push_json_f64(&mut out, f32::from_bits(raw as u32) as f64),
+                    PROTO_BOOL => out.push_str(if raw != 0 { "true" } else { "false" }),
+                    PROTO_SINT32 | PROTO_SINT64 => {
+                        write!(out, "{}", (raw >> 1) as i64 ^ -((raw & 1) as i64)).unwrap()
+                    }
+                    PROTO_FIXED64 | PROTO_FIXED32 | PROTO_UINT64 | PROTO_UINT32 => {
+                        write!(out, "{}", raw).unwrap()
+                    }
+                    PROTO_SFIXED32 => write!(out, "{}", raw as u32 as i32).unwrap(),
+                    // int32, int64, sfixed64 and enums (as their number)
+                    _ => write!(out, "{}", raw as i64).unwrap(),
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    pub(crate) fn map_entry(
+        &self,
+        entry: &ProtoMessage,
+        bytes: &[u8],
+        out: &mut String,
+        depth: usize,
+    ) -> Result<(), String> {
+        let mut key = String::from("\"\"");
+        let mut value = String::from("null");
+        let mut reader = ProtoReader::new(bytes);
+        while let Some((number, wire)) = reader.key()? {
+            match entry.fields.iter().find(|f| f.number == number) {
+                Some(field) if number == 1 => key = self.value(field, wire, &mut reader, depth)?,
+                Some(field) if number == 2 => {
+                    value = self.value(field, wire, &mut reader, depth)?
+                }
+                _ => reader.skip(wire)?,
+            }
+        }
+        if key.starts_with('"') {
+            out.push_str(&key);
+        } else {
+            push_json_str(out, &key);
+        }
+        out.push(':');
+        out.push_str(&value);
+        Ok(())
+    }
+
+    /// Protobuf encoding of `value` as the root type, for the mock server's
+    /// binary frames. Members without a field are dropped.
+    pub(crate) fn encode(&self, value: &sonic_rs::Value) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.encode_message(&self.root, value, &mut out);
+        out
+    }
+
+    pub(crate) fn encode_message(
+        &self,
+        type_name: &str,
+        value: &sonic_rs::Value,
+        out: &mut Vec<u8>,
+    ) {
+        let Some(message) = self.messages.get(type_name) else {
+            return;
+        };
+        for field in &message.fields {
+            let Some(v) = value.get(&field.name) else {
+                continue;
+            };
+            let entry = self.messages.get(&field.type_name).filter(|m| m.map_entry);
+            if let (Some(entry), Some(members)) = (entry, v.as_object()) {
+                for (key, v) in members.iter() {
+                    let mut bytes = Vec::new();
+                    for f in &entry.fields {
+                        match f.number {
+                            1 => self.encode_field(f, &sonic_rs::Value::from(key), &mut bytes),
+                            2 => self.encode_field(f, v, &mut bytes),
+                            _ => {}
+                        }
+                    }
+                    proto_put_varint(out, field.number << 3 | WIRE_LEN);
+                    proto_put_varint(out, bytes.len() as u64);
+                    out.extend(bytes);
+                }
+            } else if let Some(items) = v.as_array().filter(|_| field.repeated) {
+                for item in items.iter() {
+                    self.encode_field(field, item, out);
+                }
+            } else {
+                self.encode_field(field, v, out);
+            }
+        }
+    }
+
+    pub(crate) fn encode_field(&self, field: &ProtoField, v: &sonic_rs::Value, out: &mut Vec<u8>) {
+        let key = |out: &mut Vec<u8>, wire: u64| proto_put_varint(out, field.number << 3 | wire);
+        let float = v.as_f64().unwrap_or_default();
+        let int = v
+            .as_i64()
+            .or_else(|| v.as_u64().map(|n| n as i64))
+            .or_else(|| v.as_bool().map(i64::from))
+            .or_else(|| v.as_str().and_then(|s| s.parse().ok()))
+            .unwrap_or_default();
+        match field.kind {
+            PROTO_STRING | PROTO_BYTES => {
+                let text = match v.as_str() {
+                    Some(s) => s.to_string(),
+                    None => sonic_rs::to_string(v).unwrap_or_default(),
+                };
+                key(out, WIRE_LEN);
+                proto_put_varint(out, text.len() as u64);
+                out.extend(text.as_bytes());
+            }
+            PROTO_MESSAGE => {
+                let mut bytes = Vec::new();
+                self.encode_message(&field.type_name, v, &mut bytes);
+                key(out, WIRE_LEN);
+                proto_put_varint(out, bytes.len() as u64);
+                out.extend(bytes);
+            }
+            PROTO_GROUP => {}
+            PROTO_DOUBLE => {
+                key(out, WIRE_FIXED64);
+                out.extend(float.to_le_bytes());
+            }
+            PROTO_FLOAT => {
+                key(out, WIRE_FIXED32);
+                out.extend((float as f32).to_le_bytes());
+            }
+            PROTO_FIXED64 | PROTO_SFIXED64 => {
+                key(out, WIRE_FIXED64);
+                out.extend(int.to_le_bytes());
+            }
+            PROTO_FIXED32 | PROTO_SFIXED32 => {
+                key(out, WIRE_FIXED32);
+                out.extend((int as u32).to_le_bytes());
+            }
+            PROTO_SINT32 | PROTO_SINT64 => {
+                key(out, WIRE_VARINT);
+                proto_put_varint(out, ((int << 1) ^ (int >> 63)) as u64);
+            }
+            _ => {
+                key(out, WIRE_VARINT);
+                proto_put_varint(out, int as u64);
+            }
+        }
+    }
+}
+
+/// Collect the message types of every file in a `FileDescriptorSet`.
+pub(crate) fn proto_descriptor_set(
+    bytes: &[u8],
+    messages: &mut HashMap<String, ProtoMessage>,
+) -> Result<(), String> {
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((number, wire)) = reader.key()? {
+        match (number, wire) {
+            (1, WIRE_LEN) => proto_file(reader.bytes()?, messages)?,
+            _ => reader.skip(wire)?,
+        }
+    }
+    Ok(())
+}
+
+/// Collect the message types of a `FileDescriptorProto`.
+pub(crate) fn proto_file(
+    bytes: &[u8],
+    messages: &mut HashMap<String, ProtoMessage>,
+) -> Result<(), String> {
+    let mut package = String::new();
+    let mut types = Vec::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((number, wire)) = reader.key()? {
+        match (number, wire) {
+            (2, WIRE_LEN) => package = reader.string()?,
+            (4, WIRE_LEN) => types.push(reader.bytes()?),
+            _ => reader.skip(wire)?,
+        }
+    }
+    for bytes in types {
+        proto_message(bytes, &package, messages)?;
+    }
+    Ok(())
+}
+
+/// Collect a `DescriptorProto` declared in `scope`, and its nested types.
+pub(crate) fn proto_message(
+    bytes: &[u8],
+    scope: &str,
+    messages: &mut HashMap<String, ProtoMessage>,
+) -> Result<(), String> {
+    let mut name = String::new();
+    let mut message = ProtoMessage::default();
+    let mut nested = Vec::new();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((number, wire)) = reader.key()? {
+        match (number, wire) {
+            (1, WIRE_LEN) => name = reader.string()?,
+            (2, WIRE_LEN) => message.fields.push(proto_field(reader.bytes()?)?),
+            (3, WIRE_LEN) => nested.push(reader.bytes()?),
+            (7, WIRE_LEN) => {
+                let mut options = ProtoReader::new(reader.bytes()?);
+                while let Some((number, wire)) = options.key()? {
+                    match (number, wire) {
+                        (7, WIRE_VARINT) => message.map_entry = options.varint()? != 0,
+                        _ => options.skip(wire)?,
+                    }
+                }
+            }
+            _ => reader.skip(wire)?,
+        }
+    }
+    let full = if scope.is_empty() {
+        name
+    } else {
+        format!("{}.{}", scope, name)
+    };
+    for bytes in nested {
+        proto_message(bytes, &full, messages)?;
+    }
+    messages.insert(full, message);
+    Ok(())
+}
+
+/// Parse a `FieldDescriptorProto`.
+pub(crate) fn proto_field(bytes: &[u8]) -> Result<ProtoField, String> {
+    let mut field = ProtoField::default();
+    let mut reader = ProtoReader::new(bytes);
+    while let Some((number, wire)) = reader.key()? {
+        match (number, wire) {
+            (1, WIRE_LEN) => field.name = reader.string()?,
+            (3, WIRE_VARINT) => field.number = reader.varint()?,
+            // LABEL_REPEATED
+            (4, WIRE_VARINT) => field.repeated = reader.varint()? == 3,
+            (5, WIRE_VARINT) => field.kind = reader.varint()?,
+            (6, WIRE_LEN) => field.type_name = reader.string()?.trim_start_matches('.').to_string(),
+            _ => reader.skip(wire)?,
+        }
+    }
+    Ok(field)
+}
+
+pub(crate) fn proto_put_varint(out: &mut Vec<u8>, mut v: u64) {
+    while v >= 0x80 {
+        out.push(v as u8 | 0x80);
+        v >>= 7;
+    }
+    out.push(v as u8);
+}
+
+pub(crate) struct ProtoReader<'a> {
+    pub(crate) bytes: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl<'a> ProtoReader<'a> {
+    pub(crate) fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub(crate) fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        let slice = self
+            .bytes
+            .get(self.pos..self.pos.saturating_add(n))
+            .ok_or("truncated")?;
+        self.pos += n;
+        Ok(slice)
+    }
+
+    pub(crate) fn varint(&mut self) -> Result<u64, String> {
+        let mut v = 0u64;
+        for shift in (0..64).step_by(7) {
+            let b = self.take(1)?[0];
+            v |= ((b & 0x7f) as u64) << shift;
+            if b < 0x80 {
+                return Ok(v);
+            }
+        }
+        Err("varint too long".into())
+    }
+
+    /// The next field's number and wire type, or `None` at the end.
+    pub(crate) fn key(&mut self) -> Result<Option<(u64, u64)>, String> {
+        if self.pos == self.bytes.len() {
+            return Ok(None);
+        }
+        let key = self.varint()?;
+        Ok(Some((key >> 3, key & 7)))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], String> {
+        let len = self.varint()? as usize;
+        self.take(len)
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, String> {
+        Ok(String::from_utf8_lossy(self.bytes()?).into_owned())
+    }
+
+    pub(crate) fn skip(&mut self, wire: u64) -> Result<(), String> {
+        match wire {
+            WIRE_VARINT => self.varint().map(drop),
+            WIRE_FIXED64 => self.take(8).map(drop),
+            WIRE_LEN => self.bytes().map(drop),
+            WIRE_FIXED32 => self.take(4).map(drop),
+            _ => Err(format!("unsupported wire type {}", wire)),
+        }
+    }
+}
+
+/// MessagePack encoding of `value`, for the mock server's binary frames.
+pub(crate) fn json_to_msgpack(value: &sonic_rs::Value, out: &mut Vec<u8>) {
+    let header = |out: &mut Vec<u8>, len: usize, fix: u8, fix_max: usize, long: [u8; 3]| {
+        if len <= fix_max {
+            out.push(fix | len as u8);
+        } else if long[0] != 0 && len <= u8::MAX as usize {
+            out.extend([long[0], len as u8]);
+        } else if len <= u16::MAX as usize {
+            out.push(long[1]);
+            out.extend((len as u16).to_be_bytes());
+        } else {
+            out.push(long[2]);
+            out.extend((len as u32).to_be_bytes());
+        }
+    };
+    if let Some(s) = value.as_str() {
+        header(out, s.len(), 0xa0, 31, [0xd9, 0xda, 0xdb]);
+        out.extend(s.as_bytes());
+    } else if let Some(array) = value.as_array() {
+        header(out, array.len(), 0x90, 15, [0, 0xdc, 0xdd]);
+        for item in array.iter() {
+            json_to_msgpack(item, out);
+        }
+    } else if let Some(object) = value.as_object() {
+        header(out, object.len(), 0x80, 15, [0, 0xde, 0xdf]);
+        for (key, item) in object.iter() {
+            header(out, key.len(), 0xa0, 31, [0xd9, 0xda, 0xdb]);
+            out.extend(key.as_bytes());
+            json_to_msgpack(item, out);
+        }
+    } else if let Some(b) = value.as_bool() {
+        out.push(if b { 0xc3 } else { 0xc2 });
+    } else if let Some(n) = value.as_u64() {
+        out.push(0xcf);
+        out.extend(n.to_be_bytes());
+    } else if let Some(n) = value.as_i64() {
+        out.push(0xd3);
+        out.extend(n.to_be_bytes());
+    } else if let Some(n) = value.as_f64() {
+        out.push(0xcb);
+        out.extend(n.to_be_bytes());
+    } else {
+        out.push(0xc0);
+    }
+}
+
+// =============================================================================
+// Timestamp extraction (inlined for speed)
+// =============================================================================
+
+#[inline(always)]
+pub(crate) fn extract_timestamp(pusher_msg: &PusherMessage) -> Option<u64> {
+    // Check root-level tags first
+    if let Some(tags) = &pusher_msg.tags {
+        if let Some(ts) = tags.get("timestamp") {
+            if let Some(v) = ts.as_u64() {
+                return Some(v);
+            }
+            if let Some(s) = ts.as_str() {
+                if let Ok(v) = s.parse::<u64>() {
+                    return Some(v);
+                }
+            }
+        }
+    }
+
+    // Fallback: check inside data
+    if let Some(data) = &pusher_msg.data {
+        if let Some(tags) = data.get("tags") {
+            if let Some(ts) = tags.get("timestamp") {
+                if let Some(v) = ts.as_u64() {
+                    return Some(v);
+                }
+                if let Some(s) = ts.as_str() {
+                    if let Ok(v) = s.parse::<u64>() {
+                        return Some(v);
+                    }
+                }
+            }
+        }
+        if let Some(ts) = data.get("timestamp") {
+            if let Some(v) = ts.as_u64() {
+                return Some(v);
+            }
+            if let Some(s) = ts.as_str() {
+                if let Ok(v) = s.parse::<u64>() {
+                    return Some(v);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Look up a tag (e.g. `token_address`) using the same search order as
+/// [`extract_timestamp`].
+#[inline]
+pub(crate) fn extract_tag_value<'a>(
+    pusher_msg: &'a PusherMessage,
+    key: &str,
+) -> Option<&'a sonic_rs::Value> {
+    if let Some(v) = pusher_msg.tags.as_ref().and_then(|t| t.get(key)) {
+        return Some(v);
+    }
+
+    let data = pusher_msg.data.as_ref()?;
+    if let Some(v) = data.get("tags").and_then(|t| t.get(key)) {
+        return Some(v);
+    }
+    data.get(key)
+}
+
+/// [`extract_tag_value`] for string tags.
+#[inline]
+pub(crate) fn extract_tag<'a>(pusher_msg: &'a PusherMessage, key: &str) -> Option<&'a str> {
+    extract_tag_value(pusher_msg, key)?.as_str()
+}
+
+/// Why a `token_address` tag doesn't look like a real address, if it doesn't.
+/// Accepts `0x` + 40 hex digit (EVM) and 32-44 character base58 (Solana)
+/// addresses. Base58 addresses use both letter cases, so a single-case one
+/// has been case-mangled somewhere along the way.
+pub(crate) fn token_address_problem(address: &str) -> Option<&'static str> {
+    if let Some(hex) = address.strip_prefix("0x") {
+        if hex.len() != 40 {
+            return Some("length");
+        }
+        if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Some("charset");
+        }
+        return None;
+    }
+
+    if !(32..=44).contains(&address.len()) {
+        return Some("length");
+    }
+    if !address.bytes().all(|b| BASE58_ALPHABET.contains(&b)) {
+        return Some("charset");
+    }
+    let upper = address.bytes().any(|b| b.is_ascii_uppercase());
+    let lower = address.bytes().any(|b| b.is_ascii_lowercase());
+    if !(upper && lower) {
+        return Some("case");
+    }
+    None
+}
+
+/// Read a tag as an opaque id string, accepting numeric or string values.
+pub(crate) fn extract_event_id(pusher_msg: &PusherMessage, key: &str) -> Option<String> {
+    let lookup = |v: &sonic_rs::Value| -> Option<String> {
+        let id = v.get(key)?;
+        id.as_str()
+            .map(str::to_owned)
+            .or_else(|| id.as_u64().map(|n| n.to_string()))
+    };
+    pusher_msg.tags.as_ref().and_then(lookup).or_else(|| {
+        pusher_msg
+            .data
+            .as_ref()
+            .and_then(|d| d.get("tags"))
+            .and_then(lookup)
+    })
+}
+
+#[inline]
+pub(crate) fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
@@ -0,0 +1,1056 @@
+//! Command-line configuration, load profiles and test plans.
+
+use super::*;
+
+// =============================================================================
+// Configuration
+// =============================================================================
+
+#[derive(Parser, Debug)]
+#[command(name = "ws-benchmark")]
+#[command(about = "WebSocket tag filtering benchmark", long_about = None)]
+pub(crate) struct Config {
+    /// WebSocket host
+    #[arg(long, env = "WS_HOST", default_value = "stream-v2.projectscylla.com")]
+    pub(crate) ws_host: String,
+
+    /// WebSocket port
+    #[arg(long, env = "WS_PORT", default_value = "443")]
+    pub(crate) ws_port: u16,
+
+    /// Connect with TLS (wss://, and https:// for the derived `--publish-url`);
+    /// `--tls false` forces plain text. Defaults to TLS on port 443 only
+    #[arg(long = "tls", env = "WS_TLS", num_args = 0..=1, default_missing_value = "true")]
+    pub(crate) ws_tls: Option<bool>,
+
+    /// Application key
+    #[arg(long, env = "APP_KEY", default_value = "knife-library-likely")]
+    pub(crate) app_key: String,
+
+    /// Channel name
+    #[arg(long, env = "CHANNEL", default_value = "trident_filter_tokens_v1")]
+    pub(crate) channel: String,
+
+    /// Scenario (built-in 0-13, or an id from `--scenario-file`)
+    #[arg(long, env = "SCENARIO", default_value = "1")]
+    pub(crate) scenario: u8,
+
+    /// Values per filter in scenarios 3-5 instead of 10/100/500: `N`,
+    /// `uniform:MIN:MAX`, `zipf:MAX:S` or `lognormal:MEDIAN:SIGMA[:MAX]`
+    #[arg(long, env = "FILTER_SIZE", value_parser = ValueCount::parse)]
+    pub(crate) filter_size: Option<ValueCount>,
+
+    /// Tag the built-in scenarios filter on, e.g. `wallet_address`, `pair_address`
+    /// or `chain_id`; filter values still come from `--token-file`
+    #[arg(long, env = "FILTER_KEY", default_value = "token_address")]
+    pub(crate) filter_key: String,
+
+    /// JSON file of scenario definitions; entries reuse or extend the built-in ids
+    #[arg(long, env = "SCENARIO_FILE")]
+    pub(crate) scenario_file: Option<PathBuf>,
+
+    /// Built-in scenarios plus `--scenario-file`, filled in at startup
+    #[arg(skip)]
+    pub(crate) scenarios: ScenarioSet,
+
+    /// Assign scenarios to clients by weight, e.g. `1:70,3:20,5:10`, and report
+    /// each one separately (instead of `--scenario`)
+    #[arg(long, env = "MIX")]
+    pub(crate) mix: Option<String>,
+
+    /// Parsed `--mix` as (scenario, cumulative share), filled in at startup
+    #[arg(skip)]
+    pub(crate) scenario_mix: Vec<(u8, f64)>,
+
+    /// Send this filter JSON verbatim in every subscribe instead of one built
+    /// from the scenario
+    #[arg(long, env = "FILTER_JSON")]
+    pub(crate) filter_json: Option<String>,
+
+    /// JSON array of filters sent verbatim, client N taking entry N modulo its length
+    #[arg(long, env = "FILTER_JSON_FILE")]
+    pub(crate) filter_json_file: Option<PathBuf>,
+
+    /// Parsed `--filter-json` / `--filter-json-file`, filled in at startup
+    #[arg(skip)]
+    pub(crate) raw_filters: Vec<FilterValue>,
+
+    /// JSON object mapping client index to the filter it subscribes with, e.g.
+    /// a subscription population captured from production. Unmapped clients
+    /// fall back to the scenario; filters outside our grammar are sent verbatim
+    #[arg(long, env = "FILTER_MAP")]
+    pub(crate) filter_map_file: Option<PathBuf>,
+
+    /// Parsed `--filter-map-file`, filled in at startup
+    #[arg(skip)]
+    pub(crate) filter_map: HashMap<usize, FilterValue>,
+
+    /// Token addresses file: a JSON array, one address per line, or CSV.
+    /// Repeat (or comma-separate) to merge several lists, dropping duplicates
+    #[arg(
+        long,
+        env = "TOKEN_FILE",
+        value_delimiter = ',',
+        default_value = "token-addresses.json"
+    )]
+    pub(crate) token_file: Vec<PathBuf>,
+
+    /// Format of `--token-file`; `auto` goes by its extension (.csv, .txt)
+    #[arg(long, env = "TOKEN_FORMAT", value_enum, default_value = "auto")]
+    pub(crate) token_format: TokenFormat,
+
+    /// CSV column holding the address, by header name or 0-based index.
+    /// `chain` and `tier` columns, if present, feed `--token-strata`
+    #[arg(long, env = "TOKEN_COLUMN", default_value = "address")]
+    pub(crate) token_column: String,
+
+    /// Format of the tokens generated when `--token-file` doesn't exist:
+    /// `hex`, `evm` (checksummed), `solana` (base58) or `pattern:TEXT` with
+    /// `{i}`, `{hex:N}`, `{base58:N}` and `{digits:N}` placeholders
+    #[arg(long, env = "FAKE_TOKEN_FORMAT", default_value = "hex", value_parser = FakeTokenFormat::parse)]
+    pub(crate) fake_token_format: FakeTokenFormat,
+
+    /// Number of tokens generated when `--token-file` doesn't exist
+    #[arg(long, env = "FAKE_TOKEN_COUNT", default_value = "10000")]
+    pub(crate) fake_token_count: usize,
+
+    /// Download the token list from this URL instead of `--token-file`
+    #[arg(long, env = "TOKEN_URL")]
+    pub(crate) token_url: Option<String>,
+
+    /// Extra header for `--token-url`, e.g. `Authorization: Bearer ...`
+    #[arg(long, env = "TOKEN_URL_HEADER")]
+    pub(crate) token_url_header: Option<String>,
+
+    /// Re-download `--token-url` every N seconds; filters built afterwards
+    /// draw from the fresh list (0 = never)
+    #[arg(long, env = "TOKEN_REFRESH", default_value = "0")]
+    pub(crate) token_refresh: u64,
+
+    /// Check `--token-file` for changes every N seconds and reload it when
+    /// it's modified (0 = only on SIGHUP)
+    #[arg(long, env = "TOKEN_WATCH", default_value = "0")]
+    pub(crate) token_watch: u64,
+
+    /// Restrict filters to tokens in these strata, e.g. `chain=ethereum,tier=top100`
+    /// (repeated keys are alternatives)
+    #[arg(long, env = "TOKEN_STRATA")]
+    pub(crate) token_strata: Option<String>,
+
+    /// JSON object of token address to draw weight, overriding any `weight`
+    /// in the token list; addresses it doesn't name weigh 1
+    #[arg(long, env = "TOKEN_WEIGHTS")]
+    pub(crate) token_weights: Option<PathBuf>,
+
+    /// Token list (any `--token-format`) of addresses never to put in a
+    /// filter, e.g. known noisy or rate-limited tokens
+    #[arg(long, env = "EXCLUDE_TOKEN_FILE")]
+    pub(crate) exclude_token_file: Option<PathBuf>,
+
+    /// Refuse a token list with malformed or duplicate addresses instead of
+    /// warning and loading it anyway
+    #[arg(long, env = "STRICT_TOKENS")]
+    pub(crate) strict_tokens: bool,
+
+    /// How clients' tokens overlap: `random` draws, `disjoint` (the list is
+    /// split among `--num-clients` by client id, so subscriptions share no
+    /// tokens) or `shared` (every client takes the same leading tokens)
+    #[arg(long, env = "TOKEN_OVERLAP", value_enum, default_value = "random")]
+    pub(crate) token_overlap: TokenOverlap,
+
+    /// Draw tokens with Zipf popularity of exponent S, ranked in token-file
+    /// order, so a few hot tokens land in most filters (default uniform, or
+    /// the token list's weights)
+    #[arg(long, env = "TOKEN_ZIPF", value_name = "S")]
+    pub(crate) token_zipf: Option<f64>,
+
+    /// Filter update interval in milliseconds (Scenario 2, or any scenario with
+    /// `filter_updates`)
+    #[arg(long, env = "FILTER_UPDATE_INTERVAL", default_value = "5000")]
+    pub(crate) filter_update_interval: u64,
+
+    /// Distribution of the think time between filter updates, with mean
+    /// `--filter-update-interval`
+    #[arg(long, env = "THINK_TIME", value_enum, default_value = "fixed")]
+    pub(crate) think_time: ThinkTime,
+
+    /// Think-time spread in milliseconds: half-width for uniform, standard
+    /// deviation for normal
+    #[arg(long, env = "THINK_TIME_SPREAD", default_value = "1000")]
+    pub(crate) think_time_spread: u64,
+
+    /// Split IN filters larger than this across numbered sub-channels
+    /// (`<channel>-0`, `<channel>-1`, ...), one subscription each (0 = off)
+    #[arg(long, env = "FILTER_SPLIT_SIZE", default_value = "0")]
+    pub(crate) filter_split_size: usize,
+
+    /// Target number of clients
+    #[arg(long, env = "NUM_CLIENTS", default_value = "1000")]
+    pub(crate) num_clients: usize,
+
+    /// Duration to ramp up to target clients in seconds
+    #[arg(long, env = "RAMP_DURATION", default_value = "30")]
+    pub(crate) ramp_duration: u64,
+
+    /// Hold every client's subscribe until all clients have connected
+    #[arg(long, env = "START_BARRIER")]
+    pub(crate) start_barrier: bool,
+
+    /// Random delay (uniform, up to this many ms) between `connection_established`
+    /// and the subscribe, to stagger subscribe storms (0 = subscribe at once)
+    #[arg(long, env = "SUBSCRIBE_JITTER", default_value = "0")]
+    pub(crate) subscribe_jitter: u64,
+
+    /// Shape of the ramp-up stage
+    #[arg(long, value_enum, env = "PROFILE", default_value = "linear")]
+    pub(crate) profile: LoadProfile,
+
+    /// Clients added per plateau (`--profile step`)
+    #[arg(long, env = "STEP_SIZE", default_value = "1000")]
+    pub(crate) step_size: usize,
+
+    /// Seconds to hold each plateau (`--profile step`)
+    #[arg(long, env = "STEP_HOLD", default_value = "60")]
+    pub(crate) step_hold: u64,
+
+    /// Lowest running client count (`--profile wave`)
+    #[arg(long, env = "WAVE_FLOOR", default_value = "0")]
+    pub(crate) wave_floor: usize,
+
+    /// Seconds per full oscillation (`--profile wave`)
+    #[arg(long, env = "WAVE_PERIOD", default_value = "300")]
+    pub(crate) wave_period: u64,
+
+    /// CSV or JSON schedule of `time_offset,target_clients` points (`--profile file`)
+    #[arg(long, env = "PROFILE_FILE")]
+    pub(crate) profile_file: Option<PathBuf>,
+
+    /// Mean new connections per second (`--profile poisson`, 0 = num-clients / ramp-duration)
+    #[arg(long, env = "ARRIVAL_RATE", default_value = "0")]
+    pub(crate) arrival_rate: f64,
+
+    /// JSON test plan of sequential phases (`--profile plan`)
+    #[arg(long, env = "PLAN")]
+    pub(crate) plan: Option<PathBuf>,
+
+    /// Cap on new connection handshakes per second across all clients (0 = unlimited)
+    #[arg(long, env = "MAX_CONNECT_RATE", default_value = "0")]
+    pub(crate) max_connect_rate: f64,
+
+    /// Seconds into the hold at which every client stops answering pings
+    #[arg(long, env = "CHAOS_DROP_PONGS_AT")]
+    pub(crate) chaos_drop_pongs_at: Option<u64>,
+
+    /// How long clients ignore pings once the pong chaos starts, in seconds
+    #[arg(long, env = "CHAOS_DROP_PONGS_FOR", default_value = "30")]
+    pub(crate) chaos_drop_pongs_for: u64,
+
+    /// Cap on TCP/TLS/WebSocket handshakes in flight at once (0 = unlimited)
+    #[arg(long, env = "MAX_PENDING_CONNECTS", default_value = "0")]
+    pub(crate) max_pending_connects: usize,
+
+    /// Clients per second replaced with fresh ones during the hold (0 = no churn)
+    #[arg(long, env = "CHURN_RATE", default_value = "0")]
+    pub(crate) churn_rate: f64,
+
+    /// Run continuously as a synthetic monitor: hold until interrupted, reconnect
+    /// forever, keep no per-message samples and export health gauges on `--metrics-addr`
+    #[arg(long, env = "CANARY")]
+    pub(crate) canary: bool,
+
+    /// Window for the canary E2E latency gauges in seconds
+    #[arg(long, env = "CANARY_WINDOW", default_value = "30")]
+    pub(crate) canary_window: u64,
+
+    /// Duration to hold at target client count in seconds (0 = until Ctrl-C)
+    #[arg(long, env = "HOLD_DURATION", default_value = "60")]
+    pub(crate) hold_duration: u64,
+
+    /// Run the whole ramp/hold/ramp-down cycle this many times and report the
+    /// spread across iterations alongside merged results
+    #[arg(long, env = "ITERATIONS", default_value = "1")]
+    pub(crate) iterations: usize,
+
+    /// Duration to ramp down in seconds
+    #[arg(long, env = "RAMP_DOWN_DURATION", default_value = "10")]
+    pub(crate) ramp_down_duration: u64,
+
+    /// Client ID offset for multi-machine benchmarking
+    #[arg(long, env = "CLIENT_ID_OFFSET", default_value = "0")]
+    pub(crate) client_id_offset: usize,
+
+    /// Wall-clock moment (RFC 3339) to begin the ramp, so instances on several
+    /// machines start together, e.g. `2024-05-01T12:00:00Z`
+    #[arg(long, env = "START_AT", value_parser = parse_rfc3339)]
+    pub(crate) start_at: Option<u64>,
+
+    /// Warm-up duration in seconds (metrics discarded during this phase)
+    #[arg(long, env = "WARMUP_DURATION", default_value = "0")]
+    pub(crate) warmup_duration: u64,
+
+    /// Percentage of clients (0-100) that deliberately misbehave
+    #[arg(long, env = "MISBEHAVING_PERCENT", default_value = "0")]
+    pub(crate) misbehaving_percent: f64,
+
+    /// Size in bytes of the junk frame sent by oversized-frame clients
+    #[arg(long, env = "OVERSIZED_FRAME_BYTES", default_value = "1048576")]
+    pub(crate) oversized_frame_bytes: usize,
+
+    /// Transport underneath the WebSocket
+    #[arg(long, env = "TRANSPORT", value_enum, default_value = "tcp")]
+    pub(crate) transport: TransportKind,
+
+    /// Unix domain socket path (with `--transport unix`)
+    #[arg(long, env = "UNIX_SOCKET")]
+    pub(crate) unix_socket: Option<PathBuf>,
+
+    /// Connection setup timeout in milliseconds (transport + upgrade)
+    #[arg(long, env = "CONNECT_TIMEOUT", default_value = "10000")]
+    pub(crate) connect_timeout: u64,
+
+    /// Reconnect and resubscribe when the server drops a client
+    #[arg(long, env = "RECONNECT")]
+    pub(crate) reconnect: bool,
+
+    /// Delay before each reconnect attempt in milliseconds
+    #[arg(long, env = "RECONNECT_DELAY", default_value = "1000")]
+    pub(crate) reconnect_delay: u64,
+
+    /// Maximum reconnects per client
+    #[arg(long, env = "MAX_RECONNECTS", default_value = "10")]
+    pub(crate) max_reconnects: u64,
+
+    /// Message tag carrying the event id sent back as `resume_from` on reconnect
+    #[arg(long, env = "RESUME_TAG", default_value = "event_id")]
+    pub(crate) resume_tag: String,
+
+    /// Check the format of every received `token_address` tag and count malformed ones
+    #[arg(long, env = "VALIDATE_TOKEN_ADDRESSES")]
+    pub(crate) validate_token_addresses: bool,
+
+    /// Check every received channel message against the client's current
+    /// filter by its tags and count deliveries that don't match
+    #[arg(long, env = "VALIDATE_FILTERS")]
+    pub(crate) validate_filters: bool,
+
+    /// Acknowledge every channel message carrying `--resume-tag` (delivery-ack channels)
+    #[arg(long, env = "ACK")]
+    pub(crate) ack: bool,
+
+    /// Event name of the acknowledgment frame
+    #[arg(long, env = "ACK_EVENT", default_value = "pusher:ack")]
+    pub(crate) ack_event: String,
+
+    /// Artificial delay before each ack is sent, in milliseconds
+    #[arg(long, env = "ACK_DELAY", default_value = "0")]
+    pub(crate) ack_delay: u64,
+
+    /// Event name clients publish under scenarios with `client_event_rate`
+    /// (scenario 13); must start with `client-`
+    #[arg(long, env = "CLIENT_EVENT", default_value = "client-benchmark")]
+    pub(crate) client_event: String,
+
+    /// Server event confirming a processed ack; enables ack round-trip timing
+    #[arg(long, env = "ACK_CONFIRM_EVENT")]
+    pub(crate) ack_confirm_event: Option<String>,
+
+    /// Directory for periodic live metric snapshots (disabled if unset)
+    #[arg(long, env = "SNAPSHOT_DIR")]
+    pub(crate) snapshot_dir: Option<PathBuf>,
+
+    /// Minutes between snapshots
+    #[arg(long, env = "SNAPSHOT_INTERVAL_MINS", default_value = "5")]
+    pub(crate) snapshot_interval_mins: u64,
+
+    /// Number of rotating snapshot files to keep
+    #[arg(long, env = "SNAPSHOT_KEEP", default_value = "3")]
+    pub(crate) snapshot_keep: usize,
+
+    /// Accept certificates whose hostname does not match (chain is still verified)
+    #[arg(long, env = "INSECURE_SKIP_HOSTNAME")]
+    pub(crate) insecure_skip_hostname: bool,
+
+    /// Accept any certificate, unverified, e.g. a self-signed dev endpoint's.
+    /// `--pin-sha256` still applies
+    #[arg(long, env = "INSECURE")]
+    pub(crate) insecure: bool,
+
+    /// PEM file of CA certificates to trust on top of the system roots, e.g.
+    /// a staging environment's private CA
+    #[arg(long, env = "CA_CERT")]
+    pub(crate) ca_cert: Option<PathBuf>,
+
+    /// PEM client certificate (chain) presented to edges that require mutual
+    /// TLS; needs `--client-key`
+    #[arg(long, env = "CLIENT_CERT")]
+    pub(crate) client_cert: Option<PathBuf>,
+
+    /// PKCS#8 PEM private key of `--client-cert`
+    #[arg(long, env = "CLIENT_KEY")]
+    pub(crate) client_key: Option<PathBuf>,
+
+    /// The TLS flags above as one connector, shared by the WebSocket
+    /// transport and the HTTP helpers; filled in at startup
+    #[arg(skip)]
+    pub(crate) tls: Option<native_tls::TlsConnector>,
+
+    /// Cookie sent with every upgrade request, e.g. `session=abc`; repeat
+    /// or `;`-separate for several
+    #[arg(long = "cookie", env = "COOKIE", value_delimiter = ';')]
+    pub(crate) cookies: Vec<String>,
+
+    /// Send the cookies set on a client's upgrade response back when it
+    /// reconnects, so a load balancer's sticky session can hold
+    #[arg(long, env = "STICKY_COOKIES")]
+    pub(crate) sticky_cookies: bool,
+
+    /// Where the upgrade response names the backend that took the connection,
+    /// `cookie:NAME` or `header:NAME`; the summary reports the spread
+    #[arg(long, env = "BACKEND_ID", value_parser = BackendId::parse)]
+    pub(crate) backend_id: Option<BackendId>,
+
+    /// SHA-256 fingerprint of the expected leaf certificate (hex, colons optional); repeatable
+    #[arg(long = "pin-sha256", env = "PIN_SHA256", value_delimiter = ',')]
+    pub(crate) pin_sha256: Vec<String>,
+
+    /// Identifier for this run, used to label exported metrics and artifacts
+    #[arg(long, env = "RUN_ID", default_value_t = generate_run_id())]
+    pub(crate) run_id: String,
+
+    /// Serve live Prometheus metrics on this address (e.g. 0.0.0.0:9898); read-only
+    #[arg(long, env = "METRICS_ADDR")]
+    pub(crate) metrics_addr: Option<String>,
+
+    /// Serve the control endpoints (pong chaos, pause/resume, client target, debug client) on
+    /// this address; bare `--control-addr` listens on 127.0.0.1:9899. Off by default
+    #[arg(long, env = "CONTROL_ADDR", num_args = 0..=1, default_missing_value = "127.0.0.1:9899")]
+    pub(crate) control_addr: Option<String>,
+
+    /// Highest client target `POST /clients?target=N` may set (default 2 × --num-clients)
+    #[arg(long, env = "MAX_CLIENTS")]
+    pub(crate) max_clients: Option<usize>,
+
+    /// Grafana base URL; when set, a dashboard for this run is provisioned
+    #[arg(long, env = "GRAFANA_URL")]
+    pub(crate) grafana_url: Option<String>,
+
+    /// Grafana API token (service account)
+    #[arg(long, env = "GRAFANA_TOKEN")]
+    pub(crate) grafana_token: Option<String>,
+
+    /// UID of the Prometheus datasource scraping `--metrics-addr`
+    #[arg(long, env = "GRAFANA_DATASOURCE", default_value = "prometheus")]
+    pub(crate) grafana_datasource: String,
+
+    /// Percentage of clients (0-100) whose sockets are sampled with TCP_INFO (Linux)
+    #[arg(long, env = "TCP_INFO_SAMPLE_PERCENT", default_value = "0")]
+    pub(crate) tcp_info_sample_percent: f64,
+
+    /// TCP_INFO sampling interval in milliseconds
+    #[arg(long, env = "TCP_INFO_INTERVAL", default_value = "1000")]
+    pub(crate) tcp_info_interval: u64,
+
+    /// Have every client ping the server every N seconds and record the
+    /// round trip, a health signal independent of message delivery (0 = off)
+    #[arg(long, env = "CLIENT_PING", default_value = "0")]
+    pub(crate) client_ping: u64,
+
+    /// What `--client-ping` sends: a `pusher` ping event or a WebSocket ping `frame`
+    #[arg(long, env = "CLIENT_PING_KIND", value_enum, default_value = "pusher")]
+    pub(crate) client_ping_kind: PingKind,
+
+    /// Encoding of binary frames: `json`, `msgpack` for MessagePack Pusher
+    /// envelopes, or `protobuf` (see `--proto-descriptor`). Text frames are
+    /// always JSON
+    #[arg(long, env = "PAYLOAD_FORMAT", value_enum, default_value = "json")]
+    pub(crate) payload_format: PayloadFormat,
+
+    /// Compiled descriptor set (`protoc --descriptor_set_out`) for
+    /// `--payload-format protobuf`
+    #[arg(long, env = "PROTO_DESCRIPTOR")]
+    pub(crate) proto_descriptor: Option<PathBuf>,
+
+    /// Fully qualified message type binary frames decode as, e.g.
+    /// `stream.v1.Envelope`. Its `event`, `channel`, `data` and `tags`
+    /// fields stand in for the Pusher envelope's; a map `tags` becomes an object
+    #[arg(long, env = "PROTO_MESSAGE")]
+    pub(crate) proto_message: Option<String>,
+
+    /// Parsed `--proto-descriptor`, filled in at startup
+    #[arg(skip)]
+    pub(crate) proto: Option<Arc<ProtoCodec>>,
+
+    /// Wire protocol: `pusher`; `raw` to skip the Pusher envelope and count
+    /// every text frame as a delivered message, for non-Pusher backends and
+    /// for isolating protocol overhead; or `graphql-ws` for GraphQL
+    /// subscriptions over `graphql-transport-ws`
+    #[arg(long, env = "PROTOCOL", value_enum, default_value = "pusher")]
+    pub(crate) protocol: ProtocolKind,
+
+    /// Text frame sent on connecting under `--protocol raw`, e.g. a
+    /// registration or subscribe message
+    #[arg(long, env = "RAW_REGISTER")]
+    pub(crate) raw_register: Option<String>,
+
+    /// Endpoint path under `--protocol graphql-ws`
+    #[arg(long, env = "GRAPHQL_PATH", default_value = "/graphql")]
+    pub(crate) graphql_path: String,
+
+    /// Subscription document sent under `--protocol graphql-ws`, given the
+    /// channel and the scenario's filter as `$channel` and `$filter`
+    #[arg(long, env = "GRAPHQL_QUERY", default_value = DEFAULT_GRAPHQL_QUERY)]
+    pub(crate) graphql_query: String,
+
+    /// Publish timestamped events on `--channel` through the HTTP API at this
+    /// many per second (up to 10000), closing the E2E loop where no producer
+    /// injects them (0 = off)
+    #[arg(long, env = "PUBLISH_RATE", default_value = "0")]
+    pub(crate) publish_rate: f64,
+
+    /// Base URL of the HTTP API for `--publish-rate`; defaults to the WebSocket
+    /// host and port over http(s)
+    #[arg(long, env = "PUBLISH_URL")]
+    pub(crate) publish_url: Option<String>,
+
+    /// Event name `--publish-rate` publishes
+    #[arg(long, env = "PUBLISH_EVENT", default_value = "benchmark-event")]
+    pub(crate) publish_event: String,
+
+    /// Application id, for signing `--publish-rate` requests
+    #[arg(long, env = "APP_ID")]
+    pub(crate) app_id: Option<String>,
+
+    /// Application secret, for signing `--publish-rate` requests
+    #[arg(long, env = "APP_SECRET", hide_env_values = true)]
+    pub(crate) app_secret: Option<String>,
+
+    /// Fraction of messages whose E2E latency is extracted and recorded (0-1]
+    #[arg(long, env = "E2E_SAMPLE_RATE", default_value = "1.0")]
+    pub(crate) e2e_sample_rate: f64,
+
+    /// System clock step (vs. the monotonic clock) treated as a jump, in milliseconds
+    #[arg(long, env = "CLOCK_JUMP_THRESHOLD", default_value = "100")]
+    pub(crate) clock_jump_threshold: u64,
+
+    /// Window for rolling E2E percentiles in seconds (0 = disabled)
+    #[arg(long, env = "LATENCY_WINDOW", default_value = "5")]
+    pub(crate) latency_window: u64,
+
+    /// Longest gap between two errors of the same burst in milliseconds
+    #[arg(long, env = "BURST_GAP", default_value = "1000")]
+    pub(crate) burst_gap: u64,
+
+    /// Messages per minute every subscribed client should receive during the
+    /// hold; clients falling short in any window are reported
+    #[arg(long, env = "EXPECT_MSGS_PER_MIN")]
+    pub(crate) expect_msgs_per_min: Option<f64>,
+
+    /// Window over which `--expect-msgs-per-min` is checked, in seconds
+    #[arg(long, env = "EXPECT_WINDOW", default_value = "60")]
+    pub(crate) expect_window: u64,
+
+    /// Errors a cluster needs to count as a burst rather than background
+    #[arg(long, env = "BURST_MIN_ERRORS", default_value = "10")]
+    pub(crate) burst_min_errors: usize,
+
+    /// Stop the run without a summary after this many seconds of wall-clock (0 = no limit)
+    #[arg(long, env = "MAX_RUNTIME", default_value = "0")]
+    pub(crate) max_runtime: u64,
+
+    /// Sliding window for the live messages/s gauge in seconds
+    #[arg(long, env = "RATE_WINDOW", default_value = "10")]
+    pub(crate) rate_window: u64,
+
+    /// Stream every raw latency sample to this gzip-compressed CSV file
+    #[arg(long, env = "RAW_SAMPLES")]
+    pub(crate) raw_samples: Option<PathBuf>,
+
+    /// Client id whose events are logged at info level (with every frame it receives);
+    /// change it mid-run with `POST /debug-client?id=N` on `--control-addr`
+    #[arg(long, env = "DEBUG_CLIENT")]
+    pub(crate) debug_client: Option<usize>,
+
+    /// Upload the JSON summary to S3-compatible storage (s3://bucket/prefix)
+    #[arg(long, env = "UPLOAD")]
+    pub(crate) upload: Option<String>,
+
+    /// S3 endpoint for non-AWS storage (default https://s3.<region>.amazonaws.com)
+    #[arg(long, env = "UPLOAD_ENDPOINT")]
+    pub(crate) upload_endpoint: Option<String>,
+
+    /// Webhook URL that receives a JSON summary when the run ends (Slack/Teams compatible)
+    #[arg(long, env = "NOTIFY_URL")]
+    pub(crate) notify_url: Option<String>,
+
+    /// Additional machine-readable summary format
+    #[arg(long, value_enum, env = "OUTPUT_FORMAT", default_value = "text")]
+    pub(crate) output_format: OutputFormat,
+
+    /// Where to write the `--output-format` summary (default: stdout)
+    #[arg(long, env = "OUTPUT_FILE")]
+    pub(crate) output_file: Option<PathBuf>,
+
+    /// Custom KPI computed at report time, e.g. `delivery_ratio = messages_received / 6000`;
+    /// repeatable, may reference summary fields and earlier derived metrics
+    #[arg(long = "derive", env = "DERIVE", value_delimiter = ';', value_parser = DerivedMetric::parse)]
+    pub(crate) derive: Vec<DerivedMetric>,
+
+    /// Stop ramping, drain and report once a live condition holds, e.g.
+    /// `error_rate > 5%`; repeatable, any guard tripping aborts the run
+    #[arg(long = "abort-if", env = "ABORT_IF", value_delimiter = ';', value_parser = AbortGuard::parse)]
+    pub(crate) abort_if: Vec<AbortGuard>,
+
+    /// Connection attempts (failed + subscribed) needed before `--abort-if` is checked
+    #[arg(long, env = "ABORT_MIN_ATTEMPTS", default_value = "50")]
+    pub(crate) abort_min_attempts: u64,
+}
+
+/// Parse an RFC 3339 timestamp (`2024-05-01T12:00:00Z`, `2024-05-01T14:00:00.250+02:00`)
+/// into unix milliseconds.
+pub(crate) fn parse_rfc3339(s: &str) -> Result<u64, String> {
+    let bad = || {
+        format!(
+            "expected an RFC 3339 timestamp like 2024-05-01T12:00:00Z, got {:?}",
+            s
+        )
+    };
+    let b = s.as_bytes();
+    if b.len() < 20
+        || b[4] != b'-'
+        || b[7] != b'-'
+        || !matches!(b[10], b'T' | b't' | b' ')
+        || b[13] != b':'
+        || b[16] != b':'
+    {
+        return Err(bad());
+    }
+    let num = |from: &str, r: std::ops::Range<usize>| -> Result<i64, String> {
+        match from.get(r) {
+            Some(digits) if digits.bytes().all(|c| c.is_ascii_digit()) => {
+                digits.parse().map_err(|_| bad())
+            }
+            _ => Err(bad()),
+        }
+    };
+    let (year, month, day) = (num(s, 0..4)?, num(s, 5..7)?, num(s, 8..10)?);
+    let (hour, minute, second) = (num(s, 11..13)?, num(s, 14..16)?, num(s, 17..19)?);
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let month_days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    };
+    if !(1..=12).contains(&month)
+        || !(1..=month_days).contains(&day)
+        || hour > 23
+        || minute > 59
+        || second > 60
+    {
+        return Err(bad());
+    }
+
+    let mut rest = &s[19..];
+    let mut millis = 0;
+    if let Some(frac) = rest.strip_prefix('.') {
+        let digits = frac.bytes().take_while(u8::is_ascii_digit).count();
+        if digits == 0 {
+            return Err(bad());
+        }
+        millis = num(&format!("{:0<3}", &frac[..digits.min(3)]), 0..3)?;
+        rest = &frac[digits..];
+    }
+    let offset_secs = match rest.as_bytes() {
+        [b'Z' | b'z'] => 0,
+        [sign @ (b'+' | b'-'), _, _, b':', _, _] => {
+            let offset = num(rest, 1..3)? * 3600 + num(rest, 4..6)? * 60;
+            if *sign == b'-' {
+                -offset
+            } else {
+                offset
+            }
+        }
+        _ => return Err(bad()),
+    };
+
+    // Days-from-civil (Howard Hinnant), the inverse of `amz_date`'s conversion
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+
+    let secs = days * 86_400 + hour * 3600 + minute * 60 + second - offset_secs;
+    u64::try_from(secs * 1000 + millis).map_err(|_| bad())
+}
+
+/// Default run id: launch time plus a random suffix, e.g. `1760523600-3fa2`.
+pub(crate) fn generate_run_id() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    format!("{}-{:04x}", secs, rand::rng().random::<u16>())
+}
+
+// =============================================================================
+// Load Profiles
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum LoadProfile {
+    /// Spawn clients at a constant rate over `--ramp-duration`
+    Linear,
+    /// Add `--step-size` clients at once, holding each plateau `--step-hold` seconds
+    Step,
+    /// Connect every client at once, hold for `--hold-duration`, then drop them all
+    Spike,
+    /// Ramp to `--wave-floor`, then oscillate up to `--num-clients` and back
+    /// every `--wave-period` seconds during the hold
+    Wave,
+    /// Track the `--profile-file` schedule in place of ramp and hold
+    File,
+    /// Open loop: connections arrive as a Poisson process at `--arrival-rate`
+    Poisson,
+    /// Run the `--plan` phases in place of ramp and hold
+    Plan,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ThinkTime {
+    /// Every `--filter-update-interval`
+    Fixed,
+    /// Uniform within `--think-time-spread` of the interval
+    Uniform,
+    /// Exponential with the interval as mean (memoryless users)
+    Exponential,
+    /// Normal around the interval with `--think-time-spread` as deviation
+    Normal,
+}
+
+impl Config {
+    /// The shared TLS connector.
+    pub(crate) fn tls(&self) -> &native_tls::TlsConnector {
+        self.tls
+            .as_ref()
+            .expect("TLS connector is built at startup")
+    }
+
+    /// Whether the server speaks TLS: `--tls` if given, else port 443.
+    pub(crate) fn use_tls(&self) -> bool {
+        self.ws_tls.unwrap_or(self.ws_port == 443)
+    }
+
+    /// `scheme://host:port` of the server, with the `secure` scheme under TLS.
+    pub(crate) fn server_origin(&self, plain: &str, secure: &str) -> String {
+        let scheme = if self.use_tls() { secure } else { plain };
+        format!("{}://{}:{}", scheme, self.ws_host, self.ws_port)
+    }
+
+    pub(crate) fn step_count(&self) -> usize {
+        self.num_clients.div_ceil(self.step_size.max(1))
+    }
+
+    /// Total length of the ramp-up stage under the configured profile.
+    pub(crate) fn ramp_length(&self) -> Duration {
+        match self.profile {
+            LoadProfile::Linear => Duration::from_secs(self.ramp_duration),
+            LoadProfile::Step => Duration::from_secs(self.step_hold * self.step_count() as u64),
+            LoadProfile::Spike => Duration::ZERO,
+            LoadProfile::Wave => Duration::from_secs(self.ramp_duration),
+            LoadProfile::File | LoadProfile::Plan => Duration::ZERO,
+            // Expected time for every arrival; the actual ramp ends with the last one
+            LoadProfile::Poisson => {
+                Duration::from_secs_f64(self.num_clients as f64 / self.arrival_rate())
+            }
+        }
+    }
+
+    /// Highest client target accepted at runtime.
+    pub(crate) fn max_clients(&self) -> usize {
+        self.max_clients
+            .unwrap_or(self.num_clients.saturating_mul(2))
+    }
+
+    /// Clients per second spawned towards a runtime target: the
+    /// `--max-connect-rate` if set, else the ramp's average pace.
+    pub(crate) fn retarget_rate(&self) -> f64 {
+        if self.max_connect_rate > 0.0 {
+            self.max_connect_rate
+        } else {
+            self.num_clients.max(1) as f64 / self.ramp_duration.max(1) as f64
+        }
+    }
+
+    /// Mean arrivals per second under `--profile poisson`.
+    pub(crate) fn arrival_rate(&self) -> f64 {
+        if self.arrival_rate > 0.0 {
+            self.arrival_rate
+        } else {
+            self.num_clients.max(1) as f64 / self.ramp_duration.max(1) as f64
+        }
+    }
+
+    /// Random wait until the next Poisson arrival (exponentially distributed).
+    pub(crate) fn arrival_gap(&self) -> Duration {
+        let u: f64 = rand::rng().random();
+        Duration::from_secs_f64(-(1.0 - u).ln() / self.arrival_rate())
+    }
+
+    /// Scenario client `id` starts on: its `--mix` share, else `--scenario`.
+    /// Ids walk a golden-ratio sequence, so every prefix of the fleet stays
+    /// close to the mix as it ramps.
+    pub(crate) fn client_scenario(&self, id: usize) -> u8 {
+        let x = (id as f64 * 0.618_033_988_749_895).fract();
+        self.scenario_mix
+            .iter()
+            .find(|&&(_, upto)| x < upto)
+            .map_or(self.scenario, |&(scenario, _)| scenario)
+    }
+
+    /// Random pause before a client's next filter update under `--think-time`,
+    /// around the scenario's own interval if it sets one.
+    pub(crate) fn think_time(&self, scenario: u8) -> Duration {
+        let mean = self
+            .scenarios
+            .get(scenario)
+            .update_interval
+            .unwrap_or(self.filter_update_interval) as f64;
+        let spread = self.think_time_spread as f64;
+        let mut rng = rand::rng();
+        let ms = match self.think_time {
+            ThinkTime::Fixed => mean,
+            ThinkTime::Uniform => mean + spread * (2.0 * rng.random::<f64>() - 1.0),
+            ThinkTime::Exponential => -(1.0 - rng.random::<f64>()).ln() * mean,
+            ThinkTime::Normal => {
+                // Box-Muller
+                let (u1, u2): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+                mean + spread * (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+            }
+        };
+        // Never a zero wait, which would spin on back-to-back updates
+        Duration::from_secs_f64(ms.max(1.0) / 1000.0)
+    }
+
+    /// Clients that should have been spawned `elapsed` into the ramp.
+    pub(crate) fn ramp_target(&self, elapsed: Duration) -> usize {
+        let target = match self.profile {
+            LoadProfile::Linear => {
+                let clients_per_second = self.num_clients as f64 / self.ramp_duration as f64;
+                (clients_per_second * elapsed.as_secs_f64()) as usize
+            }
+            LoadProfile::Step => {
+                let step = elapsed.as_secs() / self.step_hold.max(1);
+                (step as usize + 1) * self.step_size.max(1)
+            }
+            LoadProfile::Spike => self.num_clients,
+            LoadProfile::Wave => {
+                let floor = self.wave_floor.min(self.num_clients);
+                let progress = elapsed.as_secs_f64() / self.ramp_duration.max(1) as f64;
+                (floor as f64 * progress.min(1.0)) as usize
+            }
+            LoadProfile::File | LoadProfile::Plan => 0,
+            LoadProfile::Poisson => self.num_clients,
+        };
+        target.min(self.num_clients)
+    }
+
+    /// Running clients `elapsed` into the hold under `--profile wave`: a
+    /// cosine from the floor up to `--num-clients` and back each period.
+    pub(crate) fn wave_target(&self, elapsed: Duration) -> usize {
+        let floor = self.wave_floor.min(self.num_clients) as f64;
+        let phase = elapsed.as_secs_f64() / self.wave_period.max(1) as f64;
+        let swing = (1.0 - (std::f64::consts::TAU * phase).cos()) / 2.0;
+        (floor + (self.num_clients as f64 - floor) * swing).round() as usize
+    }
+}
+
+/// A schedule point: `[time_offset, target_clients]` or the named object form.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum SchedulePoint {
+    Pair(f64, usize),
+    Named {
+        time_offset: f64,
+        target_clients: usize,
+    },
+}
+
+/// Target client counts over time, linearly interpolated between points.
+#[derive(Debug)]
+pub(crate) struct LoadSchedule {
+    /// (seconds from start, target clients), ascending by offset
+    pub(crate) points: Vec<(f64, f64)>,
+}
+
+impl LoadSchedule {
+    /// Load a JSON array of points, or CSV lines of `time_offset,target_clients`
+    /// with an optional header row and `#` comments.
+    pub(crate) fn load(path: &std::path::Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut points = Vec::new();
+        if content.trim_start().starts_with('[') {
+            let entries: Vec<SchedulePoint> = sonic_rs::from_str(&content)?;
+            for entry in entries {
+                let (offset, target) = match entry {
+                    SchedulePoint::Pair(offset, target) => (offset, target),
+                    SchedulePoint::Named {
+                        time_offset,
+                        target_clients,
+                    } => (time_offset, target_clients),
+                };
+                points.push((offset, target as f64));
+            }
+        } else {
+            for (n, line) in content.lines().enumerate() {
+                let line = line.trim();
+                if line.is_empty() || line.starts_with('#') {
+                    continue;
+                }
+                let (offset, target) = line.split_once(',').ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "{:?} line {}: expected time_offset,target_clients",
+                        path,
+                        n + 1
+                    )
+                })?;
+                match (offset.trim().parse::<f64>(), target.trim().parse::<usize>()) {
+                    (Ok(offset), Ok(target)) => points.push((offset, target as f64)),
+                    // Header row
+                    _ if points.is_empty() => continue,
+                    _ => anyhow::bail!("{:?} line {}: invalid point {:?}", path, n + 1, line),
+                }
+            }
+        }
+
+        if points.is_empty() {
+            anyhow::bail!("{:?}: schedule has no points", path);
+        }
+        if points
+            .iter()
+            .any(|&(offset, _)| !offset.is_finite() || offset < 0.0)
+        {
+            anyhow::bail!("{:?}: time offsets must be non-negative", path);
+        }
+        if points.windows(2).any(|w| w[1].0 < w[0].0) {
+            anyhow::bail!("{:?}: time offsets must be ascending", path);
+        }
+        Ok(Self { points })
+    }
+
+    /// Time of the last point; the schedule ends there.
+    pub(crate) fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.points.last().map_or(0.0, |&(offset, _)| offset))
+    }
+
+    pub(crate) fn peak(&self) -> usize {
+        self.points
+            .iter()
+            .map(|&(_, target)| target as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Clients that should be running `elapsed` into the schedule. Holds the
+    /// first target before the first point and the last one after the end.
+    pub(crate) fn target(&self, elapsed: Duration) -> usize {
+        let t = elapsed.as_secs_f64();
+        let next = self.points.partition_point(|&(offset, _)| offset <= t);
+        let target = match (self.points.get(next.wrapping_sub(1)), self.points.get(next)) {
+            (Some(&(t0, c0)), Some(&(t1, c1))) => c0 + (c1 - c0) * (t - t0) / (t1 - t0),
+            (Some(&(_, c)), None) | (None, Some(&(_, c))) => c,
+            (None, None) => 0.0,
+        };
+        target.round() as usize
+    }
+}
+
+/// A phase as written in the plan file; omitted fields carry over from the
+/// previous phase.
+#[derive(Debug, Deserialize)]
+pub(crate) struct PlanPhaseSpec {
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    /// Running clients at the end of the ramp
+    #[serde(default)]
+    pub(crate) clients: Option<usize>,
+    /// Seconds to move linearly from the previous phase's clients
+    #[serde(default)]
+    pub(crate) ramp: u64,
+    /// Seconds to hold once ramped
+    #[serde(default)]
+    pub(crate) hold: u64,
+    #[serde(default)]
+    pub(crate) scenario: Option<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PlanPhase {
+    pub(crate) name: String,
+    pub(crate) clients: usize,
+    pub(crate) ramp: Duration,
+    pub(crate) hold: Duration,
+    pub(crate) scenario: u8,
+}
+
+/// Sequential phases from `--plan`, e.g. ramp to 2k on scenario 1, switch the
+/// survivors to scenario 2 updates, then ramp to 10k.
+#[derive(Debug)]
+pub(crate) struct TestPlan {
+    pub(crate) phases: Vec<PlanPhase>,
+}
+
+impl TestPlan {
+    /// Load a JSON array of phases, starting from `scenario` and no clients.
+    pub(crate) fn load(path: &std::path::Path, scenario: u8) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let specs: Vec<PlanPhaseSpec> = sonic_rs::from_str(&content)?;
+        if specs.is_empty() {
+            anyhow::bail!("{:?}: plan has no phases", path);
+        }
+
+        let (mut clients, mut scenario) = (0, scenario);
+        let phases = specs
+            .into_iter()
+            .enumerate()
+            .map(|(i, spec)| {
+                clients = spec.clients.unwrap_or(clients);
+                scenario = spec.scenario.unwrap_or(scenario);
+                PlanPhase {
+                    name: spec.name.unwrap_or_else(|| format!("phase-{}", i + 1)),
+                    clients,
+                    ramp: Duration::from_secs(spec.ramp),
+                    hold: Duration::from_secs(spec.hold),
+                    scenario,
+                }
+            })
+            .collect();
+        Ok(Self { phases })
+    }
+
+    /// Client targets over the whole plan: each phase ramps, then holds.
+    pub(crate) fn schedule(&self) -> LoadSchedule {
+        let mut points = vec![(0.0, 0.0)];
+        let mut t = Duration::ZERO;
+        for phase in &self.phases {
+            t += phase.ramp;
+            points.push((t.as_secs_f64(), phase.clients as f64));
+            t += phase.hold;
+            points.push((t.as_secs_f64(), phase.clients as f64));
+        }
+        LoadSchedule { points }
+    }
+
+    /// Index of the phase running `elapsed` into the plan (the last one once over).
+    pub(crate) fn phase_at(&self, elapsed: Duration) -> usize {
+        let mut end = Duration::ZERO;
+        for (i, phase) in self.phases.iter().enumerate() {
+            end += phase.ramp + phase.hold;
+            if elapsed < end {
+                return i;
+            }
+        }
+        self.phases.len() - 1
+    }
+}
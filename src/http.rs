@@ -0,0 +1,336 @@
+//! A minimal HTTP/1.1 client and the metrics and control endpoints.
+
+use super::*;
+
+// =============================================================================
+// Minimal HTTP Client
+// =============================================================================
+
+/// Status and body of an HTTP response.
+pub(crate) struct HttpResponse {
+    pub(crate) status: u16,
+    pub(crate) body: String,
+}
+
+/// Longest an [`http_request`] may take from connect to the last byte, so a
+/// hung control-plane endpoint can't stall startup or the end of a run.
+pub(crate) const HTTP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// One-shot HTTP/1.1 request over plain TCP or TLS (`Connection: close`),
+/// https:// with the run's shared connector (see [`tls_connector`]). Fails
+/// after [`HTTP_TIMEOUT`].
+///
+/// Just enough HTTP for control-plane calls (dashboards, webhooks, uploads);
+/// the benchmark data path never goes through here.
+pub(crate) async fn http_request(
+    tls: &native_tls::TlsConnector,
+    method: &str,
+    url: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+) -> Result<HttpResponse> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let parsed = url::Url::parse(url)?;
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| anyhow::anyhow!("URL has no host: {}", url))?
+        .to_string();
+    let port = parsed
+        .port_or_known_default()
+        .ok_or_else(|| anyhow::anyhow!("URL has no port: {}", url))?;
+    let path = match parsed.query() {
+        Some(q) => format!("{}?{}", parsed.path(), q),
+        None => parsed.path().to_string(),
+    };
+
+    let host_header = match parsed.port() {
+        Some(p) => format!("{}:{}", host, p),
+        None => host.clone(),
+    };
+
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        host_header,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+
+    let exchange = async {
+        let tcp = TcpStream::connect((host.as_str(), port)).await?;
+        let mut stream: BoxedStream = if parsed.scheme() == "https" {
+            let connector = TlsConnector::from(tls.clone());
+            Box::new(connector.connect(&host, tcp).await?)
+        } else {
+            Box::new(tcp)
+        };
+
+        stream.write_all(request.as_bytes()).await?;
+        stream.write_all(body).await?;
+        stream.flush().await?;
+
+        let mut raw = Vec::new();
+        stream.read_to_end(&mut raw).await?;
+        anyhow::Ok(raw)
+    };
+    let raw = tokio::time::timeout(HTTP_TIMEOUT, exchange)
+        .await
+        .map_err(|_| anyhow::anyhow!("no response from {} within {:?}", host, HTTP_TIMEOUT))??;
+    parse_http_response(&raw).map_err(|e| anyhow::anyhow!("{} from {}", e, host))
+}
+
+/// Split a raw response into status and body, undoing chunked encoding.
+///
+/// The body is only decoded as (lossy) UTF-8 once it has been dechunked, so
+/// chunk boundaries that fall inside a multibyte character are harmless.
+pub(crate) fn parse_http_response(raw: &[u8]) -> Result<HttpResponse> {
+    let split = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| anyhow::anyhow!("malformed HTTP response"))?;
+    let head = String::from_utf8_lossy(&raw[..split]);
+    let payload = &raw[split + 4..];
+
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|s| s.parse().ok())
+        .ok_or_else(|| anyhow::anyhow!("malformed HTTP status line"))?;
+    let chunked = head.lines().any(|l| {
+        l.to_ascii_lowercase()
+            .starts_with("transfer-encoding: chunked")
+    });
+
+    let body = if chunked {
+        String::from_utf8_lossy(&dechunk(payload)).into_owned()
+    } else {
+        String::from_utf8_lossy(payload).into_owned()
+    };
+    Ok(HttpResponse { status, body })
+}
+
+/// Decode a `Transfer-Encoding: chunked` body. A truncated or malformed
+/// chunk ends the body at the last complete one.
+pub(crate) fn dechunk(mut payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(eol) = payload.windows(2).position(|w| w == b"\r\n") {
+        let size_line = String::from_utf8_lossy(&payload[..eol]);
+        let size = usize::from_str_radix(size_line.split(';').next().unwrap_or("").trim(), 16)
+            .unwrap_or(0);
+        let rest = &payload[eol + 2..];
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        out.extend_from_slice(&rest[..size]);
+        payload = rest[size..].strip_prefix(b"\r\n").unwrap_or(&rest[size..]);
+    }
+    out
+}
+
+// =============================================================================
+// Live Metrics Exporter
+// =============================================================================
+
+/// Render the live counters in Prometheus text exposition format.
+pub(crate) fn render_prometheus(snapshot: &LiveSnapshot, run_id: &str) -> String {
+    let gauges: [(&str, &str, f64); 12] = [
+        (
+            "active_connections",
+            "gauge",
+            snapshot.active_connections as f64,
+        ),
+        (
+            "pending_connects",
+            "gauge",
+            snapshot.pending_connects as f64,
+        ),
+        (
+            "messages_received_total",
+            "counter",
+            snapshot.messages_received as f64,
+        ),
+        ("bytes_sent_total", "counter", snapshot.bytes_sent as f64),
+        (
+            "bytes_received_total",
+            "counter",
+            snapshot.bytes_received as f64,
+        ),
+        (
+            "subscribe_success_total",
+            "counter",
+            snapshot.subscribe_success as f64,
+        ),
+        (
+            "subscribe_failed_total",
+            "counter",
+            snapshot.subscribe_failed as f64,
+        ),
+        (
+            "connection_errors_total",
+            "counter",
+            snapshot.connection_errors as f64,
+        ),
+        (
+            "post_close_messages_total",
+            "counter",
+            snapshot.post_close_messages as f64,
+        ),
+        ("measuring", "gauge", snapshot.measuring as u8 as f64),
+        ("cpu_suspect", "gauge", snapshot.cpu_suspect as u8 as f64),
+        ("elapsed_seconds", "gauge", snapshot.elapsed_secs),
+    ];
+
+    let mut out = String::with_capacity(1024);
+    for (name, kind, value) in gauges {
+        out.push_str(&format!(
+            "# TYPE wsbench_{name} {kind}\nwsbench_{name}{{run_id=\"{run_id}\"}} {value}\n"
+        ));
+    }
+    if let Some(canary) = &snapshot.canary {
+        let gauges: [(&str, f64); 4] = [
+            ("last_message_age_seconds", canary.last_message_age_secs),
+            ("e2e_mean_ms", canary.e2e_mean_ms),
+            ("e2e_max_ms", canary.e2e_max_ms as f64),
+            ("e2e_window_samples", canary.e2e_samples as f64),
+        ];
+        for (name, value) in gauges {
+            out.push_str(&format!(
+                "# TYPE wsbench_canary_{name} gauge\nwsbench_canary_{name}{{run_id=\"{run_id}\"}} {value}\n"
+            ));
+        }
+    }
+    out
+}
+
+/// Numeric query parameter `name` of the request line following a route,
+/// e.g. `?secs=30 HTTP/1.1`.
+pub(crate) fn query_u64(rest: &str, name: &str) -> Option<u64> {
+    rest.split_whitespace()
+        .next()?
+        .strip_prefix('?')?
+        .split('&')
+        .find_map(|pair| pair.strip_prefix(name)?.strip_prefix('='))?
+        .parse()
+        .ok()
+}
+
+/// Serve `GET /metrics` on `addr` for Prometheus to scrape.
+pub(crate) async fn serve_metrics(
+    addr: String,
+    live_stats: LiveStats,
+    run_id: String,
+) -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("Serving live metrics on http://{}/metrics", addr);
+    let started = Instant::now();
+
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let live_stats = live_stats.clone();
+        let run_id = run_id.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+
+            let response = if request.starts_with("GET /metrics") {
+                let body = render_prometheus(&LiveSnapshot::capture(&live_stats, started), &run_id);
+                format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
+
+/// Serve the control endpoints on `addr`: `POST /chaos/drop-pongs?secs=N`
+/// to start a pong chaos window, `POST /pause` / `POST /resume` to hold the
+/// load steady, `POST /clients?target=N` (up to `max_clients`) to scale
+/// the fleet and `POST /debug-client?id=N` / `POST /debug-client/off` to
+/// switch `--debug-client`. Anyone who can reach `addr` can steer the run, so it stays
+/// apart from `--metrics-addr`.
+pub(crate) async fn serve_control(
+    addr: String,
+    live_stats: LiveStats,
+    max_clients: usize,
+) -> Result<()> {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    info!("Serving control endpoints on http://{}", addr);
+
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let live_stats = live_stats.clone();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 2048];
+            let n = socket.read(&mut buf).await.unwrap_or(0);
+            let request = String::from_utf8_lossy(&buf[..n]);
+
+            let response = if let Some(query) = request.strip_prefix("POST /chaos/drop-pongs") {
+                match query_u64(query, "secs").filter(|&s| s <= MAX_CHAOS_WINDOW.as_secs()) {
+                    Some(secs) => {
+                        spawn_pong_chaos(live_stats, Duration::from_secs(secs));
+                        "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                    None => {
+                        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                }
+            } else if let Some(paused) = [("POST /pause ", true), ("POST /resume ", false)]
+                .into_iter()
+                .find_map(|(route, paused)| request.starts_with(route).then_some(paused))
+            {
+                set_paused(&live_stats, paused);
+                "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_string()
+            } else if let Some(query) = request.strip_prefix("POST /clients") {
+                match query_u64(query, "target").filter(|&t| t <= max_clients as u64) {
+                    Some(target) => {
+                        live_stats.client_target.send_replace(Some(target as usize));
+                        "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                    None => {
+                        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                }
+            } else if let Some(query) = request.strip_prefix("POST /debug-client") {
+                let id = if query.starts_with("/off ") {
+                    Some(NO_DEBUG_CLIENT)
+                } else {
+                    query_u64(query, "id").map(|id| id as usize)
+                };
+                match id {
+                    Some(id) => {
+                        set_debug_client(&live_stats, id);
+                        "HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n".to_string()
+                    }
+                    None => {
+                        "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                            .to_string()
+                    }
+                }
+            } else {
+                "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                    .to_string()
+            };
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
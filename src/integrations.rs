@@ -0,0 +1,327 @@
+//! Grafana provisioning, summary uploads and completion webhooks.
+
+use super::*;
+
+// =============================================================================
+// Grafana Provisioning
+// =============================================================================
+
+/// Dashboard JSON for one run; every query is scoped to `run_id`.
+pub(crate) fn grafana_dashboard(run_id: &str, datasource_uid: &str) -> sonic_rs::Value {
+    let panels = [
+        ("Active Connections", "wsbench_active_connections"),
+        ("Messages / s", "rate(wsbench_messages_received_total[30s])"),
+        ("Bytes In / s", "rate(wsbench_bytes_received_total[30s])"),
+        ("Bytes Out / s", "rate(wsbench_bytes_sent_total[30s])"),
+        ("Subscribed", "wsbench_subscribe_success_total"),
+        (
+            "Connection Errors / s",
+            "rate(wsbench_connection_errors_total[30s])",
+        ),
+        ("Generator CPU Suspect", "wsbench_cpu_suspect"),
+        ("Measuring", "wsbench_measuring"),
+    ];
+
+    let panels: Vec<sonic_rs::Value> = panels
+        .iter()
+        .enumerate()
+        .map(|(i, (title, expr))| {
+            // Inject the run_id selector into the metric name inside the expression
+            let metric = expr
+                .split(['(', '['])
+                .find(|part| part.starts_with("wsbench_"))
+                .unwrap_or(expr);
+            let scoped = expr.replacen(metric, &format!("{}{{run_id=\"{}\"}}", metric, run_id), 1);
+            sonic_rs::json!({
+                "id": i + 1,
+                "type": "timeseries",
+                "title": *title,
+                "datasource": { "type": "prometheus", "uid": datasource_uid },
+                "gridPos": { "h": 8, "w": 12, "x": (i % 2) * 12, "y": (i / 2) * 8 },
+                "targets": [{ "refId": "A", "expr": scoped }],
+            })
+        })
+        .collect();
+
+    sonic_rs::json!({
+        "dashboard": {
+            "uid": format!("wsbench-{}", run_id),
+            "title": format!("ws-benchmark {}", run_id),
+            "tags": ["ws-benchmark"],
+            "time": { "from": "now-30m", "to": "now" },
+            "refresh": "5s",
+            "panels": panels,
+        },
+        "overwrite": true,
+    })
+}
+
+/// Create (or overwrite) the run's dashboard through the Grafana HTTP API.
+pub(crate) async fn provision_grafana(config: &Config) -> Result<()> {
+    let Some(base) = &config.grafana_url else {
+        return Ok(());
+    };
+    let body = sonic_rs::to_string(&grafana_dashboard(
+        &config.run_id,
+        &config.grafana_datasource,
+    ))?;
+    let auth = config
+        .grafana_token
+        .as_ref()
+        .map(|t| format!("Bearer {}", t));
+
+    let mut headers = vec![("Content-Type", "application/json")];
+    if let Some(auth) = &auth {
+        headers.push(("Authorization", auth.as_str()));
+    }
+
+    let url = format!("{}/api/dashboards/db", base.trim_end_matches('/'));
+    let response = http_request(config.tls(), "POST", &url, &headers, body.as_bytes()).await?;
+    if response.status / 100 != 2 {
+        anyhow::bail!(
+            "Grafana returned {}: {}",
+            response.status,
+            response.body.trim()
+        );
+    }
+
+    let dashboard_url = sonic_rs::from_str::<sonic_rs::Value>(&response.body)
+        .ok()
+        .and_then(|v| v.get("url").and_then(|u| u.as_str()).map(str::to_owned))
+        .unwrap_or_default();
+    info!(
+        "Provisioned Grafana dashboard: {}{}",
+        base.trim_end_matches('/'),
+        dashboard_url
+    );
+    Ok(())
+}
+
+// =============================================================================
+// Object Storage Upload
+// =============================================================================
+
+/// `YYYYMMDDTHHMMSSZ` for a UTC Unix time (SigV4 `x-amz-date`).
+pub(crate) fn amz_date(secs: i64) -> String {
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+
+    // Civil-from-days (Howard Hinnant), proleptic Gregorian
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Percent-encode an S3 object key, keeping `/` separators.
+pub(crate) fn encode_s3_key(key: &str) -> String {
+    key.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+/// SigV4 credential scope and signature of a canonical request made at
+/// `timestamp` (an [`amz_date`]).
+pub(crate) fn sigv4_sign(
+    secret_key: &str,
+    timestamp: &str,
+    region: &str,
+    service: &str,
+    canonical_request: &str,
+) -> Result<(String, String)> {
+    let date = &timestamp[..8];
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        timestamp,
+        scope,
+        to_hex(&openssl::sha::sha256(canonical_request.as_bytes()))
+    );
+
+    let k_date = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date.as_bytes())?;
+    let k_region = hmac_sha256(&k_date, region.as_bytes())?;
+    let k_service = hmac_sha256(&k_region, service.as_bytes())?;
+    let k_signing = hmac_sha256(&k_service, b"aws4_request")?;
+    let signature = to_hex(&hmac_sha256(&k_signing, string_to_sign.as_bytes())?);
+    Ok((scope, signature))
+}
+
+/// PUT `summary.json` under `s3://bucket/prefix/<run_id>/`, signed with
+/// SigV4 from the standard `AWS_*` environment variables. Path-style
+/// addressing keeps it compatible with MinIO/R2/etc. via `--upload-endpoint`.
+pub(crate) async fn upload_summary(
+    target: &str,
+    config: &Config,
+    summary: &RunSummary,
+) -> Result<String> {
+    let rest = target
+        .strip_prefix("s3://")
+        .ok_or_else(|| anyhow::anyhow!("--upload must look like s3://bucket/prefix"))?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+    let prefix = prefix.trim_matches('/');
+    let key = if prefix.is_empty() {
+        format!("{}/summary.json", config.run_id)
+    } else {
+        format!("{}/{}/summary.json", prefix, config.run_id)
+    };
+
+    let access_key = std::env::var("AWS_ACCESS_KEY_ID")?;
+    let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")?;
+    let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let endpoint = config
+        .upload_endpoint
+        .clone()
+        .unwrap_or_else(|| format!("https://s3.{}.amazonaws.com", region));
+
+    let body = sonic_rs::to_string_pretty(summary)?;
+    let payload_hash = to_hex(&openssl::sha::sha256(body.as_bytes()));
+    let timestamp = amz_date((unix_millis() / 1000) as i64);
+
+    let endpoint_url = url::Url::parse(&endpoint)?;
+    let host = match (endpoint_url.host_str(), endpoint_url.port()) {
+        (Some(h), Some(p)) => format!("{}:{}", h, p),
+        (Some(h), None) => h.to_string(),
+        (None, _) => anyhow::bail!("invalid --upload-endpoint {}", endpoint),
+    };
+    let path = format!("/{}/{}", bucket, encode_s3_key(&key));
+
+    let mut canonical_headers = format!(
+        "host:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n",
+        host, payload_hash, timestamp
+    );
+    let mut signed_headers = "host;x-amz-content-sha256;x-amz-date".to_string();
+    if let Some(token) = &session_token {
+        canonical_headers.push_str(&format!("x-amz-security-token:{}\n", token));
+        signed_headers.push_str(";x-amz-security-token");
+    }
+
+    let canonical_request = format!(
+        "PUT\n{}\n\n{}\n{}\n{}",
+        path, canonical_headers, signed_headers, payload_hash
+    );
+    let (scope, signature) =
+        sigv4_sign(&secret_key, &timestamp, &region, "s3", &canonical_request)?;
+
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key, scope, signed_headers, signature
+    );
+    let mut headers = vec![
+        ("Authorization", authorization.as_str()),
+        ("Content-Type", "application/json"),
+        ("x-amz-content-sha256", payload_hash.as_str()),
+        ("x-amz-date", timestamp.as_str()),
+    ];
+    if let Some(token) = &session_token {
+        headers.push(("x-amz-security-token", token.as_str()));
+    }
+
+    let url = format!("{}{}", endpoint.trim_end_matches('/'), path);
+    let response = http_request(config.tls(), "PUT", &url, &headers, body.as_bytes()).await?;
+    if response.status / 100 != 2 {
+        anyhow::bail!(
+            "storage returned {}: {}",
+            response.status,
+            response.body.trim()
+        );
+    }
+    Ok(format!("s3://{}/{}", bucket, key))
+}
+
+// =============================================================================
+// Completion Webhook
+// =============================================================================
+
+#[derive(Serialize)]
+pub(crate) struct Notification<'a> {
+    /// `pass`, or `fail` if the run was aborted or delivery fell short
+    pub(crate) status: &'static str,
+    /// One-line summary; the field Slack and Teams incoming webhooks render
+    pub(crate) text: String,
+    pub(crate) summary: &'a RunSummary,
+}
+
+/// Why the run failed: an `--abort-if` guard tripped or clients received
+/// fewer messages than `--expect-msgs-per-min`. `None` for a pass.
+pub(crate) fn failure_reason(summary: &RunSummary) -> Option<String> {
+    if let Some(reason) = &summary.aborted {
+        return Some(format!("aborted by {}", reason));
+    }
+    summary
+        .delivery
+        .as_ref()
+        .filter(|d| d.clients_below > 0)
+        .map(|d| {
+            format!(
+                "{}/{} clients below expected delivery",
+                d.clients_below, d.clients_checked
+            )
+        })
+}
+
+/// POST the run summary to `url` once the benchmark has finished.
+pub(crate) async fn notify_webhook(config: &Config, url: &str, summary: &RunSummary) -> Result<()> {
+    let e2e_p99 = summary
+        .e2e_latency_ms
+        .as_ref()
+        .map_or_else(|| "n/a".to_string(), |l| format!("{}ms", l.p99));
+    let failure = failure_reason(summary);
+    let verdict = match &failure {
+        Some(reason) => format!("FAIL ({})", reason),
+        None => "PASS".to_string(),
+    };
+    let text = format!(
+        "ws-benchmark run {} finished: {}, scenario {}, {}/{} subscribed, {} connection errors, {} messages, e2e p99 {}",
+        summary.run_id,
+        verdict,
+        summary.scenario,
+        summary.subscribe_success,
+        summary.num_clients,
+        summary.connection_errors,
+        summary.messages_received,
+        e2e_p99
+    );
+
+    let status = if failure.is_some() { "fail" } else { "pass" };
+    let body = sonic_rs::to_string(&Notification {
+        status,
+        text,
+        summary,
+    })?;
+    let response = http_request(
+        config.tls(),
+        "POST",
+        url,
+        &[("Content-Type", "application/json")],
+        body.as_bytes(),
+    )
+    .await?;
+    if response.status / 100 != 2 {
+        anyhow::bail!(
+            "webhook returned {}: {}",
+            response.status,
+            response.body.trim()
+        );
+    }
+    Ok(())
+}
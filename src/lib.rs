@@ -0,0 +1,2076 @@
+use anyhow::Result;
+use clap::Parser;
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, SinkExt, StreamExt};
+use hdrhistogram::Histogram;
+use rand::prelude::IndexedRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use sonic_rs::{JsonContainerTrait, JsonValueTrait};
+use std::collections::{BTreeMap, HashMap};
+use std::os::fd::{AsRawFd, RawFd};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncWrite, DuplexStream};
+use tokio::net::TcpStream;
+use tokio::sync::broadcast;
+use tokio::time::{interval, sleep};
+use tokio_native_tls::TlsConnector;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::server::{
+    Callback, ErrorResponse, Request, Response,
+};
+use tokio_tungstenite::tungstenite::http::{header, HeaderMap, HeaderValue};
+use tokio_tungstenite::{client_async, tungstenite::Message, WebSocketStream};
+use tracing::{debug, error, info, warn};
+
+mod chaos;
+mod client;
+mod codecs;
+mod config;
+mod http;
+mod integrations;
+mod mock;
+mod monitors;
+mod protocol;
+mod publisher;
+mod report;
+mod runner;
+mod scenarios;
+mod stats;
+mod tokens;
+mod transport;
+
+use chaos::*;
+use client::*;
+use codecs::*;
+use config::*;
+use http::*;
+use integrations::*;
+use mock::*;
+use monitors::*;
+use protocol::*;
+use publisher::*;
+use report::*;
+use runner::*;
+use scenarios::*;
+use stats::*;
+use tokens::*;
+use transport::*;
+
+pub use monitors::{INTERRUPTED_EXIT_CODE, MAX_RUNTIME_EXIT_CODE};
+pub use runner::ABORTED_EXIT_CODE;
+
+// =============================================================================
+// Entry Point
+// =============================================================================
+
+/// Run one benchmark configured by command-line `args` (program name first)
+/// and the environment, as the `ws-benchmark` binary does. Returns the exit
+/// status: 0, [`ABORTED_EXIT_CODE`] after an `--abort-if` guard tripped, or
+/// that of a second Ctrl-C or `--max-runtime`, which end the run at once.
+///
+/// Invalid arguments, `--help` and `--version` come back as a
+/// [`clap::Error`] for the caller to print or exit with.
+pub async fn run<I, T>(args: I) -> Result<i32>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<std::ffi::OsString> + Clone,
+{
+    let mut config = Config::try_parse_from(args)?;
+    if config.canary {
+        config.reconnect = true;
+        config.max_reconnects = u64::MAX;
+        config
+            .metrics_addr
+            .get_or_insert_with(|| "0.0.0.0:9898".into());
+    }
+    let filter_key = leak_key(config.filter_key.clone());
+    config.scenarios = match &config.scenario_file {
+        Some(path) => ScenarioSet::load(path, filter_key, config.filter_size)?,
+        None => ScenarioSet::builtin(filter_key, config.filter_size),
+    };
+    if !config.scenarios.contains(config.scenario) {
+        anyhow::bail!("--scenario {}: no such scenario", config.scenario);
+    }
+    config.raw_filters = load_raw_filters(&config, filter_key)?;
+    if let Some(path) = &config.filter_map_file {
+        config.filter_map = load_filter_map(path, filter_key)?;
+    }
+    if config
+        .token_zipf
+        .is_some_and(|s| s <= 0.0 || !s.is_finite())
+    {
+        anyhow::bail!("--token-zipf needs s > 0");
+    }
+    if config.payload_format == PayloadFormat::Protobuf {
+        let (Some(path), Some(message)) = (&config.proto_descriptor, &config.proto_message) else {
+            anyhow::bail!("--payload-format protobuf needs --proto-descriptor and --proto-message");
+        };
+        let codec = ProtoCodec::load(path, message)?;
+        info!(
+            "Loaded {} message types from {:?}",
+            codec.messages.len(),
+            path
+        );
+        config.proto = Some(Arc::new(codec));
+    }
+    if !config.client_event.starts_with("client-") {
+        anyhow::bail!("--client-event must start with client-");
+    }
+    if config.chaos_drop_pongs_for > MAX_CHAOS_WINDOW.as_secs() {
+        anyhow::bail!(
+            "--chaos-drop-pongs-for {}: at most {}s",
+            config.chaos_drop_pongs_for,
+            MAX_CHAOS_WINDOW.as_secs()
+        );
+    }
+    if !(0.0..=MAX_PUBLISH_RATE).contains(&config.publish_rate) {
+        anyhow::bail!(
+            "--publish-rate {}: must be between 0 and {}",
+            config.publish_rate,
+            MAX_PUBLISH_RATE
+        );
+    }
+    if config.publish_rate > 0.0 && (config.app_id.is_none() || config.app_secret.is_none()) {
+        anyhow::bail!("--publish-rate needs --app-id and --app-secret to sign requests");
+    }
+    if config.token_zipf.is_some() && config.token_weights.is_some() {
+        anyhow::bail!("give either --token-zipf or --token-weights");
+    }
+    if let Some(spec) = &config.mix {
+        if config.profile == LoadProfile::Plan {
+            anyhow::bail!("--mix can't be combined with --profile plan");
+        }
+        config.scenario_mix = parse_mix(spec, &config.scenarios)?;
+    }
+    let updates_filters = config.profile == LoadProfile::Plan
+        || std::iter::once(config.scenario)
+            .chain(config.scenario_mix.iter().map(|&(id, _)| id))
+            .any(|id| config.scenarios.get(id).filter_updates);
+    if updates_filters && !config.protocol.wire().updates_filters() {
+        anyhow::bail!(
+            "--protocol {} can't change a live subscription, so its scenario can't update filters",
+            config.protocol.wire().describe(&config)
+        );
+    }
+    config.tls = Some(tls_connector(&config)?);
+    let config = Arc::new(config);
+
+    info!("════════════════════════════════════════════════════════════");
+    info!("              WebSocket Benchmark v2.0 (Lock-Free)");
+    info!("════════════════════════════════════════════════════════════");
+    info!("");
+    info!("Configuration:");
+    info!("  Run ID:         {}", config.run_id);
+    info!("  Host:           {}", config.server_origin("ws", "wss"));
+    info!("  Transport:      {:?}", config.transport);
+    match &config.proto {
+        Some(codec) => info!("  Payload Format: Protobuf ({})", codec.root),
+        None if config.payload_format != PayloadFormat::Json => {
+            info!("  Payload Format: {:?}", config.payload_format)
+        }
+        None => {}
+    }
+    if config.protocol != ProtocolKind::Pusher {
+        info!(
+            "  Protocol:       {}",
+            config.protocol.wire().describe(&config)
+        );
+    }
+    if !config.cookies.is_empty() || config.sticky_cookies {
+        info!(
+            "  Cookies:        {} configured{}",
+            config.cookies.len(),
+            if config.sticky_cookies {
+                ", sticky"
+            } else {
+                ""
+            }
+        );
+    }
+    match &config.backend_id {
+        Some(BackendId::Cookie(name)) => info!("  Backend ID:     cookie {}", name),
+        Some(BackendId::Header(name)) => info!("  Backend ID:     header {}", name),
+        None => {}
+    }
+    info!("  App Key:        {}", config.app_key);
+    info!("  Channel:        {}", config.channel);
+    let scenario = config.scenarios.get(config.scenario);
+    if config.scenario_mix.is_empty() {
+        info!(
+            "  Scenario:       {} {}",
+            config.scenario,
+            scenario.describe()
+        );
+    } else {
+        let mut lower = 0.0;
+        for (i, &(id, upto)) in config.scenario_mix.iter().enumerate() {
+            let share = upto.min(1.0) - lower;
+            lower = upto;
+            info!(
+                "  {:<16}{:.0}% {} {}",
+                if i == 0 { "Scenario Mix:" } else { "" },
+                share * 100.0,
+                id,
+                config.scenarios.get(id).describe()
+            );
+        }
+    }
+    if !config.raw_filters.is_empty() {
+        info!(
+            "  Filter JSON:    {} passthrough filter(s)",
+            config.raw_filters.len()
+        );
+    }
+    if !config.filter_map.is_empty() {
+        let passthrough = config
+            .filter_map
+            .values()
+            .filter(|f| matches!(f, FilterValue::Raw(_)))
+            .count();
+        info!(
+            "  Filter Map:     {} mapped client(s), {} passthrough",
+            config.filter_map.len(),
+            passthrough
+        );
+    }
+    let updating = scenario.filter_updates
+        || config
+            .scenario_mix
+            .iter()
+            .any(|&(id, _)| config.scenarios.get(id).filter_updates);
+    if updating || config.profile == LoadProfile::Plan {
+        let interval = scenario
+            .update_interval
+            .unwrap_or(config.filter_update_interval);
+        match config.think_time {
+            ThinkTime::Fixed => info!("  Think Time:     every {}ms", interval),
+            ThinkTime::Exponential => info!("  Think Time:     exponential, mean {}ms", interval),
+            dist => info!(
+                "  Think Time:     {:?}, mean {}ms, spread {}ms",
+                dist, interval, config.think_time_spread
+            ),
+        }
+    }
+    if let Some(s) = config.token_zipf {
+        info!("  Token Zipf:     s {}", s);
+    }
+    if let Some(path) = &config.token_weights {
+        info!("  Token Weights:  {:?}", path);
+    }
+    if let Some(path) = &config.exclude_token_file {
+        info!("  Token Exclude:  {:?}", path);
+    }
+    if config.token_overlap != TokenOverlap::Random {
+        info!("  Token Overlap:  {:?}", config.token_overlap);
+    }
+    if config.filter_split_size > 0 {
+        info!(
+            "  Filter Split:   {} values per subscription",
+            config.filter_split_size
+        );
+    }
+    info!("  Num Clients:    {}", config.num_clients);
+    info!("  Client Offset:  {}", config.client_id_offset);
+    if let Some(start_at) = config.start_at {
+        info!("  Start At:       unix {:.3}s", start_at as f64 / 1000.0);
+    }
+    match config.profile {
+        LoadProfile::Linear => info!("  Ramp Duration:  {}s", config.ramp_duration),
+        LoadProfile::Step => info!(
+            "  Step Profile:   +{} clients every {}s",
+            config.step_size, config.step_hold
+        ),
+        LoadProfile::Spike => info!("  Spike Profile:  all clients at once"),
+        LoadProfile::Wave => info!(
+            "  Wave Profile:   {}-{} clients, {}s period",
+            config.wave_floor, config.num_clients, config.wave_period
+        ),
+        LoadProfile::File => info!(
+            "  Schedule File:  {}",
+            config
+                .profile_file
+                .as_deref()
+                .map_or("(none)".into(), |p| p.display().to_string())
+        ),
+        LoadProfile::Poisson => info!(
+            "  Poisson Arrivals: {:.1} connections/s",
+            config.arrival_rate()
+        ),
+        LoadProfile::Plan => info!(
+            "  Test Plan:      {}",
+            config
+                .plan
+                .as_deref()
+                .map_or("(none)".into(), |p| p.display().to_string())
+        ),
+    }
+    info!("  Warmup Duration:{}s", config.warmup_duration);
+    if config.misbehaving_percent > 0.0 {
+        info!("  Misbehaving:    {}%", config.misbehaving_percent);
+    }
+    if config.canary {
+        info!(
+            "  Canary:         until interrupted, {}s latency window",
+            config.canary_window
+        );
+    } else if config.hold_duration == 0 && config.profile != LoadProfile::File {
+        info!("  Hold Duration:  until interrupted (soak)");
+    } else {
+        info!("  Hold Duration:  {}s", config.hold_duration);
+    }
+    if config.churn_rate > 0.0 {
+        info!("  Churn Rate:     {:.1} clients/s", config.churn_rate);
+    }
+    if config.publish_rate > 0.0 {
+        info!(
+            "  Publisher:      {:.1} events/s ({})",
+            config.publish_rate, config.publish_event
+        );
+    }
+    if config.client_ping > 0 {
+        info!(
+            "  Client Ping:    every {}s ({:?})",
+            config.client_ping, config.client_ping_kind
+        );
+    }
+    if config.max_connect_rate > 0.0 {
+        info!(
+            "  Connect Rate:   max {:.1} handshakes/s",
+            config.max_connect_rate
+        );
+        let ramp_secs = config.ramp_length().as_secs_f64();
+        let paced_secs = config.num_clients as f64 / config.max_connect_rate;
+        if paced_secs > ramp_secs {
+            warn!(
+                "--max-connect-rate stretches connecting {} clients to ~{:.0}s (ramp is {:.0}s)",
+                config.num_clients, paced_secs, ramp_secs
+            );
+        }
+    }
+    if config.subscribe_jitter > 0 {
+        info!("  Subscribe Jitter: 0-{}ms", config.subscribe_jitter);
+    }
+    if config.max_pending_connects > 0 {
+        info!(
+            "  Pending Connects: max {} handshakes in flight",
+            config.max_pending_connects
+        );
+    }
+    if config.max_runtime > 0 {
+        info!("  Max Runtime:    {}s", config.max_runtime);
+    }
+    for guard in &config.abort_if {
+        info!("  Abort If:       {}", guard.spec);
+    }
+    info!("");
+
+    // Load tokens
+    let loaded = config.token_url.is_some() || config.token_file.iter().any(|p| p.exists());
+    let mut tokens = if loaded {
+        load_token_list(&config).await?
+    } else {
+        let paths: Vec<String> = config
+            .token_file
+            .iter()
+            .map(|p| format!("{:?}", p))
+            .collect();
+        warn!(
+            "Token file not found: {}, generating fake tokens",
+            paths.join(", ")
+        );
+        TokenPool::generate_fake(config.fake_token_count.max(1), &config.fake_token_format)
+    };
+    if let Some(path) = &config.exclude_token_file {
+        tokens = tokens.with_exclusions(load_token_exclusions(
+            path,
+            config.token_format,
+            &config.token_column,
+        )?)?;
+    }
+    if let Some(s) = config.token_zipf {
+        tokens = tokens.with_zipf(s);
+    } else if let Some(path) = &config.token_weights {
+        tokens = tokens.with_weights(load_token_weights(path)?);
+    }
+    let pool_size = tokens.coverage().1;
+    if config.token_overlap == TokenOverlap::Disjoint && pool_size < config.num_clients {
+        warn!(
+            "--token-overlap disjoint: {} tokens for {} clients, some will share",
+            pool_size, config.num_clients
+        );
+    }
+    // Signal handlers, listeners and reloads live as long as this run
+    let mut background = tokio::task::JoinSet::new();
+    if loaded {
+        background.spawn(reload_tokens(tokens.clone(), config.clone()));
+    }
+
+    // Create live stats
+    let mut live_stats = LiveStats::new();
+    if config.max_connect_rate > 0.0 {
+        live_stats.connect_pacer = Some(Arc::new(ConnectPacer::new(config.max_connect_rate)));
+    }
+    if config.max_pending_connects > 0 {
+        live_stats.handshake_slots = Some(Arc::new(tokio::sync::Semaphore::new(
+            config.max_pending_connects,
+        )));
+    }
+    live_stats.scenario.send_replace(config.scenario);
+    if let Some(id) = config.debug_client {
+        live_stats.debug_client.store(id, Ordering::Relaxed);
+    }
+
+    if config.canary {
+        let gauges = Arc::new(CanaryGauges::new());
+        background.spawn(roll_canary_window(
+            Arc::clone(&gauges),
+            Duration::from_secs(config.canary_window.max(1)),
+        ));
+        live_stats.canary = Some(gauges);
+    }
+    background.spawn(log_interim_summaries(live_stats.clone()));
+    background.spawn(toggle_pause_on_signal(live_stats.clone()));
+
+    let _watchdog = (config.max_runtime > 0)
+        .then(|| spawn_watchdog(live_stats.clone(), Duration::from_secs(config.max_runtime)));
+
+    if let Some(addr) = config.metrics_addr.clone() {
+        let exporter_stats = live_stats.clone();
+        let run_id = config.run_id.clone();
+        background.spawn(async move {
+            if let Err(e) = serve_metrics(addr, exporter_stats, run_id).await {
+                error!("Metrics exporter stopped: {}", e);
+            }
+        });
+    }
+    if let Some(addr) = config.control_addr.clone() {
+        let control_stats = live_stats.clone();
+        let max_clients = config.max_clients();
+        background.spawn(async move {
+            if let Err(e) = serve_control(addr, control_stats, max_clients).await {
+                error!("Control endpoint stopped: {}", e);
+            }
+        });
+    }
+    let mut exit_status = live_stats.exit_status.subscribe();
+    let code = tokio::select! {
+        code = benchmark(config, tokens, live_stats) => code,
+        Ok(code) = exit_status.wait_for(Option::is_some) => Ok(code.unwrap_or_default()),
+    };
+    background.shutdown().await;
+    code
+}
+
+/// Connect, run every iteration and report; the part of [`run`] a second
+/// Ctrl-C or `--max-runtime` cuts short.
+async fn benchmark(config: Arc<Config>, tokens: TokenPool, live_stats: LiveStats) -> Result<i32> {
+    if let Err(e) = provision_grafana(&config).await {
+        warn!("Grafana provisioning failed: {}", e);
+    }
+
+    // Run the test and collect results
+    let transport = build_transport(&config)?;
+
+    if let Some(start_at) = config.start_at {
+        let now = unix_millis();
+        if start_at > now {
+            let wait = Duration::from_millis(start_at - now);
+            info!("Waiting {:.1}s for --start-at", wait.as_secs_f64());
+            sleep(wait).await;
+        } else {
+            warn!(
+                "--start-at is {}ms in the past, starting now",
+                now - start_at
+            );
+        }
+    }
+
+    let iterations = config.iterations.max(1);
+    let mut summaries = Vec::with_capacity(iterations);
+    for iteration in 1..=iterations {
+        if iterations > 1 {
+            info!("");
+            info!(
+                "════════════════ Iteration {}/{} ════════════════",
+                iteration, iterations
+            );
+            live_stats.reset_for_iteration(config.scenario);
+        }
+        let outcome = run_ramping_test(
+            Arc::clone(&config),
+            tokens.clone(),
+            live_stats.clone(),
+            Arc::clone(&transport),
+        )
+        .await?;
+
+        // Aggregate and print results (single-threaded, after all clients done)
+        summaries.push(aggregate_results(outcome, &config));
+        if live_stats.aborted() {
+            break;
+        }
+    }
+    let summary = if summaries.len() > 1 {
+        merge_iterations(summaries, &config)
+    } else {
+        summaries.remove(0)
+    };
+
+    // Each gives up after HTTP_TIMEOUT, so a dead endpoint can't hold the exit
+    if let Some(target) = &config.upload {
+        match upload_summary(target, &config, &summary).await {
+            Ok(location) => info!("Uploaded summary to {}", location),
+            Err(e) => error!("Upload to {} failed: {}", target, e),
+        }
+    }
+
+    if let Some(url) = &config.notify_url {
+        if let Err(e) = notify_webhook(&config, url, &summary).await {
+            error!("Completion webhook failed: {}", e);
+        }
+    }
+
+    Ok(if summary.aborted.is_some() {
+        ABORTED_EXIT_CODE
+    } else {
+        0
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_matches_nist_vectors() {
+        assert_eq!(
+            to_hex(&openssl::sha::sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            to_hex(&openssl::sha::sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&openssl::sha::sha256(&vec![b'a'; 1_000_000])),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc4231() {
+        assert_eq!(
+            to_hex(&hmac_sha256(&[0x0b; 20], b"Hi There").unwrap()),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&hmac_sha256(b"Jefe", b"what do ya want for nothing?").unwrap()),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // Keys longer than the block size are hashed first
+        assert_eq!(
+            to_hex(
+                &hmac_sha256(
+                    &[0xaa; 131],
+                    b"Test Using Larger Than Block-Size Key - Hash Key First"
+                )
+                .unwrap()
+            ),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn formats_amz_dates() {
+        assert_eq!(amz_date(0), "19700101T000000Z");
+        assert_eq!(amz_date(951_782_400), "20000229T000000Z");
+        assert_eq!(amz_date(1_369_353_600), "20130524T000000Z");
+        assert_eq!(amz_date(1_440_938_160), "20150830T123600Z");
+        assert_eq!(amz_date(4_107_542_399), "21000228T235959Z");
+    }
+
+    const AWS_EXAMPLE_SECRET: &str = "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY";
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    #[test]
+    fn sigv4_matches_aws_test_suite() {
+        // get-vanilla, post-vanilla and get-vanilla-query-order-key-case
+        // from the AWS SigV4 test suite
+        let cases = [
+            (
+                "GET",
+                "",
+                "5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31",
+            ),
+            (
+                "POST",
+                "",
+                "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b",
+            ),
+            (
+                "GET",
+                "Param1=value1&Param2=value2",
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500",
+            ),
+        ];
+        for (method, query, expected) in cases {
+            let canonical = format!(
+                "{}\n/\n{}\nhost:example.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+                 host;x-amz-date\n{}",
+                method, query, EMPTY_SHA256
+            );
+            let (scope, signature) = sigv4_sign(
+                AWS_EXAMPLE_SECRET,
+                "20150830T123600Z",
+                "us-east-1",
+                "service",
+                &canonical,
+            )
+            .unwrap();
+            assert_eq!(scope, "20150830/us-east-1/service/aws4_request");
+            assert_eq!(signature, expected, "{} /?{}", method, query);
+        }
+    }
+
+    #[test]
+    fn parses_fingerprints() {
+        let hex = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let expected = openssl::sha::sha256(b"abc");
+        assert_eq!(parse_fingerprint(hex).unwrap(), expected);
+        assert_eq!(parse_fingerprint(&hex.to_uppercase()).unwrap(), expected);
+        let colons = hex
+            .as_bytes()
+            .chunks(2)
+            .map(|pair| std::str::from_utf8(pair).unwrap())
+            .collect::<Vec<_>>()
+            .join(":");
+        assert_eq!(parse_fingerprint(&colons).unwrap(), expected);
+
+        assert!(parse_fingerprint(&hex[..62]).is_err());
+        assert!(parse_fingerprint(&format!("{}00", hex)).is_err());
+        assert!(parse_fingerprint(&format!("{}zz", &hex[..62])).is_err());
+        // Multibyte characters must be rejected, not sliced through
+        assert!(parse_fingerprint(&format!("é{}", &hex[..62])).is_err());
+        assert!(parse_fingerprint(&"é".repeat(32)).is_err());
+    }
+
+    type TestCert = (
+        openssl::x509::X509,
+        openssl::pkey::PKey<openssl::pkey::Private>,
+    );
+
+    /// A P-256 certificate for `cn` signed by `issuer`, or else a self-signed CA.
+    fn test_cert(cn: &str, issuer: Option<&TestCert>) -> TestCert {
+        use openssl::{asn1, bn, ec, nid, pkey, x509};
+
+        let group = ec::EcGroup::from_curve_name(nid::Nid::X9_62_PRIME256V1).unwrap();
+        let key = pkey::PKey::from_ec_key(ec::EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = x509::X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", cn).unwrap();
+        let name = name.build();
+
+        let mut builder = x509::X509::builder().unwrap();
+        builder.set_version(2).unwrap();
+        let serial = bn::BigNum::from_u32(rand::random()).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        builder.set_subject_name(&name).unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&asn1::Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&asn1::Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        match issuer {
+            Some((ca, ca_key)) => {
+                builder.set_issuer_name(ca.subject_name()).unwrap();
+                builder
+                    .sign(ca_key, openssl::hash::MessageDigest::sha256())
+                    .unwrap();
+            }
+            None => {
+                let ca = x509::extension::BasicConstraints::new()
+                    .critical()
+                    .ca()
+                    .build()
+                    .unwrap();
+                builder.append_extension(ca).unwrap();
+                builder.set_issuer_name(&name).unwrap();
+                builder
+                    .sign(&key, openssl::hash::MessageDigest::sha256())
+                    .unwrap();
+            }
+        }
+        (builder.build(), key)
+    }
+
+    /// Serve one TLS connection on localhost with `cert`, answering any
+    /// request with `200 ok`. With `client_ca`, a client certificate signed
+    /// by it is required. Returns the port.
+    fn serve_tls_once(cert: TestCert, client_ca: Option<openssl::x509::X509>) -> u16 {
+        use openssl::ssl::{SslAcceptor, SslMethod, SslVerifyMode};
+        use std::io::{Read, Write};
+
+        let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls()).unwrap();
+        acceptor.set_certificate(&cert.0).unwrap();
+        acceptor.set_private_key(&cert.1).unwrap();
+        if let Some(ca) = client_ca {
+            acceptor.cert_store_mut().add_cert(ca).unwrap();
+            acceptor.set_verify(SslVerifyMode::PEER | SslVerifyMode::FAIL_IF_NO_PEER_CERT);
+        }
+        let acceptor = acceptor.build();
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (tcp, _) = listener.accept().unwrap();
+            match acceptor.accept(tcp) {
+                Ok(mut tls) => {
+                    let mut request = [0u8; 4096];
+                    if tls.read(&mut request).is_ok() {
+                        let _ = tls.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok");
+                    }
+                    let _ = tls.shutdown();
+                }
+                // Hold the socket open until the client has read the alert
+                Err(openssl::ssl::HandshakeError::Failure(mut rejected)) => {
+                    let _ = std::io::copy(rejected.get_mut(), &mut std::io::sink());
+                }
+                Err(_) => {}
+            }
+        });
+        port
+    }
+
+    /// A config with only the given flags set, its TLS connector built.
+    fn test_config(args: &[&str]) -> Config {
+        let mut config =
+            Config::try_parse_from(std::iter::once("ws-benchmark").chain(args.iter().copied()))
+                .unwrap();
+        config.tls = Some(tls_connector(&config).unwrap());
+        config
+    }
+
+    #[test]
+    fn tls_follows_flag_then_port() {
+        for (args, origin) in [
+            (&[][..], "wss://stream-v2.projectscylla.com:443"),
+            (
+                &["--ws-port", "8080"][..],
+                "ws://stream-v2.projectscylla.com:8080",
+            ),
+            (
+                &["--ws-port", "8443", "--tls"][..],
+                "wss://stream-v2.projectscylla.com:8443",
+            ),
+            (
+                &["--tls", "false"][..],
+                "ws://stream-v2.projectscylla.com:443",
+            ),
+        ] {
+            assert_eq!(
+                test_config(args).server_origin("ws", "wss"),
+                origin,
+                "{:?}",
+                args
+            );
+        }
+        let config = test_config(&["--ws-host", "localhost", "--ws-port", "6001", "--tls"]);
+        assert_eq!(
+            config.server_origin("http", "https"),
+            "https://localhost:6001"
+        );
+    }
+
+    /// A server that accepts connections and never answers, and its URL.
+    async fn silent_server() -> (String, ScopedTask<()>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let server = ScopedTask::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+        (url, server)
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn http_requests_time_out() {
+        let (url, _server) = silent_server().await;
+        let started = tokio::time::Instant::now();
+        let err = http_request(test_config(&[]).tls(), "GET", &url, &[], &[])
+            .await
+            .err()
+            .expect("a silent server answered");
+        assert!(started.elapsed() >= HTTP_TIMEOUT);
+        assert!(err.to_string().contains("no response"), "{}", err);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn grafana_and_webhook_time_out() {
+        let (url, _server) = silent_server().await;
+        let config = test_config(&["--grafana-url", &url]);
+        let summary = aggregate_results(
+            TestOutcome {
+                results: Vec::new(),
+                measurement_duration: Duration::from_secs(1),
+                cpu_windows: Vec::new(),
+                clock_jumps: Vec::new(),
+                phases: Vec::new(),
+                chaos: None,
+                drain_samples: Vec::new(),
+                token_coverage: (0, 0),
+                stages: Vec::new(),
+                finished: Instant::now(),
+                measure_started_ms: 0,
+                peak_pending_connects: 0,
+                aborted: None,
+                publish: None,
+            },
+            &config,
+        );
+
+        let err = provision_grafana(&config)
+            .await
+            .expect_err("Grafana answered");
+        assert!(err.to_string().contains("no response"), "{}", err);
+        let err = notify_webhook(&config, &url, &summary)
+            .await
+            .expect_err("webhook answered");
+        assert!(err.to_string().contains("no response"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn https_requests_honour_insecure() {
+        let url = |port: u16| format!("https://localhost:{}/", port);
+
+        let port = serve_tls_once(test_cert("test server", None), None);
+        let strict = test_config(&[]);
+        assert!(http_request(strict.tls(), "GET", &url(port), &[], &[])
+            .await
+            .is_err());
+
+        let port = serve_tls_once(test_cert("test server", None), None);
+        let insecure = test_config(&["--insecure"]);
+        let response = http_request(insecure.tls(), "GET", &url(port), &[], &[])
+            .await
+            .unwrap();
+        assert_eq!((response.status, response.body.as_str()), (200, "ok"));
+    }
+
+    /// `--client-cert`/`--client-key` files for `cert`, unique to `name`.
+    fn write_client_cert(name: &str, cert: &TestCert) -> [String; 2] {
+        let dir = std::env::temp_dir();
+        let stem = format!("ws-benchmark-{}-{}", std::process::id(), name);
+        let cert_path = dir.join(format!("{}.pem", stem));
+        let key_path = dir.join(format!("{}.key", stem));
+        std::fs::write(&cert_path, cert.0.to_pem().unwrap()).unwrap();
+        std::fs::write(&key_path, cert.1.private_key_to_pem_pkcs8().unwrap()).unwrap();
+        [cert_path, key_path].map(|p| p.to_string_lossy().into_owned())
+    }
+
+    #[tokio::test]
+    async fn https_requests_present_client_cert() {
+        let ca = test_cert("test CA", None);
+        let [cert, key] = write_client_cert("http", &test_cert("client", Some(&ca)));
+        let url = |port: u16| format!("https://localhost:{}/", port);
+
+        let port = serve_tls_once(test_cert("test server", None), Some(ca.0.clone()));
+        let anonymous = test_config(&["--insecure"]);
+        let err = http_request(anonymous.tls(), "GET", &url(port), &[], &[])
+            .await
+            .err()
+            .expect("server requires a client certificate");
+        assert!(client_cert_rejected(err.as_ref()), "{}", err);
+
+        let port = serve_tls_once(test_cert("test server", None), Some(ca.0.clone()));
+        let mutual = test_config(&["--insecure", "--client-cert", &cert, "--client-key", &key]);
+        let response = http_request(mutual.tls(), "GET", &url(port), &[], &[]).await;
+        for path in [&cert, &key] {
+            let _ = std::fs::remove_file(path);
+        }
+        assert_eq!(response.unwrap().status, 200);
+    }
+
+    #[tokio::test]
+    async fn rejected_client_certs_are_classified() {
+        let ca = test_cert("test CA", None);
+        let rogue_ca = test_cert("rogue CA", None);
+        let [cert, key] = write_client_cert("rogue", &test_cert("client", Some(&rogue_ca)));
+
+        for mut args in [
+            vec!["--insecure"],
+            vec!["--insecure", "--client-cert", &cert, "--client-key", &key],
+        ] {
+            let port =
+                serve_tls_once(test_cert("test server", None), Some(ca.0.clone())).to_string();
+            args.extend(["--tls", "--ws-host", "localhost", "--ws-port", &port]);
+            let config = test_config(&args);
+            let transport = build_transport(&config).unwrap();
+            let url = format!("{}/app/key", config.server_origin("ws", "wss"));
+            let err =
+                match connect_timed(&*transport, &url, None, None, Duration::from_secs(5)).await {
+                    Ok(_) => panic!("{:?}: server accepted the connection", args),
+                    Err(e) => e,
+                };
+            assert_eq!(
+                ErrorCategory::from_connect(&err),
+                ErrorCategory::TlsClientCert,
+                "{:?}: {}",
+                args,
+                err
+            );
+        }
+        for path in [&cert, &key] {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+
+    fn vars(pairs: &[(&str, f64)]) -> HashMap<String, f64> {
+        pairs.iter().map(|&(k, v)| (k.to_string(), v)).collect()
+    }
+
+    #[test]
+    fn parses_abort_guards() {
+        let guard = AbortGuard::parse("error_rate > 5%").unwrap();
+        assert_eq!((guard.op, guard.threshold), (">", 0.05));
+        assert_eq!(guard.spec, "error_rate > 5%");
+
+        let guard =
+            AbortGuard::parse(" connection_errors / (subscribe_success + 1) >= 0.5 ").unwrap();
+        assert_eq!((guard.op, guard.threshold), (">=", 0.5));
+        assert_eq!(
+            guard.spec,
+            "connection_errors / (subscribe_success + 1) >= 0.5"
+        );
+
+        // Two-character operators win over their one-character prefixes
+        assert_eq!(AbortGuard::parse("a <= 1").unwrap().op, "<=");
+        assert_eq!(AbortGuard::parse("a < 1").unwrap().op, "<");
+
+        assert!(AbortGuard::parse("error_rate").is_err());
+        assert!(AbortGuard::parse("error_rate > lots").is_err());
+        assert!(AbortGuard::parse("error_rate > 5 %x").is_err());
+        assert!(AbortGuard::parse("(error_rate > 1").is_err());
+    }
+
+    #[test]
+    fn abort_guards_trip() {
+        let guard = AbortGuard::parse("error_rate > 5%").unwrap();
+        assert_eq!(guard.tripped(&vars(&[("error_rate", 0.05)])), None);
+        assert_eq!(guard.tripped(&vars(&[("error_rate", 0.5)])), Some(0.5));
+
+        let guard = AbortGuard::parse("active_connections < 10").unwrap();
+        assert_eq!(guard.tripped(&vars(&[("active_connections", 10.0)])), None);
+        assert_eq!(
+            guard.tripped(&vars(&[("active_connections", 9.0)])),
+            Some(9.0)
+        );
+
+        let guard = AbortGuard::parse("a - b <= 0").unwrap();
+        assert_eq!(guard.tripped(&vars(&[("a", 1.0), ("b", 1.0)])), Some(0.0));
+        // An expression that can't be evaluated yet never trips
+        assert_eq!(guard.tripped(&vars(&[("a", 1.0)])), None);
+    }
+
+    fn snapshot(
+        connection_errors: u64,
+        subscribe_success: u64,
+        subscribe_failed: u64,
+    ) -> LiveSnapshot {
+        LiveSnapshot {
+            unix_ms: 0,
+            elapsed_secs: 0.0,
+            active_connections: 0,
+            pending_connects: 0,
+            messages_received: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            subscribe_success,
+            subscribe_failed,
+            connection_errors,
+            post_close_messages: 0,
+            measuring: false,
+            cpu_suspect: false,
+            e2e_latency_ms: None,
+            canary: None,
+        }
+    }
+
+    #[test]
+    fn snapshots_carry_live_latency() {
+        let live_stats = LiveStats::new();
+        let started = Instant::now();
+        assert!(LiveSnapshot::capture(&live_stats, started)
+            .e2e_latency_ms
+            .is_none());
+        for ms in [5, 10, 20, 400] {
+            live_stats.e2e.record(ms);
+        }
+        let snapshot = LiveSnapshot::capture(&live_stats, started);
+        let json = sonic_rs::to_string(&snapshot).unwrap();
+        assert!(json.contains(r#""e2e_latency_ms":{"#), "{}", json);
+        // Guards can trip on live percentiles
+        let mut snapshot = snapshot;
+        snapshot.subscribe_success = 100;
+        let vars = guard_variables(&snapshot, 50).unwrap();
+        assert_eq!(vars["e2e_latency_ms_samples"], 4.0);
+        assert!((400.0..=412.0).contains(&vars["e2e_latency_ms_p99"]));
+    }
+
+    #[test]
+    fn error_rate_counts_subscribe_failures() {
+        let vars = guard_variables(&snapshot(10, 80, 10), 50).unwrap();
+        assert_eq!(vars["error_rate"], 0.2);
+        assert_eq!(vars["subscribe_failed"], 10.0);
+
+        let vars = guard_variables(&snapshot(0, 0, 60), 50).unwrap();
+        assert_eq!(vars["error_rate"], 1.0);
+
+        assert!(guard_variables(&snapshot(10, 20, 10), 50).is_none());
+        assert!(guard_variables(&snapshot(0, 0, 0), 0).is_none());
+    }
+
+    fn derive(spec: &str, vars: &[(&str, f64)]) -> Result<f64, String> {
+        let metric = DerivedMetric::parse(spec)?;
+        let vars = vars.iter().map(|(k, v)| (k.to_string(), *v)).collect();
+        metric.expr.eval(&vars)
+    }
+
+    #[test]
+    fn derived_metrics_follow_precedence() {
+        assert_eq!(derive("x = 1 + 2 * 3", &[]), Ok(7.0));
+        assert_eq!(derive("x = (1 + 2) * 3", &[]), Ok(9.0));
+        assert_eq!(derive("x = 8 - 4 - 2", &[]), Ok(2.0));
+        assert_eq!(derive("x = 16 / 4 / 2", &[]), Ok(2.0));
+        assert_eq!(derive("x = 2 * 3 + 4 * 5", &[]), Ok(26.0));
+        assert_eq!(derive("x = ((2))", &[]), Ok(2.0));
+        assert_eq!(derive("x = .5 + 1.25", &[]), Ok(1.75));
+        assert_eq!(derive("x = 1 / 0", &[]), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn derived_metrics_negate() {
+        assert_eq!(derive("x = -3", &[]), Ok(-3.0));
+        assert_eq!(derive("x = --3", &[]), Ok(3.0));
+        assert_eq!(derive("x = 2 - -3", &[]), Ok(5.0));
+        assert_eq!(derive("x = -2 * 3", &[]), Ok(-6.0));
+        assert_eq!(derive("x = -(1 + 2) * 2", &[]), Ok(-6.0));
+        assert_eq!(derive("x = -a", &[("a", 4.0)]), Ok(-4.0));
+    }
+
+    #[test]
+    fn derived_metrics_read_variables() {
+        let vars = [
+            ("messages_received", 3000.0),
+            ("e2e_latency_ms_p99", 12.5),
+            ("ratio", 0.5),
+        ];
+        assert_eq!(
+            derive("delivery = messages_received / 6000", &vars),
+            Ok(0.5)
+        );
+        // Dotted names address nested summary fields
+        assert_eq!(derive("x = e2e_latency_ms.p99 * 2", &vars), Ok(25.0));
+        // Earlier derived metrics, with or without their prefix
+        assert_eq!(derive("x = derived.ratio + 1", &vars), Ok(1.5));
+        assert_eq!(derive("derived.x = ratio", &vars).map(|_| ()), Ok(()));
+        assert_eq!(DerivedMetric::parse("derived.x = 1").unwrap().name, "x");
+        assert_eq!(
+            derive("x = missing + 1", &vars),
+            Err("unknown variable `missing`".to_string())
+        );
+    }
+
+    #[test]
+    fn malformed_derived_metrics_are_errors() {
+        for spec in [
+            "no equals sign",
+            " = 1",
+            "x = ",
+            "x = 1 +",
+            "x = (1 + 2",
+            "x = 1 + 2)",
+            "x = 2a",
+            "x = 1..2",
+            "x = 3 % 2",
+            "x = *2",
+        ] {
+            assert!(DerivedMetric::parse(spec).is_err(), "{:?}", spec);
+        }
+    }
+
+    #[test]
+    fn parses_rfc3339_timestamps() {
+        let cases = [
+            ("1970-01-01T00:00:00Z", 0),
+            ("2024-05-01T12:00:00Z", 1_714_564_800_000),
+            ("2024-05-01t12:00:00z", 1_714_564_800_000),
+            ("2024-05-01 12:00:00Z", 1_714_564_800_000),
+            // Offsets
+            ("2024-05-01T12:00:00+00:00", 1_714_564_800_000),
+            ("2024-05-01T14:00:00+02:00", 1_714_564_800_000),
+            ("2024-05-01T06:30:00-05:30", 1_714_564_800_000),
+            // Fractional seconds, truncated to milliseconds
+            ("2024-05-01T14:00:00.250+02:00", 1_714_564_800_250),
+            ("2024-05-01T12:00:00.5Z", 1_714_564_800_500),
+            ("2024-05-01T12:00:00.123456789Z", 1_714_564_800_123),
+            // Leap days, leap second
+            ("2000-02-29T23:59:59.999-05:30", 951_888_599_999),
+            ("2024-02-29T00:00:00Z", 1_709_164_800_000),
+            ("2024-12-31T23:59:60Z", 1_735_689_600_000),
+        ];
+        for (s, millis) in cases {
+            assert_eq!(parse_rfc3339(s), Ok(millis), "{}", s);
+        }
+    }
+
+    #[test]
+    fn malformed_rfc3339_is_an_error() {
+        for s in [
+            "",
+            "2024-05-01",
+            "2024-05-01T12:00:00",
+            "2024-05-01T12:00:00+0200",
+            "2024-05-01T12:00:00+02",
+            "2024-05-01T12:00:00.Z",
+            "2024-05-01T12:00:00Zjunk",
+            "2024/05/01T12:00:00Z",
+            "2024-13-01T12:00:00Z",
+            "2024-00-01T12:00:00Z",
+            "2024-05-32T12:00:00Z",
+            // Past the end of the month, and February outside leap years
+            "2024-04-31T12:00:00Z",
+            "2026-02-31T12:00:00Z",
+            "2023-02-29T12:00:00Z",
+            "2100-02-29T12:00:00Z",
+            "2024-05-01T24:00:00Z",
+            "2024-05-01T12:60:00Z",
+            "2024-05-01T12:00:61Z",
+            "2024-05-01T1a:00:00Z",
+            "2024-05-01T+1:00:00Z",
+            "2024-05-01T12:00:00+0a:00",
+            // Before the unix epoch
+            "1969-12-31T23:59:59Z",
+            "1970-01-01T00:00:00+00:01",
+        ] {
+            assert!(parse_rfc3339(s).is_err(), "{:?}", s);
+        }
+    }
+
+    #[test]
+    fn transcodes_msgpack_markers() {
+        let cases: &[(&[u8], &str)] = &[
+            // positive and negative fixint
+            (&[0x05], "5"),
+            (&[0x7f], "127"),
+            (&[0xff], "-1"),
+            (&[0xe0], "-32"),
+            // nil, bool
+            (&[0xc0], "null"),
+            (&[0xc2], "false"),
+            (&[0xc3], "true"),
+            // uint 8/16/32/64
+            (&[0xcc, 0xff], "255"),
+            (&[0xcd, 0x01, 0x00], "256"),
+            (&[0xce, 0xff, 0xff, 0xff, 0xff], "4294967295"),
+            (
+                &[0xcf, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                "18446744073709551615",
+            ),
+            // float 32/64, NaN
+            (&[0xca, 0x3f, 0xc0, 0x00, 0x00], "1.5"),
+            (
+                &[0xcb, 0xc0, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                "-2.5",
+            ),
+            (
+                &[0xcb, 0x7f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+                "null",
+            ),
+            // fixstr, str 8/16/32, with escapes
+            (b"\xa2hi", r#""hi""#),
+            (&[0xd9, 0x03, b'a', b'"', b'\n'], r#""a\"\u000a""#),
+            (&[0xda, 0x00, 0x01, b'x'], r#""x""#),
+            (&[0xdb, 0x00, 0x00, 0x00, 0x01, b'y'], r#""y""#),
+            // bin 8/16/32 as lossy strings
+            (&[0xc4, 0x02, b'o', 0xff], "\"o\u{fffd}\""),
+            (&[0xc5, 0x00, 0x00], r#""""#),
+            (&[0xc6, 0x00, 0x00, 0x00, 0x01, b'z'], r#""z""#),
+            // fixext 1..16 and ext 8/16/32 become null
+            (&[0xd4, 0x01, 0xaa], "null"),
+            (
+                &[0xd8, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+                "null",
+            ),
+            (&[0xc7, 0x02, 0x01, 0xaa, 0xbb], "null"),
+            (&[0xc8, 0x00, 0x01, 0x01, 0xaa], "null"),
+            (&[0xc9, 0x00, 0x00, 0x00, 0x00, 0x01], "null"),
+            // fixarray, array 16/32
+            (&[0x92, 0x01, 0xc0], "[1,null]"),
+            (&[0xdc, 0x00, 0x01, 0xc3], "[true]"),
+            (&[0xdd, 0x00, 0x00, 0x00, 0x00], "[]"),
+            // fixmap, map 16/32
+            (&[0x81, 0xa1, b'a', 0x01], r#"{"a":1}"#),
+            (&[0xde, 0x00, 0x01, 0xa1, b'b', 0x90], r#"{"b":[]}"#),
+            (&[0xdf, 0x00, 0x00, 0x00, 0x00], "{}"),
+        ];
+        for (bytes, json) in cases {
+            assert_eq!(
+                msgpack_to_json(bytes).as_deref(),
+                Ok(*json),
+                "{:02x?}",
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn msgpack_signed_ints_sign_extend() {
+        let cases: &[(&[u8], &str)] = &[
+            (&[0xd0, 0x80], "-128"),
+            (&[0xd0, 0x7f], "127"),
+            (&[0xd1, 0xff, 0x7f], "-129"),
+            (&[0xd1, 0x80, 0x00], "-32768"),
+            (&[0xd2, 0xff, 0xff, 0xff, 0xfe], "-2"),
+            (&[0xd2, 0x7f, 0xff, 0xff, 0xff], "2147483647"),
+            (&[0xd3, 0x80, 0, 0, 0, 0, 0, 0, 0], "-9223372036854775808"),
+            (
+                &[0xd3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+                "-1",
+            ),
+        ];
+        for (bytes, json) in cases {
+            assert_eq!(
+                msgpack_to_json(bytes).as_deref(),
+                Ok(*json),
+                "{:02x?}",
+                bytes
+            );
+        }
+    }
+
+    #[test]
+    fn msgpack_map_keys_become_strings() {
+        // {1: "a", nil: true, [1]: 0, -2: 1.5}
+        let bytes = [
+            0x84, 0x01, 0xa1, b'a', 0xc0, 0xc3, 0x91, 0x01, 0x00, 0xfe, 0xca, 0x3f, 0xc0, 0x00,
+            0x00,
+        ];
+        assert_eq!(
+            msgpack_to_json(&bytes).unwrap(),
+            r#"{"1":"a","null":true,"[1]":0,"-2":1.5}"#
+        );
+    }
+
+    #[test]
+    fn msgpack_round_trips_json() {
+        let json = r#"{"event":"e","data":{"n":-300,"big":70000,"f":0.5,"l":[true,null,"x"]}}"#;
+        let mut bytes = Vec::new();
+        json_to_msgpack(&sonic_rs::from_str(json).unwrap(), &mut bytes);
+        assert_eq!(msgpack_to_json(&bytes).unwrap(), json);
+    }
+
+    #[test]
+    fn malformed_msgpack_is_an_error() {
+        assert_eq!(msgpack_to_json(&[]).unwrap_err(), "truncated");
+        assert_eq!(msgpack_to_json(&[0xc1]).unwrap_err(), "invalid marker 0xc1");
+        assert_eq!(
+            msgpack_to_json(&[0x01, 0x02]).unwrap_err(),
+            "1 trailing bytes"
+        );
+        // Lengths past the end of the frame
+        assert!(msgpack_to_json(&[0xdb, 0xff, 0xff, 0xff, 0xff, b'a']).is_err());
+        assert!(msgpack_to_json(&[0xdd, 0xff, 0xff, 0xff, 0xff]).is_err());
+        assert!(msgpack_to_json(&[0xc9, 0xff, 0xff, 0xff, 0xff, 0x01]).is_err());
+        assert!(msgpack_to_json(&[0x81, 0xa1, b'k']).is_err());
+
+        // Every prefix of a valid frame is an error, never a panic
+        let bytes = [
+            0x82, 0xa1, b'a', 0xd1, 0xff, 0x7f, 0xa1, b'b', 0x92, 0xcb, 0x3f, 0xf0, 0, 0, 0, 0, 0,
+            0, 0xd4, 0x01, 0x00,
+        ];
+        assert_eq!(
+            msgpack_to_json(&bytes).unwrap(),
+            r#"{"a":-129,"b":[1,null]}"#
+        );
+        for n in 0..bytes.len() {
+            assert!(msgpack_to_json(&bytes[..n]).is_err(), "prefix {}", n);
+        }
+    }
+
+    #[test]
+    fn deep_msgpack_nesting_is_an_error() {
+        let nested = |depth: usize| {
+            let mut bytes = vec![0x91; depth];
+            bytes.push(0xc0);
+            bytes
+        };
+        assert!(msgpack_to_json(&nested(64)).is_ok());
+        assert_eq!(
+            msgpack_to_json(&nested(65)).unwrap_err(),
+            "nested too deeply"
+        );
+        // Map keys count towards the depth too
+        let mut bytes = vec![0x81; 65];
+        bytes.push(0xc0);
+        assert_eq!(msgpack_to_json(&bytes).unwrap_err(), "nested too deeply");
+    }
+
+    #[test]
+    fn counts_websocket_framing() {
+        assert_eq!(ws_frame_len(0, false), 2);
+        assert_eq!(ws_frame_len(125, false), 127);
+        assert_eq!(ws_frame_len(125, true), 131);
+        assert_eq!(ws_frame_len(126, false), 130);
+        assert_eq!(ws_frame_len(65_535, true), 65_543);
+        assert_eq!(ws_frame_len(65_536, false), 65_546);
+        assert_eq!(ws_frame_len(65_536, true), 65_550);
+    }
+
+    #[test]
+    fn live_histogram_buckets_cover_every_latency() {
+        let mut last = 0;
+        for ms in 0..=LIVE_MAX_MS {
+            let bucket = LiveHistogram::bucket(ms);
+            assert!(
+                bucket == last || bucket == last + 1,
+                "{}ms skips a bucket",
+                ms
+            );
+            last = bucket;
+            let top = LiveHistogram::bucket_max(bucket);
+            assert!(
+                top >= ms && top - ms <= ms / LIVE_SUB_BUCKETS,
+                "{}ms -> {}",
+                ms,
+                top
+            );
+            assert_eq!(LiveHistogram::bucket(top), bucket);
+        }
+        assert_eq!(LiveHistogram::bucket(u64::MAX), last);
+        assert_eq!(LiveHistogram::new().buckets.len(), last + 1);
+    }
+
+    #[test]
+    fn live_histogram_percentiles() {
+        let live = LiveHistogram::new();
+        assert!(LatencySummary::from_histogram(&live.histogram()).is_none());
+        for ms in 1..=1000 {
+            live.record(ms);
+        }
+        let l = LatencySummary::from_histogram(&live.histogram()).unwrap();
+        assert_eq!(l.samples, 1000);
+        // Within the bucket resolution, and never below the true value
+        for (got, want) in [(l.p50, 500), (l.p95, 950), (l.p99, 990), (l.max, 1000)] {
+            assert!(
+                got >= want && got <= want + want / 16,
+                "{} vs {}",
+                got,
+                want
+            );
+        }
+    }
+
+    fn proto_len_field(out: &mut Vec<u8>, number: u64, bytes: &[u8]) {
+        proto_put_varint(out, number << 3 | WIRE_LEN);
+        proto_put_varint(out, bytes.len() as u64);
+        out.extend_from_slice(bytes);
+    }
+
+    fn proto_varint_field(out: &mut Vec<u8>, number: u64, v: u64) {
+        proto_put_varint(out, number << 3 | WIRE_VARINT);
+        proto_put_varint(out, v);
+    }
+
+    /// A `FieldDescriptorProto`.
+    fn descriptor_field(
+        name: &str,
+        number: u64,
+        kind: u64,
+        repeated: bool,
+        type_name: &str,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        proto_len_field(&mut out, 1, name.as_bytes());
+        proto_varint_field(&mut out, 3, number);
+        proto_varint_field(&mut out, 4, if repeated { 3 } else { 1 });
+        proto_varint_field(&mut out, 5, kind);
+        if !type_name.is_empty() {
+            proto_len_field(&mut out, 6, type_name.as_bytes());
+        }
+        out
+    }
+
+    /// A `DescriptorProto` with `fields` and `nested` types.
+    fn descriptor_message(
+        name: &str,
+        fields: &[Vec<u8>],
+        nested: &[Vec<u8>],
+        map_entry: bool,
+    ) -> Vec<u8> {
+        let mut out = Vec::new();
+        proto_len_field(&mut out, 1, name.as_bytes());
+        for field in fields {
+            proto_len_field(&mut out, 2, field);
+        }
+        for message in nested {
+            proto_len_field(&mut out, 3, message);
+        }
+        if map_entry {
+            let mut options = Vec::new();
+            proto_varint_field(&mut options, 7, 1);
+            proto_len_field(&mut out, 7, &options);
+        }
+        out
+    }
+
+    /// `FileDescriptorSet` for package `test.v1`:
+    ///
+    /// ```proto
+    /// message Envelope {
+    ///   string event = 1;  sint64 delta = 2;   repeated int32 ids = 3;
+    ///   Inner inner = 4;   map<string, sint32> tags = 5;
+    ///   double ratio = 6;  fixed32 count = 7;  bool ok = 8;  sfixed32 offset = 9;
+    ///   message Inner { repeated string names = 1; Envelope parent = 2; }
+    /// }
+    /// ```
+    fn test_descriptor_set() -> Vec<u8> {
+        let entry = descriptor_message(
+            "TagsEntry",
+            &[
+                descriptor_field("key", 1, PROTO_STRING, false, ""),
+                descriptor_field("value", 2, PROTO_SINT32, false, ""),
+            ],
+            &[],
+            true,
+        );
+        let inner = descriptor_message(
+            "Inner",
+            &[
+                descriptor_field("names", 1, PROTO_STRING, true, ""),
+                descriptor_field("parent", 2, PROTO_MESSAGE, false, ".test.v1.Envelope"),
+            ],
+            &[],
+            false,
+        );
+        let envelope = descriptor_message(
+            "Envelope",
+            &[
+                descriptor_field("event", 1, PROTO_STRING, false, ""),
+                descriptor_field("delta", 2, PROTO_SINT64, false, ""),
+                descriptor_field("ids", 3, 5, true, ""),
+                descriptor_field("inner", 4, PROTO_MESSAGE, false, ".test.v1.Envelope.Inner"),
+                descriptor_field(
+                    "tags",
+                    5,
+                    PROTO_MESSAGE,
+                    true,
+                    ".test.v1.Envelope.TagsEntry",
+                ),
+                descriptor_field("ratio", 6, PROTO_DOUBLE, false, ""),
+                descriptor_field("count", 7, PROTO_FIXED32, false, ""),
+                descriptor_field("ok", 8, PROTO_BOOL, false, ""),
+                descriptor_field("offset", 9, PROTO_SFIXED32, false, ""),
+            ],
+            &[inner, entry],
+            false,
+        );
+        let mut file = Vec::new();
+        proto_len_field(&mut file, 1, b"test.proto");
+        proto_len_field(&mut file, 2, b"test.v1");
+        proto_len_field(&mut file, 4, &envelope);
+        let mut set = Vec::new();
+        proto_len_field(&mut set, 1, &file);
+        set
+    }
+
+    fn test_codec() -> ProtoCodec {
+        let mut messages = HashMap::new();
+        proto_descriptor_set(&test_descriptor_set(), &mut messages).unwrap();
+        ProtoCodec {
+            messages,
+            root: "test.v1.Envelope".to_string(),
+        }
+    }
+
+    fn proto_round_trip(codec: &ProtoCodec, json: &str) -> sonic_rs::Value {
+        let value: sonic_rs::Value = sonic_rs::from_str(json).unwrap();
+        let decoded = codec.decode(&codec.encode(&value)).unwrap();
+        sonic_rs::from_str(&decoded).unwrap()
+    }
+
+    #[test]
+    fn parses_proto_descriptors() {
+        let codec = test_codec();
+        let mut names: Vec<&str> = codec.messages.keys().map(String::as_str).collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "test.v1.Envelope",
+                "test.v1.Envelope.Inner",
+                "test.v1.Envelope.TagsEntry"
+            ]
+        );
+        let envelope = &codec.messages["test.v1.Envelope"];
+        assert_eq!(envelope.fields.len(), 9);
+        assert!(!envelope.map_entry);
+        assert!(codec.messages["test.v1.Envelope.TagsEntry"].map_entry);
+        let tags = &envelope.fields[4];
+        assert_eq!(
+            (tags.name.as_str(), tags.number, tags.repeated),
+            ("tags", 5, true)
+        );
+        assert_eq!(tags.type_name, "test.v1.Envelope.TagsEntry");
+    }
+
+    #[test]
+    fn proto_scalars_round_trip() {
+        let codec = test_codec();
+        let v = proto_round_trip(
+            &codec,
+            r#"{"event":"tick","ratio":0.25,"count":4000000000,"ok":true,"offset":-7}"#,
+        );
+        assert_eq!(v["event"].as_str(), Some("tick"));
+        assert_eq!(v["ratio"].as_f64(), Some(0.25));
+        assert_eq!(v["count"].as_u64(), Some(4_000_000_000));
+        assert_eq!(v["ok"].as_bool(), Some(true));
+        assert_eq!(v["offset"].as_i64(), Some(-7));
+    }
+
+    #[test]
+    fn proto_zigzag_round_trips() {
+        let codec = test_codec();
+        for delta in [0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+            let v = proto_round_trip(&codec, &format!(r#"{{"delta":{}}}"#, delta));
+            assert_eq!(v["delta"].as_i64(), Some(delta));
+        }
+        // -1 zigzags to 1, 1 to 2
+        let mut bytes = Vec::new();
+        proto_varint_field(&mut bytes, 2, 1);
+        assert_eq!(codec.decode(&bytes).unwrap(), r#"{"delta":-1}"#);
+    }
+
+    #[test]
+    fn proto_repeated_and_packed_fields() {
+        let codec = test_codec();
+        let v = proto_round_trip(&codec, r#"{"ids":[1,150,-1]}"#);
+        assert_eq!(
+            v["ids"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|i| i.as_i64())
+                .collect::<Vec<_>>(),
+            [Some(1), Some(150), Some(-1)]
+        );
+
+        // The same values packed into one length-delimited run
+        let mut packed = Vec::new();
+        for id in [1i64, 150, -1] {
+            proto_put_varint(&mut packed, id as u64);
+        }
+        let mut bytes = Vec::new();
+        proto_len_field(&mut bytes, 3, &packed);
+        assert_eq!(codec.decode(&bytes).unwrap(), r#"{"ids":[1,150,-1]}"#);
+
+        // Packed and unpacked runs of one field concatenate
+        proto_varint_field(&mut bytes, 3, 7);
+        assert_eq!(codec.decode(&bytes).unwrap(), r#"{"ids":[1,150,-1,7]}"#);
+
+        // A singular field keeps its last value
+        let mut bytes = Vec::new();
+        proto_len_field(&mut bytes, 1, b"first");
+        proto_len_field(&mut bytes, 1, b"second");
+        assert_eq!(codec.decode(&bytes).unwrap(), r#"{"event":"second"}"#);
+    }
+
+    #[test]
+    fn proto_nested_and_map_fields() {
+        let codec = test_codec();
+        let v = proto_round_trip(
+            &codec,
+            r#"{"inner":{"names":["a","b"],"parent":{"event":"up"}},"tags":{"x":1,"y":-2}}"#,
+        );
+        assert_eq!(v["inner"]["names"][1].as_str(), Some("b"));
+        assert_eq!(v["inner"]["parent"]["event"].as_str(), Some("up"));
+        assert_eq!(v["tags"]["x"].as_i64(), Some(1));
+        assert_eq!(v["tags"]["y"].as_i64(), Some(-2));
+        assert_eq!(v["tags"].as_object().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn proto_skips_unknown_fields() {
+        let codec = test_codec();
+        let mut bytes = Vec::new();
+        proto_varint_field(&mut bytes, 99, 5);
+        proto_len_field(&mut bytes, 98, b"ignored");
+        proto_put_varint(&mut bytes, 97 << 3 | WIRE_FIXED32);
+        bytes.extend([0; 4]);
+        proto_len_field(&mut bytes, 1, b"kept");
+        assert_eq!(codec.decode(&bytes).unwrap(), r#"{"event":"kept"}"#);
+    }
+
+    #[test]
+    fn malformed_protobuf_is_an_error() {
+        let codec = test_codec();
+        // Truncated varint, length past the end, varint over 10 bytes
+        assert!(codec.decode(&[0x80]).is_err());
+        assert!(codec.decode(&[0x0a, 0x05, b'a']).is_err());
+        assert!(codec
+            .decode(&[0x10, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01])
+            .is_err());
+        // Wire types 3 and 4 (groups) and 6/7 (undefined)
+        for wire in [3u8, 4, 6, 7] {
+            assert!(
+                codec.decode(&[99 << 3 | wire]).is_err(),
+                "wire type {}",
+                wire
+            );
+        }
+        // A fixed32 field cut short
+        assert!(codec.decode(&[7 << 3 | WIRE_FIXED32 as u8, 1, 2]).is_err());
+        // An empty packed run for a singular field
+        assert_eq!(
+            codec.decode(&[2 << 3 | WIRE_LEN as u8, 0]).unwrap(),
+            r#"{"delta":null}"#
+        );
+
+        // Every prefix of a valid message decodes or fails, never panics
+        let value: sonic_rs::Value = sonic_rs::from_str(
+            r#"{"event":"tick","delta":-5,"ids":[1,2],"inner":{"names":["a"]},"tags":{"k":3},"ratio":1.5}"#,
+        )
+        .unwrap();
+        let bytes = codec.encode(&value);
+        for n in 0..bytes.len() {
+            let _ = codec.decode(&bytes[..n]);
+        }
+    }
+
+    #[test]
+    fn deep_protobuf_nesting_is_an_error() {
+        let codec = test_codec();
+        // Envelope.inner.parent.inner.parent... 40 levels of Inner
+        let mut bytes = Vec::new();
+        for _ in 0..40 {
+            let mut inner = Vec::new();
+            proto_len_field(&mut inner, 2, &bytes);
+            bytes.clear();
+            proto_len_field(&mut bytes, 4, &inner);
+        }
+        assert_eq!(codec.decode(&bytes).unwrap_err(), "nested too deeply");
+    }
+
+    #[test]
+    fn malformed_descriptors_are_errors() {
+        let set = test_descriptor_set();
+        for n in 0..set.len() {
+            let _ = proto_descriptor_set(&set[..n], &mut HashMap::new());
+        }
+        assert!(proto_descriptor_set(&[0x0a, 0x10, 0x0a], &mut HashMap::new()).is_err());
+        assert!(proto_descriptor_set(&[0x0b], &mut HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn dechunk_joins_chunks() {
+        assert_eq!(
+            dechunk(b"5\r\nhello\r\n6;ext=1\r\n world\r\n0\r\n\r\n"),
+            b"hello world"
+        );
+        assert_eq!(dechunk(b"0\r\n\r\n"), b"");
+        assert_eq!(dechunk(b""), b"");
+    }
+
+    #[test]
+    fn dechunk_splits_multibyte_characters() {
+        // "héllo" with the chunk boundary between the two bytes of 'é'
+        let body = dechunk(b"2\r\nh\xc3\r\n4\r\n\xa9llo\r\n0\r\n\r\n");
+        assert_eq!(String::from_utf8(body).unwrap(), "héllo");
+    }
+
+    #[test]
+    fn dechunk_stops_at_truncated_chunk() {
+        assert_eq!(dechunk(b"5\r\nhello\r\nff\r\nshort"), b"hello");
+        assert_eq!(dechunk(b"zz\r\nhello\r\n"), b"");
+    }
+
+    #[test]
+    fn parses_http_responses() {
+        let plain =
+            parse_http_response(b"HTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok").unwrap();
+        assert_eq!(plain.status, 201);
+        assert_eq!(plain.body, "ok");
+
+        let chunked = parse_http_response(
+            b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\n\xe2\x9c\r\n1\r\n\x93\r\n0\r\n\r\n",
+        )
+        .unwrap();
+        assert_eq!(chunked.status, 200);
+        assert_eq!(chunked.body, "\u{2713}");
+
+        assert!(parse_http_response(b"HTTP/1.1 200 OK\r\n").is_err());
+        assert!(parse_http_response(b"garbage\r\n\r\n").is_err());
+    }
+
+    #[test]
+    fn parses_value_counts() {
+        use ValueCount::*;
+        for (spec, expected) in [
+            ("5", Fixed(5)),
+            (" 7 ", Fixed(7)),
+            ("uniform:2:10", Range { min: 2, max: 10 }),
+            ("uniform: 3 : 3", Range { min: 3, max: 3 }),
+            ("zipf:100:1.2", Zipf { max: 100, s: 1.2 }),
+            (
+                "lognormal:10:0.5",
+                LogNormal {
+                    median: 10.0,
+                    sigma: 0.5,
+                    max: None,
+                },
+            ),
+            (
+                "lognormal:2.5:1:50",
+                LogNormal {
+                    median: 2.5,
+                    sigma: 1.0,
+                    max: Some(50),
+                },
+            ),
+        ] {
+            assert_eq!(ValueCount::parse(spec), Ok(expected), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn malformed_value_counts_are_errors() {
+        for spec in [
+            "",
+            "abc",
+            "-3",
+            "0",
+            "2.5",
+            "1e3",
+            "uniform:1",
+            "uniform:1.5:3",
+            "uniform:0:3",
+            "uniform:5:2",
+            "zipf:10.5:1",
+            "zipf:0:1",
+            "zipf:10:0",
+            "lognormal:0.5:1",
+            "lognormal:10:-1",
+            "lognormal:10:1:2.5",
+            "lognormal:10:1:0",
+            "normal:1:2",
+        ] {
+            assert!(ValueCount::parse(spec).is_err(), "{:?} parsed", spec);
+        }
+    }
+
+    #[test]
+    fn value_counts_stay_within_bounds() {
+        for (spec, bounds) in [
+            ("4", (4, 4)),
+            ("uniform:2:10", (2, 10)),
+            ("zipf:100:1.2", (1, 100)),
+            ("lognormal:10:0.5", (1, usize::MAX)),
+            // A median far above the cap: most draws clamp to it
+            ("lognormal:1000:3:50", (1, 50)),
+        ] {
+            let count = ValueCount::parse(spec).unwrap();
+            assert_eq!(count.bounds(), bounds, "{}", spec);
+            for _ in 0..1000 {
+                let n = count.sample();
+                assert!((bounds.0..=bounds.1).contains(&n), "{}: drew {}", spec, n);
+            }
+        }
+    }
+
+    #[test]
+    fn parses_scenario_mixes() {
+        let scenarios = ScenarioSet::default();
+        for (spec, expected) in [
+            ("1:3,2:1", vec![(1, 0.75), (2, f64::INFINITY)]),
+            (" 0:1 , 13:1 ,", vec![(0, 0.5), (13, f64::INFINITY)]),
+            ("4:2.5", vec![(4, f64::INFINITY)]),
+        ] {
+            assert_eq!(parse_mix(spec, &scenarios).unwrap(), expected, "{}", spec);
+        }
+        for spec in ["", ",", "1", "1:0", "1:-1", "1:abc", "1:inf", "x:1", "99:1"] {
+            assert!(parse_mix(spec, &scenarios).is_err(), "{:?} parsed", spec);
+        }
+    }
+
+    #[test]
+    fn renders_templates() {
+        let vars = TemplateVars {
+            client_id: 37,
+            phase: 2,
+            scenario: 5,
+        };
+        for (source, expected) in [
+            ("plain", "plain"),
+            ("tokens_shard_{{ client_id % 16 }}", "tokens_shard_5"),
+            ("p{{phase}}-s{{scenario}}", "p2-s5"),
+            ("{{1+2*3}}", "7"),
+            ("{{ (1 + 2) * 3 }}", "9"),
+            ("{{ 10 - 4 - 3 }}", "3"),
+            ("{{ client_id / 10 }}", "3"),
+            ("{{ 7 / 0 }}{{ 7 % 0 }}", "00"),
+            ("{{ rand(4, 4) }}", "4"),
+            ("a}}b", "a}}b"),
+        ] {
+            let template = Template::parse(source).unwrap();
+            assert_eq!(template.render(&vars), expected, "{}", source);
+        }
+
+        let ranged = Template::parse("{{ rand(client_id, 30) }}").unwrap();
+        for _ in 0..100 {
+            let n: usize = ranged.render(&vars).parse().unwrap();
+            assert!((30..=37).contains(&n), "drew {}", n);
+        }
+    }
+
+    #[test]
+    fn malformed_templates_are_errors() {
+        for source in [
+            "{{",
+            "x {{ client_id",
+            "{{ }}",
+            "{{ foo }}",
+            "{{ 1 + }}",
+            "{{ -1 }}",
+            "{{ (1 }}",
+            "{{ 1 2 }}",
+            "{{ rand 1 }}",
+            "{{ rand(1) }}",
+            "{{ rand(1, 2 }}",
+        ] {
+            assert!(Template::parse(source).is_err(), "{:?} parsed", source);
+        }
+    }
+
+    #[test]
+    fn splits_large_in_filters() {
+        let vals = |names: &[&str]| names.iter().map(|&n| Arc::from(n)).collect::<Vec<_>>();
+        let multiple = |cmp, names: &[&str]| FilterValue::Multiple {
+            key: "token_address",
+            cmp,
+            vals: vals(names),
+        };
+        let json = |parts: Vec<(String, FilterValue)>| -> Vec<(String, String)> {
+            parts
+                .into_iter()
+                .map(|(channel, filter)| (channel, sonic_rs::to_string(&filter).unwrap()))
+                .collect()
+        };
+        let expect = |parts: &[(&str, &[&str])]| -> Vec<(String, String)> {
+            parts
+                .iter()
+                .map(|&(channel, names)| {
+                    let filter = multiple("in", names);
+                    (channel.to_string(), sonic_rs::to_string(&filter).unwrap())
+                })
+                .collect()
+        };
+
+        let five = ["a", "b", "c", "d", "e"];
+        assert_eq!(
+            json(split_filter("ch", multiple("in", &five), 2)),
+            expect(&[
+                ("ch-0", &["a", "b"]),
+                ("ch-1", &["c", "d"]),
+                ("ch-2", &["e"])
+            ])
+        );
+        assert_eq!(
+            json(split_filter("ch", multiple("in", &five), 5)),
+            expect(&[("ch", &five)])
+        );
+        // Off, or not an `in` list: one part on the configured channel
+        for (filter, max_vals) in [
+            (multiple("in", &five), 0),
+            (multiple("nin", &five), 2),
+            (
+                FilterValue::Single {
+                    key: "token_address",
+                    cmp: "eq",
+                    val: Arc::from("a"),
+                },
+                1,
+            ),
+            (FilterValue::All, 1),
+        ] {
+            let expected = vec![("ch".to_string(), sonic_rs::to_string(&filter).unwrap())];
+            assert_eq!(json(split_filter("ch", filter, max_vals)), expected);
+        }
+    }
+
+    #[test]
+    fn delta_updates_swap_a_fraction_of_tokens() {
+        let tokens = TokenPool::new((0..1000).map(|i| format!("t{}", i)).collect());
+        let scenarios = ScenarioSet::default();
+        let token_vals = |filter: &FilterValue| match filter {
+            FilterValue::Multiple {
+                cmp: "in", vals, ..
+            } => vals.clone(),
+            FilterValue::Single { val, .. } => vec![val.clone()],
+            other => panic!("not a token filter: {:?}", other),
+        };
+        let changed = |old: &[Arc<str>], new: &[Arc<str>]| {
+            assert_eq!(old.len(), new.len());
+            old.iter().zip(new).filter(|(a, b)| a != b).count()
+        };
+
+        // Scenario 12: 10% of 100 tokens, each replaced by one not yet held
+        let delta = scenarios.get(12);
+        assert_eq!(delta.delta, Some(0.1));
+        let old = delta.filter.build(&tokens);
+        let new = delta.filter.swap(&old, 0.1, &tokens);
+        let (old_vals, new_vals) = (token_vals(&old), token_vals(&new));
+        assert_eq!(changed(&old_vals, &new_vals), 10);
+        for (i, val) in new_vals.iter().enumerate() {
+            assert!(!new_vals[..i].contains(val), "{} held twice", val);
+        }
+
+        // At least one value per leaf, however small the fraction
+        let single = scenarios.get(1).filter.build(&tokens);
+        let swapped = scenarios.get(1).filter.swap(&single, 0.01, &tokens);
+        assert_eq!(changed(&token_vals(&single), &token_vals(&swapped)), 1);
+
+        // Compound: the token leaf swaps, the fixed chain_id choice stays
+        let compound = &scenarios.get(6).filter;
+        let old = compound.build(&tokens);
+        let new = compound.swap(&old, 0.5, &tokens);
+        let (
+            FilterValue::Compound { filters: old, .. },
+            FilterValue::Compound { filters: new, .. },
+        ) = (&old, &new)
+        else {
+            panic!("scenario 6 is not compound");
+        };
+        assert_eq!(changed(&token_vals(&old[0]), &token_vals(&new[0])), 5);
+        assert_eq!(
+            sonic_rs::to_string(&old[1]).unwrap(),
+            sonic_rs::to_string(&new[1]).unwrap()
+        );
+    }
+
+    #[test]
+    fn keccak256_matches_known_answers() {
+        let a = |n| vec![b'a'; n];
+        for (input, digest) in [
+            (
+                b"".to_vec(),
+                "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+            ),
+            (
+                b"abc".to_vec(),
+                "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+            ),
+            // Either side of the 136-byte rate, and two blocks
+            (
+                a(135),
+                "34367dc248bbd832f4e3e69dfaac2f92638bd0bbd18f2912ba4ef454919cf446",
+            ),
+            (
+                a(136),
+                "a6c4d403279fe3e0af03729caada8374b5ca54d8065329a3ebcaeb4b60aa386e",
+            ),
+            (
+                a(137),
+                "d869f639c7046b4929fc92a4d988a8b22c55fbadb802c0c66ebcd484f1915f39",
+            ),
+            (
+                a(200),
+                "96ea54061def936c4be90b518992fdc6f12f535068a256229aca54267b4d084d",
+            ),
+        ] {
+            assert_eq!(to_hex(&keccak256(&input)), digest, "{} bytes", input.len());
+        }
+    }
+
+    #[test]
+    fn eip55_matches_reference_addresses() {
+        // The test vectors from EIP-55: all caps, all lower, and mixed
+        for address in [
+            "0x52908400098527886E0F7030069857D2E4169EE7",
+            "0x8617E340B3D01FA5F11F306F4090FD50E238070D",
+            "0xde709f2102306220921060314715629080e2fb77",
+            "0x27b1fdb04752bbc536007a920d24acb045561c26",
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let mut bytes = [0u8; 20];
+            for (i, byte) in bytes.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&address[2 + 2 * i..4 + 2 * i], 16).unwrap();
+            }
+            assert_eq!(eip55_address(&bytes), address);
+        }
+    }
+
+    /// Inverse of [`base58_encode`].
+    fn base58_decode(s: &str) -> Vec<u8> {
+        // Little-endian bytes of the number, as the encoder keeps its digits
+        let mut bytes: Vec<u8> = Vec::new();
+        for c in s.bytes() {
+            let mut carry = BASE58_ALPHABET.iter().position(|&a| a == c).unwrap() as u32;
+            for byte in bytes.iter_mut() {
+                carry += *byte as u32 * 58;
+                *byte = carry as u8;
+                carry >>= 8;
+            }
+            while carry > 0 {
+                bytes.push(carry as u8);
+                carry >>= 8;
+            }
+        }
+        let zeros = s.bytes().take_while(|&c| c == b'1').count();
+        std::iter::repeat_n(0, zeros)
+            .chain(bytes.into_iter().rev())
+            .collect()
+    }
+
+    #[test]
+    fn base58_matches_known_answers_and_round_trips() {
+        for (bytes, encoded) in [
+            (&b""[..], ""),
+            (&[0][..], "1"),
+            (&[0, 0, 1][..], "112"),
+            (&[57][..], "z"),
+            (&[58][..], "21"),
+            (b"Hello World!", "2NEpo7TZRRrLZSi2U"),
+            (
+                &[
+                    0x00, 0xeb, 0x15, 0x23, 0x1d, 0xfc, 0xeb, 0x60, 0x92, 0x58, 0x86, 0xb6, 0x7d,
+                    0x06, 0x52, 0x99, 0x92, 0x59, 0x15, 0xae, 0xb1, 0x72, 0xc0, 0x66, 0x47,
+                ][..],
+                "1NS17iag9jJgTHD1VXjvLCEnZuQ3rJDE9L",
+            ),
+        ] {
+            assert_eq!(base58_encode(bytes), encoded);
+            assert_eq!(base58_decode(encoded), bytes);
+        }
+
+        let mut rng = rand::rng();
+        for len in 0..=64 {
+            let mut bytes: Vec<u8> = (0..len).map(|_| rng.random()).collect();
+            // Leading zero bytes encode as leading '1's
+            for byte in bytes.iter_mut().take(len % 3) {
+                *byte = 0;
+            }
+            assert_eq!(base58_decode(&base58_encode(&bytes)), bytes);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+//! The in-process mock server behind `--transport memory`.
+
+use super::*;
+
+// =============================================================================
+// In-Process Mock Server (memory transport)
+// =============================================================================
+
+/// Minimal Pusher-compatible server for `--transport memory`: acknowledges
+/// subscribes and unsubscribes, answers pings and publishes a timestamped
+/// event every 100ms tagged with the first value of each condition in the
+/// client's filter (untagged if it has none). `client-*` events are relayed
+/// to the other connections on their channel. Events go out as binary frames
+/// under `--payload-format msgpack`; under `--protocol raw` every connection
+/// gets bare `{"timestamp":..,"event_id":..}` frames from the start.
+pub(crate) async fn run_mock_server(
+    mut incoming: tokio::sync::mpsc::UnboundedReceiver<DuplexStream>,
+    format: PayloadFormat,
+    proto: Option<Arc<ProtoCodec>>,
+    protocol: ProtocolKind,
+) {
+    let mut next_socket: u64 = 0;
+    let (client_events, _) = broadcast::channel(1024);
+    while let Some(stream) = incoming.recv().await {
+        next_socket += 1;
+        tokio::spawn(mock_connection(
+            stream,
+            next_socket,
+            format,
+            proto.clone(),
+            protocol,
+            client_events.clone(),
+        ));
+    }
+}
+
+/// Reply to a `graphql-transport-ws` frame, tracking subscriptions by id.
+pub(crate) fn mock_graphql_reply(
+    value: &sonic_rs::Value,
+    subscriptions: &mut HashMap<String, String>,
+) -> Option<String> {
+    let id = value.get("id").and_then(|i| i.as_str()).unwrap_or_default();
+    match value.get("type").and_then(|t| t.as_str())? {
+        "connection_init" => Some(r#"{"type":"connection_ack"}"#.to_owned()),
+        "ping" => Some(GRAPHQL_PONG.to_owned()),
+        "subscribe" => {
+            let mut tags = String::new();
+            if let Some(filter) = value.pointer(["payload", "variables", "filter"]) {
+                mock_tags(filter, &mut tags);
+            }
+            subscriptions.insert(id.to_string(), tags);
+            None
+        }
+        "complete" => {
+            subscriptions.remove(id);
+            None
+        }
+        _ => None,
+    }
+}
+
+/// Append `"key":"value",` for the first value of each condition in `filter`,
+/// nested ones included, so the event passes an `and` of them too.
+pub(crate) fn mock_tags(filter: &sonic_rs::Value, tags: &mut String) {
+    if let Some(nested) = filter.get("filters").and_then(|f| f.as_array()) {
+        for filter in nested.iter() {
+            mock_tags(filter, tags);
+        }
+        return;
+    }
+    let key = filter
+        .get("key")
+        .and_then(|k| k.as_str())
+        .unwrap_or("token_address");
+    let tag = match filter.get("val").and_then(|v| v.as_f64()) {
+        // Numeric comparators: a value just across the threshold
+        Some(threshold) => {
+            let value = match filter.get("cmp").and_then(|c| c.as_str()) {
+                Some("gt") => threshold + 1.0,
+                Some("lt") => threshold - 1.0,
+                _ => threshold,
+            };
+            format!(r#""{}":{},"#, key, value)
+        }
+        None => {
+            let value = filter
+                .get("val")
+                .or_else(|| filter.get("vals").and_then(|v| v.get(0)))
+                .and_then(|v| v.as_str())
+                .unwrap_or_default();
+            match filter.get("cmp").and_then(|c| c.as_str()) {
+                // Negations: a value outside the excluded ones
+                Some("neq" | "nin") => format!(r#""{}":"{}-other","#, key, value),
+                _ => format!(r#""{}":"{}","#, key, value),
+            }
+        }
+    };
+    if !tags.contains(&format!(r#""{}":"#, key)) {
+        tags.push_str(&tag);
+    }
+}
+
+/// Poses the mock as one of two backends behind a cookie-sticky load
+/// balancer: connections without a `mock_backend` cookie get one.
+pub(crate) struct MockBackendCookie(u64);
+
+impl Callback for MockBackendCookie {
+    fn on_request(
+        self,
+        request: &Request,
+        mut response: Response,
+    ) -> std::result::Result<Response, ErrorResponse> {
+        let sent = request
+            .headers()
+            .get(header::COOKIE)
+            .and_then(|c| c.to_str().ok());
+        // Echo a requested subprotocol, as a client that asked for one requires
+        if let Some(subprotocol) = request.headers().get(header::SEC_WEBSOCKET_PROTOCOL) {
+            response
+                .headers_mut()
+                .insert(header::SEC_WEBSOCKET_PROTOCOL, subprotocol.clone());
+        }
+        let backend = BackendId::Cookie("mock_backend".into()).find(&HeaderMap::new(), sent);
+        if backend.is_none() {
+            let cookie = format!("mock_backend=mock-{}; Path=/", self.0 % 2);
+            if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+                response.headers_mut().insert(header::SET_COOKIE, cookie);
+            }
+        }
+        Ok(response)
+    }
+}
+
+pub(crate) async fn mock_connection(
+    stream: DuplexStream,
+    socket_id: u64,
+    format: PayloadFormat,
+    proto: Option<Arc<ProtoCodec>>,
+    protocol: ProtocolKind,
+    client_events: broadcast::Sender<(u64, String, String)>,
+) {
+    let Ok(ws) = tokio_tungstenite::accept_hdr_async(stream, MockBackendCookie(socket_id)).await
+    else {
+        return;
+    };
+    let (mut write, mut read) = ws.split();
+
+    let established = format!(
+        r#"{{"event":"pusher:connection_established","data":"{{\"socket_id\":\"{}.1\"}}"}}"#,
+        socket_id
+    );
+    let raw = protocol == ProtocolKind::Raw;
+    let graphql = protocol == ProtocolKind::GraphqlWs;
+    if protocol == ProtocolKind::Pusher && write.send(Message::Text(established)).await.is_err() {
+        return;
+    }
+
+    // channel -> tags matching its filter, rendered for the event template
+    let mut subscriptions: HashMap<String, String> = HashMap::new();
+    if raw {
+        subscriptions.insert(String::new(), String::new());
+    }
+    let mut next_event_id: u64 = 0;
+    let mut publish = interval(Duration::from_millis(100));
+    // (sender socket, channel, frame) of every connection's client events
+    let mut relayed = client_events.subscribe();
+
+    loop {
+        tokio::select! {
+            msg = read.next() => {
+                let text = match msg {
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    _ => continue,
+                };
+                let Ok(value) = sonic_rs::from_str::<sonic_rs::Value>(&text) else {
+                    continue;
+                };
+                if graphql {
+                    if let Some(reply) = mock_graphql_reply(&value, &mut subscriptions) {
+                        if write.send(Message::Text(reply)).await.is_err() {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                let reply = match value.get("event").and_then(|e| e.as_str()) {
+                    Some("pusher:ping") => PONG_JSON.to_owned(),
+                    Some("pusher:ack") => {
+                        let event_id = value
+                            .get("data")
+                            .and_then(|d| d.get("event_id"))
+                            .and_then(|e| e.as_str())
+                            .unwrap_or_default();
+                        format!(
+                            r#"{{"event":"pusher_internal:ack_received","data":{{"event_id":"{}"}}}}"#,
+                            event_id
+                        )
+                    }
+                    Some("pusher:subscribe") => {
+                        let data = value.get("data");
+                        let channel = data
+                            .and_then(|d| d.get("channel"))
+                            .and_then(|c| c.as_str())
+                            .unwrap_or_default()
+                            .to_string();
+                        let mut tags = String::new();
+                        if let Some(filter) = data.and_then(|d| d.get("filter")) {
+                            mock_tags(filter, &mut tags);
+                        }
+                        let reply = format!(
+                            r#"{{"event":"pusher_internal:subscription_succeeded","channel":"{}"}}"#,
+                            channel
+                        );
+                        subscriptions.insert(channel, tags);
+                        reply
+                    }
+                    Some("pusher:unsubscribe") => {
+                        let channel = value
+                            .get("data")
+                            .and_then(|d| d.get("channel"))
+                            .and_then(|c| c.as_str())
+                            .unwrap_or_default();
+                        subscriptions.remove(channel);
+                        format!(
+                            r#"{{"event":"pusher_internal:unsubscribed","channel":"{}"}}"#,
+                            channel
+                        )
+                    }
+                    Some(event) if event.starts_with("client-") => {
+                        let channel = value.get("channel").and_then(|c| c.as_str()).unwrap_or_default();
+                        let _ = client_events.send((socket_id, channel.to_string(), text.clone()));
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if write.send(Message::Text(reply)).await.is_err() {
+                    break;
+                }
+            }
+
+            Ok((from, channel, frame)) = relayed.recv() => {
+                if from != socket_id
+                    && subscriptions.contains_key(&channel)
+                    && write.send(Message::Text(frame)).await.is_err()
+                {
+                    return;
+                }
+            }
+
+            _ = publish.tick() => {
+                let now = unix_millis();
+                for (channel, tags) in &subscriptions {
+                    next_event_id += 1;
+                    let event = if raw {
+                        format!(r#"{{"timestamp":{},"event_id":"{}"}}"#, now, next_event_id)
+                    } else if graphql {
+                        format!(
+                            r#"{{"id":"{}","type":"next","payload":{{"data":{{"events":{{"event":"mock_update","tags":{{{}"timestamp":{},"event_id":"{}"}},"data":{{}}}}}}}}}}"#,
+                            channel, tags, now, next_event_id
+                        )
+                    } else {
+                        format!(
+                            r#"{{"event":"mock_update","channel":"{}","tags":{{{}"timestamp":{},"event_id":"{}"}},"data":{{}}}}"#,
+                            channel, tags, now, next_event_id
+                        )
+                    };
+                    let event = match format {
+                        PayloadFormat::Json => Message::Text(event),
+                        PayloadFormat::Msgpack => {
+                            let Ok(value) = sonic_rs::from_str::<sonic_rs::Value>(&event) else {
+                                continue;
+                            };
+                            let mut bytes = Vec::with_capacity(event.len());
+                            json_to_msgpack(&value, &mut bytes);
+                            Message::Binary(bytes)
+                        }
+                        PayloadFormat::Protobuf => {
+                            let (Some(codec), Ok(value)) = (&proto, sonic_rs::from_str::<sonic_rs::Value>(&event)) else {
+                                continue;
+                            };
+                            Message::Binary(codec.encode(&value))
+                        }
+                    };
+                    if write.send(event).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
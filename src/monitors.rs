@@ -0,0 +1,555 @@
+//! Background monitors, signal handlers and live exports that run alongside a test.
+
+use super::*;
+
+// =============================================================================
+// Load Generator CPU Monitor
+// =============================================================================
+
+/// Generator CPU above this percentage marks measurements as suspect.
+pub(crate) const CPU_SUSPECT_PCT: f64 = 85.0;
+
+/// Utilization tiers used to bucket measurement windows.
+pub(crate) const CPU_TIERS: [(&str, f64); 3] = [
+    ("<50%", 50.0),
+    ("50-85%", CPU_SUSPECT_PCT),
+    (">85%", f64::MAX),
+];
+
+/// One sampling window of the CPU monitor.
+pub(crate) struct CpuWindow {
+    pub(crate) cpu_pct: f64,
+    /// Process CPU time consumed during the window
+    pub(crate) cpu_time: Duration,
+    pub(crate) duration: Duration,
+    pub(crate) messages: u64,
+}
+
+/// Total user + system CPU time consumed by this process.
+pub(crate) fn process_cpu_time() -> Duration {
+    // SAFETY: getrusage only writes into the zeroed struct we pass it.
+    let usage = unsafe {
+        let mut usage: libc::rusage = std::mem::zeroed();
+        libc::getrusage(libc::RUSAGE_SELF, &mut usage);
+        usage
+    };
+    let tv = |t: libc::timeval| {
+        Duration::from_secs(t.tv_sec as u64) + Duration::from_micros(t.tv_usec as u64)
+    };
+    tv(usage.ru_utime) + tv(usage.ru_stime)
+}
+
+/// Sample process CPU every second, normalized to all cores, until `stop` is
+/// set. Windows are only kept once the measurement phase has begun.
+pub(crate) async fn monitor_cpu(
+    live_stats: LiveStats,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+) -> Vec<CpuWindow> {
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1) as f64;
+    let mut windows = Vec::new();
+    let mut last_wall = Instant::now();
+    let mut last_cpu = process_cpu_time();
+    let mut last_messages = live_stats.messages_received.load(Ordering::Relaxed);
+    let mut ticker = interval(Duration::from_secs(1));
+    ticker.tick().await;
+
+    while !stop.load(Ordering::Relaxed) {
+        ticker.tick().await;
+
+        let wall = last_wall.elapsed();
+        let cpu = process_cpu_time();
+        let messages = live_stats.messages_received.load(Ordering::Relaxed);
+        let cpu_pct = (cpu - last_cpu).as_secs_f64() / (wall.as_secs_f64() * cores) * 100.0;
+
+        let suspect = cpu_pct > CPU_SUSPECT_PCT;
+        if suspect && !live_stats.cpu_suspect.load(Ordering::Relaxed) {
+            warn!(
+                "Load generator CPU at {:.1}% - measurements are suspect",
+                cpu_pct
+            );
+        }
+        live_stats.cpu_suspect.store(suspect, Ordering::Relaxed);
+
+        if live_stats.warmup_complete.load(Ordering::Relaxed) {
+            windows.push(CpuWindow {
+                cpu_pct,
+                cpu_time: cpu - last_cpu,
+                duration: wall,
+                messages: messages - last_messages,
+            });
+        }
+
+        last_wall = Instant::now();
+        last_cpu = cpu;
+        last_messages = messages;
+    }
+
+    windows
+}
+
+// =============================================================================
+// System Clock Monitor
+// =============================================================================
+
+/// A discontinuity in the system clock relative to the monotonic clock.
+pub(crate) struct ClockJump {
+    /// When it was detected, relative to the start of the run
+    pub(crate) at: Duration,
+    /// How far the system clock moved beyond monotonic time (negative = back)
+    pub(crate) delta_ms: i64,
+}
+
+/// Compare the system clock against the monotonic clock every 250ms until
+/// `stop` is set. E2E latency mixes server and local wall-clock time, so while
+/// a jump (NTP step, VM pause) is being detected, samples are marked suspect.
+pub(crate) async fn monitor_clock(
+    live_stats: LiveStats,
+    threshold: Duration,
+    stop: Arc<std::sync::atomic::AtomicBool>,
+) -> Vec<ClockJump> {
+    let started = Instant::now();
+    let wall_offset = || unix_millis() as i64 - started.elapsed().as_millis() as i64;
+    let mut last_offset = wall_offset();
+    let mut jumps = Vec::new();
+    let mut ticker = interval(Duration::from_millis(250));
+    ticker.tick().await;
+
+    while !stop.load(Ordering::Relaxed) {
+        ticker.tick().await;
+
+        let offset = wall_offset();
+        let delta_ms = offset - last_offset;
+        let jumped = delta_ms.unsigned_abs() > threshold.as_millis() as u64;
+        if jumped {
+            warn!(
+                "System clock jumped {:+}ms relative to monotonic time - E2E samples around it are excluded",
+                delta_ms
+            );
+            jumps.push(ClockJump {
+                at: started.elapsed(),
+                delta_ms,
+            });
+        }
+        live_stats.clock_suspect.store(jumped, Ordering::Relaxed);
+        last_offset = offset;
+    }
+
+    jumps
+}
+
+// =============================================================================
+// Ramp-Down Drain Monitor
+// =============================================================================
+
+/// Connection count, aggregate message rate and E2E latency at one point of
+/// the ramp-down.
+pub(crate) struct DrainSample {
+    pub(crate) at: Duration,
+    pub(crate) active: usize,
+    pub(crate) messages_per_sec: f64,
+    /// Mean and max E2E latency (ms) since the previous sample
+    pub(crate) e2e: Option<(f64, u64)>,
+}
+
+/// Sample connections and message rate every 250ms from the start of the
+/// ramp-down until every client has closed or `limit` elapses.
+pub(crate) async fn monitor_drain(live_stats: LiveStats, limit: Duration) -> Vec<DrainSample> {
+    let start = Instant::now();
+    let mut samples = Vec::new();
+    let total = |s: &LiveStats| {
+        s.messages_received.load(Ordering::Relaxed) + s.post_close_messages.load(Ordering::Relaxed)
+    };
+    let mut last_messages = total(&live_stats);
+    let mut last_at = Instant::now();
+    let mut ticker = interval(Duration::from_millis(250));
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let messages = total(&live_stats);
+        let active = live_stats.active_connections.load(Ordering::Relaxed);
+        let e2e_sum = live_stats.drain_e2e_sum.swap(0, Ordering::Relaxed);
+        let e2e_count = live_stats.drain_e2e_count.swap(0, Ordering::Relaxed);
+        let e2e_max = live_stats.drain_e2e_max.swap(0, Ordering::Relaxed);
+        samples.push(DrainSample {
+            at: start.elapsed(),
+            active,
+            messages_per_sec: (messages - last_messages) as f64 / last_at.elapsed().as_secs_f64(),
+            e2e: (e2e_count > 0).then(|| (e2e_sum as f64 / e2e_count as f64, e2e_max)),
+        });
+        last_messages = messages;
+        last_at = Instant::now();
+
+        if active == 0 || start.elapsed() >= limit {
+            break;
+        }
+    }
+
+    samples
+}
+
+// =============================================================================
+// Periodic Snapshots
+// =============================================================================
+
+/// Point-in-time copy of the live counters, written during long soaks so a
+/// crash still leaves intermediate results behind.
+#[derive(Debug, Serialize)]
+pub(crate) struct LiveSnapshot {
+    pub(crate) unix_ms: u64,
+    pub(crate) elapsed_secs: f64,
+    pub(crate) active_connections: usize,
+    pub(crate) pending_connects: usize,
+    pub(crate) messages_received: u64,
+    pub(crate) bytes_sent: u64,
+    pub(crate) bytes_received: u64,
+    pub(crate) subscribe_success: u64,
+    pub(crate) subscribe_failed: u64,
+    pub(crate) connection_errors: u64,
+    pub(crate) post_close_messages: u64,
+    pub(crate) measuring: bool,
+    pub(crate) cpu_suspect: bool,
+    /// Percentiles of every E2E sample recorded so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) e2e_latency_ms: Option<LatencySummary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) canary: Option<CanarySnapshot>,
+}
+
+impl LiveSnapshot {
+    pub(crate) fn capture(live_stats: &LiveStats, started: Instant) -> Self {
+        Self {
+            unix_ms: unix_millis(),
+            elapsed_secs: started.elapsed().as_secs_f64(),
+            active_connections: live_stats.active_connections.load(Ordering::Relaxed),
+            pending_connects: live_stats.pending_connects.load(Ordering::Relaxed),
+            messages_received: live_stats.messages_received.load(Ordering::Relaxed),
+            bytes_sent: live_stats.bytes_sent.load(Ordering::Relaxed),
+            bytes_received: live_stats.bytes_received.load(Ordering::Relaxed),
+            subscribe_success: live_stats.subscribe_success.load(Ordering::Relaxed),
+            subscribe_failed: live_stats.subscribe_failed.load(Ordering::Relaxed),
+            connection_errors: live_stats.connection_errors.load(Ordering::Relaxed),
+            post_close_messages: live_stats.post_close_messages.load(Ordering::Relaxed),
+            measuring: live_stats.warmup_complete.load(Ordering::Relaxed),
+            cpu_suspect: live_stats.cpu_suspect.load(Ordering::Relaxed),
+            e2e_latency_ms: LatencySummary::from_histogram(&live_stats.e2e.histogram()),
+            canary: live_stats.canary.as_ref().map(|c| c.snapshot()),
+        }
+    }
+}
+
+/// Write a snapshot every `every` into `dir`, rotating through `keep` files.
+/// Each write goes to a temp file first so a crash never leaves a torn file.
+pub(crate) async fn write_snapshots(
+    live_stats: LiveStats,
+    dir: PathBuf,
+    every: Duration,
+    keep: usize,
+) {
+    if let Err(e) = tokio::fs::create_dir_all(&dir).await {
+        error!("Cannot create snapshot dir {:?}: {}", dir, e);
+        return;
+    }
+
+    let started = Instant::now();
+    let keep = keep.max(1);
+    let mut ticker = interval(every);
+    ticker.tick().await;
+
+    for seq in 0usize.. {
+        ticker.tick().await;
+        let snapshot = LiveSnapshot::capture(&live_stats, started);
+        let json = match sonic_rs::to_string_pretty(&snapshot) {
+            Ok(json) => json,
+            Err(e) => {
+                warn!("Failed to serialize snapshot: {}", e);
+                continue;
+            }
+        };
+
+        let path = dir.join(format!("snapshot-{}.json", seq % keep));
+        let tmp = path.with_extension("json.tmp");
+        let written = match tokio::fs::write(&tmp, json).await {
+            Ok(()) => tokio::fs::rename(&tmp, &path).await,
+            Err(e) => Err(e),
+        };
+        match written {
+            Ok(()) => debug!("Wrote snapshot {:?}", path),
+            Err(e) => warn!("Failed to write snapshot {:?}: {}", path, e),
+        }
+    }
+}
+
+/// Log the live counters each time SIGUSR1 arrives, without stopping the run.
+pub(crate) async fn log_interim_summaries(live_stats: LiveStats) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr1 = match signal(SignalKind::user_defined1()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Cannot listen for SIGUSR1: {}", e);
+            return;
+        }
+    };
+
+    let started = Instant::now();
+    while usr1.recv().await.is_some() {
+        let snapshot = LiveSnapshot::capture(&live_stats, started);
+        let secs = snapshot.elapsed_secs.max(f64::EPSILON);
+        let elapsed = Duration::from_secs_f64(secs);
+        info!("────────────────── Interim Summary ──────────────────");
+        info!("  Elapsed:             {:.0}s", secs);
+        info!("  Active Connections:  {}", snapshot.active_connections);
+        info!("  Subscribe Success:   {}", snapshot.subscribe_success);
+        info!("  Connection Errors:   {}", snapshot.connection_errors);
+        info!(
+            "  Messages Received:   {} ({:.0} msg/s avg)",
+            snapshot.messages_received,
+            snapshot.messages_received as f64 / secs
+        );
+        info!(
+            "  Throughput:          out={:.3}MB/s in={:.3}MB/s avg",
+            megabytes_per_sec(snapshot.bytes_sent, elapsed),
+            megabytes_per_sec(snapshot.bytes_received, elapsed)
+        );
+        if let Some(l) = &snapshot.e2e_latency_ms {
+            info!(
+                "  E2E Latency:         p50={}ms p95={}ms p99={}ms max={}ms ({} samples)",
+                l.p50, l.p95, l.p99, l.max, l.samples
+            );
+        }
+        if snapshot.cpu_suspect {
+            info!("  Generator CPU is above the suspect threshold");
+        }
+    }
+}
+
+/// Pause or resume the load, logging only actual changes.
+pub(crate) fn set_paused(live_stats: &LiveStats, paused: bool) {
+    if live_stats
+        .paused
+        .send_if_modified(|p| std::mem::replace(p, paused) != paused)
+    {
+        if paused {
+            warn!("Load paused: holding the current clients and filters");
+        } else {
+            info!("Load resumed");
+        }
+    }
+}
+
+/// Toggle the pause each time SIGUSR2 arrives.
+pub(crate) async fn toggle_pause_on_signal(live_stats: LiveStats) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut usr2 = match signal(SignalKind::user_defined2()) {
+        Ok(s) => s,
+        Err(e) => {
+            warn!("Cannot listen for SIGUSR2: {}", e);
+            return;
+        }
+    };
+
+    while usr2.recv().await.is_some() {
+        let paused = *live_stats.paused.borrow();
+        set_paused(&live_stats, !paused);
+    }
+}
+
+/// Load the token list from `--token-url`, or else `--token-file`.
+pub(crate) async fn load_token_list(config: &Config) -> Result<TokenPool> {
+    match &config.token_url {
+        Some(url) => {
+            TokenPool::fetch(
+                config.tls(),
+                url,
+                config.token_url_header.as_deref(),
+                config.token_format,
+                &config.token_column,
+                config.token_strata.as_deref(),
+                config.strict_tokens,
+            )
+            .await
+        }
+        None => TokenPool::load_from_files(
+            &config.token_file,
+            config.token_format,
+            &config.token_column,
+            config.token_strata.as_deref(),
+            config.strict_tokens,
+        ),
+    }
+}
+
+/// Swap a fresh token list into the pool on SIGHUP, every `--token-refresh`
+/// seconds for a URL, and whenever `--token-watch` sees the file modified.
+/// A failed reload keeps the current list.
+pub(crate) async fn reload_tokens(tokens: TokenPool, config: Arc<Config>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hup = signal(SignalKind::hangup())
+        .inspect_err(|e| warn!("Cannot listen for SIGHUP: {}", e))
+        .ok();
+    let refresh = config.token_url.is_some() && config.token_refresh > 0;
+    let watch = config.token_url.is_none() && config.token_watch > 0;
+    let mut refresh_ticker = interval(Duration::from_secs(config.token_refresh.max(1)));
+    let mut watch_ticker = interval(Duration::from_secs(config.token_watch.max(1)));
+    refresh_ticker.tick().await;
+    watch_ticker.tick().await;
+    let modified = || {
+        config
+            .token_file
+            .iter()
+            .map(|path| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+            .collect::<Option<Vec<_>>>()
+    };
+    let mut last_modified = modified();
+
+    loop {
+        let reason = tokio::select! {
+            Some(()) = async {
+                match hup.as_mut() {
+                    Some(hup) => hup.recv().await,
+                    None => std::future::pending().await,
+                }
+            } => "SIGHUP",
+            _ = refresh_ticker.tick(), if refresh => "refresh",
+            _ = watch_ticker.tick(), if watch => {
+                let now = modified();
+                if now.is_none() || now == last_modified {
+                    continue;
+                }
+                last_modified = now;
+                "file modified"
+            }
+        };
+        match load_token_list(&config).await {
+            Ok(fresh) => {
+                tokens.replace(&fresh);
+                info!("Token list reloaded ({})", reason);
+            }
+            Err(e) => warn!(
+                "Token reload ({}) failed, keeping the current list: {}",
+                reason, e
+            ),
+        }
+    }
+}
+
+/// Exit status after a second Ctrl-C, as for SIGINT.
+pub const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Set the returned flag on the first Ctrl-C so the run can wind down and
+/// report; a second Ctrl-C ends the run immediately. Listens until the
+/// returned task drops.
+pub(crate) fn spawn_interrupt_handler(live_stats: LiveStats) -> (Arc<AtomicBool>, ScopedTask<()>) {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&interrupted);
+    let handler = ScopedTask::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_err() {
+            warn!("Cannot listen for Ctrl-C");
+            return;
+        }
+        warn!("Interrupted: stopping the hold and collecting results (Ctrl-C again to abort)");
+        flag.store(true, Ordering::Relaxed);
+        let _ = tokio::signal::ctrl_c().await;
+        live_stats
+            .exit_status
+            .send_replace(Some(INTERRUPTED_EXIT_CODE));
+    });
+    (interrupted, handler)
+}
+
+// =============================================================================
+// Raw Sample Export
+// =============================================================================
+
+/// One latency measurement as it happened, for offline analysis.
+#[derive(Debug)]
+pub(crate) struct RawSample {
+    pub(crate) unix_ms: u64,
+    pub(crate) client_id: usize,
+    pub(crate) kind: &'static str,
+    pub(crate) value_ms: u64,
+}
+
+pub(crate) type SampleSender = tokio::sync::mpsc::UnboundedSender<RawSample>;
+
+/// Drain samples into `path` as gzip-compressed CSV on a blocking thread until
+/// every sender is dropped. Returns the number of samples written.
+pub(crate) fn spawn_sample_writer(
+    path: PathBuf,
+    mut samples: tokio::sync::mpsc::UnboundedReceiver<RawSample>,
+) -> tokio::task::JoinHandle<Result<u64>> {
+    tokio::task::spawn_blocking(move || {
+        use std::io::Write;
+
+        let file = std::fs::File::create(&path)?;
+        let mut out = flate2::write::GzEncoder::new(
+            std::io::BufWriter::new(file),
+            flate2::Compression::fast(),
+        );
+        writeln!(out, "unix_ms,client_id,kind,value_ms")?;
+
+        let mut written = 0;
+        while let Some(s) = samples.blocking_recv() {
+            writeln!(
+                out,
+                "{},{},{},{}",
+                s.unix_ms, s.client_id, s.kind, s.value_ms
+            )?;
+            written += 1;
+        }
+        out.finish()?.flush()?;
+        Ok(written)
+    })
+}
+
+// =============================================================================
+// Per-Client Debug Toggle
+// =============================================================================
+
+/// Point `--debug-client` at client `id`, or turn it off with
+/// [`NO_DEBUG_CLIENT`], so a suspicious connection can be inspected mid-soak.
+pub(crate) fn set_debug_client(live_stats: &LiveStats, id: usize) {
+    live_stats.debug_client.store(id, Ordering::Relaxed);
+    if id == NO_DEBUG_CLIENT {
+        info!("Client debug logging disabled");
+    } else {
+        info!("Client debug logging enabled for client {}", id);
+    }
+}
+
+// =============================================================================
+// Max Runtime Watchdog
+// =============================================================================
+
+/// Exit status when `--max-runtime` fires, matching `timeout(1)`.
+pub const MAX_RUNTIME_EXIT_CODE: i32 = 124;
+
+/// End the run once `limit` has elapsed, logging the live counters as the
+/// only summary left. Times out on an OS thread so a busy runtime cannot
+/// delay it; dropping the returned sender disarms it.
+pub(crate) fn spawn_watchdog(
+    live_stats: LiveStats,
+    limit: Duration,
+) -> std::sync::mpsc::Sender<()> {
+    let (disarm, disarmed) = std::sync::mpsc::channel::<()>();
+    let started = Instant::now();
+    std::thread::spawn(move || {
+        if disarmed.recv_timeout(limit) != Err(std::sync::mpsc::RecvTimeoutError::Timeout) {
+            return;
+        }
+        let snapshot = LiveSnapshot::capture(&live_stats, started);
+        error!("Max runtime of {}s exceeded, stopping", limit.as_secs());
+        if let Ok(json) = sonic_rs::to_string(&snapshot) {
+            error!("Last live counters: {}", json);
+        }
+        live_stats
+            .exit_status
+            .send_replace(Some(MAX_RUNTIME_EXIT_CODE));
+    });
+    disarm
+}
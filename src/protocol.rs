@@ -0,0 +1,647 @@
+//! Pusher, raw and GraphQL wire protocols and their message types.
+
+use super::*;
+
+// =============================================================================
+// Data Structures
+// =============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub(crate) enum FilterValue {
+    Single {
+        key: &'static str,
+        cmp: &'static str,
+        val: Arc<str>,
+    },
+    Multiple {
+        key: &'static str,
+        cmp: &'static str,
+        vals: Vec<Arc<str>>,
+    },
+    /// Numeric comparison (`gt`, `lt`, `gte`, `lte`)
+    Threshold {
+        key: &'static str,
+        cmp: &'static str,
+        val: f64,
+    },
+    /// `and` / `or` of nested filters
+    Compound {
+        op: &'static str,
+        filters: Vec<FilterValue>,
+    },
+    /// `--filter-json` passthrough, sent as written
+    Raw(Arc<RawFilter>),
+    /// No filter: the subscribe frame omits it and every message matches
+    All,
+}
+
+/// A filter from `--filter-json`. Its grammar is opaque to us, so it never
+/// matches a message and `key` only serves per-stratum attribution.
+#[derive(Debug)]
+pub(crate) struct RawFilter {
+    pub(crate) key: &'static str,
+    pub(crate) json: sonic_rs::Value,
+}
+
+impl Serialize for RawFilter {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        self.json.serialize(serializer)
+    }
+}
+
+impl FilterValue {
+    /// Whether a message whose tags are looked up by `tag` would pass this
+    /// filter. A missing tag fails its condition.
+    pub(crate) fn matches<'a>(&self, tag: &dyn Fn(&str) -> Option<&'a sonic_rs::Value>) -> bool {
+        match self {
+            FilterValue::Single { key, cmp, val } => {
+                match (*cmp, tag(key).and_then(|v| v.as_str())) {
+                    ("eq", Some(value)) => &**val == value,
+                    ("neq", Some(value)) => &**val != value,
+                    _ => false,
+                }
+            }
+            FilterValue::Multiple { key, cmp, vals } => {
+                match (*cmp, tag(key).and_then(|v| v.as_str())) {
+                    ("in", Some(value)) => vals.iter().any(|v| &**v == value),
+                    ("nin", Some(value)) => vals.iter().all(|v| &**v != value),
+                    _ => false,
+                }
+            }
+            FilterValue::Threshold { key, cmp, val } => {
+                // Numeric tags may arrive as strings
+                let Some(x) =
+                    tag(key).and_then(|v| v.as_f64().or_else(|| v.as_str()?.parse().ok()))
+                else {
+                    return false;
+                };
+                match *cmp {
+                    "gt" => x > *val,
+                    "lt" => x < *val,
+                    "gte" => x >= *val,
+                    _ => x <= *val,
+                }
+            }
+            FilterValue::Compound { op, filters } => match *op {
+                "and" => filters.iter().all(|f| f.matches(tag)),
+                _ => filters.iter().any(|f| f.matches(tag)),
+            },
+            FilterValue::Raw(_) => false,
+            FilterValue::All => true,
+        }
+    }
+
+    pub(crate) fn is_all(&self) -> bool {
+        matches!(self, FilterValue::All)
+    }
+
+    /// Whether [`FilterValue::matches`] can judge this filter, i.e. it holds
+    /// no passthrough parts.
+    pub(crate) fn verifiable(&self) -> bool {
+        match self {
+            FilterValue::Compound { filters, .. } => filters.iter().all(FilterValue::verifiable),
+            FilterValue::Raw(_) => false,
+            _ => true,
+        }
+    }
+
+    /// Parse a filter in the wire format we send, interning keys in `keys`.
+    /// `None` if it uses anything we can't evaluate.
+    pub(crate) fn from_json(
+        json: &sonic_rs::Value,
+        keys: &mut HashMap<String, &'static str>,
+    ) -> Option<FilterValue> {
+        if json.is_null() {
+            return Some(FilterValue::All);
+        }
+        if let Some(op) = json.get("op").and_then(|o| o.as_str()) {
+            let op = match op {
+                "and" => "and",
+                "or" => "or",
+                _ => return None,
+            };
+            let filters = json
+                .get("filters")?
+                .as_array()?
+                .iter()
+                .map(|f| Self::from_json(f, keys))
+                .collect::<Option<Vec<_>>>()?;
+            return (!filters.is_empty()).then_some(FilterValue::Compound { op, filters });
+        }
+        let key = json.get("key")?.as_str()?;
+        let key = match keys.get(key) {
+            Some(&key) => key,
+            None => {
+                let leaked = leak_key(key.to_string());
+                keys.insert(key.to_string(), leaked);
+                leaked
+            }
+        };
+        let val = json.get("val");
+        match json.get("cmp")?.as_str()? {
+            cmp @ ("eq" | "neq") => Some(FilterValue::Single {
+                key,
+                cmp: if cmp == "eq" { "eq" } else { "neq" },
+                val: val?.as_str()?.into(),
+            }),
+            cmp @ ("in" | "nin") => Some(FilterValue::Multiple {
+                key,
+                cmp: if cmp == "in" { "in" } else { "nin" },
+                vals: json
+                    .get("vals")?
+                    .as_array()?
+                    .iter()
+                    .map(|v| v.as_str().map(Arc::from))
+                    .collect::<Option<_>>()?,
+            }),
+            other => Some(FilterValue::Threshold {
+                key,
+                cmp: NUMERIC_CMPS.iter().find(|&&c| c == other)?,
+                val: val?.as_f64()?,
+            }),
+        }
+    }
+
+    /// Key of the (first) condition, which messages are attributed by.
+    pub(crate) fn key(&self) -> &'static str {
+        match self {
+            FilterValue::Single { key, .. }
+            | FilterValue::Multiple { key, .. }
+            | FilterValue::Threshold { key, .. } => key,
+            FilterValue::Compound { filters, .. } => {
+                filters.first().map_or("token_address", FilterValue::key)
+            }
+            FilterValue::Raw(raw) => raw.key,
+            FilterValue::All => "token_address",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PusherMessage {
+    pub(crate) event: String,
+    #[serde(default)]
+    pub(crate) channel: Option<String>,
+    #[serde(default)]
+    pub(crate) data: Option<sonic_rs::Value>,
+    #[serde(default)]
+    pub(crate) tags: Option<sonic_rs::Value>,
+}
+
+impl PusherMessage {
+    /// A `--protocol raw` frame as an envelope-less message: its JSON, if
+    /// any, stands in for `data`, so a `timestamp` at the top level or
+    /// under `tags` still yields E2E latency.
+    pub(crate) fn raw(text: &str) -> Self {
+        Self {
+            event: "raw".to_string(),
+            channel: None,
+            data: sonic_rs::from_str(text).ok(),
+            tags: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum ProtocolKind {
+    Pusher,
+    Raw,
+    GraphqlWs,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SubscribeMessage<'a> {
+    pub(crate) event: &'static str,
+    pub(crate) data: SubscribeData<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct SubscribeData<'a> {
+    pub(crate) channel: &'a str,
+    #[serde(skip_serializing_if = "FilterValue::is_all")]
+    pub(crate) filter: &'a FilterValue,
+    /// Last event id seen before a reconnect, for servers that can replay
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) resume_from: Option<&'a str>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UnsubscribeMessage<'a> {
+    pub(crate) event: &'static str,
+    pub(crate) data: UnsubscribeData<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct UnsubscribeData<'a> {
+    pub(crate) channel: &'a str,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ClientEventMessage<'a> {
+    pub(crate) event: &'a str,
+    pub(crate) channel: &'a str,
+    pub(crate) data: ClientEventData,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ClientEventData {
+    pub(crate) sender: usize,
+    pub(crate) sent_ms: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AckMessage<'a> {
+    pub(crate) event: &'a str,
+    pub(crate) data: AckData<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct AckData<'a> {
+    pub(crate) channel: &'a str,
+    pub(crate) event_id: &'a str,
+}
+
+/// Pre-serialized `pusher:pong` reply, identical for every client. This saves
+/// the serialization, not the allocation: tungstenite 0.24's `Message::Text`
+/// owns a `String`, so each send still copies it.
+pub(crate) const PONG_JSON: &str = r#"{"event":"pusher:pong","data":{}}"#;
+
+/// Reply to the raw-text `ping` keepalive.
+pub(crate) const RAW_PONG: &str = "pong";
+
+/// Pre-serialized `pusher:ping` sent under `--client-ping pusher`; copied per
+/// send like [`PONG_JSON`].
+pub(crate) const PING_JSON: &str = r#"{"event":"pusher:ping","data":{}}"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum PingKind {
+    /// `pusher:ping`, answered by `pusher:pong`
+    Pusher,
+    /// WebSocket ping frame, answered by a pong frame
+    Frame,
+}
+
+/// Serialize a `pusher:unsubscribe` frame for `channel`.
+pub(crate) fn unsubscribe_payload(channel: &str) -> Option<String> {
+    sonic_rs::to_string(&UnsubscribeMessage {
+        event: "pusher:unsubscribe",
+        data: UnsubscribeData { channel },
+    })
+    .ok()
+}
+
+/// Serialize a `pusher:subscribe` frame for `channel` with `filter`.
+///
+/// Borrows everything so serializing allocates only the output buffer.
+#[inline]
+pub(crate) fn subscribe_payload(
+    channel: &str,
+    filter: &FilterValue,
+    resume_from: Option<&str>,
+) -> Option<String> {
+    sonic_rs::to_string(&SubscribeMessage {
+        event: "pusher:subscribe",
+        data: SubscribeData {
+            channel,
+            filter,
+            resume_from,
+        },
+    })
+    .ok()
+}
+
+// =============================================================================
+// GraphQL over WebSocket
+// =============================================================================
+
+/// `Sec-WebSocket-Protocol` of `--protocol graphql-ws`.
+pub(crate) const GRAPHQL_SUBPROTOCOL: &str = "graphql-transport-ws";
+
+pub(crate) const DEFAULT_GRAPHQL_QUERY: &str = "subscription Events($channel: String!, $filter: JSON) { events(channel: $channel, filter: $filter) { event tags data } }";
+
+/// Sent once on connecting; the server answers `connection_ack`.
+pub(crate) const GRAPHQL_INIT: &str = r#"{"type":"connection_init","payload":{}}"#;
+
+/// Keepalive sent under `--client-ping pusher`, and the reply to the server's.
+pub(crate) const GRAPHQL_PING: &str = r#"{"type":"ping"}"#;
+pub(crate) const GRAPHQL_PONG: &str = r#"{"type":"pong"}"#;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct GraphqlFrame {
+    #[serde(rename = "type")]
+    pub(crate) kind: String,
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+    #[serde(default)]
+    pub(crate) payload: Option<sonic_rs::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GraphqlSubscribe<'a> {
+    pub(crate) id: &'a str,
+    #[serde(rename = "type")]
+    pub(crate) kind: &'static str,
+    pub(crate) payload: GraphqlPayload<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GraphqlPayload<'a> {
+    pub(crate) query: &'a str,
+    pub(crate) variables: GraphqlVariables<'a>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct GraphqlVariables<'a> {
+    pub(crate) channel: &'a str,
+    #[serde(skip_serializing_if = "FilterValue::is_all")]
+    pub(crate) filter: &'a FilterValue,
+}
+
+/// A `subscribe` of `query` for `filter` on `channel`. The channel doubles as
+/// the operation id, so `next` frames of split filters stay apart.
+pub(crate) fn graphql_subscribe_payload(
+    query: &str,
+    channel: &str,
+    filter: &FilterValue,
+) -> Option<String> {
+    sonic_rs::to_string(&GraphqlSubscribe {
+        id: channel,
+        kind: "subscribe",
+        payload: GraphqlPayload {
+            query,
+            variables: GraphqlVariables { channel, filter },
+        },
+    })
+    .ok()
+}
+
+impl PusherMessage {
+    /// A `graphql-transport-ws` frame in Pusher terms: `connection_ack` as
+    /// `pusher:connection_established`, `next` as a message on the channel
+    /// named by its id, with the subscription field's value as `data`, and
+    /// `error` or a server-side `complete` as `pusher:error`. `None` for
+    /// frames we don't act on.
+    pub(crate) fn graphql(text: &str) -> Option<Self> {
+        let frame: GraphqlFrame = sonic_rs::from_str(text).ok()?;
+        let event = match frame.kind.as_str() {
+            "connection_ack" => "pusher:connection_established",
+            "next" => "graphql:next",
+            "error" | "complete" => "pusher:error",
+            "ping" => "pusher:ping",
+            "pong" => "pusher:pong",
+            _ => return None,
+        };
+        let data = match (frame.kind.as_str(), frame.payload) {
+            ("complete", _) => sonic_rs::from_str(r#"{"message":"completed by the server"}"#).ok(),
+            // `{"data":{"events":{...}}}`: the one root field is the event
+            ("next", Some(payload)) => payload
+                .get("data")
+                .and_then(|d| d.as_object())
+                .and_then(|fields| fields.iter().next().map(|(_, v)| v.clone())),
+            (_, payload) => payload,
+        };
+        Some(Self {
+            event: event.to_string(),
+            channel: frame.id,
+            data,
+            tags: None,
+        })
+    }
+}
+
+// =============================================================================
+// Wire Protocols
+// =============================================================================
+
+/// Framing above the WebSocket: the upgrade, the frames sent on connecting and
+/// subscribing, and what incoming frames mean.
+///
+/// Incoming frames are classified into Pusher's vocabulary
+/// (`pusher:connection_established`, `pusher:ping`, channel events, ...),
+/// which the client loop, reconnects and metrics are written against, so a
+/// new protocol only implements this.
+pub(crate) trait Protocol: Send + Sync {
+    /// Path of the upgrade URL
+    fn path(&self, config: &Config) -> String;
+
+    /// `Sec-WebSocket-Protocol` requested on upgrade
+    fn subprotocol(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Frame sent as soon as the connection is up
+    fn hello(&self, _config: &Config) -> Option<String> {
+        None
+    }
+
+    /// Whether connections subscribe once established; if not, they count as
+    /// subscribed once the hello is sent
+    fn subscribes(&self) -> bool {
+        true
+    }
+
+    /// Subscribe frame for `filter` on `channel`, asking to replay from
+    /// `resume_from` where [`Protocol::resumes`]
+    fn subscribe(
+        &self,
+        config: &Config,
+        channel: &str,
+        filter: &FilterValue,
+        resume_from: Option<&str>,
+    ) -> Option<String>;
+
+    /// Unsubscribe frame for `channel`, where [`Protocol::updates_filters`]
+    fn unsubscribe(&self, _channel: &str) -> Option<String> {
+        None
+    }
+
+    /// Whether the server acknowledges a subscribe; if not, it counts as
+    /// succeeded once sent
+    fn acknowledges_subscribe(&self) -> bool {
+        true
+    }
+
+    /// Whether a reconnect can ask to replay from the last seen event
+    fn resumes(&self) -> bool {
+        false
+    }
+
+    /// Whether a live connection can change its filter, as updating
+    /// scenarios and plans need
+    fn updates_filters(&self) -> bool {
+        false
+    }
+
+    /// Whether messages name the channel they belong to; if not, every
+    /// message counts as the subscription's
+    fn channel_scoped(&self) -> bool {
+        true
+    }
+
+    /// An incoming text frame in Pusher terms; `None` to ignore it
+    fn classify(&self, text: &str) -> Option<PusherMessage>;
+
+    /// Sent under `--client-ping pusher`
+    fn ping(&self) -> &'static str {
+        PING_JSON
+    }
+
+    /// Reply to the server's ping
+    fn pong(&self) -> &'static str {
+        PONG_JSON
+    }
+
+    /// Unix ms at which the server published a message
+    fn timestamp(&self, msg: &PusherMessage) -> Option<u64> {
+        extract_timestamp(msg)
+    }
+
+    /// Startup banner description
+    fn describe(&self, config: &Config) -> String;
+}
+
+impl ProtocolKind {
+    pub(crate) fn wire(self) -> &'static dyn Protocol {
+        match self {
+            ProtocolKind::Pusher => &PusherProtocol,
+            ProtocolKind::Raw => &RawProtocol,
+            ProtocolKind::GraphqlWs => &GraphqlWsProtocol,
+        }
+    }
+}
+
+pub(crate) struct PusherProtocol;
+
+impl Protocol for PusherProtocol {
+    fn path(&self, config: &Config) -> String {
+        format!("/app/{}", config.app_key)
+    }
+
+    fn subscribe(
+        &self,
+        _config: &Config,
+        channel: &str,
+        filter: &FilterValue,
+        resume_from: Option<&str>,
+    ) -> Option<String> {
+        subscribe_payload(channel, filter, resume_from)
+    }
+
+    fn unsubscribe(&self, channel: &str) -> Option<String> {
+        unsubscribe_payload(channel)
+    }
+
+    fn resumes(&self) -> bool {
+        true
+    }
+
+    fn updates_filters(&self) -> bool {
+        true
+    }
+
+    fn classify(&self, text: &str) -> Option<PusherMessage> {
+        sonic_rs::from_str(text).ok()
+    }
+
+    fn describe(&self, _config: &Config) -> String {
+        "pusher".to_string()
+    }
+}
+
+/// Envelope-less text frames, each counted as a delivered message, for
+/// non-Pusher backends and for isolating protocol overhead.
+pub(crate) struct RawProtocol;
+
+impl Protocol for RawProtocol {
+    fn path(&self, config: &Config) -> String {
+        PusherProtocol.path(config)
+    }
+
+    fn hello(&self, config: &Config) -> Option<String> {
+        config.raw_register.clone()
+    }
+
+    fn subscribes(&self) -> bool {
+        false
+    }
+
+    fn subscribe(
+        &self,
+        _config: &Config,
+        _channel: &str,
+        _filter: &FilterValue,
+        _resume_from: Option<&str>,
+    ) -> Option<String> {
+        None
+    }
+
+    fn channel_scoped(&self) -> bool {
+        false
+    }
+
+    fn classify(&self, text: &str) -> Option<PusherMessage> {
+        Some(PusherMessage::raw(text))
+    }
+
+    fn describe(&self, config: &Config) -> String {
+        match &config.raw_register {
+            Some(register) => format!("raw, registering with {}", register),
+            None => "raw".to_string(),
+        }
+    }
+}
+
+/// GraphQL subscriptions over `graphql-transport-ws`.
+pub(crate) struct GraphqlWsProtocol;
+
+impl Protocol for GraphqlWsProtocol {
+    fn path(&self, config: &Config) -> String {
+        config.graphql_path.clone()
+    }
+
+    fn subprotocol(&self) -> Option<&'static str> {
+        Some(GRAPHQL_SUBPROTOCOL)
+    }
+
+    fn hello(&self, _config: &Config) -> Option<String> {
+        Some(GRAPHQL_INIT.to_owned())
+    }
+
+    fn subscribe(
+        &self,
+        config: &Config,
+        channel: &str,
+        filter: &FilterValue,
+        _resume_from: Option<&str>,
+    ) -> Option<String> {
+        graphql_subscribe_payload(&config.graphql_query, channel, filter)
+    }
+
+    /// Errors for a subscribe arrive as `error` frames
+    fn acknowledges_subscribe(&self) -> bool {
+        false
+    }
+
+    fn classify(&self, text: &str) -> Option<PusherMessage> {
+        PusherMessage::graphql(text)
+    }
+
+    fn ping(&self) -> &'static str {
+        GRAPHQL_PING
+    }
+
+    fn pong(&self) -> &'static str {
+        GRAPHQL_PONG
+    }
+
+    fn describe(&self, config: &Config) -> String {
+        format!(
+            "graphql-ws ({}) on {}",
+            GRAPHQL_SUBPROTOCOL, config.graphql_path
+        )
+    }
+}
@@ -0,0 +1,151 @@
+//! The built-in event publisher.
+
+use super::*;
+
+// =============================================================================
+// Built-in Publisher
+// =============================================================================
+
+/// Longest a single `--publish-rate` request may take before it counts as failed.
+pub(crate) const PUBLISH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Highest `--publish-rate`; every event is its own HTTP request.
+pub(crate) const MAX_PUBLISH_RATE: f64 = 10_000.0;
+
+/// What `--publish-rate` sent over the run.
+pub(crate) struct PublishReport {
+    pub(crate) sent: u64,
+    pub(crate) failed: u64,
+    /// HTTP API round trips (ms) of accepted events
+    pub(crate) latency: Histogram<u64>,
+    /// The most recent rejection or transport error
+    pub(crate) last_error: Option<String>,
+}
+
+/// Event body of the Pusher HTTP API.
+#[derive(Serialize)]
+pub(crate) struct PublishedEvent<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) channel: &'a str,
+    pub(crate) data: String,
+    pub(crate) tags: HashMap<&'a str, String>,
+}
+
+/// POST timestamped events at `--publish-rate` until `stop` is set. Each
+/// event carries a random token from the pool under `--filter-key`, so
+/// filtered scenarios receive their share. Requests run concurrently; a slow
+/// API shows up as latency rather than a lower rate.
+pub(crate) async fn publish_events(
+    config: Arc<Config>,
+    tokens: TokenPool,
+    stop: Arc<AtomicBool>,
+) -> Result<PublishReport> {
+    let base = match &config.publish_url {
+        Some(url) => url.trim_end_matches('/').to_string(),
+        None => config.server_origin("http", "https"),
+    };
+    let app_id = config.app_id.as_deref().unwrap_or_default();
+    let path = format!(
+        "{}/apps/{}/events",
+        url::Url::parse(&base)?.path().trim_end_matches('/'),
+        app_id
+    );
+    let url: Arc<str> = format!("{}/apps/{}/events", base, app_id).into();
+
+    let mut report = PublishReport {
+        sent: 0,
+        failed: 0,
+        latency: latency_histogram(),
+        last_error: None,
+    };
+    let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut ticker = interval(Duration::from_secs_f64(1.0 / config.publish_rate));
+    let mut poll = interval(Duration::from_millis(250));
+
+    while !stop.load(Ordering::Relaxed) {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let timestamp = unix_millis();
+                let mut tags = HashMap::from([("timestamp", timestamp.to_string())]);
+                if let Some(token) = tokens.peek_random() {
+                    tags.insert(config.filter_key.as_str(), token.to_string());
+                }
+                let body = sonic_rs::to_string(&PublishedEvent {
+                    name: &config.publish_event,
+                    channel: &config.channel,
+                    data: format!("{{\"timestamp\":{}}}", timestamp),
+                    tags,
+                })?;
+                let signed = format!("{}?{}", url, sign_publish(&config, &path, body.as_bytes())?);
+                let done_tx = done_tx.clone();
+                let tls = config.tls().clone();
+                tokio::spawn(async move {
+                    let started = Instant::now();
+                    let headers = [("Content-Type", "application/json")];
+                    let outcome = match tokio::time::timeout(
+                        PUBLISH_TIMEOUT,
+                        http_request(&tls, "POST", &signed, &headers, body.as_bytes()),
+                    )
+                    .await
+                    {
+                        Ok(Ok(r)) if (200..300).contains(&r.status) => {
+                            Ok(started.elapsed().as_millis() as u64)
+                        }
+                        Ok(Ok(r)) => Err(format!("HTTP {}: {}", r.status, r.body.trim())),
+                        Ok(Err(e)) => Err(e.to_string()),
+                        Err(_) => Err(format!("no response within {:?}", PUBLISH_TIMEOUT)),
+                    };
+                    let _ = done_tx.send(outcome);
+                });
+            }
+            Some(outcome) = done_rx.recv() => report.record(outcome),
+            _ = poll.tick() => {}
+        }
+    }
+
+    // Requests still in flight finish within PUBLISH_TIMEOUT
+    drop(done_tx);
+    while let Some(outcome) = done_rx.recv().await {
+        report.record(outcome);
+    }
+    Ok(report)
+}
+
+/// Query string authenticating a Pusher HTTP API request: key, timestamp,
+/// body MD5 and the HMAC-SHA256 of method, path and the sorted parameters.
+pub(crate) fn sign_publish(config: &Config, path: &str, body: &[u8]) -> Result<String> {
+    let query = format!(
+        "auth_key={}&auth_timestamp={}&auth_version=1.0&body_md5={}",
+        config.app_key,
+        unix_millis() / 1000,
+        to_hex(&openssl::hash::hash(
+            openssl::hash::MessageDigest::md5(),
+            body
+        )?)
+    );
+    let secret = config.app_secret.as_deref().unwrap_or_default();
+    let to_sign = format!("POST\n{}\n{}", path, query);
+    Ok(format!(
+        "{}&auth_signature={}",
+        query,
+        to_hex(&hmac_sha256(secret.as_bytes(), to_sign.as_bytes())?)
+    ))
+}
+
+impl PublishReport {
+    pub(crate) fn record(&mut self, outcome: std::result::Result<u64, String>) {
+        match outcome {
+            Ok(ms) => {
+                self.sent += 1;
+                let _ = self.latency.record(ms.max(1));
+            }
+            Err(e) => {
+                if self.failed == 0 {
+                    warn!("Publishing to the HTTP API failed: {}", e);
+                }
+                self.failed += 1;
+                self.last_error = Some(e);
+            }
+        }
+    }
+}
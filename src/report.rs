@@ -0,0 +1,2126 @@
+//! Result aggregation, derived metrics and summary output formats.
+
+use super::*;
+
+// =============================================================================
+// Aggregate Results
+// =============================================================================
+
+/// Percentiles of one histogram, for machine-readable output.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LatencySummary {
+    pub(crate) min: u64,
+    pub(crate) mean: f64,
+    pub(crate) p50: u64,
+    pub(crate) p95: u64,
+    pub(crate) p99: u64,
+    pub(crate) max: u64,
+    pub(crate) samples: u64,
+}
+
+impl LatencySummary {
+    pub(crate) fn from_histogram(hist: &Histogram<u64>) -> Option<Self> {
+        (!hist.is_empty()).then(|| Self {
+            min: hist.min(),
+            mean: hist.mean(),
+            p50: hist.value_at_quantile(0.50),
+            p95: hist.value_at_quantile(0.95),
+            p99: hist.value_at_quantile(0.99),
+            max: hist.max(),
+            samples: hist.len(),
+        })
+    }
+}
+
+/// Headline results of a run, serialized for uploads and notifications.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RunSummary {
+    pub(crate) run_id: String,
+    pub(crate) scenario: u8,
+    pub(crate) num_clients: usize,
+    pub(crate) measurement_secs: f64,
+    pub(crate) subscribe_success: u64,
+    pub(crate) subscribe_failed: u64,
+    pub(crate) connection_errors: u64,
+    /// Most TCP/TLS/WebSocket handshakes in flight at once
+    pub(crate) peak_pending_connects: usize,
+    pub(crate) errors_by_category: HashMap<String, u64>,
+    pub(crate) filter_updates: u64,
+    pub(crate) messages_received: u64,
+    pub(crate) bytes_sent: u64,
+    pub(crate) bytes_received: u64,
+    pub(crate) subscribe_latency_ms: Option<LatencySummary>,
+    pub(crate) filter_update_latency_ms: Option<LatencySummary>,
+    pub(crate) e2e_latency_ms: Option<LatencySummary>,
+    /// Fraction of messages the E2E percentiles were computed from
+    pub(crate) e2e_sample_rate: f64,
+    /// System clock jumps detected during the run
+    pub(crate) clock_jumps: u64,
+    /// E2E samples excluded because they straddled a clock jump
+    pub(crate) clock_excluded_e2e: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) e2e_windows: Vec<WindowLatency>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) normalized: Option<NormalizedMetrics>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) phases: Vec<PhaseSummary>,
+    /// Per-scenario results under `--mix`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) scenario_mix: Vec<ScenarioBreakdown>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub(crate) error_bursts: Vec<ErrorBurst>,
+    /// Malformed `token_address` tags (`--validate-token-addresses`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) malformed_token_addresses: Option<u64>,
+    /// Messages delivered outside the client's filter (`--validate-filters`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) false_positive_deliveries: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) delivery: Option<DeliveryCheck>,
+    /// Unsubscribe and resubscribe behaviour of resubscribing scenarios
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) churn: Option<ChurnSummary>,
+    /// `--client-ping` round trips
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) client_ping: Option<PingSummary>,
+    /// Connections per backend (`--backend-id`)
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) backends: HashMap<String, u64>,
+    /// Client events of publishing scenarios
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) client_events: Option<ClientEventSummary>,
+    /// Events sent by `--publish-rate`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) publisher: Option<PublisherSummary>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) derived: HashMap<String, f64>,
+    /// The `--abort-if` guard that stopped the run early
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) aborted: Option<String>,
+    /// Per-iteration results and their spread (`--iterations`)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) iterations: Option<IterationStats>,
+    /// Connect, subscribe, filter update and E2E latency histograms, kept for
+    /// the output formats and for merging iterations
+    #[serde(skip)]
+    pub(crate) trends: Vec<(&'static str, Histogram<u64>)>,
+}
+
+/// Headline numbers of one iteration.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct IterationResult {
+    pub(crate) messages_received: u64,
+    pub(crate) messages_per_sec: f64,
+    pub(crate) connection_errors: u64,
+    pub(crate) subscribe_p99_ms: Option<u64>,
+    pub(crate) e2e_p50_ms: Option<u64>,
+    pub(crate) e2e_p99_ms: Option<u64>,
+}
+
+/// Mean, standard deviation and range of one number across iterations.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Spread {
+    pub(crate) mean: f64,
+    pub(crate) stddev: f64,
+    pub(crate) min: f64,
+    pub(crate) max: f64,
+}
+
+impl Spread {
+    pub(crate) fn of(values: impl IntoIterator<Item = f64>) -> Option<Self> {
+        let values: Vec<f64> = values.into_iter().collect();
+        if values.is_empty() {
+            return None;
+        }
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        // Sample standard deviation; a single value has none
+        let stddev = if values.len() > 1 {
+            (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+        Some(Self {
+            mean,
+            stddev,
+            min: values.iter().copied().fold(f64::INFINITY, f64::min),
+            max: values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        })
+    }
+
+    /// Coefficient of variation, in percent.
+    pub(crate) fn cv_percent(&self) -> f64 {
+        if self.mean == 0.0 {
+            0.0
+        } else {
+            self.stddev / self.mean * 100.0
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct IterationStats {
+    pub(crate) runs: Vec<IterationResult>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) messages_per_sec: Option<Spread>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) subscribe_p99_ms: Option<Spread>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) e2e_p50_ms: Option<Spread>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) e2e_p99_ms: Option<Spread>,
+}
+
+/// Outcome of `--expect-msgs-per-min`: windows in which subscribed clients
+/// received fewer messages than expected.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct DeliveryCheck {
+    pub(crate) expected_per_min: f64,
+    pub(crate) window_secs: u64,
+    pub(crate) windows_checked: u64,
+    pub(crate) windows_below: u64,
+    pub(crate) clients_checked: u64,
+    pub(crate) clients_below: u64,
+}
+
+/// Subscription churn of a resubscribing scenario.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ChurnSummary {
+    pub(crate) cycles: u64,
+    pub(crate) unsubscribe_acks: u64,
+    pub(crate) unsubscribe_ack_latency_ms: Option<LatencySummary>,
+    /// Channel messages received between an unsubscribe and the fresh subscription
+    pub(crate) post_unsubscribe_messages: u64,
+    pub(crate) resubscribe_latency_ms: Option<LatencySummary>,
+}
+
+/// Client events published and relayed to other benchmark clients.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ClientEventSummary {
+    pub(crate) published: u64,
+    pub(crate) received: u64,
+    pub(crate) latency_ms: Option<LatencySummary>,
+}
+
+/// Events `--publish-rate` sent through the HTTP API.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PublisherSummary {
+    pub(crate) published: u64,
+    pub(crate) failed: u64,
+    pub(crate) latency_ms: Option<LatencySummary>,
+}
+
+/// Round trips of `--client-ping`.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PingSummary {
+    pub(crate) pings_sent: u64,
+    pub(crate) pings_unanswered: u64,
+    pub(crate) rtt_us: Option<LatencySummary>,
+}
+
+/// Costs per received message, comparable across scenarios whose message
+/// volumes differ by orders of magnitude.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct NormalizedMetrics {
+    /// Mean E2E latency of a message
+    pub(crate) e2e_ms_per_message: Option<f64>,
+    /// Generator CPU time per 1000 messages
+    pub(crate) cpu_ms_per_1k_messages: Option<f64>,
+    pub(crate) bytes_received_per_message: f64,
+    pub(crate) bytes_sent_per_message: f64,
+    pub(crate) messages_per_client_per_sec: f64,
+}
+
+/// Counters and E2E latency over one `--plan` phase.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct PhaseSummary {
+    pub(crate) name: String,
+    pub(crate) scenario: u8,
+    pub(crate) duration_secs: f64,
+    /// Connections open when the phase ended
+    pub(crate) active_at_end: usize,
+    pub(crate) subscribe_success: u64,
+    pub(crate) connection_errors: u64,
+    pub(crate) messages_received: u64,
+    pub(crate) e2e_latency_ms: Option<LatencySummary>,
+}
+
+/// Counters and latencies of the clients that ran one `--mix` scenario.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ScenarioBreakdown {
+    pub(crate) scenario: u8,
+    pub(crate) name: String,
+    pub(crate) clients: u64,
+    pub(crate) subscribe_success: u64,
+    pub(crate) connection_errors: u64,
+    pub(crate) messages_received: u64,
+    pub(crate) subscribe_latency_ms: Option<LatencySummary>,
+    pub(crate) e2e_latency_ms: Option<LatencySummary>,
+}
+
+/// Running totals behind a [`ScenarioBreakdown`].
+pub(crate) struct MixAccumulator {
+    pub(crate) clients: u64,
+    pub(crate) subscribe_success: u64,
+    pub(crate) connection_errors: u64,
+    pub(crate) messages_received: u64,
+    pub(crate) subscribe_hist: Histogram<u64>,
+    pub(crate) e2e_hist: Histogram<u64>,
+}
+
+impl MixAccumulator {
+    pub(crate) fn new() -> Self {
+        Self {
+            clients: 0,
+            subscribe_success: 0,
+            connection_errors: 0,
+            messages_received: 0,
+            subscribe_hist: latency_histogram(),
+            e2e_hist: latency_histogram(),
+        }
+    }
+
+    /// Count one client the way the headline totals do.
+    pub(crate) fn record(&mut self, r: &ClientResult) {
+        self.clients += 1;
+        self.messages_received += r.messages_received;
+        if r.connection_error || !r.connected {
+            self.connection_errors += 1;
+        } else if r.subscribe_success {
+            self.subscribe_success += 1;
+            if let Some(lat) = r.subscribe_latency_ms {
+                let _ = self.subscribe_hist.record(lat);
+            }
+        }
+        for &lat in r.e2e_latencies.values().flatten() {
+            let _ = self.e2e_hist.record(lat);
+        }
+    }
+
+    pub(crate) fn summary(&self, scenario: u8, name: &str) -> ScenarioBreakdown {
+        ScenarioBreakdown {
+            scenario,
+            name: name.to_string(),
+            clients: self.clients,
+            subscribe_success: self.subscribe_success,
+            connection_errors: self.connection_errors,
+            messages_received: self.messages_received,
+            subscribe_latency_ms: LatencySummary::from_histogram(&self.subscribe_hist),
+            e2e_latency_ms: LatencySummary::from_histogram(&self.e2e_hist),
+        }
+    }
+}
+
+/// A cluster of errors close together in time.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct ErrorBurst {
+    /// First error, seconds after the run began
+    pub(crate) offset_secs: f64,
+    pub(crate) duration_ms: u64,
+    pub(crate) errors: u64,
+    /// Categories in the burst, most frequent first
+    pub(crate) categories: Vec<(String, u64)>,
+}
+
+/// E2E percentiles over one `--latency-window` interval.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct WindowLatency {
+    /// Window start, seconds after the measurement phase began
+    pub(crate) offset_secs: u64,
+    pub(crate) p50: u64,
+    pub(crate) p99: u64,
+    pub(crate) max: u64,
+    pub(crate) samples: u64,
+}
+
+/// Everything a finished run hands to the report.
+pub(crate) struct TestOutcome {
+    pub(crate) results: Vec<ClientResult>,
+    /// Wall-clock length of the measurement phase (post warm-up)
+    pub(crate) measurement_duration: Duration,
+    pub(crate) cpu_windows: Vec<CpuWindow>,
+    pub(crate) clock_jumps: Vec<ClockJump>,
+    /// Completed `--plan` phases, in order
+    pub(crate) phases: Vec<PhaseMark>,
+    /// Outcome of `--chaos-drop-pongs-at`
+    pub(crate) chaos: Option<ChaosReport>,
+    pub(crate) drain_samples: Vec<DrainSample>,
+    /// Distinct tokens used by filters, and the token pool size
+    pub(crate) token_coverage: (usize, usize),
+    /// Stage number (as logged) and when it began, in order
+    pub(crate) stages: Vec<(u8, Instant)>,
+    /// When result collection finished
+    pub(crate) finished: Instant,
+    /// Unix ms at which the measurement phase began
+    pub(crate) measure_started_ms: u64,
+    /// Most handshakes that were in flight at once
+    pub(crate) peak_pending_connects: usize,
+    /// The `--abort-if` guard that ended the run early, if one did
+    pub(crate) aborted: Option<String>,
+    /// What `--publish-rate` sent
+    pub(crate) publish: Option<PublishReport>,
+}
+
+#[inline]
+pub(crate) fn latency_histogram() -> Histogram<u64> {
+    Histogram::<u64>::new_with_bounds(1, 60_000, 3).unwrap()
+}
+
+#[inline]
+pub(crate) fn size_histogram() -> Histogram<u64> {
+    Histogram::<u64>::new_with_bounds(1, 64 * 1024 * 1024, 3).unwrap()
+}
+
+pub(crate) fn log_latency_stats(hist: &Histogram<u64>) {
+    info!("  Min:    {}", hist.min());
+    info!("  Mean:   {:.2}", hist.mean());
+    info!("  p50:    {}", hist.value_at_quantile(0.50));
+    info!("  p95:    {}", hist.value_at_quantile(0.95));
+    info!("  p99:    {}", hist.value_at_quantile(0.99));
+    info!("  Max:    {}", hist.max());
+    info!("  Samples:{}", hist.len());
+}
+
+/// Chart error counts over time, one row per category under a row of stage
+/// numbers, so clustering during the ramp, hold or teardown stands out.
+pub(crate) fn log_error_timeline(
+    errors: &[(Instant, ErrorCategory)],
+    stages: &[(u8, Instant)],
+    finished: Instant,
+) {
+    const COLUMNS: usize = 60;
+    const LEVELS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    const STAGE_NAMES: [&str; 4] = ["ramp-up", "warm-up", "hold", "ramp-down"];
+
+    let Some(&(first_stage, started)) = stages.first() else {
+        return;
+    };
+    let span = finished
+        .saturating_duration_since(started)
+        .max(Duration::from_millis(COLUMNS as u64));
+    let column_of = |at: Instant| {
+        let offset = at.saturating_duration_since(started).as_secs_f64();
+        ((offset / span.as_secs_f64() * COLUMNS as f64) as usize).min(COLUMNS - 1)
+    };
+    let stage_of = |at: Instant| {
+        stages
+            .iter()
+            .rev()
+            .find(|(_, begun)| at >= *begun)
+            .map_or(first_stage, |(stage, _)| *stage)
+    };
+
+    let stage_row: String = (0..COLUMNS)
+        .map(|c| {
+            let at = started + span.mul_f64(c as f64 / COLUMNS as f64);
+            char::from(b'0' + stage_of(at))
+        })
+        .collect();
+
+    // category -> (errors per column, errors per stage)
+    let mut rows: HashMap<ErrorCategory, ([u64; COLUMNS], [u64; 4])> = HashMap::new();
+    for &(at, category) in errors {
+        let (columns, per_stage) = rows.entry(category).or_insert(([0; COLUMNS], [0; 4]));
+        columns[column_of(at)] += 1;
+        per_stage[(stage_of(at) as usize).clamp(1, 4) - 1] += 1;
+    }
+    let mut rows: Vec<_> = rows.into_iter().collect();
+    rows.sort_by_key(|(category, _)| *category);
+
+    info!(
+        "  Timeline (1 column = {:.2}s, digits = stage):",
+        span.as_secs_f64() / COLUMNS as f64
+    );
+    info!("    {:<18} |{}|", "stage", stage_row);
+    for (category, (columns, _)) in &rows {
+        let peak = columns.iter().copied().max().unwrap_or(0).max(1);
+        let line: String = columns
+            .iter()
+            .map(|&n| LEVELS[(n * 8).div_ceil(peak) as usize])
+            .collect();
+        info!("    {:<18} |{}| peak {}", category.label(), line, peak);
+    }
+    info!("  By Stage:");
+    for (category, (_, per_stage)) in &rows {
+        let counts: Vec<String> = STAGE_NAMES
+            .iter()
+            .zip(per_stage)
+            .map(|(name, n)| format!("{}={}", name, n))
+            .collect();
+        info!("    {:<18} {}", category.label(), counts.join(" "));
+    }
+}
+
+/// Cluster time-ordered `errors` into bursts: runs whose consecutive errors
+/// are at most `gap` apart and that hold at least `min_errors`. Errors in
+/// shorter runs are background and not returned.
+pub(crate) fn find_error_bursts(
+    errors: &[(Instant, ErrorCategory)],
+    started: Instant,
+    gap: Duration,
+    min_errors: usize,
+) -> Vec<ErrorBurst> {
+    let mut bursts = Vec::new();
+    let mut run_start = 0;
+    for end in 1..=errors.len() {
+        let split =
+            end == errors.len() || errors[end].0.saturating_duration_since(errors[end - 1].0) > gap;
+        if !split {
+            continue;
+        }
+        let run = &errors[run_start..end];
+        run_start = end;
+        if run.len() < min_errors.max(1) {
+            continue;
+        }
+
+        let (first, last) = (run[0].0, run[run.len() - 1].0);
+        let mut counts: HashMap<ErrorCategory, u64> = HashMap::new();
+        for &(_, category) in run {
+            *counts.entry(category).or_default() += 1;
+        }
+        let mut counts: Vec<_> = counts.into_iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        bursts.push(ErrorBurst {
+            offset_secs: first.saturating_duration_since(started).as_secs_f64(),
+            duration_ms: last.duration_since(first).as_millis() as u64,
+            errors: run.len() as u64,
+            categories: counts
+                .into_iter()
+                .map(|(category, n)| (category.label(), n))
+                .collect(),
+        });
+    }
+    bursts
+}
+
+/// Subscribe outcome per plateau of a step profile, one report line each.
+/// `results` is in spawn order, so plateau `k` is the k-th run of `step_size`.
+pub(crate) fn step_profile_lines(results: &[ClientResult], step_size: usize) -> Vec<String> {
+    results
+        .chunks(step_size)
+        .enumerate()
+        .map(|(step, cohort)| {
+            let mut hist = latency_histogram();
+            for lat in cohort.iter().filter_map(|r| r.subscribe_latency_ms) {
+                let _ = hist.record(lat);
+            }
+            let subscribed = cohort.iter().filter(|r| r.subscribe_success).count();
+            let errors: usize = cohort.iter().map(|r| r.errors.len()).sum();
+            let latency = if hist.is_empty() {
+                String::new()
+            } else {
+                format!(
+                    " p50={:>5} p99={:>5} max={:>5}",
+                    hist.value_at_quantile(0.50),
+                    hist.value_at_quantile(0.99),
+                    hist.max()
+                )
+            };
+            format!(
+                "  step {:>3} ({:>7} clients): subscribed={:>6}/{}{} errors={}",
+                step + 1,
+                step * step_size + cohort.len(),
+                subscribed,
+                cohort.len(),
+                latency,
+                errors
+            )
+        })
+        .collect()
+}
+
+pub(crate) fn aggregate_results(outcome: TestOutcome, config: &Config) -> RunSummary {
+    let TestOutcome {
+        results,
+        measurement_duration,
+        cpu_windows,
+        clock_jumps,
+        phases,
+        chaos,
+        drain_samples,
+        token_coverage,
+        stages,
+        finished,
+        measure_started_ms,
+        peak_pending_connects,
+        aborted,
+        publish,
+    } = outcome;
+
+    let step_lines = match config.profile {
+        LoadProfile::Step => step_profile_lines(&results, config.step_size.max(1)),
+        LoadProfile::Linear
+        | LoadProfile::Spike
+        | LoadProfile::Wave
+        | LoadProfile::File
+        | LoadProfile::Poisson
+        | LoadProfile::Plan => Vec::new(),
+    };
+
+    // Well-behaved clients drive the headline numbers; the misbehaving cohort
+    // is reported separately so degradation is visible by comparison.
+    let (results, non_compliant): (Vec<_>, Vec<_>) =
+        results.into_iter().partition(|r| r.misbehavior.is_none());
+    let num_results = results.len() as u64;
+
+    let mut subscribe_hist = latency_histogram();
+    let mut filter_hist = latency_histogram();
+    let mut e2e_hist = latency_histogram();
+    let mut e2e_by_event: HashMap<String, Histogram<u64>> = HashMap::new();
+    let mut e2e_by_stratum: HashMap<String, Histogram<u64>> = HashMap::new();
+    let mut by_scenario: BTreeMap<u8, MixAccumulator> = BTreeMap::new();
+    let mut propagation_hist = latency_histogram();
+    let mut stale_hist = latency_histogram();
+    let mut stale_messages: u64 = 0;
+    let mut resubscribe_cycles: u64 = 0;
+    let mut unsubscribe_ack_hist = latency_histogram();
+    let mut resubscribe_hist = latency_histogram();
+    let mut post_unsubscribe_messages: u64 = 0;
+    let mut suspect_hist = latency_histogram();
+    let mut clock_excluded_e2e: u64 = 0;
+    let mut phase_e2e: Vec<Histogram<u64>> = phases.iter().map(|_| latency_histogram()).collect();
+    let mut size_hist = size_histogram();
+    let mut rtt_hist = Histogram::<u64>::new_with_bounds(1, 60_000_000, 3).unwrap();
+    let mut ping_rtt_hist = Histogram::<u64>::new_with_bounds(1, 60_000_000, 3).unwrap();
+    let (mut pings_sent, mut pings_unanswered): (u64, u64) = (0, 0);
+    let mut backends: HashMap<String, u64> = HashMap::new();
+    let (mut client_events_sent, mut client_events_received): (u64, u64) = (0, 0);
+    let mut client_event_hist = latency_histogram();
+    // Reconnects, and those that landed on the backend they left
+    let (mut backend_reconnects, mut backend_stayed): (u64, u64) = (0, 0);
+    let mut server_time_hist = latency_histogram();
+    let mut tcp_retransmits: u64 = 0;
+    let mut dns_hist = latency_histogram();
+    let mut connect_hist = latency_histogram();
+    let mut tls_hist = latency_histogram();
+    let mut upgrade_hist = latency_histogram();
+    let mut connecting_hist = latency_histogram();
+    let window_ms = config.latency_window * 1000;
+    let mut e2e_windows: Vec<Histogram<u64>> = Vec::new();
+    let mut established_hist = latency_histogram();
+    let mut connect_queue_hist = latency_histogram();
+    let mut close_drain_hist = latency_histogram();
+    let mut reconnect_hist = latency_histogram();
+    let mut catchup_hist = latency_histogram();
+    let mut reconnects: u64 = 0;
+    let mut acks_sent: u64 = 0;
+    let mut acks_confirmed: u64 = 0;
+    let mut acks_outstanding: u64 = 0;
+    let mut ack_rtt_hist = latency_histogram();
+    let mut addresses_checked: u64 = 0;
+    let mut filters_checked: u64 = 0;
+    let mut false_positives: u64 = 0;
+    let mut malformed_addresses: HashMap<&'static str, u64> = HashMap::new();
+    // (client id, messages per checked window) under `--expect-msgs-per-min`
+    let mut throughput_windows: Vec<(usize, Vec<u64>)> = Vec::new();
+    let mut resume_attempts: u64 = 0;
+    let mut replayed_messages: u64 = 0;
+    let mut post_close_messages: u64 = 0;
+    let mut bytes_sent: u64 = 0;
+    let mut bytes_received: u64 = 0;
+
+    let mut total_messages: u64 = 0;
+    let mut subscribe_success: u64 = 0;
+    let mut subscribe_failed: u64 = 0;
+    let mut connection_errors: u64 = 0;
+    let mut filter_updates: u64 = 0;
+    let mut error_counts: HashMap<ErrorCategory, u64> = HashMap::new();
+    let mut timed_errors: Vec<(Instant, ErrorCategory)> = Vec::new();
+    // code -> (count, first reason seen)
+    let mut close_codes: HashMap<u16, (u64, String)> = HashMap::new();
+
+    for r in results {
+        total_messages += r.messages_received;
+        if !config.scenario_mix.is_empty() {
+            by_scenario
+                .entry(r.scenario)
+                .or_insert_with(MixAccumulator::new)
+                .record(&r);
+        }
+
+        for &(at, category) in &r.errors {
+            *error_counts.entry(category).or_default() += 1;
+            timed_errors.push((at, category));
+        }
+
+        if let Some((code, reason)) = r.close_frame {
+            close_codes.entry(code).or_insert((0, reason)).0 += 1;
+        }
+
+        if let Some(setup) = r.setup {
+            let _ = dns_hist.record(setup.dns_ms);
+            let _ = connect_hist.record(setup.connect_ms);
+            if let Some(tls_ms) = setup.tls_ms {
+                let _ = tls_hist.record(tls_ms);
+            }
+            let _ = upgrade_hist.record(setup.upgrade_ms);
+            let _ = connecting_hist.record(
+                setup.dns_ms + setup.connect_ms + setup.tls_ms.unwrap_or(0) + setup.upgrade_ms,
+            );
+        }
+        post_close_messages += r.post_close_messages;
+        reconnects += r.reconnects;
+        acks_sent += r.acks_sent;
+        acks_confirmed += r.acks_confirmed;
+        acks_outstanding += r.acks_outstanding;
+        for lat in r.ack_rtt_latencies {
+            let _ = ack_rtt_hist.record(lat);
+        }
+        addresses_checked += r.addresses_checked;
+        filters_checked += r.filters_checked;
+        false_positives += r.false_positives;
+        if !r.throughput_windows.is_empty() {
+            throughput_windows.push((r.id, r.throughput_windows));
+        }
+        for (problem, n) in r.malformed_addresses {
+            *malformed_addresses.entry(problem).or_default() += n;
+        }
+        resume_attempts += r.resume_attempts;
+        replayed_messages += r.replayed_messages;
+        for lat in r.reconnect_latencies {
+            let _ = reconnect_hist.record(lat);
+        }
+        for lat in r.catchup_latencies {
+            let _ = catchup_hist.record(lat);
+        }
+        if let Some(drain) = r.close_drain_ms {
+            let _ = close_drain_hist.record(drain);
+        }
+        if let Some(wait) = r.established_wait_ms {
+            let _ = established_hist.record(wait);
+        }
+        for wait in r.connect_queue_ms {
+            let _ = connect_queue_hist.record(wait);
+        }
+        bytes_sent += r.bytes_sent;
+        bytes_received += r.bytes_received;
+
+        if r.connection_error {
+            connection_errors += 1;
+        } else if r.subscribe_success {
+            subscribe_success += 1;
+            if let Some(lat) = r.subscribe_latency_ms {
+                let _ = subscribe_hist.record(lat);
+            }
+        } else if r.connected {
+            subscribe_failed += 1;
+        } else {
+            connection_errors += 1;
+        }
+
+        for lat in r.filter_update_latencies {
+            let _ = filter_hist.record(lat);
+            filter_updates += 1;
+        }
+
+        for lat in r.filter_propagation_latencies {
+            let _ = propagation_hist.record(lat);
+        }
+        for lat in r.stale_delivery_windows {
+            let _ = stale_hist.record(lat);
+        }
+        stale_messages += r.stale_messages;
+
+        resubscribe_cycles += r.resubscribe_cycles;
+        for lat in r.unsubscribe_ack_latencies {
+            let _ = unsubscribe_ack_hist.record(lat);
+        }
+        for lat in r.resubscribe_latencies {
+            let _ = resubscribe_hist.record(lat);
+        }
+        post_unsubscribe_messages += r.post_unsubscribe_messages;
+
+        for rtt in r.tcp_rtt_us {
+            let _ = rtt_hist.record(rtt);
+        }
+        tcp_retransmits += r.tcp_retransmits;
+        for rtt in r.ping_rtt_us {
+            let _ = ping_rtt_hist.record(rtt);
+        }
+        pings_sent += r.pings_sent;
+        pings_unanswered += r.pings_unanswered;
+        for pair in r.backends.windows(2) {
+            backend_reconnects += 1;
+            backend_stayed += (pair[0] == pair[1]) as u64;
+        }
+        for backend in r.backends {
+            *backends.entry(backend).or_default() += 1;
+        }
+        client_events_sent += r.client_events_sent;
+        client_events_received += r.client_events_received;
+        for lat in r.client_event_latencies {
+            let _ = client_event_hist.record(lat);
+        }
+        for (latency, rtt_us) in r.rtt_tagged_e2e {
+            // Subtract the one-way network estimate (RTT / 2) to isolate server time
+            let _ = server_time_hist.record(latency.saturating_sub(rtt_us as u64 / 2000));
+        }
+
+        for size in r.message_sizes {
+            let _ = size_hist.record(size.max(1));
+        }
+
+        for (received_ms, latency) in r.e2e_timeline {
+            let phase = phases.partition_point(|p| p.start.unix_ms <= received_ms);
+            if let Some(hist) = phase
+                .checked_sub(1)
+                .filter(|&i| received_ms < phases[i].end.unix_ms)
+                .map(|i| &mut phase_e2e[i])
+            {
+                let _ = hist.record(latency);
+            }
+
+            let Some(window) = received_ms
+                .saturating_sub(measure_started_ms)
+                .checked_div(window_ms)
+            else {
+                continue;
+            };
+            let window = window as usize;
+            if e2e_windows.len() <= window {
+                e2e_windows.resize_with(window + 1, latency_histogram);
+            }
+            let _ = e2e_windows[window].record(latency);
+        }
+
+        for lat in r.suspect_e2e_latencies {
+            let _ = suspect_hist.record(lat);
+        }
+        clock_excluded_e2e += r.clock_excluded_e2e;
+
+        for (event, lats) in r.e2e_latencies {
+            let event_hist = e2e_by_event.entry(event).or_insert_with(latency_histogram);
+            for lat in lats {
+                let _ = e2e_hist.record(lat);
+                let _ = event_hist.record(lat);
+            }
+        }
+
+        for (stratum, lats) in r.e2e_by_stratum {
+            let stratum_hist = e2e_by_stratum
+                .entry(stratum)
+                .or_insert_with(latency_histogram);
+            for lat in lats {
+                let _ = stratum_hist.record(lat);
+            }
+        }
+    }
+
+    info!("╔════════════════════════════════════════════════════════════╗");
+    info!("║                    BENCHMARK SUMMARY                       ║");
+    info!("╚════════════════════════════════════════════════════════════╝");
+    if let Some(reason) = &aborted {
+        warn!("");
+        warn!("Run ABORTED by --abort-if: {}", reason);
+    }
+
+    info!("");
+    info!("Connection Metrics:");
+    info!("  Subscribe Success:   {}", subscribe_success);
+    info!("  Subscribe Failed:    {}", subscribe_failed);
+    info!("  Connection Errors:   {}", connection_errors);
+    let mut categories: Vec<_> = error_counts.into_iter().collect();
+    categories.sort();
+    for (category, count) in &categories {
+        info!("    {:<18} {}", category.label(), count);
+    }
+    info!("  Filter Updates:      {}", filter_updates);
+    if !close_codes.is_empty() {
+        info!("  Server Close Codes:");
+        let mut codes: Vec<_> = close_codes.into_iter().collect();
+        codes.sort_by(|a, b| b.1 .0.cmp(&a.1 .0).then(a.0.cmp(&b.0)));
+        for (code, (count, reason)) in codes {
+            if reason.is_empty() {
+                info!("    {:<6} {}", code, count);
+            } else {
+                info!("    {:<6} {} ({})", code, count, reason);
+            }
+        }
+    }
+    info!("  Messages Received:   {}", total_messages);
+
+    let (tokens_used, pool_size) = token_coverage;
+    info!("");
+    info!("Token Coverage:");
+    info!(
+        "  Distinct Tokens:     {} of {} ({:.1}%)",
+        tokens_used,
+        pool_size,
+        if pool_size > 0 {
+            tokens_used as f64 / pool_size as f64 * 100.0
+        } else {
+            0.0
+        }
+    );
+
+    info!("");
+    info!("Bandwidth:");
+    info!(
+        "  Bytes Sent:          {} ({:.3} MB/s)",
+        bytes_sent,
+        megabytes_per_sec(bytes_sent, measurement_duration)
+    );
+    info!(
+        "  Bytes Received:      {} ({:.3} MB/s)",
+        bytes_received,
+        megabytes_per_sec(bytes_received, measurement_duration)
+    );
+    if let (Some(sent), Some(received)) = (
+        bytes_sent.checked_div(num_results),
+        bytes_received.checked_div(num_results),
+    ) {
+        info!("  Per Client (avg):    sent={} received={}", sent, received);
+    }
+
+    info!("");
+    info!("Connection Setup (ms):    p50      p95      p99      max");
+    for (phase, hist) in [
+        ("Queue Wait", &connect_queue_hist),
+        ("DNS", &dns_hist),
+        ("Connect", &connect_hist),
+        ("TLS Handshake", &tls_hist),
+        ("WS Upgrade", &upgrade_hist),
+        ("Established Wait", &established_hist),
+    ] {
+        if hist.is_empty() {
+            continue;
+        }
+        info!(
+            "  {:<18} {:>8} {:>8} {:>8} {:>8}",
+            phase,
+            hist.value_at_quantile(0.50),
+            hist.value_at_quantile(0.95),
+            hist.value_at_quantile(0.99),
+            hist.max()
+        );
+    }
+    if config.max_pending_connects > 0 {
+        info!(
+            "  Peak Pending:      {} handshakes in flight (cap {})",
+            peak_pending_connects, config.max_pending_connects
+        );
+    } else {
+        info!(
+            "  Peak Pending:      {} handshakes in flight",
+            peak_pending_connects
+        );
+    }
+
+    info!("");
+    info!("Subscribe Latency (ms):");
+    if !subscribe_hist.is_empty() {
+        info!("  Min:    {}", subscribe_hist.min());
+        info!("  Mean:   {:.2}", subscribe_hist.mean());
+        info!("  p50:    {}", subscribe_hist.value_at_quantile(0.50));
+        info!("  p95:    {}", subscribe_hist.value_at_quantile(0.95));
+        info!("  p99:    {}", subscribe_hist.value_at_quantile(0.99));
+        info!("  Max:    {}", subscribe_hist.max());
+    } else {
+        info!("  No data");
+    }
+
+    if !filter_hist.is_empty() {
+        info!("");
+        info!("Filter Update Latency (ms):");
+        info!("  Min:    {}", filter_hist.min());
+        info!("  Mean:   {:.2}", filter_hist.mean());
+        info!("  p50:    {}", filter_hist.value_at_quantile(0.50));
+        info!("  p95:    {}", filter_hist.value_at_quantile(0.95));
+        info!("  p99:    {}", filter_hist.value_at_quantile(0.99));
+        info!("  Max:    {}", filter_hist.max());
+    }
+
+    if filter_updates > 0 {
+        info!("");
+        info!("Filter Propagation Latency (ms):");
+        if !propagation_hist.is_empty() {
+            log_latency_stats(&propagation_hist);
+        } else {
+            info!("  No data");
+        }
+
+        info!("");
+        info!("Stale Delivery Window (ms):");
+        info!("  Stale Messages:{}", stale_messages);
+        if !stale_hist.is_empty() {
+            log_latency_stats(&stale_hist);
+        } else {
+            info!("  No data");
+        }
+    }
+
+    if resubscribe_cycles > 0 {
+        let acks = unsubscribe_ack_hist.len();
+        info!("");
+        info!("Subscription Churn:");
+        info!("  Cycles:             {}", resubscribe_cycles);
+        info!(
+            "  Unsubscribe Acks:   {} ({:.1}%)",
+            acks,
+            acks as f64 / resubscribe_cycles as f64 * 100.0
+        );
+        info!("  Post-Unsub Msgs:    {}", post_unsubscribe_messages);
+        info!("");
+        info!("Unsubscribe Ack Latency (ms):");
+        if !unsubscribe_ack_hist.is_empty() {
+            log_latency_stats(&unsubscribe_ack_hist);
+        } else {
+            info!("  No data");
+        }
+        info!("");
+        info!("Resubscribe Latency (ms):");
+        if !resubscribe_hist.is_empty() {
+            log_latency_stats(&resubscribe_hist);
+        } else {
+            info!("  No data");
+        }
+    }
+
+    info!("");
+    if config.e2e_sample_rate < 1.0 {
+        info!(
+            "End-to-End Latency (ms, {:.2}% of messages sampled):",
+            config.e2e_sample_rate * 100.0
+        );
+    } else {
+        info!("End-to-End Latency (ms):");
+    }
+    if !e2e_hist.is_empty() {
+        info!("  Min:    {}", e2e_hist.min());
+        info!("  Mean:   {:.2}", e2e_hist.mean());
+        info!("  p50:    {}", e2e_hist.value_at_quantile(0.50));
+        info!("  p95:    {}", e2e_hist.value_at_quantile(0.95));
+        info!("  p99:    {}", e2e_hist.value_at_quantile(0.99));
+        info!("  Max:    {}", e2e_hist.max());
+        info!("  Samples:{}", e2e_hist.len());
+    } else {
+        info!("  No data");
+    }
+    if !clock_jumps.is_empty() {
+        info!(
+            "  Clock Jumps: {} ({} samples excluded)",
+            clock_jumps.len(),
+            clock_excluded_e2e
+        );
+        for jump in &clock_jumps {
+            info!(
+                "    t={:>8.2}s  {:+}ms",
+                jump.at.as_secs_f64(),
+                jump.delta_ms
+            );
+        }
+    }
+
+    // Windows whose p99 exceeds twice the run-wide p99 are flagged
+    let overall_p99 = e2e_hist.value_at_quantile(0.99);
+    let e2e_windows: Vec<WindowLatency> = e2e_windows
+        .iter()
+        .enumerate()
+        .filter(|(_, hist)| !hist.is_empty())
+        .map(|(i, hist)| WindowLatency {
+            offset_secs: i as u64 * config.latency_window,
+            p50: hist.value_at_quantile(0.50),
+            p99: hist.value_at_quantile(0.99),
+            max: hist.max(),
+            samples: hist.len(),
+        })
+        .collect();
+    if !e2e_windows.is_empty() {
+        info!("");
+        info!(
+            "Rolling E2E Latency (ms, {}s windows from measurement start):",
+            config.latency_window
+        );
+        for w in &e2e_windows {
+            info!(
+                "  t={:>5}s p50={:>6} p99={:>6} max={:>6} samples={:>8}{}",
+                w.offset_secs,
+                w.p50,
+                w.p99,
+                w.max,
+                w.samples,
+                if w.p99 > overall_p99 * 2 {
+                    "  <- spike"
+                } else {
+                    ""
+                }
+            );
+        }
+    }
+
+    if !rtt_hist.is_empty() {
+        info!("");
+        info!("Kernel TCP RTT (us, TCP_INFO sampled clients):");
+        log_latency_stats(&rtt_hist);
+        info!("  Retransmits:{}", tcp_retransmits);
+        if !server_time_hist.is_empty() {
+            info!("");
+            info!("E2E Latency minus RTT/2 (ms, sampled clients):");
+            log_latency_stats(&server_time_hist);
+        }
+    }
+
+    if config.client_ping > 0 {
+        info!("");
+        info!(
+            "Client Ping RTT (us, {}):",
+            match config.client_ping_kind {
+                PingKind::Pusher if config.protocol == ProtocolKind::GraphqlWs => "ping -> pong",
+                PingKind::Pusher => "pusher:ping -> pusher:pong",
+                PingKind::Frame => "ping frame -> pong frame",
+            }
+        );
+        info!("  Pings Sent:          {}", pings_sent);
+        info!("  Unanswered:          {}", pings_unanswered);
+        if !ping_rtt_hist.is_empty() {
+            log_latency_stats(&ping_rtt_hist);
+        }
+    }
+
+    if client_events_sent > 0 || client_events_received > 0 {
+        info!("");
+        info!("Client Events ({}):", config.client_event);
+        info!("  Published:           {}", client_events_sent);
+        info!("  Received by Others:  {}", client_events_received);
+        if !client_event_hist.is_empty() {
+            info!("  Round Trip (ms, published -> another client's receipt):");
+            log_latency_stats(&client_event_hist);
+        }
+    }
+
+    if let Some(publish) = &publish {
+        info!("");
+        info!(
+            "Built-in Publisher ({} on {}, {:.1}/s):",
+            config.publish_event, config.channel, config.publish_rate
+        );
+        info!("  Published:           {}", publish.sent);
+        info!("  Failed:              {}", publish.failed);
+        if let Some(e) = &publish.last_error {
+            info!("  Last Error:          {}", e);
+        }
+        if !publish.latency.is_empty() {
+            info!("  HTTP API Round Trip (ms):");
+            log_latency_stats(&publish.latency);
+        }
+    }
+
+    if config.backend_id.is_some() {
+        const SHOWN: usize = 20;
+        let connections: u64 = backends.values().sum();
+        info!("");
+        info!("Backend Spread ({} connections):", connections);
+        let mut spread: Vec<_> = backends.iter().collect();
+        spread.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        for (backend, n) in spread.iter().take(SHOWN) {
+            info!(
+                "  {:<20} {} ({:.1}%)",
+                backend,
+                n,
+                **n as f64 / connections.max(1) as f64 * 100.0
+            );
+        }
+        if spread.len() > SHOWN {
+            info!("  ... {} more", spread.len() - SHOWN);
+        }
+        if backend_reconnects > 0 {
+            info!(
+                "  Sticky Reconnects:   {} of {} ({:.1}%)",
+                backend_stayed,
+                backend_reconnects,
+                backend_stayed as f64 / backend_reconnects as f64 * 100.0
+            );
+        }
+    }
+
+    info!("");
+    info!("Message Size (bytes):");
+    if !size_hist.is_empty() {
+        info!("  Min:    {}", size_hist.min());
+        info!("  Mean:   {:.2}", size_hist.mean());
+        info!("  p50:    {}", size_hist.value_at_quantile(0.50));
+        info!("  p99:    {}", size_hist.value_at_quantile(0.99));
+        info!("  Max:    {}", size_hist.max());
+    } else {
+        info!("  No data");
+    }
+
+    if !e2e_by_event.is_empty() {
+        let mut events: Vec<_> = e2e_by_event.iter().collect();
+        events.sort_by(|a, b| b.1.len().cmp(&a.1.len()).then_with(|| a.0.cmp(b.0)));
+        for (event, hist) in events {
+            info!("");
+            info!("End-to-End Latency [{}] (ms):", event);
+            log_latency_stats(hist);
+        }
+    }
+
+    if !step_lines.is_empty() {
+        info!("");
+        info!("Step Profile (subscribe latency ms, per plateau):");
+        for line in &step_lines {
+            info!("{}", line);
+        }
+    }
+
+    let phases: Vec<PhaseSummary> = phases
+        .iter()
+        .zip(&phase_e2e)
+        .map(|(mark, hist)| PhaseSummary {
+            name: mark.phase.name.clone(),
+            scenario: mark.phase.scenario,
+            duration_secs: mark.end.elapsed_secs - mark.start.elapsed_secs,
+            active_at_end: mark.end.active_connections,
+            subscribe_success: mark.end.subscribe_success - mark.start.subscribe_success,
+            connection_errors: mark.end.connection_errors - mark.start.connection_errors,
+            messages_received: mark.end.messages_received - mark.start.messages_received,
+            e2e_latency_ms: LatencySummary::from_histogram(hist),
+        })
+        .collect();
+    if !phases.is_empty() {
+        info!("");
+        info!("Plan Phases (E2E ms):");
+        for (i, phase) in phases.iter().enumerate() {
+            let e2e = phase
+                .e2e_latency_ms
+                .as_ref()
+                .map_or("no data".to_string(), |l| {
+                    format!("p50={} p99={} max={}", l.p50, l.p99, l.max)
+                });
+            info!(
+                "  {}. {:<16} s{} {:>6.0}s  active={:<6} subscribed={:<6} errors={:<5} messages={:<9} {}",
+                i + 1,
+                phase.name,
+                phase.scenario,
+                phase.duration_secs,
+                phase.active_at_end,
+                phase.subscribe_success,
+                phase.connection_errors,
+                phase.messages_received,
+                e2e
+            );
+        }
+    }
+
+    let scenario_mix: Vec<ScenarioBreakdown> = by_scenario
+        .iter()
+        .map(|(&scenario, acc)| acc.summary(scenario, &config.scenarios.get(scenario).name))
+        .collect();
+    if !scenario_mix.is_empty() {
+        info!("");
+        info!("Scenario Mix (ms):");
+        let p = |l: &Option<LatencySummary>, f: fn(&LatencySummary) -> u64| {
+            l.as_ref().map_or("-".to_string(), |l| f(l).to_string())
+        };
+        for mix in &scenario_mix {
+            info!(
+                "  s{:<3} {:<16} clients={:<6} subscribed={:<6} errors={:<5} messages={:<9} sub p99={:<6} e2e p50={:<6} p99={}",
+                mix.scenario,
+                mix.name,
+                mix.clients,
+                mix.subscribe_success,
+                mix.connection_errors,
+                mix.messages_received,
+                p(&mix.subscribe_latency_ms, |l| l.p99),
+                p(&mix.e2e_latency_ms, |l| l.p50),
+                p(&mix.e2e_latency_ms, |l| l.p99)
+            );
+        }
+    }
+
+    if !e2e_by_stratum.is_empty() {
+        info!("");
+        info!("End-to-End Latency by Token Stratum (ms):");
+        let mut strata: Vec<_> = e2e_by_stratum.iter().collect();
+        strata.sort_by(|a, b| a.0.cmp(b.0));
+        for (stratum, hist) in strata {
+            info!(
+                "  {:<32} p50={:>6} p95={:>6} p99={:>6} max={:>6} samples={}",
+                stratum,
+                hist.value_at_quantile(0.50),
+                hist.value_at_quantile(0.95),
+                hist.value_at_quantile(0.99),
+                hist.max(),
+                hist.len()
+            );
+        }
+    }
+
+    if !cpu_windows.is_empty() {
+        info!("");
+        info!("Load Generator CPU:");
+        let mut lower = 0.0;
+        for (label, upper) in CPU_TIERS {
+            let tier: Vec<_> = cpu_windows
+                .iter()
+                .filter(|w| w.cpu_pct >= lower && w.cpu_pct < upper)
+                .collect();
+            lower = upper;
+            let secs = tier
+                .iter()
+                .fold(0.0, |acc, w| acc + w.duration.as_secs_f64());
+            let messages: u64 = tier.iter().map(|w| w.messages).sum();
+            let rate = if secs > 0.0 {
+                messages as f64 / secs
+            } else {
+                0.0
+            };
+            info!("  {:<8} {:>6.0}s  {:>10.1} msg/s", label, secs, rate);
+        }
+
+        if !suspect_hist.is_empty() {
+            let total = e2e_hist.len().max(1);
+            warn!(
+                "  SUSPECT: {} of {} E2E samples ({:.1}%) were taken with generator CPU >{:.0}%",
+                suspect_hist.len(),
+                e2e_hist.len(),
+                suspect_hist.len() as f64 / total as f64 * 100.0,
+                CPU_SUSPECT_PCT
+            );
+            info!("  E2E Latency while suspect (ms):");
+            log_latency_stats(&suspect_hist);
+        }
+    }
+
+    let normalized = (total_messages > 0).then(|| {
+        let messages = total_messages as f64;
+        let (cpu_time, cpu_messages) = cpu_windows.iter().fold((Duration::ZERO, 0), |(t, m), w| {
+            (t + w.cpu_time, m + w.messages)
+        });
+        NormalizedMetrics {
+            e2e_ms_per_message: (!e2e_hist.is_empty()).then(|| e2e_hist.mean()),
+            cpu_ms_per_1k_messages: (cpu_messages > 0)
+                .then(|| cpu_time.as_secs_f64() * 1000.0 / cpu_messages as f64 * 1000.0),
+            bytes_received_per_message: bytes_received as f64 / messages,
+            bytes_sent_per_message: bytes_sent as f64 / messages,
+            messages_per_client_per_sec: messages
+                / measurement_duration.as_secs_f64().max(f64::EPSILON)
+                / num_results.max(1) as f64,
+        }
+    });
+    if let Some(n) = &normalized {
+        info!("");
+        info!("Normalized (per message):");
+        if let Some(e2e) = n.e2e_ms_per_message {
+            info!("  E2E Latency:         {:.2} ms/msg", e2e);
+        }
+        if let Some(cpu) = n.cpu_ms_per_1k_messages {
+            info!("  Generator CPU:       {:.2} ms per 1k msgs", cpu);
+        }
+        info!(
+            "  Bytes Received:      {:.1} B/msg",
+            n.bytes_received_per_message
+        );
+        info!(
+            "  Bytes Sent:          {:.1} B/msg",
+            n.bytes_sent_per_message
+        );
+        info!(
+            "  Delivery Rate:       {:.2} msg/s per client",
+            n.messages_per_client_per_sec
+        );
+    }
+
+    if config.ack {
+        info!("");
+        info!("Delivery Acks (artificial delay {}ms):", config.ack_delay);
+        info!("  Acks Sent:           {}", acks_sent);
+        if config.ack_confirm_event.is_some() {
+            info!("  Acks Confirmed:      {}", acks_confirmed);
+        }
+        info!("  Outstanding at End:  {}", acks_outstanding);
+        let secs = measurement_duration.as_secs_f64().max(f64::EPSILON);
+        let per_client = total_messages as f64 / secs / num_results.max(1) as f64;
+        info!("  Delivery Rate:       {:.2} msg/s per client", per_client);
+        if !ack_rtt_hist.is_empty() {
+            info!("  Ack Round Trip (ms, sent -> confirmed):");
+            log_latency_stats(&ack_rtt_hist);
+        }
+    }
+
+    let malformed_total: u64 = malformed_addresses.values().sum();
+    if config.validate_token_addresses {
+        info!("");
+        info!("Token Address Validation:");
+        info!("  Checked:             {}", addresses_checked);
+        info!(
+            "  Malformed:           {} ({:.4}%)",
+            malformed_total,
+            malformed_total as f64 / addresses_checked.max(1) as f64 * 100.0
+        );
+        let mut problems: Vec<_> = malformed_addresses.iter().collect();
+        problems.sort();
+        for (problem, n) in problems {
+            info!("    {:<18} {}", problem, n);
+        }
+    }
+
+    if config.validate_filters {
+        info!("");
+        info!("Filter Validation:");
+        info!("  Checked:             {}", filters_checked);
+        info!(
+            "  False Positives:     {} ({:.4}%)",
+            false_positives,
+            false_positives as f64 / filters_checked.max(1) as f64 * 100.0
+        );
+    }
+
+    let delivery = config.expect_msgs_per_min.map(|expected| {
+        let window_mins = config.expect_window.max(1) as f64 / 60.0;
+        let per_min = |n: u64| n as f64 / window_mins;
+        // (client id, lowest window rate, windows short, windows checked)
+        let mut short: Vec<(usize, f64, usize, usize)> = throughput_windows
+            .iter()
+            .filter_map(|(id, windows)| {
+                let below = windows.iter().filter(|&&n| per_min(n) < expected).count();
+                let lowest = windows
+                    .iter()
+                    .map(|&n| per_min(n))
+                    .fold(f64::INFINITY, f64::min);
+                (below > 0).then_some((*id, lowest, below, windows.len()))
+            })
+            .collect();
+        short.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+
+        let check = DeliveryCheck {
+            expected_per_min: expected,
+            window_secs: config.expect_window.max(1),
+            windows_checked: throughput_windows.iter().map(|(_, w)| w.len() as u64).sum(),
+            windows_below: short.iter().map(|s| s.2 as u64).sum(),
+            clients_checked: throughput_windows.len() as u64,
+            clients_below: short.len() as u64,
+        };
+
+        info!("");
+        info!(
+            "Delivery Expectation (>= {} msgs/min per client, {}s windows):",
+            expected, check.window_secs
+        );
+        if check.windows_checked == 0 {
+            info!("  No complete windows (hold shorter than --expect-window?)");
+            return check;
+        }
+        info!("  Windows Checked:     {}", check.windows_checked);
+        info!(
+            "  Windows Below:       {} ({:.2}%)",
+            check.windows_below,
+            check.windows_below as f64 / check.windows_checked as f64 * 100.0
+        );
+        info!(
+            "  Clients Below:       {} of {}",
+            check.clients_below, check.clients_checked
+        );
+        for (id, lowest, below, checked) in short.iter().take(10) {
+            info!(
+                "    client {:<8} lowest {:.1}/min, {} of {} windows short",
+                id, lowest, below, checked
+            );
+        }
+        if short.len() > 10 {
+            info!("    ... and {} more", short.len() - 10);
+        }
+        check
+    });
+
+    if reconnects > 0 {
+        info!("");
+        info!("Reconnect / Resume:");
+        info!("  Reconnects:          {}", reconnects);
+        info!("  Resume Attempts:     {}", resume_attempts);
+        info!("  Replayed Messages:   {}", replayed_messages);
+        if !reconnect_hist.is_empty() {
+            info!("  Reconnect Time (ms, disconnect -> resubscribed):");
+            log_latency_stats(&reconnect_hist);
+        }
+        if !catchup_hist.is_empty() {
+            info!("  Catch-Up Time (ms, resubscribed -> first live message):");
+            log_latency_stats(&catchup_hist);
+        }
+    }
+
+    timed_errors.sort_by_key(|&(at, _)| at);
+    let run_started = stages.first().map_or(finished, |&(_, at)| at);
+    let error_bursts = find_error_bursts(
+        &timed_errors,
+        run_started,
+        Duration::from_millis(config.burst_gap),
+        config.burst_min_errors,
+    );
+
+    if let Some(chaos) = &chaos {
+        info!("");
+        info!("Liveness Chaos:");
+        chaos.log();
+    }
+
+    if !timed_errors.is_empty() {
+        info!("");
+        info!("Error Timeline:");
+        log_error_timeline(&timed_errors, &stages, finished);
+
+        let in_bursts: u64 = error_bursts.iter().map(|b| b.errors).sum();
+        info!(
+            "  Bursts (gap <= {}ms, >= {} errors): {}, holding {} of {} errors",
+            config.burst_gap,
+            config.burst_min_errors,
+            error_bursts.len(),
+            in_bursts,
+            timed_errors.len()
+        );
+        for (i, burst) in error_bursts.iter().enumerate() {
+            let categories: Vec<String> = burst
+                .categories
+                .iter()
+                .map(|(label, n)| format!("{} {}", label, n))
+                .collect();
+            info!(
+                "    #{:<3} t={:>8.2}s  span={:>7}ms  errors={:>6}  ({})",
+                i + 1,
+                burst.offset_secs,
+                burst.duration_ms,
+                burst.errors,
+                categories.join(", ")
+            );
+        }
+        let background = timed_errors.len() as u64 - in_bursts;
+        let run_mins = finished
+            .saturating_duration_since(run_started)
+            .as_secs_f64()
+            / 60.0;
+        info!(
+            "  Background:          {} errors ({:.2}/min)",
+            background,
+            background as f64 / run_mins.max(f64::EPSILON)
+        );
+    }
+
+    if let Some(first) = drain_samples.first() {
+        info!("");
+        info!("Ramp-Down Drain:");
+        let initial_active = first.active.max(1) as f64;
+        let initial_rate = drain_samples
+            .iter()
+            .map(|d| d.messages_per_sec)
+            .fold(0.0, f64::max);
+        for d in &drain_samples {
+            let e2e = d.e2e.map_or(String::new(), |(mean, max)| {
+                format!("  e2e avg={:.1}ms max={}ms", mean, max)
+            });
+            info!(
+                "  t={:>6.2}s active={:>6} ({:>5.1}%)  msg/s={:>10.1} ({:>5.1}%){}",
+                d.at.as_secs_f64(),
+                d.active,
+                d.active as f64 / initial_active * 100.0,
+                d.messages_per_sec,
+                if initial_rate > 0.0 {
+                    d.messages_per_sec / initial_rate * 100.0
+                } else {
+                    0.0
+                },
+                e2e
+            );
+        }
+        let zero_conn = drain_samples.iter().find(|d| d.active == 0).map(|d| d.at);
+        let zero_rate = drain_samples
+            .iter()
+            .find(|d| d.messages_per_sec == 0.0)
+            .map(|d| d.at);
+        match zero_conn {
+            Some(t) => info!("  Time to zero connections: {:.2}s", t.as_secs_f64()),
+            None => info!("  Time to zero connections: not reached"),
+        }
+        match zero_rate {
+            Some(t) => info!("  Time to zero msg rate:    {:.2}s", t.as_secs_f64()),
+            None => info!("  Time to zero msg rate:    not reached"),
+        }
+        info!("  Post-Close Messages:      {}", post_close_messages);
+        if post_close_messages > 0 {
+            warn!(
+                "  Server kept fanning out to {} closing connections' frames",
+                post_close_messages
+            );
+        }
+        if !close_drain_hist.is_empty() {
+            info!("  Close Handshake (ms):");
+            log_latency_stats(&close_drain_hist);
+        }
+    }
+
+    if !non_compliant.is_empty() {
+        info!("");
+        info!("Non-Compliant Clients (excluded from metrics above):");
+        for kind in Misbehavior::ALL {
+            let cohort: Vec<_> = non_compliant
+                .iter()
+                .filter(|r| r.misbehavior == Some(kind))
+                .collect();
+            if cohort.is_empty() {
+                continue;
+            }
+            let connected = cohort.iter().filter(|r| r.connected).count();
+            let subscribed = cohort.iter().filter(|r| r.subscribe_success).count();
+            let server_closed = cohort.iter().filter(|r| r.server_closed).count();
+            let messages: u64 = cohort.iter().map(|r| r.messages_received).sum();
+            info!(
+                "  {:<20} clients={} connected={} subscribed={} server_closed={} messages={}",
+                kind.label(),
+                cohort.len(),
+                connected,
+                subscribed,
+                server_closed,
+                messages
+            );
+        }
+    }
+
+    let mut summary = RunSummary {
+        run_id: config.run_id.clone(),
+        scenario: config.scenario,
+        num_clients: config.num_clients,
+        measurement_secs: measurement_duration.as_secs_f64(),
+        subscribe_success,
+        subscribe_failed,
+        connection_errors,
+        peak_pending_connects,
+        errors_by_category: categories
+            .into_iter()
+            .map(|(category, count)| (category.label(), count))
+            .collect(),
+        filter_updates,
+        messages_received: total_messages,
+        bytes_sent,
+        bytes_received,
+        subscribe_latency_ms: LatencySummary::from_histogram(&subscribe_hist),
+        filter_update_latency_ms: LatencySummary::from_histogram(&filter_hist),
+        e2e_latency_ms: LatencySummary::from_histogram(&e2e_hist),
+        e2e_sample_rate: config.e2e_sample_rate.min(1.0),
+        clock_jumps: clock_jumps.len() as u64,
+        clock_excluded_e2e,
+        e2e_windows,
+        normalized,
+        phases,
+        scenario_mix,
+        error_bursts,
+        malformed_token_addresses: config.validate_token_addresses.then_some(malformed_total),
+        false_positive_deliveries: config.validate_filters.then_some(false_positives),
+        delivery,
+        churn: (resubscribe_cycles > 0).then(|| ChurnSummary {
+            cycles: resubscribe_cycles,
+            unsubscribe_acks: unsubscribe_ack_hist.len(),
+            unsubscribe_ack_latency_ms: LatencySummary::from_histogram(&unsubscribe_ack_hist),
+            post_unsubscribe_messages,
+            resubscribe_latency_ms: LatencySummary::from_histogram(&resubscribe_hist),
+        }),
+        client_ping: (config.client_ping > 0).then(|| PingSummary {
+            pings_sent,
+            pings_unanswered,
+            rtt_us: LatencySummary::from_histogram(&ping_rtt_hist),
+        }),
+        backends,
+        client_events: (client_events_sent > 0 || client_events_received > 0).then(|| {
+            ClientEventSummary {
+                published: client_events_sent,
+                received: client_events_received,
+                latency_ms: LatencySummary::from_histogram(&client_event_hist),
+            }
+        }),
+        publisher: publish.map(|publish| PublisherSummary {
+            published: publish.sent,
+            failed: publish.failed,
+            latency_ms: LatencySummary::from_histogram(&publish.latency),
+        }),
+        derived: HashMap::new(),
+        aborted,
+        iterations: None,
+        trends: vec![
+            ("ws_connecting", connecting_hist),
+            ("ws_subscribe_latency", subscribe_hist),
+            ("ws_filter_update_latency", filter_hist),
+            ("ws_e2e_latency", e2e_hist),
+        ],
+    };
+
+    evaluate_derived(&mut summary, config);
+
+    info!("");
+    info!("════════════════════════════════════════════════════════════");
+    info!("                  BENCHMARK COMPLETE");
+    info!("════════════════════════════════════════════════════════════");
+
+    write_output(&summary, config);
+    summary
+}
+
+/// Evaluate `--derive` metrics against `summary`, logging and storing each.
+pub(crate) fn evaluate_derived(summary: &mut RunSummary, config: &Config) {
+    if config.derive.is_empty() {
+        return;
+    }
+    info!("");
+    info!("Derived Metrics:");
+    let mut vars = summary_variables(summary);
+    for metric in &config.derive {
+        match metric.expr.eval(&vars) {
+            Ok(value) => {
+                info!("  {:<24} {:.4}", metric.name, value);
+                vars.insert(metric.name.clone(), value);
+                summary.derived.insert(metric.name.clone(), value);
+            }
+            Err(e) => warn!("  {:<24} {}", metric.name, e),
+        }
+    }
+}
+
+/// Write the `--output-format` rendering of `summary` to its destination.
+pub(crate) fn write_output(summary: &RunSummary, config: &Config) {
+    let trend = |name: &str| {
+        summary
+            .trends
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, hist)| hist)
+    };
+    let rendered = match config.output_format {
+        OutputFormat::Text => None,
+        OutputFormat::K6 => {
+            let trends: Vec<(&str, &Histogram<u64>)> = summary
+                .trends
+                .iter()
+                .map(|(name, hist)| (*name, hist))
+                .collect();
+            Some(render_k6(summary, &trends))
+        }
+        OutputFormat::Wrk2 => trend("ws_e2e_latency").map(render_wrk2),
+    };
+    if let Some(rendered) = rendered {
+        match &config.output_file {
+            Some(path) => {
+                if let Err(e) = std::fs::write(path, rendered) {
+                    error!("Failed to write summary to {:?}: {}", path, e);
+                }
+            }
+            None => println!("{}", rendered),
+        }
+    }
+}
+
+/// Fold the summaries of `--iterations` runs into one: counters are summed,
+/// latency histograms merged, and the spread of each iteration's headline
+/// numbers is logged and kept in `iterations`.
+pub(crate) fn merge_iterations(summaries: Vec<RunSummary>, config: &Config) -> RunSummary {
+    let runs: Vec<IterationResult> = summaries
+        .iter()
+        .map(|s| IterationResult {
+            messages_received: s.messages_received,
+            messages_per_sec: s.messages_received as f64 / s.measurement_secs.max(f64::EPSILON),
+            connection_errors: s.connection_errors,
+            subscribe_p99_ms: s.subscribe_latency_ms.as_ref().map(|l| l.p99),
+            e2e_p50_ms: s.e2e_latency_ms.as_ref().map(|l| l.p50),
+            e2e_p99_ms: s.e2e_latency_ms.as_ref().map(|l| l.p99),
+        })
+        .collect();
+    let spread = |f: fn(&IterationResult) -> Option<f64>| Spread::of(runs.iter().filter_map(f));
+    let stats = IterationStats {
+        messages_per_sec: spread(|r| Some(r.messages_per_sec)),
+        subscribe_p99_ms: spread(|r| r.subscribe_p99_ms.map(|v| v as f64)),
+        e2e_p50_ms: spread(|r| r.e2e_p50_ms.map(|v| v as f64)),
+        e2e_p99_ms: spread(|r| r.e2e_p99_ms.map(|v| v as f64)),
+        runs,
+    };
+
+    info!("");
+    info!("════════════════════════════════════════════════════════════");
+    info!("              ITERATIONS ({} runs)", summaries.len());
+    info!("════════════════════════════════════════════════════════════");
+    info!("  Run        msg/s   errors   sub p99   e2e p50   e2e p99");
+    let ms = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+    for (i, run) in stats.runs.iter().enumerate() {
+        info!(
+            "  #{:<5} {:>9.0} {:>8} {:>9} {:>9} {:>9}",
+            i + 1,
+            run.messages_per_sec,
+            run.connection_errors,
+            ms(run.subscribe_p99_ms),
+            ms(run.e2e_p50_ms),
+            ms(run.e2e_p99_ms)
+        );
+    }
+    info!("");
+    for (label, spread) in [
+        ("Messages/s", &stats.messages_per_sec),
+        ("Subscribe p99 (ms)", &stats.subscribe_p99_ms),
+        ("E2E p50 (ms)", &stats.e2e_p50_ms),
+        ("E2E p99 (ms)", &stats.e2e_p99_ms),
+    ] {
+        if let Some(spread) = spread {
+            info!(
+                "  {:<20} mean={:.1} stddev={:.1} cv={:.1}% range={:.0}-{:.0}",
+                label,
+                spread.mean,
+                spread.stddev,
+                spread.cv_percent(),
+                spread.min,
+                spread.max
+            );
+        }
+    }
+
+    let mut summaries = summaries.into_iter();
+    let mut merged = summaries.next().expect("at least one iteration");
+    // Per-run timelines don't line up across iterations, and breakdowns keep
+    // percentiles only
+    merged.e2e_windows.clear();
+    merged.phases.clear();
+    merged.scenario_mix.clear();
+    merged.churn = None;
+    merged.client_ping = None;
+    merged.client_events = None;
+    merged.publisher = None;
+    merged.error_bursts.clear();
+    merged.normalized = None;
+    merged.derived.clear();
+    for s in summaries {
+        merged.measurement_secs += s.measurement_secs;
+        merged.subscribe_success += s.subscribe_success;
+        merged.subscribe_failed += s.subscribe_failed;
+        merged.connection_errors += s.connection_errors;
+        merged.peak_pending_connects = merged.peak_pending_connects.max(s.peak_pending_connects);
+        for (category, count) in s.errors_by_category {
+            *merged.errors_by_category.entry(category).or_default() += count;
+        }
+        for (backend, count) in s.backends {
+            *merged.backends.entry(backend).or_default() += count;
+        }
+        merged.filter_updates += s.filter_updates;
+        merged.messages_received += s.messages_received;
+        merged.bytes_sent += s.bytes_sent;
+        merged.bytes_received += s.bytes_received;
+        merged.clock_jumps += s.clock_jumps;
+        merged.aborted = merged.aborted.or(s.aborted);
+        merged.clock_excluded_e2e += s.clock_excluded_e2e;
+        if let (Some(total), Some(more)) = (
+            &mut merged.malformed_token_addresses,
+            s.malformed_token_addresses,
+        ) {
+            *total += more;
+        }
+        if let (Some(total), Some(more)) = (
+            &mut merged.false_positive_deliveries,
+            s.false_positive_deliveries,
+        ) {
+            *total += more;
+        }
+        if let (Some(into), Some(more)) = (&mut merged.delivery, s.delivery) {
+            into.windows_checked += more.windows_checked;
+            into.windows_below += more.windows_below;
+            into.clients_checked += more.clients_checked;
+            into.clients_below += more.clients_below;
+        }
+        for ((_, into), (_, hist)) in merged.trends.iter_mut().zip(&s.trends) {
+            let _ = into.add(hist);
+        }
+    }
+    let trend = |name: &str| {
+        merged
+            .trends
+            .iter()
+            .find(|(n, _)| *n == name)
+            .and_then(|(_, hist)| LatencySummary::from_histogram(hist))
+    };
+    merged.subscribe_latency_ms = trend("ws_subscribe_latency");
+    merged.filter_update_latency_ms = trend("ws_filter_update_latency");
+    merged.e2e_latency_ms = trend("ws_e2e_latency");
+    merged.iterations = Some(stats);
+
+    info!("");
+    info!("Merged ({:.0}s measured):", merged.measurement_secs);
+    for (label, latency) in [
+        ("Subscribe", &merged.subscribe_latency_ms),
+        ("E2E", &merged.e2e_latency_ms),
+    ] {
+        if let Some(l) = latency {
+            info!(
+                "  {:<10} p50={} p95={} p99={} max={} ({} samples)",
+                label, l.p50, l.p95, l.p99, l.max, l.samples
+            );
+        }
+    }
+    evaluate_derived(&mut merged, config);
+
+    write_output(&merged, config);
+    merged
+}
+
+// =============================================================================
+// Derived Metrics
+// =============================================================================
+
+/// `name = expression` from `--derive`.
+#[derive(Debug, Clone)]
+pub(crate) struct DerivedMetric {
+    pub(crate) name: String,
+    pub(crate) expr: Expr,
+}
+
+/// Arithmetic over summary variables: `+ - * /`, unary minus and parentheses.
+#[derive(Debug, Clone)]
+pub(crate) enum Expr {
+    Num(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Bin(char, Box<Expr>, Box<Expr>),
+}
+
+impl DerivedMetric {
+    pub(crate) fn parse(spec: &str) -> Result<Self, String> {
+        let (name, expr) = spec
+            .split_once('=')
+            .ok_or_else(|| format!("expected `name = expression`, got {:?}", spec))?;
+        let name = name.trim();
+        let name = name.strip_prefix("derived.").unwrap_or(name);
+        if name.is_empty() {
+            return Err(format!("missing metric name in {:?}", spec));
+        }
+
+        let tokens: Vec<char> = expr.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut pos = 0;
+        let expr = Expr::parse_sum(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!("unexpected {:?} in {:?}", tokens[pos], spec));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            expr,
+        })
+    }
+}
+
+impl Expr {
+    pub(crate) fn parse_sum(tokens: &[char], pos: &mut usize) -> Result<Self, String> {
+        let mut lhs = Self::parse_product(tokens, pos)?;
+        while let Some(&op @ ('+' | '-')) = tokens.get(*pos) {
+            *pos += 1;
+            let rhs = Self::parse_product(tokens, pos)?;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    pub(crate) fn parse_product(tokens: &[char], pos: &mut usize) -> Result<Self, String> {
+        let mut lhs = Self::parse_factor(tokens, pos)?;
+        while let Some(&op @ ('*' | '/')) = tokens.get(*pos) {
+            *pos += 1;
+            let rhs = Self::parse_factor(tokens, pos)?;
+            lhs = Expr::Bin(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    pub(crate) fn parse_factor(tokens: &[char], pos: &mut usize) -> Result<Self, String> {
+        match tokens.get(*pos) {
+            Some('-') => {
+                *pos += 1;
+                Ok(Expr::Neg(Box::new(Self::parse_factor(tokens, pos)?)))
+            }
+            Some('(') => {
+                *pos += 1;
+                let inner = Self::parse_sum(tokens, pos)?;
+                if tokens.get(*pos) != Some(&')') {
+                    return Err("missing `)`".to_string());
+                }
+                *pos += 1;
+                Ok(inner)
+            }
+            Some(c) if c.is_ascii_digit() || *c == '.' => {
+                let start = *pos;
+                while tokens
+                    .get(*pos)
+                    .is_some_and(|c| c.is_ascii_digit() || *c == '.')
+                {
+                    *pos += 1;
+                }
+                let literal: String = tokens[start..*pos].iter().collect();
+                literal
+                    .parse()
+                    .map(Expr::Num)
+                    .map_err(|_| format!("bad number {:?}", literal))
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let start = *pos;
+                while tokens
+                    .get(*pos)
+                    .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '.')
+                {
+                    *pos += 1;
+                }
+                let ident: String = tokens[start..*pos].iter().collect();
+                Ok(Expr::Var(ident.replace('.', "_")))
+            }
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    pub(crate) fn eval(&self, vars: &HashMap<String, f64>) -> Result<f64, String> {
+        Ok(match self {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => *vars
+                .get(name)
+                .or_else(|| vars.get(name.strip_prefix("derived_").unwrap_or(name)))
+                .ok_or_else(|| format!("unknown variable `{}`", name))?,
+            Expr::Neg(inner) => -inner.eval(vars)?,
+            Expr::Bin(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(vars)?, rhs.eval(vars)?);
+                match op {
+                    '+' => lhs + rhs,
+                    '-' => lhs - rhs,
+                    '*' => lhs * rhs,
+                    _ => lhs / rhs,
+                }
+            }
+        })
+    }
+}
+
+/// Every numeric field of a summary or snapshot, nested names joined with `_`
+/// (e.g. `e2e_latency_ms_p99`).
+pub(crate) fn summary_variables(summary: &impl Serialize) -> HashMap<String, f64> {
+    fn flatten(prefix: &str, value: &sonic_rs::Value, out: &mut HashMap<String, f64>) {
+        if let Some(object) = value.as_object() {
+            for (key, child) in object.iter() {
+                let name = if prefix.is_empty() {
+                    key.to_string()
+                } else {
+                    format!("{}_{}", prefix, key)
+                };
+                flatten(&name, child, out);
+            }
+        } else if let Some(n) = value.as_f64() {
+            out.insert(prefix.to_string(), n);
+        }
+    }
+
+    let mut vars = HashMap::new();
+    if let Ok(value) = sonic_rs::to_value(summary) {
+        flatten("", &value, &mut vars);
+    }
+    vars
+}
+
+// =============================================================================
+// Summary Output Formats
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum OutputFormat {
+    /// Log report only
+    Text,
+    /// k6 `--summary-export` JSON (trends in ms, counters with rates)
+    K6,
+    /// wrk2 `--latency` style E2E percentile table and spectrum
+    Wrk2,
+}
+
+/// Shape the summary like k6's `--summary-export`, so existing k6 tooling
+/// can ingest it unchanged.
+pub(crate) fn render_k6(summary: &RunSummary, trends: &[(&str, &Histogram<u64>)]) -> String {
+    let secs = summary.measurement_secs.max(f64::EPSILON);
+    let counter = |count: u64| sonic_rs::json!({ "count": count, "rate": count as f64 / secs });
+
+    let mut metrics = sonic_rs::Object::new();
+    for &(name, hist) in trends {
+        if hist.is_empty() {
+            continue;
+        }
+        metrics.insert(
+            &name,
+            sonic_rs::json!({
+                "avg": hist.mean(),
+                "min": hist.min(),
+                "med": hist.value_at_quantile(0.50),
+                "max": hist.max(),
+                "p(90)": hist.value_at_quantile(0.90),
+                "p(95)": hist.value_at_quantile(0.95),
+                "p(99)": hist.value_at_quantile(0.99),
+            }),
+        );
+    }
+    metrics.insert(
+        &"ws_sessions",
+        counter(summary.subscribe_success + summary.subscribe_failed),
+    );
+    metrics.insert(&"ws_msgs_received", counter(summary.messages_received));
+    metrics.insert(&"data_received", counter(summary.bytes_received));
+    metrics.insert(&"data_sent", counter(summary.bytes_sent));
+    metrics.insert(&"ws_connection_errors", counter(summary.connection_errors));
+
+    let export = sonic_rs::json!({
+        "metrics": metrics,
+        "state": {
+            "testRunDurationMs": summary.measurement_secs * 1000.0,
+            "e2eSampleRate": summary.e2e_sample_rate,
+        },
+    });
+    sonic_rs::to_string_pretty(&export).unwrap_or_default()
+}
+
+/// wrk2's `--latency` report for E2E latency: headline percentiles followed
+/// by the HdrHistogram percentile spectrum.
+pub(crate) fn render_wrk2(hist: &Histogram<u64>) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "  Latency Distribution (HdrHistogram - Recorded Latency)"
+    );
+    for pct in [50.0, 75.0, 90.0, 99.0, 99.9, 99.99, 99.999, 100.0] {
+        let _ = writeln!(
+            out,
+            "{:>7.3}%  {:>8.2}ms",
+            pct,
+            hist.value_at_percentile(pct) as f64
+        );
+    }
+
+    let _ = writeln!(out);
+    let _ = writeln!(out, "  Detailed Percentile spectrum:");
+    let _ = writeln!(
+        out,
+        "       Value   Percentile   TotalCount 1/(1-Percentile)"
+    );
+    let _ = writeln!(out);
+    let mut total = 0u64;
+    for v in hist.iter_quantiles(1) {
+        total += v.count_since_last_iteration();
+        let quantile = v.quantile_iterated_to();
+        let inverse = if quantile < 1.0 {
+            format!("{:>12.2}", 1.0 / (1.0 - quantile))
+        } else {
+            format!("{:>12}", "inf")
+        };
+        let _ = writeln!(
+            out,
+            "{:>12.3} {:>12.6} {:>12} {}",
+            v.value_iterated_to() as f64,
+            quantile,
+            total,
+            inverse
+        );
+    }
+    let _ = writeln!(
+        out,
+        "#[Mean    = {:>12.3}, StdDeviation   = {:>12.3}]",
+        hist.mean(),
+        hist.stdev()
+    );
+    let _ = writeln!(
+        out,
+        "#[Max     = {:>12.3}, Total count    = {:>12}]",
+        hist.max() as f64,
+        hist.len()
+    );
+    out
+}
@@ -0,0 +1,906 @@
+//! The ramping test runner, abort guards and client fleet.
+
+use super::*;
+
+// =============================================================================
+// Abort Guards
+// =============================================================================
+
+/// Exit status after an `--abort-if` guard stopped the run.
+pub const ABORTED_EXIT_CODE: i32 = 3;
+
+/// `expression <op> threshold` from `--abort-if`; the threshold may be a
+/// percentage.
+#[derive(Debug, Clone)]
+pub(crate) struct AbortGuard {
+    pub(crate) spec: String,
+    pub(crate) expr: Expr,
+    pub(crate) op: &'static str,
+    pub(crate) threshold: f64,
+}
+
+impl AbortGuard {
+    pub(crate) fn parse(spec: &str) -> Result<Self, String> {
+        let (at, op) = [">=", "<=", ">", "<"]
+            .into_iter()
+            .find_map(|op| spec.find(op).map(|at| (at, op)))
+            .ok_or_else(|| format!("expected `expression > threshold`, got {:?}", spec))?;
+        let (lhs, rhs) = (&spec[..at], spec[at + op.len()..].trim());
+
+        let tokens: Vec<char> = lhs.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut pos = 0;
+        let expr = Expr::parse_sum(&tokens, &mut pos)?;
+        if pos != tokens.len() {
+            return Err(format!("unexpected {:?} in {:?}", tokens[pos], spec));
+        }
+        let threshold = match rhs.strip_suffix('%') {
+            Some(percent) => percent.trim().parse::<f64>().map(|p| p / 100.0),
+            None => rhs.parse(),
+        }
+        .map_err(|_| format!("bad threshold {:?} in {:?}", rhs, spec))?;
+
+        Ok(Self {
+            spec: spec.trim().to_string(),
+            expr,
+            op,
+            threshold,
+        })
+    }
+
+    /// The guard's current value if it has tripped.
+    pub(crate) fn tripped(&self, vars: &HashMap<String, f64>) -> Option<f64> {
+        let value = self.expr.eval(vars).ok()?;
+        let hit = match self.op {
+            ">=" => value >= self.threshold,
+            "<=" => value <= self.threshold,
+            ">" => value > self.threshold,
+            _ => value < self.threshold,
+        };
+        hit.then_some(value)
+    }
+}
+
+/// Variables `--abort-if` guards see: the snapshot fields plus `error_rate`,
+/// failed connections and subscriptions over all attempts. `None` until
+/// `min_attempts` have finished one way or the other.
+pub(crate) fn guard_variables(
+    snapshot: &LiveSnapshot,
+    min_attempts: u64,
+) -> Option<HashMap<String, f64>> {
+    let failed = snapshot.connection_errors + snapshot.subscribe_failed;
+    let attempts = failed + snapshot.subscribe_success;
+    if attempts == 0 || attempts < min_attempts {
+        return None;
+    }
+    let mut vars = summary_variables(snapshot);
+    vars.insert("error_rate".to_string(), failed as f64 / attempts as f64);
+    Some(vars)
+}
+
+/// Check the `--abort-if` guards against the live counters twice a second.
+/// The first guard to trip records why in `live_stats.abort_reason`, which
+/// ends the ramp or hold early.
+pub(crate) async fn watch_abort_guards(
+    live_stats: LiveStats,
+    guards: Vec<AbortGuard>,
+    min_attempts: u64,
+) {
+    let started = Instant::now();
+    let mut ticker = interval(Duration::from_millis(500));
+
+    loop {
+        ticker.tick().await;
+        let snapshot = LiveSnapshot::capture(&live_stats, started);
+        let Some(vars) = guard_variables(&snapshot, min_attempts) else {
+            continue;
+        };
+        for guard in &guards {
+            if let Some(value) = guard.tripped(&vars) {
+                let reason = format!("{} (was {:.4})", guard.spec, value);
+                error!("Abort guard tripped: {}; draining and reporting", reason);
+                let _ = live_stats.abort_reason.set(reason);
+                return;
+            }
+        }
+    }
+}
+
+// =============================================================================
+// Test Runner
+// =============================================================================
+
+/// A task aborted when its handle drops, so a run cut short or failing
+/// early leaves no monitors behind.
+pub(crate) struct ScopedTask<T>(tokio::task::JoinHandle<T>);
+
+impl<T: Send + 'static> ScopedTask<T> {
+    pub(crate) fn spawn(future: impl std::future::Future<Output = T> + Send + 'static) -> Self {
+        Self(tokio::spawn(future))
+    }
+
+    pub(crate) fn abort(&self) {
+        self.0.abort();
+    }
+}
+
+impl<T> Drop for ScopedTask<T> {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+impl<T> std::future::Future for ScopedTask<T> {
+    type Output = Result<T, tokio::task::JoinError>;
+
+    fn poll(
+        mut self: std::pin::Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Self::Output> {
+        std::pin::Pin::new(&mut self.0).poll(cx)
+    }
+}
+
+pub(crate) async fn run_ramping_test(
+    config: Arc<Config>,
+    tokens: TokenPool,
+    live_stats: LiveStats,
+    transport: Arc<dyn Transport>,
+) -> Result<TestOutcome> {
+    let cpu_stop = Arc::new(std::sync::atomic::AtomicBool::new(false));
+    let cpu_monitor = ScopedTask::spawn(monitor_cpu(live_stats.clone(), Arc::clone(&cpu_stop)));
+    let clock_monitor = ScopedTask::spawn(monitor_clock(
+        live_stats.clone(),
+        Duration::from_millis(config.clock_jump_threshold),
+        Arc::clone(&cpu_stop),
+    ));
+    let publisher = (config.publish_rate > 0.0).then(|| {
+        ScopedTask::spawn(publish_events(
+            Arc::clone(&config),
+            tokens.clone(),
+            Arc::clone(&cpu_stop),
+        ))
+    });
+
+    let snapshots = config.snapshot_dir.clone().map(|dir| {
+        ScopedTask::spawn(write_snapshots(
+            live_stats.clone(),
+            dir,
+            Duration::from_secs(config.snapshot_interval_mins * 60),
+            config.snapshot_keep,
+        ))
+    });
+
+    let abort_watch = (!config.abort_if.is_empty()).then(|| {
+        ScopedTask::spawn(watch_abort_guards(
+            live_stats.clone(),
+            config.abort_if.clone(),
+            config.abort_min_attempts,
+        ))
+    });
+
+    let (sample_tx, sample_writer) = match &config.raw_samples {
+        Some(path) => {
+            let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+            (Some(tx), Some(spawn_sample_writer(path.clone(), rx)))
+        }
+        None => (None, None),
+    };
+
+    let mut fleet = ClientFleet {
+        config: Arc::clone(&config),
+        tokens: tokens.clone(),
+        live_stats: live_stats.clone(),
+        transport,
+        raw_samples: sample_tx,
+        spawned: 0,
+        tasks: Vec::with_capacity(config.num_clients),
+        finished: Vec::new(),
+        running: std::collections::VecDeque::new(),
+        spawn_credit: 0.0,
+        credited_at: Instant::now(),
+    };
+
+    info!("Starting ramping test");
+    info!(
+        "Target: {} clients (IDs {}-{})",
+        config.num_clients,
+        config.client_id_offset,
+        config.client_id_offset + config.num_clients - 1
+    );
+
+    let plan = match (config.profile, &config.plan) {
+        (LoadProfile::Plan, Some(path)) => {
+            let plan = TestPlan::load(path, config.scenario)?;
+            if let Some(phase) = plan
+                .phases
+                .iter()
+                .find(|phase| !config.scenarios.contains(phase.scenario))
+            {
+                anyhow::bail!("plan phase {}: no scenario {}", phase.name, phase.scenario);
+            }
+            info!("Plan: {} phases", plan.phases.len());
+            for (i, phase) in plan.phases.iter().enumerate() {
+                info!(
+                    "  {}. {}: {} clients, ramp {}s, hold {}s, scenario {}",
+                    i + 1,
+                    phase.name,
+                    phase.clients,
+                    phase.ramp.as_secs(),
+                    phase.hold.as_secs(),
+                    phase.scenario
+                );
+            }
+            Some(plan)
+        }
+        (LoadProfile::Plan, None) => anyhow::bail!("--profile plan requires --plan"),
+        _ => None,
+    };
+    let schedule = match (config.profile, &config.profile_file) {
+        (LoadProfile::Plan, _) => plan.as_ref().map(TestPlan::schedule),
+        (LoadProfile::File, Some(path)) => {
+            let schedule = LoadSchedule::load(path)?;
+            info!(
+                "Schedule: {} points over {}s, peak {} clients",
+                schedule.points.len(),
+                schedule.duration().as_secs(),
+                schedule.peak()
+            );
+            Some(schedule)
+        }
+        (LoadProfile::File, None) => anyhow::bail!("--profile file requires --profile-file"),
+        _ => None,
+    };
+
+    let mut message_rate = SlidingRate::new(Duration::from_secs(config.rate_window));
+    let connect_timeout = Duration::from_millis(config.connect_timeout);
+    if config.start_barrier {
+        live_stats.start_gate.send_replace(false);
+    }
+
+    // Stage 1: Ramp up to target clients. Time spent paused is pushed out of
+    // every stage clock below, so the load picks up where it stopped.
+    let mut stage_start = Instant::now();
+    let mut stages = vec![(1, stage_start)];
+    let ramp_length = config.ramp_length();
+    let ramp_goal = config.ramp_target(ramp_length);
+    info!(
+        "Stage 1: ramping to {} clients over {}s ({:?} profile)",
+        ramp_goal,
+        ramp_length.as_secs(),
+        config.profile
+    );
+
+    let mut last_log = Instant::now();
+    let mut next_arrival = tokio::time::Instant::now();
+    let mut client_target = live_stats.client_target.subscribe();
+    let mut retargeted = false;
+
+    while fleet.spawned() < ramp_goal {
+        let paused_for = wait_while_paused(&live_stats).await;
+        stage_start += paused_for;
+        next_arrival += paused_for;
+
+        // A target set at runtime replaces the rest of the ramp
+        if fleet.follow_target(&mut client_target) {
+            retargeted = true;
+            break;
+        }
+        if live_stats.aborted() {
+            break;
+        }
+
+        if config.profile == LoadProfile::Poisson {
+            // Open loop: each arrival is due on its own clock, never batched to a
+            // tick, and one falling behind doesn't delay the next
+            tokio::time::sleep_until(next_arrival).await;
+            fleet.spawn();
+            next_arrival += config.arrival_gap();
+        } else {
+            let target_now = config.ramp_target(stage_start.elapsed());
+            if config.profile == LoadProfile::Step && target_now > fleet.spawned() {
+                info!(
+                    "Stage 1: step {}/{} -> {} clients",
+                    target_now.div_ceil(config.step_size.max(1)),
+                    config.step_count(),
+                    target_now
+                );
+            }
+
+            while fleet.spawned() < target_now {
+                fleet.spawn();
+            }
+
+            // Sleep a bit before checking again
+            sleep(Duration::from_millis(50)).await;
+        }
+        let received = live_stats.messages_received.load(Ordering::Relaxed);
+        let rate = message_rate.observe(received);
+
+        // Log progress every 5 seconds
+        if last_log.elapsed() >= Duration::from_secs(5) {
+            let active = live_stats.active_connections.load(Ordering::Relaxed);
+            info!(
+                "Stage 1: spawned={}, active={}, pending={}, messages_received={}, msg/s={:.0}",
+                fleet.spawned(),
+                active,
+                live_stats.pending_connects.load(Ordering::Relaxed),
+                received,
+                rate
+            );
+            last_log = Instant::now();
+        }
+    }
+
+    // Wait for remaining ramp time
+    let remaining = ramp_length.saturating_sub(stage_start.elapsed());
+    if !remaining.is_zero() && !retargeted && !live_stats.aborted() {
+        sleep(remaining).await;
+    }
+
+    // Spike: time the thundering herd until every client is connected
+    if config.profile == LoadProfile::Spike {
+        let (active, failed) =
+            settle_connections(&live_stats, fleet.spawned(), stage_start, connect_timeout).await;
+        info!(
+            "Spike: {}/{} connected, {} failed, after {}ms",
+            active,
+            fleet.spawned(),
+            failed,
+            stage_start.elapsed().as_millis()
+        );
+    }
+
+    if config.start_barrier {
+        let waiting_since = Instant::now();
+        let (active, failed) =
+            settle_connections(&live_stats, fleet.spawned(), waiting_since, connect_timeout).await;
+        live_stats.start_gate.send_replace(true);
+        info!(
+            "Start barrier released after {}ms: {}/{} connected, {} failed",
+            waiting_since.elapsed().as_millis(),
+            active,
+            fleet.spawned(),
+            failed
+        );
+    }
+
+    info!(
+        "Stage 1 complete: {} clients spawned, {} active",
+        fleet.spawned(),
+        live_stats.active_connections.load(Ordering::Relaxed)
+    );
+
+    // The schedule clock covers warm-up and measurement
+    let mut schedule_start = Instant::now();
+    let mut plan = plan.map(|plan| PlanTracker::start(plan, &live_stats, schedule_start));
+
+    // Stage 2: Warm-up phase (if configured)
+    if config.warmup_duration > 0 {
+        let mut stage_start = Instant::now();
+        stages.push((2, stage_start));
+        info!(
+            "Stage 2: warming up for {}s (metrics discarded)",
+            config.warmup_duration
+        );
+
+        let warmup_interval = Duration::from_secs(5);
+        let mut last_log = Instant::now();
+        let mut last_tick = Instant::now();
+
+        while stage_start.elapsed() < Duration::from_secs(config.warmup_duration) {
+            sleep(Duration::from_millis(500)).await;
+            if live_stats.aborted() {
+                break;
+            }
+            let tick = last_tick.elapsed();
+            last_tick = Instant::now();
+
+            if *live_stats.paused.borrow() {
+                stage_start += tick;
+                schedule_start += tick;
+            } else {
+                let retargeted = fleet.follow_target(&mut client_target);
+                if let (Some(schedule), false) = (&schedule, retargeted) {
+                    fleet.scale_to(schedule.target(schedule_start.elapsed()));
+                }
+                if let Some(plan) = plan.as_mut() {
+                    plan.advance(&live_stats, schedule_start);
+                }
+            }
+            let received = live_stats.messages_received.load(Ordering::Relaxed);
+            let rate = message_rate.observe(received);
+
+            if last_log.elapsed() >= warmup_interval {
+                let active = live_stats.active_connections.load(Ordering::Relaxed);
+                info!(
+                    "Warm-up: active={}, messages={}, msg/s={:.0} (discarding)",
+                    active, received, rate
+                );
+                last_log = Instant::now();
+            }
+        }
+
+        info!("Warm-up complete, starting measurement phase");
+    }
+
+    // Mark warmup as complete - start recording metrics
+    live_stats.warmup_complete.store(true, Ordering::Relaxed);
+    let measure_start = Instant::now();
+    let measure_started_ms = unix_millis();
+
+    // Stage 3: Hold at target (measurement phase)
+    let mut stage_start = Instant::now();
+    stages.push((3, stage_start));
+    let hold_length = match &schedule {
+        Some(schedule) => schedule.duration().saturating_sub(schedule_start.elapsed()),
+        None if config.hold_duration == 0 || config.canary => Duration::MAX,
+        None => Duration::from_secs(config.hold_duration),
+    };
+    let interrupt_handler =
+        (hold_length == Duration::MAX).then(|| spawn_interrupt_handler(live_stats.clone()));
+    let interrupted = if let Some((interrupted, _)) = &interrupt_handler {
+        info!("Stage 3: measuring until interrupted (Ctrl-C to finish, SIGUSR1 for an interim summary)");
+        Some(Arc::clone(interrupted))
+    } else {
+        info!("Stage 3: measuring for {:.0}s", hold_length.as_secs_f64());
+        None
+    };
+
+    let hold_interval = Duration::from_secs(5);
+    let mut last_log = Instant::now();
+    let mut last_bytes_sent = live_stats.bytes_sent.load(Ordering::Relaxed);
+    let mut last_bytes_received = live_stats.bytes_received.load(Ordering::Relaxed);
+    let mut chaos: Option<tokio::task::JoinHandle<ChaosReport>> = None;
+    let mut churn_due = 0.0;
+    let mut churned = 0;
+    let mut last_tick = Instant::now();
+
+    while stage_start.elapsed() < hold_length {
+        sleep(Duration::from_millis(500)).await;
+        if interrupted
+            .as_ref()
+            .is_some_and(|i| i.load(Ordering::Relaxed))
+            || live_stats.aborted()
+        {
+            break;
+        }
+        let tick = last_tick.elapsed();
+        last_tick = Instant::now();
+
+        if *live_stats.paused.borrow() {
+            stage_start += tick;
+            schedule_start += tick;
+        } else {
+            if !fleet.follow_target(&mut client_target) {
+                if config.profile == LoadProfile::Wave {
+                    fleet.scale_to(config.wave_target(stage_start.elapsed()));
+                }
+                if let Some(schedule) = &schedule {
+                    fleet.scale_to(schedule.target(schedule_start.elapsed()));
+                }
+            }
+            if let Some(plan) = plan.as_mut() {
+                plan.advance(&live_stats, schedule_start);
+            }
+            if let Some(at) = config.chaos_drop_pongs_at {
+                if chaos.is_none() && stage_start.elapsed() >= Duration::from_secs(at) {
+                    chaos = Some(spawn_pong_chaos(
+                        live_stats.clone(),
+                        Duration::from_secs(config.chaos_drop_pongs_for),
+                    ));
+                }
+            }
+            if config.churn_rate > 0.0 {
+                // Carry the fractional part so low rates still churn on average
+                churn_due += config.churn_rate * tick.as_secs_f64();
+                let count = churn_due as usize;
+                fleet.churn(count);
+                churn_due -= count as f64;
+                churned += count;
+            }
+        }
+
+        let received = live_stats.messages_received.load(Ordering::Relaxed);
+        let rate = message_rate.observe(received);
+
+        if last_log.elapsed() >= hold_interval {
+            let active = live_stats.active_connections.load(Ordering::Relaxed);
+            let success = live_stats.subscribe_success.load(Ordering::Relaxed);
+            let errors = live_stats.connection_errors.load(Ordering::Relaxed);
+            let sent_bytes = live_stats.bytes_sent.load(Ordering::Relaxed);
+            let received_bytes = live_stats.bytes_received.load(Ordering::Relaxed);
+            let window = last_log.elapsed();
+            info!(
+                "Stage 3: active={}, subscribed={}, errors={}, messages={}, msg/s={:.0}, out={:.3}MB/s, in={:.3}MB/s",
+                active,
+                success,
+                errors,
+                received,
+                rate,
+                megabytes_per_sec(sent_bytes - last_bytes_sent, window),
+                megabytes_per_sec(received_bytes - last_bytes_received, window)
+            );
+            if config.churn_rate > 0.0 {
+                info!(
+                    "Churn: {} clients replaced ({:.1}/s)",
+                    churned, config.churn_rate
+                );
+            }
+            if let Some(schedule) = &schedule {
+                info!(
+                    "Schedule: target={}, running={}",
+                    schedule.target(schedule_start.elapsed()),
+                    fleet.running.len()
+                );
+            }
+            last_bytes_sent = sent_bytes;
+            last_bytes_received = received_bytes;
+            last_log = Instant::now();
+        }
+    }
+
+    info!(
+        "Stage 4 complete: {} active",
+        live_stats.active_connections.load(Ordering::Relaxed)
+    );
+
+    // Stage 4: Ramp down
+    info!("Stage 4: ramping down over {}s", config.ramp_down_duration);
+
+    let chaos = match chaos {
+        Some(handle) if handle.is_finished() => handle.await.ok(),
+        Some(handle) => {
+            warn!("Hold ended before pong chaos recovery was observed");
+            handle.abort();
+            None
+        }
+        None => None,
+    };
+
+    // Measurement ends here; clients still connected report E2E to the drain monitor
+    let measurement_duration = measure_start.elapsed();
+    let phases = plan.map_or_else(Vec::new, |plan| plan.finish(&live_stats, schedule_start));
+    let stage_start = Instant::now();
+    stages.push((4, stage_start));
+    live_stats.draining.store(true, Ordering::Relaxed);
+    cpu_stop.store(true, Ordering::Relaxed);
+    let ramp_down = Duration::from_secs(config.ramp_down_duration);
+    let drain_monitor = ScopedTask::spawn(monitor_drain(
+        live_stats.clone(),
+        ramp_down + CLOSE_DRAIN_TIMEOUT,
+    ));
+
+    // Disconnect at a constant rate (oldest first) across the ramp-down
+    let draining_from = fleet.running.len();
+    while stage_start.elapsed() < ramp_down {
+        let left = 1.0 - stage_start.elapsed().as_secs_f64() / ramp_down.as_secs_f64();
+        fleet.scale_to((draining_from as f64 * left).ceil() as usize);
+        sleep(Duration::from_millis(50)).await;
+    }
+    fleet.stop_all();
+    let (mut results, tasks) = fleet.into_results();
+
+    // Collect all results
+    info!("Collecting results from all clients...");
+    results.reserve(tasks.len());
+
+    for task in tasks {
+        match tokio::time::timeout(Duration::from_secs(10), task).await {
+            Ok(Ok(result)) => results.push(result),
+            Ok(Err(e)) => {
+                warn!("Task join error: {}", e);
+                results.push(ClientResult::new());
+            }
+            Err(_) => {
+                warn!("Task timed out during collection");
+                results.push(ClientResult::new());
+            }
+        }
+    }
+
+    info!(
+        "Stage 3 complete: {} active",
+        live_stats.active_connections.load(Ordering::Relaxed)
+    );
+
+    if let Some(snapshots) = snapshots {
+        snapshots.abort();
+    }
+    if let Some(abort_watch) = abort_watch {
+        abort_watch.abort();
+    }
+
+    let cpu_windows = cpu_monitor.await.unwrap_or_default();
+    let clock_jumps = clock_monitor.await.unwrap_or_default();
+    let drain_samples = drain_monitor.await.unwrap_or_default();
+    let publish = match publisher {
+        Some(handle) => match handle.await {
+            Ok(Ok(report)) => Some(report),
+            Ok(Err(e)) => {
+                error!("Publisher stopped: {}", e);
+                None
+            }
+            Err(e) => {
+                error!("Publisher panicked: {}", e);
+                None
+            }
+        },
+        None => None,
+    };
+
+    // Clients are done; their senders dropped with them, letting the writer finish
+    if let (Some(writer), Some(path)) = (sample_writer, &config.raw_samples) {
+        // A client stuck past collection still holds a sender; don't wait on it forever
+        match tokio::time::timeout(Duration::from_secs(10), writer).await {
+            Ok(Ok(Ok(written))) => info!("Wrote {} raw latency samples to {:?}", written, path),
+            Ok(Ok(Err(e))) => error!("Raw sample export to {:?} failed: {}", path, e),
+            Ok(Err(e)) => error!("Raw sample writer panicked: {}", e),
+            Err(_) => warn!(
+                "Raw sample export to {:?} did not finish; file may be truncated",
+                path
+            ),
+        }
+    }
+    let finished = Instant::now();
+
+    Ok(TestOutcome {
+        results,
+        measurement_duration,
+        cpu_windows,
+        clock_jumps,
+        drain_samples,
+        token_coverage: tokens.coverage(),
+        phases,
+        chaos,
+        stages,
+        finished,
+        measure_started_ms,
+        peak_pending_connects: live_stats.pending_connects_peak.load(Ordering::Relaxed),
+        aborted: live_stats.abort_reason.get().cloned(),
+        publish,
+    })
+}
+
+/// Live counters over one completed plan phase.
+pub(crate) struct PhaseMark {
+    pub(crate) phase: PlanPhase,
+    pub(crate) start: LiveSnapshot,
+    pub(crate) end: LiveSnapshot,
+}
+
+/// Follows a running plan: applies each phase's scenario as it begins and
+/// snapshots the live counters at every boundary.
+pub(crate) struct PlanTracker {
+    pub(crate) plan: TestPlan,
+    pub(crate) current: usize,
+    pub(crate) start: LiveSnapshot,
+    pub(crate) marks: Vec<PhaseMark>,
+}
+
+impl PlanTracker {
+    pub(crate) fn start(plan: TestPlan, live_stats: &LiveStats, started: Instant) -> Self {
+        let tracker = Self {
+            current: 0,
+            start: LiveSnapshot::capture(live_stats, started),
+            marks: Vec::new(),
+            plan,
+        };
+        tracker.enter(live_stats);
+        tracker
+    }
+
+    pub(crate) fn enter(&self, live_stats: &LiveStats) {
+        let phase = &self.plan.phases[self.current];
+        info!(
+            "Phase {}/{}: {} ({} clients, scenario {})",
+            self.current + 1,
+            self.plan.phases.len(),
+            phase.name,
+            phase.clients,
+            phase.scenario
+        );
+        live_stats.phase.store(self.current, Ordering::Relaxed);
+        live_stats
+            .scenario
+            .send_if_modified(|s| std::mem::replace(s, phase.scenario) != phase.scenario);
+    }
+
+    /// Move on to whichever phase is due, closing out the ones passed.
+    pub(crate) fn advance(&mut self, live_stats: &LiveStats, started: Instant) {
+        let due = self.plan.phase_at(started.elapsed());
+        while self.current < due {
+            self.close(live_stats, started);
+            self.current += 1;
+            self.enter(live_stats);
+        }
+    }
+
+    pub(crate) fn close(&mut self, live_stats: &LiveStats, started: Instant) {
+        let end = LiveSnapshot::capture(live_stats, started);
+        let start = std::mem::replace(&mut self.start, LiveSnapshot::capture(live_stats, started));
+        self.marks.push(PhaseMark {
+            phase: self.plan.phases[self.current].clone(),
+            start,
+            end,
+        });
+    }
+
+    pub(crate) fn finish(mut self, live_stats: &LiveStats, started: Instant) -> Vec<PhaseMark> {
+        self.close(live_stats, started);
+        self.marks
+    }
+}
+
+/// Spawned clients, with individual stop signals for those still running.
+pub(crate) struct ClientFleet {
+    pub(crate) config: Arc<Config>,
+    pub(crate) tokens: TokenPool,
+    pub(crate) live_stats: LiveStats,
+    pub(crate) transport: Arc<dyn Transport>,
+    pub(crate) raw_samples: Option<SampleSender>,
+    /// Clients spawned so far; the next one's id offset
+    pub(crate) spawned: usize,
+    pub(crate) tasks: Vec<tokio::task::JoinHandle<ClientResult>>,
+    /// Results of clients whose tasks have been reaped
+    pub(crate) finished: Vec<ClientResult>,
+    /// Stop signals of clients not yet told to stop, oldest first
+    pub(crate) running: std::collections::VecDeque<broadcast::Sender<()>>,
+    /// Clients a runtime target may still spawn, refilled at `retarget_rate`
+    pub(crate) spawn_credit: f64,
+    pub(crate) credited_at: Instant,
+}
+
+impl ClientFleet {
+    pub(crate) fn spawned(&self) -> usize {
+        self.spawned
+    }
+
+    pub(crate) fn spawn(&mut self) {
+        let id = self.config.client_id_offset + self.spawned;
+        self.spawned += 1;
+        let (stop, stop_rx) = broadcast::channel::<()>(1);
+        let task = tokio::spawn(run_client(
+            id,
+            Arc::clone(&self.config),
+            self.tokens.clone(),
+            self.live_stats.clone(),
+            Arc::clone(&self.transport),
+            stop_rx,
+            self.raw_samples.clone(),
+        ));
+        self.tasks.push(task);
+        self.running.push_back(stop);
+    }
+
+    /// Take the results of finished clients out of their tasks, so a long
+    /// run with churn doesn't keep every completed task alive until the end.
+    pub(crate) fn reap(&mut self) {
+        let (done, running) = std::mem::take(&mut self.tasks)
+            .into_iter()
+            .partition(|task| task.is_finished());
+        self.tasks = running;
+        for task in done {
+            match task.now_or_never() {
+                Some(Ok(result)) => self.finished.push(result),
+                Some(Err(e)) => {
+                    warn!("Task join error: {}", e);
+                    self.finished.push(ClientResult::new());
+                }
+                None => unreachable!("task reported finished"),
+            }
+        }
+    }
+
+    /// Spawn or stop (oldest first) clients until `target` are running.
+    pub(crate) fn scale_to(&mut self, target: usize) {
+        self.reap();
+        while self.running.len() < target {
+            self.spawn();
+        }
+        while self.running.len() > target {
+            if let Some(stop) = self.running.pop_front() {
+                let _ = stop.send(());
+            }
+        }
+    }
+
+    /// Converge on the client target set at runtime, if any; returns whether
+    /// one is set.
+    pub(crate) fn follow_target(
+        &mut self,
+        target: &mut tokio::sync::watch::Receiver<Option<usize>>,
+    ) -> bool {
+        if target.has_changed().unwrap_or(false) {
+            if let Some(n) = *target.borrow_and_update() {
+                info!(
+                    "Client target set to {} ({} running)",
+                    n,
+                    self.running.len()
+                );
+            }
+        }
+        let Some(n) = *target.borrow() else {
+            return false;
+        };
+
+        // Grow at the ramp's pace (with up to a second's burst), not all at once
+        let rate = self.config.retarget_rate();
+        self.spawn_credit = (self.spawn_credit + self.credited_at.elapsed().as_secs_f64() * rate)
+            .min(rate.max(1.0));
+        self.credited_at = Instant::now();
+        let running = self.running.len();
+        let grow = n.saturating_sub(running).min(self.spawn_credit as usize);
+        self.spawn_credit -= grow as f64;
+        self.scale_to(if n > running { running + grow } else { n });
+        true
+    }
+
+    /// Stop the `count` oldest running clients and spawn as many replacements.
+    pub(crate) fn churn(&mut self, count: usize) {
+        self.reap();
+        for stop in self.running.drain(..count.min(self.running.len())) {
+            let _ = stop.send(());
+        }
+        for _ in 0..count {
+            self.spawn();
+        }
+    }
+
+    pub(crate) fn stop_all(&mut self) {
+        for stop in self.running.drain(..) {
+            let _ = stop.send(());
+        }
+    }
+
+    /// Results already reaped, and the tasks still to join.
+    pub(crate) fn into_results(
+        mut self,
+    ) -> (
+        Vec<ClientResult>,
+        Vec<tokio::task::JoinHandle<ClientResult>>,
+    ) {
+        (
+            std::mem::take(&mut self.finished),
+            std::mem::take(&mut self.tasks),
+        )
+    }
+}
+
+/// A fleet dropped before its results were collected belongs to a run cut
+/// short; its clients go with it.
+impl Drop for ClientFleet {
+    fn drop(&mut self) {
+        for task in &self.tasks {
+            task.abort();
+        }
+    }
+}
+
+/// Block while the load is paused; returns how long that was.
+pub(crate) async fn wait_while_paused(live_stats: &LiveStats) -> Duration {
+    let mut paused = live_stats.paused.subscribe();
+    if !*paused.borrow_and_update() {
+        return Duration::ZERO;
+    }
+    let since = Instant::now();
+    let _ = paused.wait_for(|p| !*p).await;
+    since.elapsed()
+}
+
+/// Wait until each of `spawned` clients has connected or failed, or `limit`
+/// has passed since `since`. Returns (connected, failed).
+pub(crate) async fn settle_connections(
+    live_stats: &LiveStats,
+    spawned: usize,
+    since: Instant,
+    limit: Duration,
+) -> (usize, usize) {
+    loop {
+        let active = live_stats.active_connections.load(Ordering::Relaxed);
+        let failed = live_stats.connection_errors.load(Ordering::Relaxed) as usize;
+        if active + failed >= spawned || since.elapsed() >= limit {
+            return (active, failed);
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+}
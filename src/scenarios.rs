@@ -0,0 +1,1070 @@
+//! Filter scenarios, templates and subscription building.
+
+use super::*;
+
+// =============================================================================
+// Filter Building
+// =============================================================================
+
+/// Values per filter: a fixed count, or one drawn for each filter built,
+/// uniformly from `min..=max`, Zipf over `1..=max` with exponent `s` (most
+/// filters small, a long tail of large ones) or log-normal around `median`.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum ValueCount {
+    Fixed(usize),
+    Zipf {
+        max: usize,
+        s: f64,
+    },
+    LogNormal {
+        median: f64,
+        sigma: f64,
+        #[serde(default)]
+        max: Option<usize>,
+    },
+    Range {
+        min: usize,
+        max: usize,
+    },
+}
+
+impl ValueCount {
+    /// Parse `N`, `uniform:MIN:MAX`, `zipf:MAX:S` or `lognormal:MEDIAN:SIGMA[:MAX]`.
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = s.split(':').map(str::trim).collect();
+        let num = |i: usize| -> Result<f64, String> {
+            parts
+                .get(i)
+                .and_then(|p| p.parse::<f64>().ok())
+                .filter(|v| v.is_finite() && *v >= 0.0)
+                .ok_or_else(|| format!("{:?}: expected a number in field {}", s, i + 1))
+        };
+        let int = |i: usize| -> Result<usize, String> {
+            parts
+                .get(i)
+                .and_then(|p| p.parse::<usize>().ok())
+                .ok_or_else(|| format!("{:?}: expected a whole number in field {}", s, i + 1))
+        };
+        let count = match parts[0] {
+            "uniform" if parts.len() == 3 => ValueCount::Range {
+                min: int(1)?,
+                max: int(2)?,
+            },
+            "zipf" if parts.len() == 3 => ValueCount::Zipf {
+                max: int(1)?,
+                s: num(2)?,
+            },
+            "lognormal" if matches!(parts.len(), 3 | 4) => ValueCount::LogNormal {
+                median: num(1)?,
+                sigma: num(2)?,
+                max: (parts.len() == 4).then(|| int(3)).transpose()?,
+            },
+            _ if parts.len() == 1 => ValueCount::Fixed(int(0)?),
+            _ => {
+                return Err(format!(
+                    "{:?}: expected N, uniform:MIN:MAX, zipf:MAX:S or lognormal:MEDIAN:SIGMA[:MAX]",
+                    s
+                ))
+            }
+        };
+        count.validate().map_err(|e| format!("{:?}: {}", s, e))?;
+        Ok(count)
+    }
+
+    /// Smallest and largest count this can draw.
+    pub(crate) fn bounds(self) -> (usize, usize) {
+        match self {
+            ValueCount::Fixed(n) => (n, n),
+            ValueCount::Zipf { max, .. } => (1, max),
+            ValueCount::LogNormal { max, .. } => (1, max.unwrap_or(usize::MAX)),
+            ValueCount::Range { min, max } => (min, max),
+        }
+    }
+
+    pub(crate) fn validate(self) -> Result<(), String> {
+        let (min, max) = self.bounds();
+        if min == 0 || min > max {
+            return Err("values needs 1 <= min <= max".into());
+        }
+        match self {
+            ValueCount::Zipf { s, .. } if s <= 0.0 => Err("zipf needs s > 0".into()),
+            ValueCount::LogNormal { median, sigma, .. } if median < 1.0 || sigma < 0.0 => {
+                Err("lognormal needs median >= 1 and sigma >= 0".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn sample(self) -> usize {
+        let mut rng = rand::rng();
+        match self {
+            ValueCount::Fixed(n) => n,
+            ValueCount::Range { min, max } => rng.random_range(min..=max),
+            ValueCount::Zipf { max, s } => {
+                // Inverse CDF over the (small) support
+                let total: f64 = (1..=max).map(|k| (k as f64).powf(-s)).sum();
+                let mut u = rng.random::<f64>() * total;
+                for k in 1..=max {
+                    u -= (k as f64).powf(-s);
+                    if u <= 0.0 {
+                        return k;
+                    }
+                }
+                max
+            }
+            ValueCount::LogNormal { median, sigma, max } => {
+                // Box-Muller
+                let (u1, u2): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+                let z = (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos();
+                let n = (median * (sigma * z).exp()).round() as usize;
+                n.clamp(1, max.unwrap_or(usize::MAX))
+            }
+        }
+    }
+
+    pub(crate) fn describe(self) -> String {
+        match self {
+            ValueCount::Fixed(n) => format!("x{}", n),
+            ValueCount::Range { min, max } => format!("x{}-{}", min, max),
+            ValueCount::Zipf { max, s } => format!("zipf(max {}, s {})", max, s),
+            ValueCount::LogNormal { median, sigma, max } => match max {
+                Some(max) => format!("lognormal(median {}, sigma {}, max {})", median, sigma, max),
+                None => format!("lognormal(median {}, sigma {})", median, sigma),
+            },
+        }
+    }
+}
+
+/// Threshold for a numeric comparator: a fixed number, or drawn from a
+/// uniform or normal distribution for each filter built.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Threshold {
+    Fixed(f64),
+    Uniform { min: f64, max: f64 },
+    Normal { mean: f64, stddev: f64 },
+}
+
+impl Threshold {
+    pub(crate) fn sample(self) -> f64 {
+        let mut rng = rand::rng();
+        match self {
+            Threshold::Fixed(x) => x,
+            Threshold::Uniform { min, max } => rng.random_range(min..=max),
+            Threshold::Normal { mean, stddev } => {
+                // Box-Muller
+                let (u1, u2): (f64, f64) = (1.0 - rng.random::<f64>(), rng.random());
+                mean + stddev * (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+            }
+        }
+    }
+
+    pub(crate) fn describe(self) -> String {
+        match self {
+            Threshold::Fixed(x) => x.to_string(),
+            Threshold::Uniform { min, max } => format!("U({}, {})", min, max),
+            Threshold::Normal { mean, stddev } => format!("N({}, {})", mean, stddev),
+        }
+    }
+}
+
+/// Comparators that take one numeric threshold instead of string values.
+pub(crate) const NUMERIC_CMPS: [&str; 4] = ["gt", "lt", "gte", "lte"];
+
+/// Values a scenario-file template can refer to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TemplateVars {
+    pub(crate) client_id: usize,
+    /// Index of the current plan phase (0 without a plan)
+    pub(crate) phase: usize,
+    pub(crate) scenario: u8,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum TemplateExpr {
+    Num(i64),
+    Var(&'static str),
+    /// `rand(a, b)`: uniform in `a..=b`
+    Rand(Box<TemplateExpr>, Box<TemplateExpr>),
+    Op(u8, Box<TemplateExpr>, Box<TemplateExpr>),
+}
+
+impl TemplateExpr {
+    /// Integer arithmetic; division or remainder by zero gives 0.
+    pub(crate) fn eval(&self, vars: &TemplateVars) -> i64 {
+        match self {
+            TemplateExpr::Num(n) => *n,
+            TemplateExpr::Var(var) => match *var {
+                "client_id" => vars.client_id as i64,
+                "phase" => vars.phase as i64,
+                _ => vars.scenario as i64,
+            },
+            TemplateExpr::Rand(lo, hi) => {
+                let (lo, hi) = (lo.eval(vars), hi.eval(vars));
+                rand::rng().random_range(lo.min(hi)..=lo.max(hi))
+            }
+            TemplateExpr::Op(op, a, b) => {
+                let (a, b) = (a.eval(vars), b.eval(vars));
+                match op {
+                    b'+' => a.wrapping_add(b),
+                    b'-' => a.wrapping_sub(b),
+                    b'*' => a.wrapping_mul(b),
+                    b'/' => a.checked_div(b).unwrap_or(0),
+                    _ => a.checked_rem(b).unwrap_or(0),
+                }
+            }
+        }
+    }
+}
+
+/// Recursive-descent parser for the expressions inside `{{ }}`.
+pub(crate) struct TemplateParser<'a> {
+    pub(crate) src: &'a [u8],
+    pub(crate) pos: usize,
+}
+
+impl TemplateParser<'_> {
+    pub(crate) fn skip_space(&mut self) {
+        while self.src.get(self.pos).is_some_and(u8::is_ascii_whitespace) {
+            self.pos += 1;
+        }
+    }
+
+    pub(crate) fn eat(&mut self, c: u8) -> bool {
+        self.skip_space();
+        let found = self.src.get(self.pos) == Some(&c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    /// `sum := product (('+' | '-') product)*`
+    pub(crate) fn sum(&mut self) -> Result<TemplateExpr, String> {
+        let mut expr = self.product()?;
+        loop {
+            let op = if self.eat(b'+') {
+                b'+'
+            } else if self.eat(b'-') {
+                b'-'
+            } else {
+                return Ok(expr);
+            };
+            expr = TemplateExpr::Op(op, Box::new(expr), Box::new(self.product()?));
+        }
+    }
+
+    /// `product := atom (('*' | '/' | '%') atom)*`
+    pub(crate) fn product(&mut self) -> Result<TemplateExpr, String> {
+        let mut expr = self.atom()?;
+        loop {
+            let Some(&op) = b"*/%".iter().find(|&&op| self.eat(op)) else {
+                return Ok(expr);
+            };
+            expr = TemplateExpr::Op(op, Box::new(expr), Box::new(self.atom()?));
+        }
+    }
+
+    /// `atom := number | variable | rand(sum, sum) | '(' sum ')'`
+    pub(crate) fn atom(&mut self) -> Result<TemplateExpr, String> {
+        if self.eat(b'(') {
+            let expr = self.sum()?;
+            return match self.eat(b')') {
+                true => Ok(expr),
+                false => Err("missing )".into()),
+            };
+        }
+        self.skip_space();
+        let start = self.pos;
+        while self
+            .src
+            .get(self.pos)
+            .is_some_and(|c| c.is_ascii_alphanumeric() || *c == b'_')
+        {
+            self.pos += 1;
+        }
+        let word = std::str::from_utf8(&self.src[start..self.pos]).unwrap_or_default();
+        match word {
+            "" => Err(format!("expected a value at offset {}", start)),
+            "client_id" => Ok(TemplateExpr::Var("client_id")),
+            "phase" => Ok(TemplateExpr::Var("phase")),
+            "scenario" => Ok(TemplateExpr::Var("scenario")),
+            "rand" => {
+                if !self.eat(b'(') {
+                    return Err("rand needs (min, max)".into());
+                }
+                let lo = self.sum()?;
+                if !self.eat(b',') {
+                    return Err("rand needs (min, max)".into());
+                }
+                let hi = self.sum()?;
+                if !self.eat(b')') {
+                    return Err("missing ) after rand".into());
+                }
+                Ok(TemplateExpr::Rand(Box::new(lo), Box::new(hi)))
+            }
+            _ => word.parse().map(TemplateExpr::Num).map_err(|_| {
+                format!(
+                    "unknown variable {:?} (client_id, phase, scenario, rand)",
+                    word
+                )
+            }),
+        }
+    }
+}
+
+/// A string with `{{ expr }}` placeholders, resolved per client each time it
+/// subscribes, e.g. `tokens_shard_{{ client_id % 16 }}`.
+#[derive(Debug, Clone)]
+pub(crate) struct Template {
+    pub(crate) source: String,
+    pub(crate) parts: Vec<(String, Option<TemplateExpr>)>,
+}
+
+impl Template {
+    pub(crate) fn parse(source: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = source;
+        while let Some(open) = rest.find("{{") {
+            let close = rest[open..]
+                .find("}}")
+                .ok_or_else(|| format!("{:?}: unclosed {{{{", source))?
+                + open;
+            let mut parser = TemplateParser {
+                src: &rest.as_bytes()[open + 2..close],
+                pos: 0,
+            };
+            let expr = parser.sum().map_err(|e| format!("{:?}: {}", source, e))?;
+            parser.skip_space();
+            if parser.pos < parser.src.len() {
+                return Err(format!(
+                    "{:?}: unexpected {:?}",
+                    source,
+                    &rest[open + 2 + parser.pos..close]
+                ));
+            }
+            parts.push((rest[..open].to_string(), Some(expr)));
+            rest = &rest[close + 2..];
+        }
+        parts.push((rest.to_string(), None));
+        Ok(Self {
+            source: source.to_string(),
+            parts,
+        })
+    }
+
+    pub(crate) fn render(&self, vars: &TemplateVars) -> String {
+        let mut out = String::with_capacity(self.source.len());
+        for (text, expr) in &self.parts {
+            out.push_str(text);
+            if let Some(expr) = expr {
+                out.push_str(&expr.eval(vars).to_string());
+            }
+        }
+        out
+    }
+}
+
+/// A filter as written in `--scenario-file`: either one condition
+/// (`key`/`cmp`/`values`/`choices`, or `threshold` for numeric comparators)
+/// or an `all`/`any` of nested ones.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ConditionSpec {
+    /// Tag to filter on (default `--filter-key`)
+    #[serde(default)]
+    pub(crate) key: Option<String>,
+    /// `eq`/`neq` (one value), `in`/`nin`, or numeric `gt`/`lt`/`gte`/`lte`
+    #[serde(default)]
+    pub(crate) cmp: Option<String>,
+    /// Numeric comparators only
+    #[serde(default)]
+    pub(crate) threshold: Option<Threshold>,
+    #[serde(default)]
+    pub(crate) values: Option<ValueCount>,
+    /// Literal values to draw from instead of `--token-file`, e.g. `["1", "56"]`
+    /// for `chain_id`
+    #[serde(default)]
+    pub(crate) choices: Option<Vec<String>>,
+    #[serde(default)]
+    pub(crate) all: Option<Vec<ConditionSpec>>,
+    #[serde(default)]
+    pub(crate) any: Option<Vec<ConditionSpec>>,
+}
+
+/// A scenario as written in `--scenario-file`; one without any condition
+/// subscribes unfiltered.
+#[derive(Debug, Deserialize)]
+pub(crate) struct ScenarioSpec {
+    pub(crate) id: u8,
+    #[serde(default)]
+    pub(crate) name: Option<String>,
+    #[serde(flatten)]
+    pub(crate) filter: ConditionSpec,
+    /// Periodically replace the filter, as scenario 2 does
+    #[serde(default)]
+    pub(crate) filter_updates: bool,
+    /// Replace it by unsubscribing and subscribing afresh (implies `filter_updates`)
+    #[serde(default)]
+    pub(crate) resubscribe: bool,
+    /// Fraction of token values to swap on each update instead of drawing a
+    /// new filter (implies `filter_updates`)
+    #[serde(default)]
+    pub(crate) update_delta: Option<f64>,
+    /// Mean milliseconds between updates, instead of `--filter-update-interval`
+    #[serde(default)]
+    pub(crate) update_interval_ms: Option<u64>,
+    /// Channel instead of `--channel`, with `{{ }}` placeholders over
+    /// `client_id`, `phase`, `scenario` and `rand(min, max)`
+    #[serde(default)]
+    pub(crate) channel: Option<String>,
+    /// Client events each client publishes per second once subscribed
+    #[serde(default)]
+    pub(crate) client_event_rate: Option<f64>,
+}
+
+/// Template for the filters a scenario subscribes with; values are drawn
+/// afresh for each filter built.
+#[derive(Debug, Clone)]
+pub(crate) enum Condition {
+    Leaf {
+        key: &'static str,
+        cmp: &'static str,
+        values: ValueCount,
+        choices: Option<Vec<Arc<str>>>,
+    },
+    /// `key cmp X` for a numeric comparator, X drawn from `threshold`
+    Numeric {
+        key: &'static str,
+        cmp: &'static str,
+        threshold: Threshold,
+    },
+    /// `and` / `or` of the nested conditions
+    Compound {
+        op: &'static str,
+        conditions: Vec<Condition>,
+    },
+    /// Unfiltered: subscribe to the whole channel
+    All,
+}
+
+impl Condition {
+    pub(crate) fn leaf(key: &'static str, cmp: &'static str, values: usize) -> Self {
+        Condition::Leaf {
+            key,
+            cmp,
+            values: ValueCount::Fixed(values),
+            choices: None,
+        }
+    }
+
+    pub(crate) fn from_spec(mut spec: ConditionSpec, default_key: &'static str) -> Result<Self> {
+        let (op, nested) = match (spec.all.take(), spec.any.take()) {
+            (Some(_), Some(_)) => anyhow::bail!("give either all or any, not both"),
+            (Some(nested), None) => ("and", nested),
+            (None, Some(nested)) => ("or", nested),
+            (None, None) => return Self::leaf_from_spec(spec, default_key),
+        };
+        if spec.key.is_some()
+            || spec.cmp.is_some()
+            || spec.values.is_some()
+            || spec.choices.is_some()
+            || spec.threshold.is_some()
+        {
+            anyhow::bail!(
+                "{} takes nested conditions, not key/cmp/values/choices/threshold",
+                op
+            );
+        }
+        if nested.is_empty() {
+            anyhow::bail!("{} needs at least one condition", op);
+        }
+        let conditions = nested
+            .into_iter()
+            .map(|spec| Self::from_spec(spec, default_key))
+            .collect::<Result<_>>()?;
+        Ok(Condition::Compound { op, conditions })
+    }
+
+    pub(crate) fn leaf_from_spec(spec: ConditionSpec, default_key: &'static str) -> Result<Self> {
+        let key = spec.key.map_or(default_key, leak_key);
+        let cmp = match spec.cmp.as_deref() {
+            Some("eq") => "eq",
+            Some("in") => "in",
+            Some("neq") => "neq",
+            Some("nin") => "nin",
+            Some(other) => match NUMERIC_CMPS.iter().find(|&&c| c == other) {
+                Some(cmp) => cmp,
+                None => anyhow::bail!(
+                    "unknown cmp {:?} (eq, in, neq, nin, gt, lt, gte, lte)",
+                    other
+                ),
+            },
+            None => anyhow::bail!("missing cmp (or all/any)"),
+        };
+        if NUMERIC_CMPS.contains(&cmp) {
+            if spec.values.is_some() || spec.choices.is_some() {
+                anyhow::bail!("{} takes a threshold, not values/choices", cmp);
+            }
+            let threshold = spec
+                .threshold
+                .ok_or_else(|| anyhow::anyhow!("{} needs a threshold", cmp))?;
+            if let Threshold::Uniform { min, max } = threshold {
+                if min > max {
+                    anyhow::bail!("threshold needs min <= max");
+                }
+            }
+            return Ok(Condition::Numeric {
+                key,
+                cmp,
+                threshold,
+            });
+        }
+        if spec.threshold.is_some() {
+            anyhow::bail!("{} takes values, not a threshold", cmp);
+        }
+        let (values, choices) = (
+            spec.values.unwrap_or(ValueCount::Fixed(1)),
+            spec.choices.map(|c| c.into_iter().map(Arc::from).collect()),
+        );
+        values.validate().map_err(anyhow::Error::msg)?;
+        if matches!(cmp, "eq" | "neq") && values.bounds().1 > 1 {
+            anyhow::bail!("{} takes exactly one value", cmp);
+        }
+        if choices.as_ref().is_some_and(Vec::is_empty) {
+            anyhow::bail!("choices is empty");
+        }
+        Ok(Condition::Leaf {
+            key,
+            cmp,
+            values,
+            choices,
+        })
+    }
+
+    pub(crate) fn build(&self, tokens: &TokenPool) -> FilterValue {
+        match self {
+            Condition::Leaf {
+                key,
+                cmp,
+                values,
+                choices,
+            } => {
+                let mut vals = match choices {
+                    Some(choices) => choices
+                        .choose_multiple(&mut rand::rng(), values.sample())
+                        .cloned()
+                        .collect(),
+                    None if matches!(*cmp, "eq" | "neq") => vec![tokens.get_random()],
+                    None => tokens.get_random_unique(values.sample()),
+                };
+                match *cmp {
+                    "eq" | "neq" => FilterValue::Single {
+                        key,
+                        cmp,
+                        val: vals.pop().unwrap_or_default(),
+                    },
+                    _ => FilterValue::Multiple { key, cmp, vals },
+                }
+            }
+            Condition::Numeric {
+                key,
+                cmp,
+                threshold,
+            } => FilterValue::Threshold {
+                key,
+                cmp,
+                val: threshold.sample(),
+            },
+            Condition::Compound { op, conditions } => FilterValue::Compound {
+                op,
+                filters: conditions.iter().map(|c| c.build(tokens)).collect(),
+            },
+            Condition::All => FilterValue::All,
+        }
+    }
+
+    /// `old`, built from this condition, with `fraction` of its token values
+    /// (at least one per leaf) replaced by tokens it doesn't hold yet. Values
+    /// from `choices` and numeric thresholds are kept.
+    pub(crate) fn swap(&self, old: &FilterValue, fraction: f64, tokens: &TokenPool) -> FilterValue {
+        let swap_vals = |vals: &mut [Arc<str>]| {
+            let mut rng = rand::rng();
+            let count = ((vals.len() as f64 * fraction).ceil() as usize).clamp(1, vals.len());
+            let indices: Vec<usize> = (0..vals.len()).collect();
+            for &i in indices.choose_multiple(&mut rng, count) {
+                // A few draws to avoid a value the filter already holds
+                let mut fresh = tokens.get_random();
+                for _ in 0..8 {
+                    if !vals.contains(&fresh) {
+                        break;
+                    }
+                    fresh = tokens.get_random();
+                }
+                vals[i] = fresh;
+            }
+        };
+        match (self, old) {
+            (Condition::Leaf { choices: None, .. }, FilterValue::Single { key, cmp, val }) => {
+                let mut vals = [val.clone()];
+                swap_vals(&mut vals);
+                let [val] = vals;
+                FilterValue::Single { key, cmp, val }
+            }
+            (Condition::Leaf { choices: None, .. }, FilterValue::Multiple { key, cmp, vals })
+                if !vals.is_empty() =>
+            {
+                let mut vals = vals.clone();
+                swap_vals(&mut vals);
+                FilterValue::Multiple { key, cmp, vals }
+            }
+            (Condition::Leaf { .. } | Condition::Numeric { .. } | Condition::All, _) => old.clone(),
+            (Condition::Compound { conditions, .. }, FilterValue::Compound { op, filters })
+                if conditions.len() == filters.len() =>
+            {
+                FilterValue::Compound {
+                    op,
+                    filters: conditions
+                        .iter()
+                        .zip(filters)
+                        .map(|(c, f)| c.swap(f, fraction, tokens))
+                        .collect(),
+                }
+            }
+            (Condition::Compound { .. }, _) => self.build(tokens),
+        }
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            Condition::Leaf {
+                key, cmp, values, ..
+            } => format!("{} {} {}", key, cmp, values.describe()),
+            Condition::Numeric {
+                key,
+                cmp,
+                threshold,
+            } => format!("{} {} {}", key, cmp, threshold.describe()),
+            Condition::Compound { op, conditions } => {
+                let parts: Vec<String> = conditions
+                    .iter()
+                    .map(|c| match c {
+                        Condition::Compound { .. } => format!("({})", c.describe()),
+                        _ => c.describe(),
+                    })
+                    .collect();
+                parts.join(&format!(" {} ", op))
+            }
+            Condition::All => "no filter".to_string(),
+        }
+    }
+}
+
+/// How a client builds its filter, and whether it keeps changing it.
+#[derive(Debug, Clone)]
+pub(crate) struct Scenario {
+    pub(crate) name: String,
+    pub(crate) filter: Condition,
+    pub(crate) filter_updates: bool,
+    /// Updates send `pusher:unsubscribe` and a fresh subscribe rather than
+    /// resubscribing in place
+    pub(crate) resubscribe: bool,
+    /// Updates swap this fraction of the token values instead of drawing a
+    /// new filter
+    pub(crate) delta: Option<f64>,
+    pub(crate) update_interval: Option<u64>,
+    /// Per-client channel instead of `--channel`
+    pub(crate) channel: Option<Template>,
+    /// `--client-event`s each client publishes per second
+    pub(crate) client_events: Option<f64>,
+}
+
+impl Scenario {
+    pub(crate) fn builtin(name: &str, filter: Condition, filter_updates: bool) -> Self {
+        Self {
+            name: name.to_string(),
+            filter,
+            filter_updates,
+            resubscribe: false,
+            delta: None,
+            update_interval: None,
+            channel: None,
+            client_events: None,
+        }
+    }
+
+    pub(crate) fn from_spec(spec: ScenarioSpec, default_key: &'static str) -> Result<Self> {
+        if let Some(delta) = spec.update_delta {
+            if !(delta > 0.0 && delta <= 1.0) {
+                anyhow::bail!("scenario {}: update_delta must be in (0, 1]", spec.id);
+            }
+        }
+        if spec
+            .client_event_rate
+            .is_some_and(|r| !(r > 0.0 && r.is_finite()))
+        {
+            anyhow::bail!("scenario {}: client_event_rate must be positive", spec.id);
+        }
+        let channel = spec
+            .channel
+            .as_deref()
+            .map(Template::parse)
+            .transpose()
+            .map_err(|e| anyhow::anyhow!("scenario {}: channel {}", spec.id, e))?;
+        let f = &spec.filter;
+        let unfiltered = f.key.is_none()
+            && f.cmp.is_none()
+            && f.threshold.is_none()
+            && f.values.is_none()
+            && f.choices.is_none()
+            && f.all.is_none()
+            && f.any.is_none();
+        let filter = if unfiltered {
+            Condition::All
+        } else {
+            Condition::from_spec(spec.filter, default_key)
+                .map_err(|e| anyhow::anyhow!("scenario {}: {}", spec.id, e))?
+        };
+        Ok(Self {
+            name: spec.name.unwrap_or_else(|| format!("scenario-{}", spec.id)),
+            filter,
+            filter_updates: spec.filter_updates || spec.resubscribe || spec.update_delta.is_some(),
+            resubscribe: spec.resubscribe,
+            delta: spec.update_delta,
+            update_interval: spec.update_interval_ms,
+            channel,
+            client_events: spec.client_event_rate,
+        })
+    }
+
+    pub(crate) fn describe(&self) -> String {
+        let updates = match (self.filter_updates, self.resubscribe, self.delta) {
+            (_, true, _) => ", resubscribing".to_string(),
+            (true, false, Some(delta)) => format!(", swapping {:.0}%", delta * 100.0),
+            (true, false, None) => ", updating".to_string(),
+            (false, false, _) => String::new(),
+        };
+        let channel = match &self.channel {
+            Some(template) => format!(" on {}", template.source),
+            None => String::new(),
+        };
+        let events = match self.client_events {
+            Some(rate) => format!(", publishing {}/s", rate),
+            None => String::new(),
+        };
+        format!(
+            "{} ({}{}{}){}",
+            self.name,
+            self.filter.describe(),
+            updates,
+            events,
+            channel
+        )
+    }
+}
+
+/// Scenarios by id: the built-in 0-13, overridden or extended by `--scenario-file`.
+#[derive(Debug, Clone)]
+pub(crate) struct ScenarioSet {
+    pub(crate) scenarios: BTreeMap<u8, Scenario>,
+}
+
+/// Filter keys live for the whole run and filters copy them around by
+/// reference, so a configured key is leaked once at startup.
+pub(crate) fn leak_key(key: String) -> &'static str {
+    Box::leak(key.into_boxed_str())
+}
+
+impl Default for ScenarioSet {
+    fn default() -> Self {
+        Self::builtin("token_address", None)
+    }
+}
+
+impl ScenarioSet {
+    /// Scenarios 0-13. 0 subscribes without a filter, as the fan-out
+    /// baseline; 1-12 filter on `key`, which 6 and 7 combine with a
+    /// `chain_id eq 1` condition; 8 is a `price gt` range filter instead;
+    /// 9 and 10 are the negations of 1 and 4; 11 is 2 with unsubscribe and
+    /// subscribe instead of in-place updates; 12 is 4 updating by swapping
+    /// 10% of its tokens; 13 is 0 with every client also publishing a
+    /// client event a second. `filter_size` replaces the 100 values of 12 and
+    /// the 10/100/500 of 3-5.
+    pub(crate) fn builtin(key: &'static str, filter_size: Option<ValueCount>) -> Self {
+        let sized = |n| Condition::Leaf {
+            key,
+            cmp: "in",
+            values: filter_size.unwrap_or(ValueCount::Fixed(n)),
+            choices: None,
+        };
+        let chain = Condition::Leaf {
+            key: "chain_id",
+            cmp: "eq",
+            values: ValueCount::Fixed(1),
+            choices: Some(vec!["1".into()]),
+        };
+        let scenarios = [
+            (0, Scenario::builtin("unfiltered", Condition::All, false)),
+            (
+                1,
+                Scenario::builtin("single", Condition::leaf(key, "eq", 1), false),
+            ),
+            (
+                2,
+                Scenario::builtin("single-updating", Condition::leaf(key, "eq", 1), true),
+            ),
+            (3, Scenario::builtin("in-10", sized(10), false)),
+            (4, Scenario::builtin("in-100", sized(100), false)),
+            (5, Scenario::builtin("in-500", sized(500), false)),
+            (
+                6,
+                Scenario::builtin(
+                    "in-10-and-chain",
+                    Condition::Compound {
+                        op: "and",
+                        conditions: vec![Condition::leaf(key, "in", 10), chain.clone()],
+                    },
+                    false,
+                ),
+            ),
+            (
+                7,
+                Scenario::builtin(
+                    "single-or-chain",
+                    Condition::Compound {
+                        op: "or",
+                        conditions: vec![Condition::leaf(key, "eq", 1), chain],
+                    },
+                    false,
+                ),
+            ),
+            (
+                8,
+                Scenario::builtin(
+                    "price-gt",
+                    Condition::Numeric {
+                        key: "price",
+                        cmp: "gt",
+                        threshold: Threshold::Uniform {
+                            min: 0.0,
+                            max: 1000.0,
+                        },
+                    },
+                    false,
+                ),
+            ),
+            (
+                9,
+                Scenario::builtin("single-neq", Condition::leaf(key, "neq", 1), false),
+            ),
+            (
+                10,
+                Scenario::builtin("nin-100", Condition::leaf(key, "nin", 100), false),
+            ),
+            (
+                11,
+                Scenario {
+                    resubscribe: true,
+                    ..Scenario::builtin("single-resubscribe", Condition::leaf(key, "eq", 1), true)
+                },
+            ),
+            (
+                12,
+                Scenario {
+                    delta: Some(0.1),
+                    ..Scenario::builtin("in-100-delta", sized(100), true)
+                },
+            ),
+            (
+                13,
+                Scenario {
+                    client_events: Some(1.0),
+                    ..Scenario::builtin("unfiltered-client-events", Condition::All, false)
+                },
+            ),
+        ];
+        Self {
+            scenarios: scenarios.into_iter().collect(),
+        }
+    }
+
+    /// Load a JSON array of scenarios on top of the built-ins; conditions
+    /// without a `key` filter on `key`.
+    pub(crate) fn load(
+        path: &std::path::Path,
+        key: &'static str,
+        filter_size: Option<ValueCount>,
+    ) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let specs: Vec<ScenarioSpec> = sonic_rs::from_str(&content)?;
+        let mut set = Self::builtin(key, filter_size);
+        for spec in specs {
+            let id = spec.id;
+            let scenario =
+                Scenario::from_spec(spec, key).map_err(|e| anyhow::anyhow!("{:?}: {}", path, e))?;
+            set.scenarios.insert(id, scenario);
+        }
+        Ok(set)
+    }
+
+    /// Scenario `id`; unknown ids fall back to scenario 1.
+    pub(crate) fn get(&self, id: u8) -> &Scenario {
+        self.scenarios
+            .get(&id)
+            .or_else(|| self.scenarios.get(&1))
+            .expect("scenario 1 is built in")
+    }
+
+    pub(crate) fn contains(&self, id: u8) -> bool {
+        self.scenarios.contains_key(&id)
+    }
+}
+
+/// Parse `--filter-json` or `--filter-json-file` into passthrough filters,
+/// attributed to messages by `key`.
+pub(crate) fn load_raw_filters(config: &Config, key: &'static str) -> Result<Vec<FilterValue>> {
+    let filters: Vec<sonic_rs::Value> = match (&config.filter_json, &config.filter_json_file) {
+        (Some(_), Some(_)) => anyhow::bail!("give either --filter-json or --filter-json-file"),
+        (Some(json), None) => {
+            vec![sonic_rs::from_str(json).map_err(|e| anyhow::anyhow!("--filter-json: {}", e))?]
+        }
+        (None, Some(path)) => {
+            let content = std::fs::read_to_string(path)?;
+            let filters: Vec<sonic_rs::Value> = sonic_rs::from_str(&content)?;
+            if filters.is_empty() {
+                anyhow::bail!("{:?}: no filters", path);
+            }
+            filters
+        }
+        (None, None) => Vec::new(),
+    };
+    Ok(filters
+        .into_iter()
+        .map(|json| FilterValue::Raw(Arc::new(RawFilter { key, json })))
+        .collect())
+}
+
+/// Parse `--filter-map-file` into filters by client index; those we can't
+/// evaluate become passthrough filters attributed by `key`.
+pub(crate) fn load_filter_map(
+    path: &std::path::Path,
+    key: &'static str,
+) -> Result<HashMap<usize, FilterValue>> {
+    let content = std::fs::read_to_string(path)?;
+    let entries: HashMap<String, sonic_rs::Value> = sonic_rs::from_str(&content)?;
+    let mut keys = HashMap::new();
+    let mut map = HashMap::with_capacity(entries.len());
+    for (client, json) in entries {
+        let client: usize = client
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{:?}: {:?} is not a client index", path, client))?;
+        let filter = FilterValue::from_json(&json, &mut keys)
+            .unwrap_or_else(|| FilterValue::Raw(Arc::new(RawFilter { key, json })));
+        map.insert(client, filter);
+    }
+    Ok(map)
+}
+
+/// Parse `--mix 1:70,3:20,5:10` into (scenario, cumulative share) pairs.
+pub(crate) fn parse_mix(spec: &str, scenarios: &ScenarioSet) -> Result<Vec<(u8, f64)>> {
+    let mut weights: Vec<(u8, f64)> = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let parsed = part
+            .split_once(':')
+            .and_then(|(s, w)| Some((s.trim().parse::<u8>().ok()?, w.trim().parse::<f64>().ok()?)));
+        let Some((scenario, weight)) = parsed.filter(|&(_, w)| w.is_finite() && w > 0.0) else {
+            anyhow::bail!(
+                "--mix {:?}: expected scenario:weight with a positive weight",
+                part
+            );
+        };
+        if !scenarios.contains(scenario) {
+            anyhow::bail!("--mix: no scenario {}", scenario);
+        }
+        weights.push((scenario, weight));
+    }
+    let total: f64 = weights.iter().map(|&(_, w)| w).sum();
+    if total == 0.0 {
+        anyhow::bail!("--mix {:?}: no scenarios", spec);
+    }
+    let mut upto = 0.0;
+    let mut mix: Vec<(u8, f64)> = weights
+        .into_iter()
+        .map(|(scenario, weight)| {
+            upto += weight / total;
+            (scenario, upto)
+        })
+        .collect();
+    // Rounding must not leave a sliver past the last share
+    if let Some(last) = mix.last_mut() {
+        last.1 = f64::INFINITY;
+    }
+    Ok(mix)
+}
+
+/// Filter for client `id`: its `--filter-map-file` entry or `--filter-json`
+/// passthrough if given, else one built from `scenario`.
+#[inline]
+pub(crate) fn build_filter(
+    config: &Config,
+    scenario: u8,
+    id: usize,
+    tokens: &TokenPool,
+) -> FilterValue {
+    if let Some(filter) = config.filter_map.get(&id) {
+        return filter.clone();
+    }
+    match config.raw_filters.len() {
+        0 => config.scenarios.get(scenario).filter.build(tokens),
+        n => config.raw_filters[id % n].clone(),
+    }
+}
+
+/// Channel client `id` subscribes on: the scenario's template rendered for
+/// it, or `--channel`.
+pub(crate) fn subscription_channel(
+    config: &Config,
+    scenario: u8,
+    id: usize,
+    live_stats: &LiveStats,
+) -> String {
+    match &config.scenarios.get(scenario).channel {
+        Some(template) => template.render(&TemplateVars {
+            client_id: id,
+            phase: live_stats.phase.load(Ordering::Relaxed),
+            scenario,
+        }),
+        None => config.channel.clone(),
+    }
+}
+
+/// Break an `in` filter with more than `max_vals` values into chunks, each
+/// on its own numbered sub-channel. Anything else stays a single subscription
+/// on `channel`; a split `nin` would let every chunk's exclusions through
+/// the other sub-channels.
+pub(crate) fn split_filter(
+    channel: &str,
+    filter: FilterValue,
+    max_vals: usize,
+) -> Vec<(String, FilterValue)> {
+    match filter {
+        FilterValue::Multiple { key, cmp, vals }
+            if cmp == "in" && max_vals > 0 && vals.len() > max_vals =>
+        {
+            vals.chunks(max_vals)
+                .enumerate()
+                .map(|(i, chunk)| {
+                    (
+                        format!("{}-{}", channel, i),
+                        FilterValue::Multiple {
+                            key,
+                            cmp,
+                            vals: chunk.to_vec(),
+                        },
+                    )
+                })
+                .collect()
+        }
+        filter => vec![(channel.to_string(), filter)],
+    }
+}
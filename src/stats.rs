@@ -0,0 +1,503 @@
+//! Per-client results and the live counters, gauges and histograms shared across clients.
+
+use super::*;
+
+// =============================================================================
+// Per-Client Results (Lock-Free)
+// =============================================================================
+
+pub(crate) struct ClientResult {
+    pub(crate) id: usize,
+    pub(crate) misbehavior: Option<Misbehavior>,
+    pub(crate) setup: Option<SetupTimings>,
+    /// Time spent queued behind `--max-connect-rate` / `--max-pending-connects`
+    pub(crate) connect_queue_ms: Vec<u64>,
+    /// Time from upgrade completion to `pusher:connection_established`
+    pub(crate) established_wait_ms: Option<u64>,
+    pub(crate) subscribe_latency_ms: Option<u64>,
+    pub(crate) filter_update_latencies: Vec<u64>,
+    /// Time from a filter update until the first message matching the new filter
+    pub(crate) filter_propagation_latencies: Vec<u64>,
+    /// Time from a filter update until the last message matching only the old filter
+    pub(crate) stale_delivery_windows: Vec<u64>,
+    pub(crate) stale_messages: u64,
+    /// E2E latencies recorded while the generator CPU was above the suspect threshold
+    pub(crate) suspect_e2e_latencies: Vec<u64>,
+    /// E2E samples dropped because the system clock had just jumped
+    pub(crate) clock_excluded_e2e: u64,
+    pub(crate) bytes_sent: u64,
+    pub(crate) bytes_received: u64,
+    /// Payload sizes (bytes) of received channel messages
+    pub(crate) message_sizes: Vec<u64>,
+    /// Kernel smoothed RTT samples in microseconds (TCP_INFO sampled clients)
+    pub(crate) tcp_rtt_us: Vec<u64>,
+    pub(crate) tcp_retransmits: u64,
+    /// E2E latency (ms) paired with the kernel RTT (us) current at receipt
+    pub(crate) rtt_tagged_e2e: Vec<(u64, u32)>,
+    /// (receipt unix ms, E2E latency ms) for rolling percentiles
+    pub(crate) e2e_timeline: Vec<(u64, u64)>,
+    /// E2E latencies keyed by the incoming `event` name
+    pub(crate) e2e_latencies: HashMap<String, Vec<u64>>,
+    /// E2E latencies keyed by the stratum of the message's token
+    pub(crate) e2e_by_stratum: HashMap<String, Vec<u64>>,
+    pub(crate) messages_received: u64,
+    pub(crate) messages_received_during_warmup: u64,
+    pub(crate) connected: bool,
+    pub(crate) subscribe_success: bool,
+    pub(crate) connection_error: bool,
+    /// Categorized errors with when they happened
+    pub(crate) errors: Vec<(Instant, ErrorCategory)>,
+    /// Connection was closed by the server rather than by shutdown
+    pub(crate) server_closed: bool,
+    /// Code and reason of a server-initiated Close frame
+    pub(crate) close_frame: Option<(u16, String)>,
+    /// Reconnects after server-side disconnects (`--reconnect`)
+    pub(crate) reconnects: u64,
+    /// Reconnects that carried a resume token
+    pub(crate) resume_attempts: u64,
+    /// Time from disconnect until the resubscribe was acknowledged
+    pub(crate) reconnect_latencies: Vec<u64>,
+    /// Time from resubscribe until the first live (non-replayed) message
+    pub(crate) catchup_latencies: Vec<u64>,
+    /// Messages published during a gap and replayed after resubscribing
+    pub(crate) replayed_messages: u64,
+    /// Frames the server kept sending after we sent our Close frame
+    pub(crate) post_close_messages: u64,
+    /// Time from sending our Close frame to the server's close/EOF
+    pub(crate) close_drain_ms: Option<u64>,
+    pub(crate) acks_sent: u64,
+    pub(crate) acks_confirmed: u64,
+    /// Acks still queued or unconfirmed when the session ended
+    pub(crate) acks_outstanding: u64,
+    /// Time from sending an ack to the server's confirmation
+    pub(crate) ack_rtt_latencies: Vec<u64>,
+    /// Messages whose `token_address` tag was format-checked
+    pub(crate) addresses_checked: u64,
+    /// Malformed `token_address` tags by problem
+    pub(crate) malformed_addresses: HashMap<&'static str, u64>,
+    /// Messages checked against the current filter (`--validate-filters`)
+    pub(crate) filters_checked: u64,
+    /// Messages matching neither the current filter nor, mid-update, the old one
+    pub(crate) false_positives: u64,
+    /// Messages received in each `--expect-window` the client was subscribed
+    /// and measuring throughout
+    pub(crate) throughput_windows: Vec<u64>,
+    /// Scenario the client started on (its `--mix` share)
+    pub(crate) scenario: u8,
+    /// Unsubscribe + subscribe cycles (resubscribing scenarios)
+    pub(crate) resubscribe_cycles: u64,
+    /// Time from `pusher:unsubscribe` to `pusher_internal:unsubscribed`
+    pub(crate) unsubscribe_ack_latencies: Vec<u64>,
+    /// Channel messages between an unsubscribe and the fresh subscription
+    pub(crate) post_unsubscribe_messages: u64,
+    /// Time from the fresh subscribe to its acknowledgment
+    pub(crate) resubscribe_latencies: Vec<u64>,
+    /// Backend of each connection, in order (`--backend-id`)
+    pub(crate) backends: Vec<String>,
+    pub(crate) client_events_sent: u64,
+    /// Other clients' client events received
+    pub(crate) client_events_received: u64,
+    /// Time from another client publishing a client event to its receipt here
+    pub(crate) client_event_latencies: Vec<u64>,
+    /// `--client-ping` round trips in microseconds
+    pub(crate) ping_rtt_us: Vec<u64>,
+    pub(crate) pings_sent: u64,
+    /// Pings whose pong hadn't arrived by the time the next was due
+    pub(crate) pings_unanswered: u64,
+}
+
+impl ClientResult {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: 0,
+            scenario: 0,
+            resubscribe_cycles: 0,
+            unsubscribe_ack_latencies: Vec::new(),
+            post_unsubscribe_messages: 0,
+            resubscribe_latencies: Vec::new(),
+            misbehavior: None,
+            setup: None,
+            connect_queue_ms: Vec::new(),
+            established_wait_ms: None,
+            subscribe_latency_ms: None,
+            filter_update_latencies: Vec::with_capacity(64),
+            filter_propagation_latencies: Vec::with_capacity(64),
+            stale_delivery_windows: Vec::with_capacity(64),
+            stale_messages: 0,
+            suspect_e2e_latencies: Vec::new(),
+            clock_excluded_e2e: 0,
+            bytes_sent: 0,
+            bytes_received: 0,
+            message_sizes: Vec::with_capacity(1024),
+            tcp_rtt_us: Vec::new(),
+            tcp_retransmits: 0,
+            rtt_tagged_e2e: Vec::new(),
+            e2e_timeline: Vec::new(),
+            e2e_by_stratum: HashMap::new(),
+            e2e_latencies: HashMap::new(),
+            messages_received: 0,
+            messages_received_during_warmup: 0,
+            connected: false,
+            subscribe_success: false,
+            connection_error: false,
+            errors: Vec::new(),
+            server_closed: false,
+            close_frame: None,
+            reconnects: 0,
+            resume_attempts: 0,
+            reconnect_latencies: Vec::new(),
+            catchup_latencies: Vec::new(),
+            replayed_messages: 0,
+            post_close_messages: 0,
+            close_drain_ms: None,
+            acks_sent: 0,
+            acks_confirmed: 0,
+            acks_outstanding: 0,
+            ack_rtt_latencies: Vec::new(),
+            addresses_checked: 0,
+            malformed_addresses: HashMap::new(),
+            filters_checked: 0,
+            false_positives: 0,
+            throughput_windows: Vec::new(),
+            backends: Vec::new(),
+            client_events_sent: 0,
+            client_events_received: 0,
+            client_event_latencies: Vec::new(),
+            ping_rtt_us: Vec::new(),
+            pings_sent: 0,
+            pings_unanswered: 0,
+        }
+    }
+}
+
+// =============================================================================
+// Global Atomic Counters (for live stats only)
+// =============================================================================
+
+#[derive(Clone)]
+pub(crate) struct LiveStats {
+    pub(crate) active_connections: Arc<AtomicUsize>,
+    pub(crate) messages_received: Arc<AtomicU64>,
+    pub(crate) bytes_sent: Arc<AtomicU64>,
+    pub(crate) bytes_received: Arc<AtomicU64>,
+    pub(crate) subscribe_success: Arc<AtomicU64>,
+    /// Clients that connected but never subscribed, counted once each
+    pub(crate) subscribe_failed: Arc<AtomicU64>,
+    pub(crate) connection_errors: Arc<AtomicU64>,
+    pub(crate) warmup_complete: Arc<std::sync::atomic::AtomicBool>,
+    /// Set by the CPU monitor while the generator is above the suspect threshold
+    pub(crate) cpu_suspect: Arc<std::sync::atomic::AtomicBool>,
+    /// Set by the clock monitor for the interval in which the system clock jumped
+    pub(crate) clock_suspect: Arc<std::sync::atomic::AtomicBool>,
+    /// Channel frames received after the client initiated its close handshake
+    pub(crate) post_close_messages: Arc<AtomicU64>,
+    /// Set once the ramp-down begins; clients stop recording headline metrics
+    pub(crate) draining: Arc<AtomicBool>,
+    /// E2E latency sum, count and max (ms) from clients during the ramp-down,
+    /// drained by the drain monitor each sample
+    pub(crate) drain_e2e_sum: Arc<AtomicU64>,
+    pub(crate) drain_e2e_count: Arc<AtomicU64>,
+    pub(crate) drain_e2e_max: Arc<AtomicU64>,
+    /// Unix ms until which clients ignore pings (pong chaos); 0 when inactive
+    pub(crate) drop_pongs_until_ms: Arc<AtomicU64>,
+    /// Client id currently traced at info level (`NO_DEBUG_CLIENT` for none)
+    pub(crate) debug_client: Arc<AtomicUsize>,
+    /// Open once clients may start subscribing (`--start-barrier`)
+    pub(crate) start_gate: Arc<tokio::sync::watch::Sender<bool>>,
+    /// Scenario clients subscribe with; a plan phase changing it makes
+    /// subscribed clients switch filters
+    pub(crate) scenario: Arc<tokio::sync::watch::Sender<u8>>,
+    /// Index of the current plan phase, for channel templates
+    pub(crate) phase: Arc<AtomicUsize>,
+    /// Spaces out handshakes under `--max-connect-rate`
+    pub(crate) connect_pacer: Option<Arc<ConnectPacer>>,
+    /// Bounds handshakes in flight under `--max-pending-connects`
+    pub(crate) handshake_slots: Option<Arc<tokio::sync::Semaphore>>,
+    /// Handshakes in flight right now, and the most seen at once
+    pub(crate) pending_connects: Arc<AtomicUsize>,
+    pub(crate) pending_connects_peak: Arc<AtomicUsize>,
+    /// Health gauges fed instead of per-client results under `--canary`
+    pub(crate) canary: Option<Arc<CanaryGauges>>,
+    /// Set while the load is paused: no clients are spawned or stopped and
+    /// periodic filter updates are skipped
+    pub(crate) paused: Arc<tokio::sync::watch::Sender<bool>>,
+    /// Client count set at runtime (`POST /clients?target=N`); overrides the
+    /// load profile once set
+    pub(crate) client_target: Arc<tokio::sync::watch::Sender<Option<usize>>>,
+    /// Why an `--abort-if` guard stopped the run, once one has
+    pub(crate) abort_reason: Arc<std::sync::OnceLock<String>>,
+    /// E2E latencies recorded so far, for reports before results are collected
+    pub(crate) e2e: Arc<LiveHistogram>,
+    /// Exit status to end the run with at once, without a summary (second
+    /// Ctrl-C, `--max-runtime`)
+    pub(crate) exit_status: Arc<tokio::sync::watch::Sender<Option<i32>>>,
+}
+
+pub(crate) const NO_DEBUG_CLIENT: usize = usize::MAX;
+
+impl LiveStats {
+    pub(crate) fn new() -> Self {
+        Self {
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            messages_received: Arc::new(AtomicU64::new(0)),
+            bytes_sent: Arc::new(AtomicU64::new(0)),
+            bytes_received: Arc::new(AtomicU64::new(0)),
+            subscribe_success: Arc::new(AtomicU64::new(0)),
+            subscribe_failed: Arc::new(AtomicU64::new(0)),
+            connection_errors: Arc::new(AtomicU64::new(0)),
+            warmup_complete: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            cpu_suspect: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            clock_suspect: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            post_close_messages: Arc::new(AtomicU64::new(0)),
+            draining: Arc::new(AtomicBool::new(false)),
+            drain_e2e_sum: Arc::new(AtomicU64::new(0)),
+            drain_e2e_count: Arc::new(AtomicU64::new(0)),
+            drain_e2e_max: Arc::new(AtomicU64::new(0)),
+            drop_pongs_until_ms: Arc::new(AtomicU64::new(0)),
+            debug_client: Arc::new(AtomicUsize::new(NO_DEBUG_CLIENT)),
+            start_gate: Arc::new(tokio::sync::watch::Sender::new(true)),
+            scenario: Arc::new(tokio::sync::watch::Sender::new(1)),
+            phase: Arc::new(AtomicUsize::new(0)),
+            connect_pacer: None,
+            handshake_slots: None,
+            pending_connects: Arc::new(AtomicUsize::new(0)),
+            pending_connects_peak: Arc::new(AtomicUsize::new(0)),
+            canary: None,
+            paused: Arc::new(tokio::sync::watch::Sender::new(false)),
+            client_target: Arc::new(tokio::sync::watch::Sender::new(None)),
+            abort_reason: Arc::new(std::sync::OnceLock::new()),
+            e2e: Arc::new(LiveHistogram::new()),
+            exit_status: Arc::new(tokio::sync::watch::Sender::new(None)),
+        }
+    }
+
+    pub(crate) fn aborted(&self) -> bool {
+        self.abort_reason.get().is_some()
+    }
+
+    /// Return the per-run flags to their starting state for another
+    /// `--iterations` run. Counters keep accumulating for the exporter.
+    pub(crate) fn reset_for_iteration(&self, scenario: u8) {
+        self.warmup_complete.store(false, Ordering::Relaxed);
+        self.draining.store(false, Ordering::Relaxed);
+        self.drop_pongs_until_ms.store(0, Ordering::Relaxed);
+        self.pending_connects_peak.store(0, Ordering::Relaxed);
+        self.scenario.send_replace(scenario);
+        self.phase.store(0, Ordering::Relaxed);
+        self.client_target.send_replace(None);
+    }
+}
+
+/// Bytes on the wire for one WebSocket frame carrying `payload` bytes: the
+/// 2-byte header, a 2- or 8-byte extended length, and the 4-byte mask key
+/// on frames the client sends. TLS records and TCP/IP headers are not counted.
+#[inline(always)]
+pub(crate) fn ws_frame_len(payload: usize, masked: bool) -> usize {
+    let extended = match payload {
+        0..=125 => 0,
+        126..=0xffff => 2,
+        _ => 8,
+    };
+    2 + extended + if masked { 4 } else { 0 } + payload
+}
+
+/// Add `n` wire bytes to a live counter and, once measuring, the client total.
+#[inline(always)]
+pub(crate) fn record_bytes(live: &AtomicU64, local: &mut u64, n: usize, recording: bool) {
+    live.fetch_add(n as u64, Ordering::Relaxed);
+    if recording {
+        *local += n as u64;
+    }
+}
+
+#[inline]
+pub(crate) fn megabytes_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        bytes as f64 / 1_000_000.0 / secs
+    } else {
+        0.0
+    }
+}
+
+/// Rate of a cumulative counter over a trailing time window.
+pub(crate) struct SlidingRate {
+    pub(crate) window: Duration,
+    pub(crate) samples: std::collections::VecDeque<(Instant, u64)>,
+}
+
+impl SlidingRate {
+    pub(crate) fn new(window: Duration) -> Self {
+        Self {
+            window,
+            samples: std::collections::VecDeque::new(),
+        }
+    }
+
+    /// Record the counter's current `total` and return the per-second rate
+    /// across the window (or whatever shorter span has been observed).
+    pub(crate) fn observe(&mut self, total: u64) -> f64 {
+        let now = Instant::now();
+        self.samples.push_back((now, total));
+        // Keep one sample at or beyond the window edge as the baseline
+        while self.samples.len() > 2 && now.duration_since(self.samples[1].0) >= self.window {
+            self.samples.pop_front();
+        }
+
+        let (first_at, first_total) = self.samples[0];
+        let secs = now.duration_since(first_at).as_secs_f64();
+        if secs > 0.0 {
+            total.saturating_sub(first_total) as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+// =============================================================================
+// Canary Gauges
+// =============================================================================
+
+/// Rolling health signals for `--canary`: how long since any client last got
+/// a message, and E2E latency over the last completed window.
+pub(crate) struct CanaryGauges {
+    pub(crate) last_message_ms: AtomicU64,
+    pub(crate) e2e_sum: AtomicU64,
+    pub(crate) e2e_count: AtomicU64,
+    pub(crate) e2e_max: AtomicU64,
+    /// Mean E2E (f64 bits), max E2E and sample count of the last window
+    pub(crate) window_mean_ms: AtomicU64,
+    pub(crate) window_max_ms: AtomicU64,
+    pub(crate) window_samples: AtomicU64,
+}
+
+/// Canary gauges as exported.
+#[derive(Debug, Serialize)]
+pub(crate) struct CanarySnapshot {
+    pub(crate) last_message_age_secs: f64,
+    pub(crate) e2e_mean_ms: f64,
+    pub(crate) e2e_max_ms: u64,
+    pub(crate) e2e_samples: u64,
+}
+
+impl CanaryGauges {
+    pub(crate) fn new() -> Self {
+        Self {
+            // Staleness counts from startup until the first message arrives
+            last_message_ms: AtomicU64::new(unix_millis()),
+            e2e_sum: AtomicU64::new(0),
+            e2e_count: AtomicU64::new(0),
+            e2e_max: AtomicU64::new(0),
+            window_mean_ms: AtomicU64::new(0f64.to_bits()),
+            window_max_ms: AtomicU64::new(0),
+            window_samples: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn observe(&self, now_ms: u64, e2e: Option<u64>) {
+        self.last_message_ms.fetch_max(now_ms, Ordering::Relaxed);
+        if let Some(latency) = e2e {
+            self.e2e_sum.fetch_add(latency, Ordering::Relaxed);
+            self.e2e_count.fetch_add(1, Ordering::Relaxed);
+            self.e2e_max.fetch_max(latency, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> CanarySnapshot {
+        let last = self.last_message_ms.load(Ordering::Relaxed);
+        CanarySnapshot {
+            last_message_age_secs: unix_millis().saturating_sub(last) as f64 / 1000.0,
+            e2e_mean_ms: f64::from_bits(self.window_mean_ms.load(Ordering::Relaxed)),
+            e2e_max_ms: self.window_max_ms.load(Ordering::Relaxed),
+            e2e_samples: self.window_samples.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Close a canary latency window every `every`, publishing its mean and max.
+/// An empty window publishes zero samples so alerts can tell silence apart.
+pub(crate) async fn roll_canary_window(gauges: Arc<CanaryGauges>, every: Duration) {
+    let mut ticker = interval(every);
+    ticker.tick().await;
+
+    loop {
+        ticker.tick().await;
+        let sum = gauges.e2e_sum.swap(0, Ordering::Relaxed);
+        let count = gauges.e2e_count.swap(0, Ordering::Relaxed);
+        let max = gauges.e2e_max.swap(0, Ordering::Relaxed);
+        let mean = if count > 0 {
+            sum as f64 / count as f64
+        } else {
+            0.0
+        };
+        gauges
+            .window_mean_ms
+            .store(mean.to_bits(), Ordering::Relaxed);
+        gauges.window_max_ms.store(max, Ordering::Relaxed);
+        gauges.window_samples.store(count, Ordering::Relaxed);
+    }
+}
+
+// =============================================================================
+// Live Latency
+// =============================================================================
+
+/// Sub-buckets per power of two in a [`LiveHistogram`]
+pub(crate) const LIVE_SUB_BUCKETS: u64 = 32;
+
+/// E2E samples above this are discarded before recording
+pub(crate) const LIVE_MAX_MS: u64 = 60_000;
+
+/// E2E latencies (ms) shared by all clients as they record them; per-client
+/// results only arrive once clients finish. Lock-free log-linear buckets:
+/// exact below 32ms, then 32 per power of two (within about 3%).
+pub(crate) struct LiveHistogram {
+    pub(crate) buckets: Vec<AtomicU64>,
+}
+
+impl LiveHistogram {
+    pub(crate) fn new() -> Self {
+        Self {
+            buckets: (0..=Self::bucket(LIVE_MAX_MS))
+                .map(|_| AtomicU64::new(0))
+                .collect(),
+        }
+    }
+
+    pub(crate) fn bucket(ms: u64) -> usize {
+        let ms = ms.min(LIVE_MAX_MS);
+        if ms < LIVE_SUB_BUCKETS {
+            return ms as usize;
+        }
+        let shift = ms.ilog2() - LIVE_SUB_BUCKETS.ilog2();
+        ((shift as u64 + 1) * LIVE_SUB_BUCKETS + (ms >> shift) - LIVE_SUB_BUCKETS) as usize
+    }
+
+    /// Highest value that lands in bucket `index`.
+    pub(crate) fn bucket_max(index: usize) -> u64 {
+        let index = index as u64;
+        if index < LIVE_SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / LIVE_SUB_BUCKETS - 1;
+        let top = ((index % LIVE_SUB_BUCKETS + LIVE_SUB_BUCKETS + 1) << shift) - 1;
+        top.min(LIVE_MAX_MS)
+    }
+
+    pub(crate) fn record(&self, ms: u64) {
+        self.buckets[Self::bucket(ms)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The samples so far, each at its bucket's highest value, so
+    /// percentiles err high.
+    pub(crate) fn histogram(&self) -> Histogram<u64> {
+        let mut hist = latency_histogram();
+        for (index, count) in self.buckets.iter().enumerate() {
+            let count = count.load(Ordering::Relaxed);
+            if count > 0 {
+                let _ = hist.record_n(Self::bucket_max(index), count);
+            }
+        }
+        hist
+    }
+}
@@ -0,0 +1,975 @@
+//! Token lists, pools and fake token generation.
+
+use super::*;
+
+// =============================================================================
+// Token Management
+// =============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum TokenFormat {
+    /// By extension: `.csv` is CSV, `.txt` is text, anything else JSON
+    Auto,
+    /// Array of addresses or `{address, chain, tier}` objects
+    Json,
+    /// One address per line; blank lines and `#` comments are skipped
+    Text,
+    /// Header row, then one token per row
+    Csv,
+}
+
+impl TokenFormat {
+    /// The concrete format, going by `extension` under `auto`.
+    pub(crate) fn resolve(self, extension: Option<&str>) -> Self {
+        match (self, extension) {
+            (TokenFormat::Auto, Some("csv")) => TokenFormat::Csv,
+            (TokenFormat::Auto, Some("txt")) => TokenFormat::Text,
+            (TokenFormat::Auto, _) => TokenFormat::Json,
+            (format, _) => format,
+        }
+    }
+}
+
+/// Split one CSV line into fields, honouring double quotes (`""` escapes one).
+pub(crate) fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        let field = fields.last_mut().expect("never empty");
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(String::new()),
+            _ => field.push(c),
+        }
+    }
+    fields
+}
+
+/// Token entries from CSV `content`, the address taken from `column`.
+pub(crate) fn parse_token_csv(content: &str, column: &str) -> Result<Vec<TokenEntry>> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+    let header: Vec<String> = lines
+        .next()
+        .map(split_csv_line)
+        .ok_or_else(|| anyhow::anyhow!("empty CSV"))?
+        .into_iter()
+        .map(|h| h.trim().to_ascii_lowercase())
+        .collect();
+    let find = |name: &str| header.iter().position(|h| h == name);
+    let address = match column.parse::<usize>() {
+        Ok(index) if index < header.len() => index,
+        Ok(index) => anyhow::bail!("no column {} (the CSV has {})", index, header.len()),
+        Err(_) => find(&column.to_ascii_lowercase())
+            .ok_or_else(|| anyhow::anyhow!("no column {:?} in {:?}", column, header))?,
+    };
+    let (chain, tier, weight) = (find("chain"), find("tier"), find("weight"));
+    let field = |fields: &[String], i: Option<usize>| {
+        i.and_then(|i| fields.get(i))
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+    };
+    let mut entries = Vec::new();
+    for fields in lines.map(split_csv_line) {
+        let Some(address) = field(&fields, Some(address)) else {
+            continue;
+        };
+        let weight = field(&fields, weight)
+            .map(|w| {
+                w.parse::<f64>()
+                    .map_err(|_| anyhow::anyhow!("{}: weight {:?} is not a number", address, w))
+            })
+            .transpose()?;
+        entries.push(
+            match (field(&fields, chain), field(&fields, tier), weight) {
+                (None, None, None) => TokenEntry::Address(address),
+                (chain, tier, weight) => TokenEntry::Tagged {
+                    address,
+                    chain,
+                    tier,
+                    weight,
+                },
+            },
+        );
+    }
+    Ok(entries)
+}
+
+/// A token file entry: a bare address, or an object carrying market metadata.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum TokenEntry {
+    Address(String),
+    Tagged {
+        address: String,
+        #[serde(default)]
+        chain: Option<String>,
+        #[serde(default)]
+        tier: Option<String>,
+        /// Relative draw weight; unweighted entries of a weighted list weigh 1
+        #[serde(default)]
+        weight: Option<f64>,
+    },
+}
+
+impl TokenEntry {
+    /// `chain=...,tier=...` label, `None` when the entry has no metadata.
+    pub(crate) fn stratum(&self) -> Option<String> {
+        let TokenEntry::Tagged { chain, tier, .. } = self else {
+            return None;
+        };
+        let parts: Vec<String> = [("chain", chain), ("tier", tier)]
+            .into_iter()
+            .filter_map(|(key, value)| value.as_ref().map(|v| format!("{}={}", key, v)))
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(","))
+    }
+
+    pub(crate) fn address(&self) -> &str {
+        match self {
+            TokenEntry::Address(address) | TokenEntry::Tagged { address, .. } => address,
+        }
+    }
+
+    pub(crate) fn weight(&self) -> Option<f64> {
+        match self {
+            TokenEntry::Tagged { weight, .. } => *weight,
+            TokenEntry::Address(_) => None,
+        }
+    }
+}
+
+/// One loaded token list; `--token-refresh` swaps in a fresh one.
+pub(crate) struct TokenSet {
+    /// Shared with every filter drawn from them, so a draw is a refcount bump
+    pub(crate) addresses: Vec<Arc<str>>,
+    /// Which addresses have appeared in at least one filter
+    pub(crate) used: Vec<AtomicBool>,
+    /// Stratum label by address; empty for plain address lists
+    pub(crate) strata: HashMap<String, Arc<str>>,
+    /// Draw weights the list carried, if any
+    pub(crate) weights: Option<Vec<f64>>,
+    /// Cumulative draw weights (the list's, `--token-weights` or
+    /// `--token-zipf`); uniform if absent
+    pub(crate) popularity: Option<Vec<f64>>,
+}
+
+impl TokenSet {
+    /// Random index within `range`, by weight if the list has any.
+    #[inline]
+    pub(crate) fn draw(&self, rng: &mut impl Rng, range: std::ops::Range<usize>) -> usize {
+        match &self.popularity {
+            Some(cdf) => {
+                let lo = range.start.checked_sub(1).map_or(0.0, |i| cdf[i]);
+                let hi = cdf[range.end - 1];
+                if hi <= lo {
+                    return rng.random_range(range);
+                }
+                let u = lo + rng.random::<f64>() * (hi - lo);
+                cdf.partition_point(|&c| c < u)
+                    .clamp(range.start, range.end - 1)
+            }
+            None => rng.random_range(range),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn take(&self, index: usize) -> Arc<str> {
+        self.used[index].store(true, Ordering::Relaxed);
+        self.addresses[index].clone()
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct TokenPool {
+    pub(crate) set: Arc<std::sync::RwLock<Arc<TokenSet>>>,
+    /// `--token-zipf` exponent, reapplied to refreshed lists
+    pub(crate) zipf: Option<f64>,
+    /// `--token-weights` by address, reapplied to refreshed lists
+    pub(crate) weight_overrides: Option<Arc<HashMap<String, f64>>>,
+    /// `--exclude-token-file` addresses, removed from refreshed lists too
+    pub(crate) excluded: Option<Arc<std::collections::HashSet<String>>>,
+    /// How this client's draws relate to other clients' (`--token-overlap`)
+    pub(crate) overlap: TokenOverlap,
+    /// This client's share of the list under `disjoint`: (partition, count)
+    pub(crate) partition: (usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum TokenOverlap {
+    /// Every client draws from the whole list independently
+    Random,
+    /// The list is split among clients by id, so no two share a token
+    Disjoint,
+    /// Every client takes the same leading tokens, maximizing overlap
+    Shared,
+}
+
+impl TokenPool {
+    pub(crate) fn new(addresses: Vec<String>) -> Self {
+        Self::with_strata(addresses, HashMap::new(), None)
+    }
+
+    pub(crate) fn with_strata(
+        addresses: Vec<String>,
+        strata: HashMap<String, Arc<str>>,
+        weights: Option<Vec<f64>>,
+    ) -> Self {
+        let used = (0..addresses.len())
+            .map(|_| AtomicBool::new(false))
+            .collect();
+        let popularity = weights.as_deref().and_then(cumulative);
+        let set = TokenSet {
+            addresses: addresses.into_iter().map(Arc::from).collect(),
+            used,
+            strata,
+            weights,
+            popularity,
+        };
+        Self {
+            set: Arc::new(std::sync::RwLock::new(Arc::new(set))),
+            zipf: None,
+            weight_overrides: None,
+            excluded: None,
+            overlap: TokenOverlap::Random,
+            partition: (0, 1),
+        }
+    }
+
+    /// This pool as client `id` of `clients` draws from it under `overlap`.
+    pub(crate) fn for_client(&self, id: usize, clients: usize, overlap: TokenOverlap) -> Self {
+        let clients = clients.max(1);
+        Self {
+            overlap,
+            partition: (id % clients, clients),
+            ..self.clone()
+        }
+    }
+
+    /// Indices this pool draws from in a list of `len`. A partition too
+    /// small to hold a token falls back to one shared with its neighbours.
+    pub(crate) fn range(&self, len: usize) -> std::ops::Range<usize> {
+        let (partition, count) = self.partition;
+        match self.overlap {
+            TokenOverlap::Disjoint => {
+                let (start, end) = (partition * len / count, (partition + 1) * len / count);
+                if start < end {
+                    start..end
+                } else {
+                    let i = start.min(len - 1);
+                    i..i + 1
+                }
+            }
+            _ => 0..len,
+        }
+    }
+
+    /// The current token list.
+    #[inline]
+    pub(crate) fn set(&self) -> Arc<TokenSet> {
+        self.set.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Weight the address at rank `k` (from 1) by `k^-s`.
+    pub(crate) fn with_zipf(mut self, s: f64) -> Self {
+        self.zipf = Some(s);
+        self.replace(&self.clone());
+        self
+    }
+
+    /// Weight addresses by `--token-weights` instead of the list's own
+    /// weights; addresses it doesn't name weigh 1.
+    pub(crate) fn with_weights(mut self, weights: HashMap<String, f64>) -> Self {
+        self.weight_overrides = Some(Arc::new(weights));
+        self.replace(&self.clone());
+        self
+    }
+
+    /// Drop `excluded` addresses from the list. Fails if none would be left.
+    pub(crate) fn with_exclusions(
+        mut self,
+        excluded: std::collections::HashSet<String>,
+    ) -> Result<Self> {
+        let set = self.set();
+        let dropped = set
+            .addresses
+            .iter()
+            .filter(|a| excluded.contains(&***a))
+            .count();
+        if dropped == set.addresses.len() {
+            anyhow::bail!("--exclude-token-file excludes every token in the list");
+        }
+        info!(
+            "Excluded {} of {} token addresses",
+            dropped,
+            set.addresses.len()
+        );
+        self.excluded = Some(Arc::new(excluded));
+        self.replace(&self.clone());
+        Ok(self)
+    }
+
+    /// Switch every clone of this pool over to `fresh`'s token list, keeping
+    /// the exclusions and the Zipf or `--token-weights` weighting. Filters
+    /// already built keep their tokens.
+    pub(crate) fn replace(&self, fresh: &TokenPool) {
+        let set = fresh.set();
+        let keep: Vec<usize> = (0..set.addresses.len())
+            .filter(|&i| {
+                self.excluded
+                    .as_ref()
+                    .is_none_or(|x| !x.contains(&*set.addresses[i]))
+            })
+            .collect();
+        if keep.is_empty() {
+            warn!("Every token in the new list is excluded, keeping the current list");
+            return;
+        }
+        let addresses: Vec<Arc<str>> = keep.iter().map(|&i| set.addresses[i].clone()).collect();
+        let weights = match &self.weight_overrides {
+            Some(overrides) => Some(
+                addresses
+                    .iter()
+                    .map(|a| overrides.get(&**a).copied().unwrap_or(1.0))
+                    .collect(),
+            ),
+            None => set
+                .weights
+                .as_ref()
+                .map(|w| keep.iter().map(|&i| w[i]).collect()),
+        };
+        let popularity = match self.zipf {
+            Some(s) => cumulative(
+                &(1..=addresses.len())
+                    .map(|k| (k as f64).powf(-s))
+                    .collect::<Vec<_>>(),
+            ),
+            None => weights.as_deref().and_then(cumulative),
+        };
+        let set = TokenSet {
+            used: (0..addresses.len())
+                .map(|_| AtomicBool::new(false))
+                .collect(),
+            addresses,
+            strata: set.strata.clone(),
+            weights,
+            popularity,
+        };
+        *self.set.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(set);
+    }
+
+    /// Merge the lists in `paths`; an address listed more than once keeps
+    /// its first entry.
+    pub(crate) fn load_from_files(
+        paths: &[PathBuf],
+        format: TokenFormat,
+        column: &str,
+        strata_spec: Option<&str>,
+        strict: bool,
+    ) -> Result<Self> {
+        let mut seen = std::collections::HashSet::new();
+        let mut entries = Vec::new();
+        for path in paths {
+            let source = format!("{:?}", path);
+            let content =
+                std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", source, e))?;
+            let loaded = Self::parse_entries(
+                &content,
+                &source,
+                format.resolve(path.extension().and_then(|e| e.to_str())),
+                column,
+            )?;
+            check_token_entries(&loaded, &source, strict)?;
+            let (total, before) = (loaded.len(), entries.len());
+            entries.extend(
+                loaded
+                    .into_iter()
+                    .filter(|e| seen.insert(e.address().to_string())),
+            );
+            info!(
+                "Loaded {} token addresses from {} ({} new)",
+                total,
+                source,
+                entries.len() - before
+            );
+        }
+        if paths.len() > 1 {
+            info!(
+                "Merged {} distinct token addresses from {} files",
+                entries.len(),
+                paths.len()
+            );
+        }
+        let sources: Vec<String> = paths.iter().map(|p| format!("{:?}", p)).collect();
+        Self::from_entries(entries, &sources.join(", "), strata_spec)
+    }
+
+    /// Download the token list from `url`, with an optional `Name: value`
+    /// header for auth.
+    pub(crate) async fn fetch(
+        tls: &native_tls::TlsConnector,
+        url: &str,
+        header: Option<&str>,
+        format: TokenFormat,
+        column: &str,
+        strata_spec: Option<&str>,
+        strict: bool,
+    ) -> Result<Self> {
+        let headers: Vec<(&str, &str)> = header
+            .map(|h| {
+                h.split_once(':')
+                    .map(|(name, value)| (name.trim(), value.trim()))
+                    .ok_or_else(|| {
+                        anyhow::anyhow!("--token-url-header {:?}: expected Name: value", h)
+                    })
+            })
+            .transpose()?
+            .into_iter()
+            .collect();
+        let response = http_request(tls, "GET", url, &headers, &[]).await?;
+        if !(200..300).contains(&response.status) {
+            anyhow::bail!("{} returned HTTP {}", url, response.status);
+        }
+        let extension = url::Url::parse(url)?
+            .path()
+            .rsplit_once('.')
+            .map(|(_, ext)| ext.to_string());
+        Self::parse(
+            &response.body,
+            url,
+            format.resolve(extension.as_deref()),
+            column,
+            strata_spec,
+            strict,
+        )
+    }
+
+    /// Parse a token list read from `source`.
+    pub(crate) fn parse(
+        content: &str,
+        source: &str,
+        format: TokenFormat,
+        column: &str,
+        strata_spec: Option<&str>,
+        strict: bool,
+    ) -> Result<Self> {
+        let entries = Self::parse_entries(content, source, format, column)?;
+        check_token_entries(&entries, source, strict)?;
+        info!("Loaded {} token addresses", entries.len());
+        Self::from_entries(entries, source, strata_spec)
+    }
+
+    pub(crate) fn parse_entries(
+        content: &str,
+        source: &str,
+        format: TokenFormat,
+        column: &str,
+    ) -> Result<Vec<TokenEntry>> {
+        let entries: Vec<TokenEntry> = match format {
+            TokenFormat::Csv => parse_token_csv(content, column)
+                .map_err(|e| anyhow::anyhow!("{}: {}", source, e))?,
+            TokenFormat::Text => content
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                .map(|l| TokenEntry::Address(l.to_string()))
+                .collect(),
+            _ => sonic_rs::from_str(content).map_err(|e| anyhow::anyhow!("{}: {}", source, e))?,
+        };
+        if entries.is_empty() {
+            anyhow::bail!("no token addresses in {}", source);
+        }
+        Ok(entries)
+    }
+
+    /// Apply `--token-strata` and build the pool.
+    pub(crate) fn from_entries(
+        mut entries: Vec<TokenEntry>,
+        source: &str,
+        strata_spec: Option<&str>,
+    ) -> Result<Self> {
+        if let Some(spec) = strata_spec {
+            let constraints = parse_strata(spec)?;
+            entries.retain(|entry| match entry {
+                TokenEntry::Address(_) => false,
+                TokenEntry::Tagged { chain, tier, .. } => {
+                    constraints.iter().all(|(key, allowed)| {
+                        let value = if *key == "chain" { chain } else { tier };
+                        value.as_ref().is_some_and(|v| allowed.contains(v))
+                    })
+                }
+            });
+            if entries.is_empty() {
+                anyhow::bail!("no tokens in {} match --token-strata {}", source, spec);
+            }
+            info!("Restricted to {} tokens in strata {}", entries.len(), spec);
+        }
+
+        let mut labels: HashMap<String, Arc<str>> = HashMap::new();
+        let mut strata = HashMap::new();
+        for entry in &entries {
+            if let Some(label) = entry.stratum() {
+                let label = labels
+                    .entry(label.clone())
+                    .or_insert_with(|| Arc::from(label))
+                    .clone();
+                strata.insert(entry.address().to_string(), label);
+            }
+        }
+        let addresses = entries.iter().map(|e| e.address().to_string()).collect();
+        let weights = entries.iter().any(|e| e.weight().is_some()).then(|| {
+            entries
+                .iter()
+                .map(|e| e.weight().unwrap_or(1.0))
+                .collect::<Vec<_>>()
+        });
+        if let Some(w) = weights
+            .iter()
+            .flatten()
+            .find(|w| !(w.is_finite() && **w >= 0.0))
+        {
+            anyhow::bail!(
+                "{}: token weight {} is not a non-negative number",
+                source,
+                w
+            );
+        }
+        Ok(Self::with_strata(addresses, strata, weights))
+    }
+
+    /// Stratum of `address`, if the token list carried metadata for it.
+    #[inline]
+    pub(crate) fn stratum(&self, address: &str) -> Option<Arc<str>> {
+        self.set().strata.get(address).cloned()
+    }
+
+    pub(crate) fn has_strata(&self) -> bool {
+        !self.set().strata.is_empty()
+    }
+
+    /// `count` distinct addresses in `format`, the same on every run.
+    pub(crate) fn generate_fake(count: usize, format: &FakeTokenFormat) -> Self {
+        use rand::SeedableRng;
+
+        let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+        let mut seen = std::collections::HashSet::with_capacity(count);
+        let mut addresses = Vec::with_capacity(count);
+        // Narrow patterns run out of distinct values; give up rather than spin
+        for i in 0..count * 4 {
+            if addresses.len() == count {
+                break;
+            }
+            let address = format.generate(i, &mut rng);
+            if seen.insert(address.clone()) {
+                addresses.push(address);
+            }
+        }
+        if addresses.len() < count {
+            warn!(
+                "Only {} distinct fake tokens of {} requested",
+                addresses.len(),
+                count
+            );
+        }
+        Self::new(addresses)
+    }
+
+    /// Distinct addresses of the current list used by any filter so far, and
+    /// the list's size.
+    pub(crate) fn coverage(&self) -> (usize, usize) {
+        let set = self.set();
+        let used = set
+            .used
+            .iter()
+            .filter(|u| u.load(Ordering::Relaxed))
+            .count();
+        (used, set.addresses.len())
+    }
+
+    /// A random address of the current list, without marking it used.
+    pub(crate) fn peek_random(&self) -> Option<Arc<str>> {
+        self.set().addresses.choose(&mut rand::rng()).cloned()
+    }
+
+    pub(crate) fn get_random(&self) -> Arc<str> {
+        let set = self.set();
+        if self.overlap == TokenOverlap::Shared {
+            return set.take(0);
+        }
+        let index = set.draw(&mut rand::rng(), self.range(set.addresses.len()));
+        set.take(index)
+    }
+
+    pub(crate) fn get_random_unique(&self, count: usize) -> Vec<Arc<str>> {
+        let set = self.set();
+        let mut rng = rand::rng();
+        let range = self.range(set.addresses.len());
+        let count = count.min(range.len());
+        if self.overlap == TokenOverlap::Shared {
+            return (0..count).map(|i| set.take(i)).collect();
+        }
+        if set.popularity.is_some() {
+            // Redraw duplicates; the tail is too cold to fill a large filter
+            // that way, so top up uniformly once redraws stop paying off
+            let mut picked = std::collections::HashSet::with_capacity(count);
+            for _ in 0..count * 32 {
+                if picked.len() == count {
+                    break;
+                }
+                picked.insert(set.draw(&mut rng, range.clone()));
+            }
+            if picked.len() < count {
+                let rest: Vec<usize> = range.filter(|i| !picked.contains(i)).collect();
+                picked.extend(rest.choose_multiple(&mut rng, count - picked.len()));
+            }
+            return picked.into_iter().map(|i| set.take(i)).collect();
+        }
+        let indices: Vec<usize> = range
+            .collect::<Vec<_>>()
+            .choose_multiple(&mut rng, count)
+            .copied()
+            .collect();
+        indices.iter().map(|&i| set.take(i)).collect()
+    }
+}
+
+/// Running totals of `weights`, or `None` if they're all zero.
+pub(crate) fn cumulative(weights: &[f64]) -> Option<Vec<f64>> {
+    let mut total = 0.0;
+    let cdf: Vec<f64> = weights
+        .iter()
+        .map(|w| {
+            total += w;
+            total
+        })
+        .collect();
+    (total > 0.0).then_some(cdf)
+}
+
+/// Parse `--token-weights`: a JSON object of address to weight.
+pub(crate) fn load_token_weights(path: &std::path::Path) -> Result<HashMap<String, f64>> {
+    let content = std::fs::read_to_string(path)?;
+    let weights: HashMap<String, f64> = sonic_rs::from_str(&content)?;
+    if let Some((address, w)) = weights.iter().find(|(_, w)| !(w.is_finite() && **w >= 0.0)) {
+        anyhow::bail!(
+            "{:?}: weight {} of {} is not a non-negative number",
+            path,
+            w,
+            address
+        );
+    }
+    Ok(weights)
+}
+
+/// Report addresses in a token list that don't look like real ones or repeat,
+/// failing under `--strict-tokens`: the server rejects filters holding them
+/// and the run's subscribe failures stop meaning anything.
+pub(crate) fn check_token_entries(
+    entries: &[TokenEntry],
+    source: &str,
+    strict: bool,
+) -> Result<()> {
+    let mut seen = std::collections::HashSet::with_capacity(entries.len());
+    let (mut duplicates, mut flagged) = (0, 0);
+    let mut problems: BTreeMap<&str, (usize, &str)> = BTreeMap::new();
+    for entry in entries {
+        let address = entry.address();
+        let duplicate = !seen.insert(address);
+        let problem = token_address_problem(address);
+        if let Some(problem) = problem {
+            problems.entry(problem).or_insert((0, address)).0 += 1;
+        }
+        duplicates += duplicate as usize;
+        flagged += (duplicate || problem.is_some()) as usize;
+    }
+    let mut issues: Vec<String> = problems
+        .iter()
+        .map(|(problem, (count, example))| {
+            format!("{} with bad {} (e.g. {:?})", count, problem, example)
+        })
+        .collect();
+    if duplicates > 0 {
+        issues.push(format!("{} duplicates", duplicates));
+    }
+    if issues.is_empty() {
+        return Ok(());
+    }
+    if strict {
+        anyhow::bail!("{}: {}", source, issues.join(", "));
+    }
+    warn!(
+        "{}: {} of {} token addresses look wrong: {}",
+        source,
+        flagged,
+        entries.len(),
+        issues.join(", ")
+    );
+    Ok(())
+}
+
+/// Parse `--exclude-token-file`, a token list in any `--token-format`.
+pub(crate) fn load_token_exclusions(
+    path: &std::path::Path,
+    format: TokenFormat,
+    column: &str,
+) -> Result<std::collections::HashSet<String>> {
+    let source = format!("{:?}", path);
+    let content =
+        std::fs::read_to_string(path).map_err(|e| anyhow::anyhow!("{}: {}", source, e))?;
+    let entries = TokenPool::parse_entries(
+        &content,
+        &source,
+        format.resolve(path.extension().and_then(|e| e.to_str())),
+        column,
+    )?;
+    Ok(entries.iter().map(|e| e.address().to_string()).collect())
+}
+
+/// Address format of the tokens generated when there's no token file.
+#[derive(Debug, Clone)]
+pub(crate) enum FakeTokenFormat {
+    /// `token_0000002a`
+    Hex,
+    /// EIP-55 checksummed `0x` + 40 hex digits
+    Evm,
+    /// Base58 of 32 random bytes
+    Solana,
+    /// Literal text with `{i}` (index), `{hex:N}`, `{base58:N}` and
+    /// `{digits:N}` placeholders
+    Pattern(String),
+}
+
+impl FakeTokenFormat {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "hex" => Ok(FakeTokenFormat::Hex),
+            "evm" => Ok(FakeTokenFormat::Evm),
+            "solana" => Ok(FakeTokenFormat::Solana),
+            _ => {
+                let pattern = s
+                    .strip_prefix("pattern:")
+                    .ok_or_else(|| format!("{:?}: expected hex, evm, solana or pattern:TEXT", s))?;
+                let mut rest = pattern;
+                while let Some(open) = rest.find('{') {
+                    let close = rest[open..]
+                        .find('}')
+                        .ok_or_else(|| format!("{:?}: unclosed {{", pattern))?
+                        + open;
+                    let field = &rest[open + 1..close];
+                    let valid = field == "i"
+                        || field.split_once(':').is_some_and(|(kind, n)| {
+                            matches!(kind, "hex" | "base58" | "digits")
+                                && n.parse::<usize>().is_ok()
+                        });
+                    if !valid {
+                        return Err(format!(
+                            "{:?}: unknown placeholder {{{}}} (i, hex:N, base58:N, digits:N)",
+                            pattern, field
+                        ));
+                    }
+                    rest = &rest[close + 1..];
+                }
+                Ok(FakeTokenFormat::Pattern(pattern.to_string()))
+            }
+        }
+    }
+
+    pub(crate) fn generate(&self, i: usize, rng: &mut impl Rng) -> String {
+        match self {
+            FakeTokenFormat::Hex => format!("token_{:08x}", i),
+            FakeTokenFormat::Evm => {
+                let bytes: [u8; 20] = rng.random();
+                eip55_address(&bytes)
+            }
+            FakeTokenFormat::Solana => {
+                let bytes: [u8; 32] = rng.random();
+                base58_encode(&bytes)
+            }
+            FakeTokenFormat::Pattern(pattern) => {
+                let mut out = String::with_capacity(pattern.len() + 32);
+                let mut rest = pattern.as_str();
+                while let Some(open) = rest.find('{') {
+                    let close = rest[open..].find('}').unwrap_or(rest.len() - open) + open;
+                    out.push_str(&rest[..open]);
+                    let field = &rest[open + 1..close];
+                    if field == "i" {
+                        out.push_str(&i.to_string());
+                    } else if let Some((kind, n)) = field.split_once(':') {
+                        let alphabet: &[u8] = match kind {
+                            "hex" => b"0123456789abcdef",
+                            "base58" => BASE58_ALPHABET,
+                            _ => b"0123456789",
+                        };
+                        let n = n.parse().unwrap_or(0);
+                        out.extend(
+                            (0..n).map(|_| alphabet[rng.random_range(0..alphabet.len())] as char),
+                        );
+                    }
+                    rest = &rest[(close + 1).min(rest.len())..];
+                }
+                out.push_str(rest);
+                out
+            }
+        }
+    }
+}
+
+pub(crate) const BASE58_ALPHABET: &[u8] =
+    b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Bitcoin-alphabet base58, as Solana addresses use.
+pub(crate) fn base58_encode(bytes: &[u8]) -> String {
+    // Little-endian base-58 digits of the big-endian number in `bytes`
+    let mut digits: Vec<u8> = Vec::with_capacity(bytes.len() * 138 / 100 + 1);
+    for &byte in bytes {
+        let mut carry = byte as u32;
+        for digit in digits.iter_mut() {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+    let zeros = bytes.iter().take_while(|&&b| b == 0).count();
+    std::iter::repeat_n('1', zeros)
+        .chain(
+            digits
+                .iter()
+                .rev()
+                .map(|&d| BASE58_ALPHABET[d as usize] as char),
+        )
+        .collect()
+}
+
+/// EIP-55 mixed-case checksum encoding of a 20-byte address: a hex letter is
+/// upper case when the matching nibble of the lowercase form's Keccak-256 is 8+.
+pub(crate) fn eip55_address(bytes: &[u8; 20]) -> String {
+    let lower: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let hash = keccak256(lower.as_bytes());
+    let checksummed: String = lower
+        .chars()
+        .enumerate()
+        .map(|(i, c)| {
+            let nibble = (hash[i / 2] >> if i % 2 == 0 { 4 } else { 0 }) & 0x0f;
+            if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        })
+        .collect();
+    format!("0x{}", checksummed)
+}
+
+/// Keccak-256 (the pre-standard padding Ethereum uses, not SHA3-256).
+pub(crate) fn keccak256(data: &[u8]) -> [u8; 32] {
+    const RATE: usize = 136;
+    const ROUND_CONSTANTS: [u64; 24] = [
+        0x0000000000000001,
+        0x0000000000008082,
+        0x800000000000808a,
+        0x8000000080008000,
+        0x000000000000808b,
+        0x0000000080000001,
+        0x8000000080008081,
+        0x8000000000008009,
+        0x000000000000008a,
+        0x0000000000000088,
+        0x0000000080008009,
+        0x000000008000000a,
+        0x000000008000808b,
+        0x800000000000008b,
+        0x8000000000008089,
+        0x8000000000008003,
+        0x8000000000008002,
+        0x8000000000000080,
+        0x000000000000800a,
+        0x800000008000000a,
+        0x8000000080008081,
+        0x8000000000008080,
+        0x0000000080000001,
+        0x8000000080008008,
+    ];
+    const ROTATIONS: [u32; 24] = [
+        1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14, 27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+    ];
+    const PI: [usize; 24] = [
+        10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4, 15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+    ];
+
+    fn permute(state: &mut [u64; 25]) {
+        for rc in ROUND_CONSTANTS {
+            // Theta
+            let mut column = [0u64; 5];
+            for (x, c) in column.iter_mut().enumerate() {
+                *c = state[x] ^ state[x + 5] ^ state[x + 10] ^ state[x + 15] ^ state[x + 20];
+            }
+            for x in 0..5 {
+                let d = column[(x + 4) % 5] ^ column[(x + 1) % 5].rotate_left(1);
+                for y in 0..5 {
+                    state[5 * y + x] ^= d;
+                }
+            }
+            // Rho and pi
+            let mut carried = state[1];
+            for (&to, &rotation) in PI.iter().zip(&ROTATIONS) {
+                let next = state[to];
+                state[to] = carried.rotate_left(rotation);
+                carried = next;
+            }
+            // Chi
+            for y in 0..5 {
+                let row: [u64; 5] = std::array::from_fn(|x| state[5 * y + x]);
+                for x in 0..5 {
+                    state[5 * y + x] = row[x] ^ (!row[(x + 1) % 5] & row[(x + 2) % 5]);
+                }
+            }
+            // Iota
+            state[0] ^= rc;
+        }
+    }
+
+    let mut state = [0u64; 25];
+    let mut padded = data.to_vec();
+    padded.push(0x01);
+    padded.resize(padded.len().div_ceil(RATE) * RATE, 0);
+    *padded.last_mut().expect("at least one block") |= 0x80;
+    for block in padded.chunks(RATE) {
+        for (lane, bytes) in state.iter_mut().zip(block.chunks(8)) {
+            *lane ^= u64::from_le_bytes(bytes.try_into().expect("8-byte lane"));
+        }
+        permute(&mut state);
+    }
+    let mut out = [0u8; 32];
+    for (chunk, lane) in out.chunks_mut(8).zip(&state) {
+        chunk.copy_from_slice(&lane.to_le_bytes());
+    }
+    out
+}
+
+/// Parse `chain=a,tier=b,tier=c` into allowed values per key.
+pub(crate) fn parse_strata(spec: &str) -> Result<Vec<(&'static str, Vec<String>)>> {
+    let mut constraints: Vec<(&'static str, Vec<String>)> = Vec::new();
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (key, value) = part
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("--token-strata {:?}: expected key=value", part))?;
+        let key = match key.trim() {
+            "chain" => "chain",
+            "tier" => "tier",
+            other => anyhow::bail!("--token-strata: unknown key {:?} (chain, tier)", other),
+        };
+        match constraints.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, values)) => values.push(value.trim().to_string()),
+            None => constraints.push((key, vec![value.trim().to_string()])),
+        }
+    }
+    Ok(constraints)
+}
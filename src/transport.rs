@@ -0,0 +1,626 @@
+//! TCP, Unix and in-memory transports, TLS setup and connect error classification.
+
+use super::*;
+
+// =============================================================================
+// Transport
+// =============================================================================
+
+/// Any bidirectional byte stream a WebSocket can run over.
+pub(crate) trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+pub(crate) type BoxedStream = Box<dyn AsyncStream>;
+pub(crate) type WsStream = WebSocketStream<BoxedStream>;
+
+/// Per-phase timings of a single connection setup, in milliseconds.
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct SetupTimings {
+    pub(crate) dns_ms: u64,
+    pub(crate) connect_ms: u64,
+    /// `None` for plain `ws://` connections
+    pub(crate) tls_ms: Option<u64>,
+    pub(crate) upgrade_ms: u64,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum ConnectError {
+    #[error("DNS resolution failed: {0}")]
+    Dns(std::io::Error),
+    #[error("no addresses resolved for {0}")]
+    NoAddress(String),
+    #[error("connect failed: {0}")]
+    Connect(std::io::Error),
+    #[error("TLS handshake failed: {0}")]
+    Tls(native_tls::Error),
+    #[error("WebSocket upgrade failed: {0}")]
+    Upgrade(tokio_tungstenite::tungstenite::Error),
+    #[error("connection setup timed out after {0:?}")]
+    Timeout(Duration),
+    #[error("server certificate does not match any pinned fingerprint (got {0})")]
+    PinMismatch(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub(crate) enum TransportKind {
+    /// TCP, with TLS under `--tls` (by default on port 443)
+    Tcp,
+    /// Unix domain socket at `--unix-socket`
+    Unix,
+    /// In-process duplex pipe to the built-in mock server
+    Memory,
+}
+
+/// A freshly opened transport stream.
+pub(crate) struct TransportStream {
+    pub(crate) stream: BoxedStream,
+    /// Underlying TCP socket, for kernel `TCP_INFO` sampling
+    pub(crate) tcp_fd: Option<RawFd>,
+}
+
+/// Opens the byte stream underneath a client's WebSocket.
+///
+/// Everything above this (upgrade, Pusher protocol, metrics) is shared, so
+/// colocated and in-process runs exercise exactly the same client code.
+pub(crate) trait Transport: Send + Sync {
+    fn connect<'a>(
+        &'a self,
+        timings: &'a mut SetupTimings,
+    ) -> BoxFuture<'a, Result<TransportStream, ConnectError>>;
+}
+
+pub(crate) struct TcpTransport {
+    pub(crate) host: String,
+    pub(crate) port: u16,
+    pub(crate) tls: Option<TlsConnector>,
+    /// Accepted leaf certificate SHA-256 fingerprints; empty disables pinning
+    pub(crate) pins: Vec<[u8; 32]>,
+}
+
+impl Transport for TcpTransport {
+    fn connect<'a>(
+        &'a self,
+        timings: &'a mut SetupTimings,
+    ) -> BoxFuture<'a, Result<TransportStream, ConnectError>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs: Vec<_> = tokio::net::lookup_host((self.host.as_str(), self.port))
+                .await
+                .map_err(ConnectError::Dns)?
+                .collect();
+            let (first, rest) = addrs
+                .split_first()
+                .ok_or_else(|| ConnectError::NoAddress(self.host.clone()))?;
+            timings.dns_ms = start.elapsed().as_millis() as u64;
+
+            // Fall through the resolved addresses in order, as a resolver-aware
+            // client would; the connect phase covers every attempt
+            let start = Instant::now();
+            let mut connected = TcpStream::connect(first).await;
+            for addr in rest {
+                if connected.is_ok() {
+                    break;
+                }
+                connected = TcpStream::connect(addr).await;
+            }
+            let tcp = connected.map_err(ConnectError::Connect)?;
+            timings.connect_ms = start.elapsed().as_millis() as u64;
+            let tcp_fd = tcp.as_raw_fd();
+
+            match &self.tls {
+                Some(connector) => {
+                    let start = Instant::now();
+                    let tls_stream = connector
+                        .connect(&self.host, tcp)
+                        .await
+                        .map_err(ConnectError::Tls)?;
+                    if !self.pins.is_empty() {
+                        let der = tls_stream
+                            .get_ref()
+                            .peer_certificate()
+                            .map_err(ConnectError::Tls)?
+                            .map(|cert| cert.to_der())
+                            .transpose()
+                            .map_err(ConnectError::Tls)?
+                            .unwrap_or_default();
+                        let fingerprint = openssl::sha::sha256(&der);
+                        if !self.pins.contains(&fingerprint) {
+                            return Err(ConnectError::PinMismatch(to_hex(&fingerprint)));
+                        }
+                    }
+                    timings.tls_ms = Some(start.elapsed().as_millis() as u64);
+                    Ok(TransportStream {
+                        stream: Box::new(tls_stream),
+                        tcp_fd: Some(tcp_fd),
+                    })
+                }
+                None => Ok(TransportStream {
+                    stream: Box::new(tcp),
+                    tcp_fd: Some(tcp_fd),
+                }),
+            }
+        })
+    }
+}
+
+pub(crate) struct UnixTransport {
+    pub(crate) path: PathBuf,
+}
+
+impl Transport for UnixTransport {
+    fn connect<'a>(
+        &'a self,
+        timings: &'a mut SetupTimings,
+    ) -> BoxFuture<'a, Result<TransportStream, ConnectError>> {
+        Box::pin(async move {
+            let start = Instant::now();
+            let stream = tokio::net::UnixStream::connect(&self.path)
+                .await
+                .map_err(ConnectError::Connect)?;
+            timings.connect_ms = start.elapsed().as_millis() as u64;
+            Ok(TransportStream {
+                stream: Box::new(stream),
+                tcp_fd: None,
+            })
+        })
+    }
+}
+
+/// Hands the server half of a fresh duplex pipe to an in-process server.
+pub(crate) struct MemoryTransport {
+    pub(crate) acceptor: tokio::sync::mpsc::UnboundedSender<DuplexStream>,
+}
+
+impl Transport for MemoryTransport {
+    fn connect<'a>(
+        &'a self,
+        _timings: &'a mut SetupTimings,
+    ) -> BoxFuture<'a, Result<TransportStream, ConnectError>> {
+        Box::pin(async move {
+            let (client, server) = tokio::io::duplex(64 * 1024);
+            self.acceptor.send(server).map_err(|_| {
+                ConnectError::Connect(std::io::Error::new(
+                    std::io::ErrorKind::ConnectionRefused,
+                    "in-memory server is gone",
+                ))
+            })?;
+            Ok(TransportStream {
+                stream: Box::new(client),
+                tcp_fd: None,
+            })
+        })
+    }
+}
+
+/// Build the transport selected by `--transport`.
+pub(crate) fn build_transport(config: &Config) -> Result<Arc<dyn Transport>> {
+    Ok(match config.transport {
+        TransportKind::Tcp => {
+            let tls = if config.use_tls() {
+                Some(TlsConnector::from(config.tls().clone()))
+            } else {
+                if config.ca_cert.is_some() || config.client_cert.is_some() {
+                    warn!(
+                        "--ca-cert and --client-cert only apply to https:// requests; \
+                         the WebSocket connection isn't TLS (see --tls)"
+                    );
+                }
+                if !config.pin_sha256.is_empty() {
+                    anyhow::bail!("--pin-sha256 requires TLS (--tls or port 443)");
+                }
+                None
+            };
+            let pins = config
+                .pin_sha256
+                .iter()
+                .map(|pin| parse_fingerprint(pin))
+                .collect::<Result<Vec<_>>>()?;
+            Arc::new(TcpTransport {
+                host: config.ws_host.clone(),
+                port: config.ws_port,
+                tls,
+                pins,
+            })
+        }
+        TransportKind::Unix => {
+            let path = config
+                .unix_socket
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("--transport unix requires --unix-socket"))?;
+            Arc::new(UnixTransport { path })
+        }
+        TransportKind::Memory => {
+            let (acceptor, incoming) = tokio::sync::mpsc::unbounded_channel();
+            tokio::spawn(run_mock_server(
+                incoming,
+                config.payload_format,
+                config.proto.clone(),
+                config.protocol,
+            ));
+            Arc::new(MemoryTransport { acceptor })
+        }
+    })
+}
+
+/// One TLS connector from `--ca-cert`, `--client-cert`/`--client-key`,
+/// `--insecure` and `--insecure-skip-hostname`, used for the WebSocket
+/// connections and every https:// request alike.
+pub(crate) fn tls_connector(config: &Config) -> Result<native_tls::TlsConnector> {
+    let mut builder = native_tls::TlsConnector::builder();
+    builder.danger_accept_invalid_hostnames(config.insecure_skip_hostname);
+    builder.danger_accept_invalid_certs(config.insecure);
+    if let Some(identity) = load_client_identity(config)? {
+        builder.identity(identity);
+    }
+    if let Some(path) = &config.ca_cert {
+        let certs = load_ca_certs(path)?;
+        info!("Trusting {} CA certificates from {:?}", certs.len(), path);
+        for cert in certs {
+            builder.add_root_certificate(cert);
+        }
+    }
+
+    if config.insecure {
+        warn!("════════════════════════════════════════════════════════════");
+        warn!("  TLS CERTIFICATE VERIFICATION DISABLED (--insecure)");
+        warn!(
+            "  Any server can impersonate {} or an https:// endpoint;",
+            config.ws_host
+        );
+        warn!("  for dev endpoints only");
+        warn!("════════════════════════════════════════════════════════════");
+    } else if config.insecure_skip_hostname {
+        warn!("Hostname verification disabled (--insecure-skip-hostname)");
+    }
+    Ok(builder.build()?)
+}
+
+/// Parse a SHA-256 fingerprint given as hex, with or without `:` separators.
+pub(crate) fn parse_fingerprint(pin: &str) -> Result<[u8; 32]> {
+    let hex: Vec<u8> = pin.bytes().filter(|c| *c != b':').collect();
+    if !hex.iter().all(u8::is_ascii_hexdigit) {
+        anyhow::bail!("--pin-sha256 {:?}: invalid hex", pin);
+    }
+    if hex.len() != 64 {
+        anyhow::bail!("--pin-sha256 {:?}: expected 64 hex digits", pin);
+    }
+    let mut out = [0u8; 32];
+    for (byte, pair) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        let digit = |c: u8| (c as char).to_digit(16).unwrap_or(0) as u8;
+        *byte = digit(pair[0]) << 4 | digit(pair[1]);
+    }
+    Ok(out)
+}
+
+/// The certificates of a PEM bundle, for `--ca-cert`.
+pub(crate) fn load_ca_certs(path: &std::path::Path) -> Result<Vec<native_tls::Certificate>> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("--ca-cert {:?}: {}", path, e))?;
+    let certs = pem
+        .split_inclusive(END)
+        .filter(|block| block.contains("-----BEGIN CERTIFICATE-----"))
+        .map(|block| {
+            native_tls::Certificate::from_pem(block.as_bytes())
+                .map_err(|e| anyhow::anyhow!("--ca-cert {:?}: {}", path, e))
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if certs.is_empty() {
+        anyhow::bail!("--ca-cert {:?}: no PEM certificates found", path);
+    }
+    Ok(certs)
+}
+
+/// The `--client-cert` / `--client-key` pair for mutual TLS, if given.
+pub(crate) fn load_client_identity(config: &Config) -> Result<Option<native_tls::Identity>> {
+    let (cert_path, key_path) = match (&config.client_cert, &config.client_key) {
+        (Some(cert), Some(key)) => (cert, key),
+        (None, None) => return Ok(None),
+        _ => anyhow::bail!("--client-cert and --client-key go together"),
+    };
+    let cert = std::fs::read(cert_path)
+        .map_err(|e| anyhow::anyhow!("--client-cert {:?}: {}", cert_path, e))?;
+    let key = std::fs::read(key_path)
+        .map_err(|e| anyhow::anyhow!("--client-key {:?}: {}", key_path, e))?;
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key).map_err(|e| {
+        anyhow::anyhow!(
+            "--client-cert {:?} / --client-key {:?}: {} (convert the key with \
+             `openssl pkcs8 -topk8 -nocrypt`)",
+            cert_path,
+            key_path,
+            e
+        )
+    })?;
+    info!("Presenting client certificate {:?}", cert_path);
+    Ok(Some(identity))
+}
+
+pub(crate) fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// HMAC-SHA256 (RFC 2104).
+pub(crate) fn hmac_sha256(key: &[u8], message: &[u8]) -> Result<[u8; 32]> {
+    let key = openssl::pkey::PKey::hmac(key)?;
+    let mut signer = openssl::sign::Signer::new(openssl::hash::MessageDigest::sha256(), &key)?;
+    signer.update(message)?;
+    let mut out = [0u8; 32];
+    signer.sign(&mut out)?;
+    Ok(out)
+}
+
+/// Hands out connection slots at most `rate` per second across all clients,
+/// so a short ramp can't burst more handshakes at the server than it allows.
+pub(crate) struct ConnectPacer {
+    pub(crate) base: tokio::time::Instant,
+    pub(crate) interval_ns: u64,
+    /// Next free slot, in nanoseconds after `base`
+    pub(crate) next_ns: AtomicU64,
+}
+
+impl ConnectPacer {
+    pub(crate) fn new(rate: f64) -> Self {
+        Self {
+            base: tokio::time::Instant::now(),
+            interval_ns: (1e9 / rate) as u64,
+            next_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// Claim the next slot and sleep until it comes round.
+    pub(crate) async fn wait(&self) {
+        let now_ns = self.base.elapsed().as_nanos() as u64;
+        let interval_ns = self.interval_ns;
+        let claimed = self
+            .next_ns
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                Some(next.max(now_ns) + interval_ns)
+            })
+            .unwrap_or(now_ns);
+        tokio::time::sleep_until(self.base + Duration::from_nanos(claimed.max(now_ns))).await;
+    }
+}
+
+/// Open the transport and perform the WebSocket upgrade, timing each phase.
+/// An upgraded connection: the stream, its setup timings, the TCP socket
+/// (for TCP_INFO) and the upgrade response's headers.
+pub(crate) type Connected = (WsStream, SetupTimings, Option<RawFd>, HeaderMap);
+
+pub(crate) async fn connect_timed(
+    transport: &dyn Transport,
+    url: &str,
+    cookie: Option<&str>,
+    subprotocol: Option<&'static str>,
+    timeout: Duration,
+) -> Result<Connected, ConnectError> {
+    let connect = async {
+        let mut timings = SetupTimings::default();
+        let TransportStream { stream, tcp_fd } = transport.connect(&mut timings).await?;
+
+        let mut request = url.into_client_request().map_err(ConnectError::Upgrade)?;
+        if let Some(cookie) = cookie.and_then(|c| HeaderValue::from_str(c).ok()) {
+            request.headers_mut().insert(header::COOKIE, cookie);
+        }
+        if let Some(subprotocol) = subprotocol {
+            request.headers_mut().insert(
+                header::SEC_WEBSOCKET_PROTOCOL,
+                HeaderValue::from_static(subprotocol),
+            );
+        }
+        let start = Instant::now();
+        let (ws_stream, response) = client_async(request, stream)
+            .await
+            .map_err(ConnectError::Upgrade)?;
+        timings.upgrade_ms = start.elapsed().as_millis() as u64;
+
+        Ok((ws_stream, timings, tcp_fd, response.into_parts().0.headers))
+    };
+
+    tokio::time::timeout(timeout, connect)
+        .await
+        .unwrap_or(Err(ConnectError::Timeout(timeout)))
+}
+
+/// Where the upgrade response names the backend behind a connection.
+#[derive(Debug, Clone)]
+pub(crate) enum BackendId {
+    Cookie(String),
+    Header(String),
+}
+
+impl BackendId {
+    pub(crate) fn parse(s: &str) -> Result<Self, String> {
+        match s.split_once(':') {
+            Some(("cookie", name)) if !name.is_empty() => Ok(BackendId::Cookie(name.to_string())),
+            Some(("header", name)) if !name.is_empty() => Ok(BackendId::Header(name.to_string())),
+            _ => Err(format!("{:?}: expected cookie:NAME or header:NAME", s)),
+        }
+    }
+
+    /// The backend named by `headers`, or for a cookie the value we sent in
+    /// `cookie` when the server didn't set it again.
+    pub(crate) fn find(&self, headers: &HeaderMap, cookie: Option<&str>) -> Option<String> {
+        match self {
+            BackendId::Header(name) => headers.get(name)?.to_str().ok().map(str::to_string),
+            BackendId::Cookie(name) => set_cookies(headers)
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v)
+                .or_else(|| {
+                    cookie?
+                        .split(';')
+                        .filter_map(|c| c.trim().split_once('='))
+                        .find(|(n, _)| n == name)
+                        .map(|(_, v)| v)
+                })
+                .map(str::to_string),
+        }
+    }
+}
+
+/// `(name, value)` of each `Set-Cookie` in `headers`, attributes dropped.
+pub(crate) fn set_cookies(headers: &HeaderMap) -> impl Iterator<Item = (&str, &str)> {
+    headers
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .filter_map(|v| v.split(';').next()?.trim().split_once('='))
+}
+
+/// `Cookie` header for an upgrade: `--cookie`s, then (`--sticky-cookies`)
+/// those the server set earlier, which win on a name clash.
+pub(crate) fn cookie_header(configured: &[String], jar: &[(String, String)]) -> Option<String> {
+    let mut cookies: Vec<String> = configured
+        .iter()
+        .map(|c| c.trim())
+        .filter(|c| {
+            let name = c.split('=').next().unwrap_or_default();
+            !c.is_empty() && !jar.iter().any(|(n, _)| n == name)
+        })
+        .map(str::to_string)
+        .collect();
+    cookies.extend(jar.iter().map(|(n, v)| format!("{}={}", n, v)));
+    (!cookies.is_empty()).then(|| cookies.join("; "))
+}
+
+// =============================================================================
+// Socket RTT Sampling (TCP_INFO)
+// =============================================================================
+
+/// Kernel view of one TCP connection.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TcpInfoSample {
+    pub(crate) rtt_us: u32,
+    pub(crate) total_retrans: u32,
+}
+
+#[cfg(target_os = "linux")]
+pub(crate) fn query_tcp_info(fd: RawFd) -> Option<TcpInfoSample> {
+    // SAFETY: getsockopt writes at most `len` bytes into the zeroed struct.
+    let info = unsafe {
+        let mut info: libc::tcp_info = std::mem::zeroed();
+        let mut len = std::mem::size_of::<libc::tcp_info>() as libc::socklen_t;
+        let rc = libc::getsockopt(
+            fd,
+            libc::IPPROTO_TCP,
+            libc::TCP_INFO,
+            &mut info as *mut libc::tcp_info as *mut libc::c_void,
+            &mut len,
+        );
+        if rc != 0 {
+            return None;
+        }
+        info
+    };
+    Some(TcpInfoSample {
+        rtt_us: info.tcpi_rtt,
+        total_retrans: info.tcpi_total_retrans,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+pub(crate) fn query_tcp_info(_fd: RawFd) -> Option<TcpInfoSample> {
+    None
+}
+
+// =============================================================================
+// Error Taxonomy
+// =============================================================================
+
+/// Where a client failure came from, so the summary can be read without logs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub(crate) enum ErrorCategory {
+    Dns,
+    TcpRefused,
+    TcpConnect,
+    Tls,
+    /// The server refused our client certificate, or wanted one we didn't send
+    TlsClientCert,
+    /// Upgrade rejected with this HTTP status
+    HttpRejected(u16),
+    Protocol,
+    Io,
+    Timeout,
+    /// `pusher:error` with this code, if the server sent one
+    Pusher(Option<i64>),
+}
+
+impl ErrorCategory {
+    pub(crate) fn from_connect(err: &ConnectError) -> Self {
+        use tokio_tungstenite::tungstenite::Error as WsError;
+        match err {
+            ConnectError::Dns(_) | ConnectError::NoAddress(_) => ErrorCategory::Dns,
+            ConnectError::Connect(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+                ErrorCategory::TcpRefused
+            }
+            ConnectError::Connect(_) => ErrorCategory::TcpConnect,
+            ConnectError::Tls(e) if client_cert_rejected(e) => ErrorCategory::TlsClientCert,
+            // Under TLS 1.3 the rejection arrives after our side of the handshake
+            ConnectError::Upgrade(WsError::Io(e)) if client_cert_rejected(e) => {
+                ErrorCategory::TlsClientCert
+            }
+            ConnectError::Tls(_) | ConnectError::PinMismatch(_) => ErrorCategory::Tls,
+            ConnectError::Upgrade(WsError::Http(resp)) => {
+                ErrorCategory::HttpRejected(resp.status().as_u16())
+            }
+            ConnectError::Upgrade(e) => Self::from_ws(e),
+            ConnectError::Timeout(_) => ErrorCategory::Timeout,
+        }
+    }
+
+    pub(crate) fn from_ws(err: &tokio_tungstenite::tungstenite::Error) -> Self {
+        use tokio_tungstenite::tungstenite::Error as WsError;
+        match err {
+            WsError::Io(_) | WsError::ConnectionClosed | WsError::AlreadyClosed => {
+                ErrorCategory::Io
+            }
+            WsError::Tls(_) => ErrorCategory::Tls,
+            _ => ErrorCategory::Protocol,
+        }
+    }
+
+    pub(crate) fn label(self) -> String {
+        match self {
+            ErrorCategory::Dns => "dns".to_string(),
+            ErrorCategory::TcpRefused => "tcp_refused".to_string(),
+            ErrorCategory::TcpConnect => "tcp_connect".to_string(),
+            ErrorCategory::Tls => "tls".to_string(),
+            ErrorCategory::TlsClientCert => "tls_client_cert".to_string(),
+            ErrorCategory::HttpRejected(status) => format!("http_{}", status),
+            ErrorCategory::Protocol => "ws_protocol".to_string(),
+            ErrorCategory::Io => "socket_io".to_string(),
+            ErrorCategory::Timeout => "timeout".to_string(),
+            ErrorCategory::Pusher(Some(code)) => format!("pusher_{}", code),
+            ErrorCategory::Pusher(None) => "pusher".to_string(),
+        }
+    }
+}
+
+/// TLS alerts (RFC 8446 §6) a server sends about our client certificate:
+/// bad, unsupported, revoked, expired, unknown, unknown CA and required.
+pub(crate) const CLIENT_CERT_ALERTS: [i32; 7] = [42, 43, 44, 45, 46, 48, 116];
+
+/// Whether a TLS failure is the server's alert about our client certificate:
+/// one it required and didn't get, couldn't verify or won't accept. Alerts
+/// about the server's own certificate are raised locally and read differently.
+///
+/// Alerts are read off the OpenSSL error stack, which is what native-tls uses
+/// on Linux; on the Schannel and Security Framework backends (Windows, macOS)
+/// these rejections count as plain `tls` errors.
+pub(crate) fn client_cert_rejected(err: &(dyn std::error::Error + 'static)) -> bool {
+    /// OpenSSL's `ERR_LIB_SSL`; it reports a received alert as reason `1000 + alert`
+    const ERR_LIB_SSL: i32 = 20;
+    const ALERT_REASON_OFFSET: i32 = 1000;
+
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if let Some(stack) = err.downcast_ref::<openssl::error::ErrorStack>() {
+            return stack.errors().iter().any(|e| {
+                e.library_code() == ERR_LIB_SSL
+                    && CLIENT_CERT_ALERTS.contains(&(e.reason_code() - ALERT_REASON_OFFSET))
+            });
+        }
+        source = err.source();
+    }
+    false
+}
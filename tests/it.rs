@@ -0,0 +1,282 @@
+//! End-to-end runs of the benchmark through [`ws_benchmark::run`], in process,
+//! checking the metrics pipeline through its k6 summary export. Enabled with
+//! `--features it`; the Soketi test also needs a docker daemon.
+#![cfg(feature = "it")]
+
+use sonic_rs::JsonValueTrait;
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+/// Run a short benchmark with `args` and return its k6 summary JSON.
+async fn run_benchmark(name: &str, args: &[&str]) -> sonic_rs::Value {
+    let output: PathBuf = std::env::temp_dir().join(format!(
+        "ws-benchmark-it-{}-{}.json",
+        name,
+        std::process::id()
+    ));
+    let output_arg = output.to_str().expect("temp dir is not UTF-8");
+    let mut argv = vec![
+        "ws-benchmark",
+        "--num-clients",
+        "5",
+        "--ramp-duration",
+        "1",
+        "--hold-duration",
+        "3",
+        "--ramp-down-duration",
+        "1",
+        "--output-format",
+        "k6",
+        "--output-file",
+        output_arg,
+    ];
+    argv.extend_from_slice(args);
+
+    let code = ws_benchmark::run(argv).await.expect("ws-benchmark failed");
+    assert_eq!(code, 0, "ws-benchmark exited with {}", code);
+
+    let summary = std::fs::read_to_string(&output).expect("no summary written");
+    let _ = std::fs::remove_file(&output);
+    sonic_rs::from_str(&summary).expect("summary is not JSON")
+}
+
+fn count(summary: &sonic_rs::Value, metric: &str) -> u64 {
+    summary["metrics"][metric]["count"]
+        .as_u64()
+        .unwrap_or_else(|| panic!("missing counter {}", metric))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn memory_transport_end_to_end() {
+    let summary = run_benchmark("memory", &["--transport", "memory", "--ws-port", "80"]).await;
+
+    assert_eq!(count(&summary, "ws_sessions"), 5);
+    assert_eq!(count(&summary, "ws_connection_errors"), 0);
+    assert!(count(&summary, "ws_msgs_received") > 0);
+    assert!(count(&summary, "data_received") > 0);
+    assert!(summary["metrics"]["ws_subscribe_latency"]["p(99)"].is_number());
+    assert!(summary["metrics"]["ws_e2e_latency"]["p(99)"].is_number());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn abort_guard_sets_exit_status() {
+    let output =
+        std::env::temp_dir().join(format!("ws-benchmark-it-abort-{}.json", std::process::id()));
+    let code = ws_benchmark::run([
+        "ws-benchmark",
+        "--transport",
+        "memory",
+        "--ws-port",
+        "80",
+        "--num-clients",
+        "5",
+        "--ramp-duration",
+        "1",
+        "--hold-duration",
+        "30",
+        "--ramp-down-duration",
+        "1",
+        "--abort-if",
+        "subscribe_success >= 1",
+        "--abort-min-attempts",
+        "1",
+        "--output-format",
+        "k6",
+        "--output-file",
+        output.to_str().expect("temp dir is not UTF-8"),
+    ])
+    .await
+    .expect("ws-benchmark failed");
+    let _ = std::fs::remove_file(&output);
+
+    assert_eq!(code, ws_benchmark::ABORTED_EXIT_CODE);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn max_runtime_returns_instead_of_exiting() {
+    let started = std::time::Instant::now();
+    let code = ws_benchmark::run([
+        "ws-benchmark",
+        "--transport",
+        "memory",
+        "--ws-port",
+        "80",
+        "--num-clients",
+        "2",
+        "--ramp-duration",
+        "1",
+        "--hold-duration",
+        "60",
+        "--max-runtime",
+        "2",
+    ])
+    .await
+    .expect("ws-benchmark failed");
+
+    assert_eq!(code, ws_benchmark::MAX_RUNTIME_EXIT_CODE);
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn listeners_close_when_run_returns() {
+    let free_port = || {
+        std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|l| l.local_addr())
+            .expect("no free port")
+            .to_string()
+    };
+    let (metrics, control) = (free_port(), free_port());
+    for _ in 0..2 {
+        let code = ws_benchmark::run([
+            "ws-benchmark",
+            "--transport",
+            "memory",
+            "--ws-port",
+            "80",
+            "--num-clients",
+            "1",
+            "--ramp-duration",
+            "1",
+            "--hold-duration",
+            "1",
+            "--ramp-down-duration",
+            "1",
+            "--metrics-addr",
+            &metrics,
+            "--control-addr",
+            &control,
+        ])
+        .await
+        .expect("ws-benchmark failed");
+        assert_eq!(code, 0);
+        // Both listeners are gone, so their ports are free for the next run
+        std::net::TcpListener::bind(&metrics).expect("metrics port still bound");
+        std::net::TcpListener::bind(&control).expect("control port still bound");
+    }
+}
+
+#[tokio::test]
+async fn bad_arguments_are_returned() {
+    let err = ws_benchmark::run(["ws-benchmark", "--no-such-flag"])
+        .await
+        .expect_err("bad arguments accepted");
+    let usage = err.downcast_ref::<clap::Error>().expect("not a clap error");
+    assert_eq!(usage.kind(), clap::error::ErrorKind::UnknownArgument);
+
+    let help = ws_benchmark::run(["ws-benchmark", "--help"])
+        .await
+        .expect_err("--help ran a benchmark");
+    assert_eq!(
+        help.downcast_ref::<clap::Error>().map(clap::Error::kind),
+        Some(clap::error::ErrorKind::DisplayHelp)
+    );
+}
+
+/// A throwaway Soketi server, started through the docker CLI on a free host
+/// port and removed on drop.
+struct Soketi {
+    id: String,
+    port: u16,
+}
+
+impl Soketi {
+    const IMAGE: &'static str = "quay.io/soketi/soketi:1.6-16-debian";
+
+    /// Start a container, or `None` when no docker daemon is reachable.
+    fn start() -> Option<Self> {
+        let reachable = Command::new("docker")
+            .arg("info")
+            .output()
+            .is_ok_and(|out| out.status.success());
+        if !reachable {
+            return None;
+        }
+        let run = Command::new("docker")
+            .args(["run", "-d", "-P"])
+            .args(["-e", "SOKETI_DEFAULT_APP_ID=app-id"])
+            .args(["-e", "SOKETI_DEFAULT_APP_KEY=app-key"])
+            .args(["-e", "SOKETI_DEFAULT_APP_SECRET=app-secret"])
+            .arg(Self::IMAGE)
+            .output()
+            .expect("docker run failed");
+        assert!(
+            run.status.success(),
+            "docker run failed: {}",
+            String::from_utf8_lossy(&run.stderr)
+        );
+        let mut soketi = Soketi {
+            id: String::from_utf8_lossy(&run.stdout).trim().to_string(),
+            port: 0,
+        };
+
+        let mapping = Command::new("docker")
+            .args(["port", &soketi.id, "6001/tcp"])
+            .output()
+            .expect("docker port failed");
+        let mapping = String::from_utf8_lossy(&mapping.stdout);
+        soketi.port = mapping
+            .lines()
+            .next()
+            .and_then(|line| line.rsplit(':').next())
+            .and_then(|port| port.parse().ok())
+            .unwrap_or_else(|| panic!("no host port for 6001/tcp in {:?}", mapping));
+        soketi.wait_ready(Duration::from_secs(60));
+        Some(soketi)
+    }
+
+    /// Docker accepts connections on the mapped port before Soketi listens, so
+    /// poll its health endpoint rather than the socket.
+    fn wait_ready(&self, limit: Duration) {
+        let deadline = Instant::now() + limit;
+        while Instant::now() < deadline {
+            let ok = TcpStream::connect(("127.0.0.1", self.port)).and_then(|mut stream| {
+                stream.set_read_timeout(Some(Duration::from_secs(2)))?;
+                stream.write_all(b"GET / HTTP/1.0\r\n\r\n")?;
+                let mut reply = String::new();
+                stream.read_to_string(&mut reply)?;
+                Ok(reply.starts_with("HTTP/1.1 200") || reply.starts_with("HTTP/1.0 200"))
+            });
+            if ok.unwrap_or(false) {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+        panic!("Soketi not ready on port {} after {:?}", self.port, limit);
+    }
+}
+
+impl Drop for Soketi {
+    fn drop(&mut self) {
+        let _ = Command::new("docker").args(["rm", "-f", &self.id]).output();
+    }
+}
+
+/// Soketi ignores the tag filter and publishes nothing, so only the subscribe
+/// path is checked. Skipped, with a note, when docker is unavailable.
+#[tokio::test(flavor = "multi_thread")]
+async fn soketi_subscribe_end_to_end() {
+    let Some(soketi) = Soketi::start() else {
+        eprintln!("skipping soketi_subscribe_end_to_end: no docker daemon reachable");
+        return;
+    };
+    let port = soketi.port.to_string();
+    let summary = run_benchmark(
+        "soketi",
+        &[
+            "--ws-host",
+            "127.0.0.1",
+            "--ws-port",
+            &port,
+            "--app-key",
+            "app-key",
+        ],
+    )
+    .await;
+
+    assert_eq!(count(&summary, "ws_sessions"), 5);
+    assert_eq!(count(&summary, "ws_connection_errors"), 0);
+    assert!(summary["metrics"]["ws_subscribe_latency"]["p(99)"].is_number());
+}